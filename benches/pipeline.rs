@@ -0,0 +1,99 @@
+// Per-datagram cost of each pipeline stage, for the reports hubs send most often: rapid wind
+// every few seconds and observations every minute. Run with `cargo bench`.
+
+use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use tempest_exporter::decoder::TempestMsg;
+use tempest_exporter::exporter::Exporter;
+use tempest_exporter::params::{self, ExporterParams, StationParams};
+use tempest_exporter::reader::{self, DecodeMode};
+
+const FIXTURES: [(&str, &str); 2] = [
+    ("rapid_wind", "rapid_wind_tempest.json"),
+    ("obs_st", "obs_st_fw156_rain_lightning.json"),
+];
+
+fn datagram(fixture: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures/decode")
+        .join(fixture);
+    fs::read_to_string(path).unwrap()
+}
+
+fn decoded(datagram: &str) -> TempestMsg {
+    TempestMsg::try_from(reader::parse(datagram).unwrap())
+        .map_err(|(_, e)| e)
+        .unwrap()
+}
+
+fn exporter() -> Exporter {
+    Exporter::new(
+        params::shared(StationParams {
+            elevation: 100.0,
+            ..Default::default()
+        }),
+        params::shared(ExporterParams {
+            instant_wind_ttl: Duration::from_secs(3600),
+            observation_ttl: Duration::from_secs(3600),
+            ..Default::default()
+        }),
+    )
+}
+
+fn read(c: &mut Criterion) {
+    let mut group = c.benchmark_group("read");
+    for (name, fixture) in FIXTURES {
+        let datagram = datagram(fixture);
+        for mode in [DecodeMode::Strict, DecodeMode::Lenient] {
+            group.bench_function(format!("{}/{:?}", name, mode), |b| {
+                b.iter(|| reader::parse_with(black_box(&datagram), mode).unwrap())
+            });
+        }
+    }
+    group.finish();
+}
+
+fn decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+    for (name, fixture) in FIXTURES {
+        let raw = reader::parse(&datagram(fixture)).unwrap();
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || raw.clone(),
+                |raw| TempestMsg::try_from(raw).map_err(|(_, e)| e).unwrap(),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn export(c: &mut Criterion) {
+    let mut group = c.benchmark_group("export");
+    let exporter = exporter();
+    for (name, fixture) in FIXTURES {
+        let msg = decoded(&datagram(fixture));
+        group.bench_function(name, |b| b.iter(|| exporter.handle_report(black_box(&msg))));
+    }
+    group.finish();
+}
+
+// A datagram all the way from bytes off the socket to metrics.
+fn end_to_end(c: &mut Criterion) {
+    let mut group = c.benchmark_group("end_to_end");
+    let exporter = exporter();
+    for (name, fixture) in FIXTURES {
+        let datagram = datagram(fixture);
+        group.bench_function(name, |b| {
+            b.iter(|| exporter.handle_report(&decoded(black_box(&datagram))))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, read, decode, export, end_to_end);
+criterion_main!(benches);
@@ -0,0 +1,302 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::config::{ExporterParams, Shared, StationMetadata, StationParams};
+use crate::decoder::TempestMsg;
+use crate::publisher::{
+    Publisher, INSTANT_WIND_TOPICS, WIND_AVG_TOPICS, WIND_GUST_TOPICS, WIND_LULL_TOPICS,
+};
+use crate::schema;
+use crate::sink_queue::LastError;
+use tempest_exporter::clock::SharedClock;
+use tempest_exporter::lightning::{LightningParams, LightningTracker};
+use tempest_exporter::threshold::{AlertState, Threshold, Trigger};
+use tempest_exporter::trend::Trend;
+
+// A value that alert rules can be set on, taken from the reports that carry it.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Quantity {
+    InstantWind,
+    WindLull,
+    WindAvg,
+    WindGust,
+    StationPressure,
+    BarometricPressure,
+    Temperature,
+    RelativeHumidity,
+    DewPoint,
+    WetBulbTemperature,
+    WetBulbGlobeTemperature,
+    ApparentTemperature,
+    Illuminance,
+    Irradiance,
+    UvIndex,
+    RainRate,
+    LightningDistance,
+    BatteryVolts,
+}
+
+impl Quantity {
+    // The MQTT topic the quantity is published under, which names it to the publish filter in
+    // the outputs that take quantities.
+    pub fn topic(&self) -> &'static str {
+        use Quantity as Q;
+        match self {
+            Q::InstantWind => INSTANT_WIND_TOPICS.speed_magnitude,
+            Q::WindLull => WIND_LULL_TOPICS.speed_magnitude,
+            Q::WindAvg => WIND_AVG_TOPICS.speed_magnitude,
+            Q::WindGust => WIND_GUST_TOPICS.speed_magnitude,
+            Q::StationPressure => "observation/pressure/station_hpa",
+            Q::BarometricPressure => "observation/pressure/barometric_hpa",
+            Q::Temperature => "observation/thermal/temperature_deg_c",
+            Q::RelativeHumidity => "observation/thermal/relative_humidity_pct",
+            Q::DewPoint => "observation/thermal/dew_point_deg_c",
+            Q::WetBulbTemperature => "observation/thermal/wet_bulb_temperature_deg_c",
+            Q::WetBulbGlobeTemperature => "observation/thermal/wet_bulb_globe_temperature_deg_c",
+            Q::ApparentTemperature => "observation/thermal/apparent_temperature_deg_c",
+            Q::Illuminance => "observation/solar/illuminance_lux",
+            Q::Irradiance => "observation/solar/irradiance_w_per_m2",
+            Q::UvIndex => "observation/solar/uv_index",
+            Q::RainRate => "observation/precip/previous_minute_rain_mm",
+            Q::LightningDistance => "event/lightning",
+            Q::BatteryVolts => "status/battery_volts",
+        }
+    }
+
+    pub fn value(&self, msg: &TempestMsg, station_params: &StationParams) -> Option<f64> {
+        use Quantity as Q;
+        use TempestMsg as TM;
+        match (self, msg) {
+            (Q::InstantWind, TM::RapidWind(rw)) => Some(rw.wind.speed_magnitude().0),
+            (Q::LightningDistance, TM::StrikeEvent(se)) => Some(se.distance),
+            (Q::BatteryVolts, TM::DeviceStatus(ds)) => Some(ds.voltage),
+            (_, TM::Observation(obs)) => match self {
+                Q::WindLull => Some(obs.wind.as_ref()?.lull.speed_magnitude().0),
+                Q::WindAvg => Some(obs.wind.as_ref()?.avg.speed_magnitude().0),
+                Q::WindGust => Some(obs.wind.as_ref()?.gust.speed_magnitude().0),
+                Q::StationPressure => obs.station_pressure.map(f64::from),
+                Q::BarometricPressure => obs
+                    .barometric_pressure(station_params.elevation)
+                    .map(f64::from),
+                Q::Temperature => obs.air_temperature.map(f64::from),
+                Q::RelativeHumidity => obs.relative_humidity,
+                Q::DewPoint => obs.dew_point().map(f64::from),
+                Q::WetBulbTemperature => obs.wet_bulb_temperature().map(f64::from),
+                Q::WetBulbGlobeTemperature => obs.wet_bulb_globe_temperature().map(f64::from),
+                Q::ApparentTemperature => obs
+                    .apparent_temperature_by(station_params.apparent_temperature_formula)
+                    .map(f64::from),
+                Q::Illuminance => Some(obs.solar.as_ref()?.illuminance),
+                Q::Irradiance => Some(obs.solar.as_ref()?.irradiance),
+                Q::UvIndex => Some(obs.solar.as_ref()?.ultraviolet_index),
+                Q::RainRate => Some(obs.precip.as_ref()?.quantity_last_minute.0),
+                // A minute without strikes counts as lightning infinitely far away, so that
+                // nearby lightning alerts resolve once the storm has passed.
+                Q::LightningDistance => {
+                    let lightning = obs.lightning.as_ref()?;
+                    if lightning.count > 0 {
+                        Some(lightning.average_distance)
+                    } else {
+                        Some(f64::INFINITY)
+                    }
+                }
+                Q::BatteryVolts => Some(obs.battery_volts),
+                Q::InstantWind => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AlertRule {
+    pub name: String,
+    pub quantity: Quantity,
+    pub threshold: Threshold,
+    // How far back past the threshold the value must go before the alert resolves.
+    pub hysteresis: f64,
+    // Minimum time between firings, so a value hovering around the threshold doesn't flap.
+    pub cooldown: Duration,
+    pub webhook: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Notification<'a> {
+    alert: &'a str,
+    state: AlertState,
+    value: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    above: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    below: Option<f64>,
+    timestamp: DateTime<Utc>,
+    #[serde(skip_serializing_if = "StationMetadata::is_empty")]
+    station: &'a StationMetadata,
+}
+
+// Evaluates alert rules against each report, notifying over MQTT and webhooks when an alert
+// starts firing or resolves.
+pub struct Alerter {
+    station_params: Shared<StationParams>,
+    exporter_params: Shared<ExporterParams>,
+    publisher: Arc<Publisher>,
+    http: reqwest::Client,
+    rules: Mutex<Vec<(AlertRule, Trigger)>>,
+    lightning: Mutex<LightningTracker>,
+    storm: Mutex<(Trend, Option<bool>)>,
+    errors: Arc<LastError>,
+    clock: SharedClock,
+}
+
+impl Alerter {
+    pub fn new(
+        station_params: Shared<StationParams>,
+        exporter_params: Shared<ExporterParams>,
+        publisher: Arc<Publisher>,
+        rules: Vec<AlertRule>,
+        lightning_params: LightningParams,
+        clock: SharedClock,
+    ) -> Self {
+        Self {
+            station_params,
+            exporter_params,
+            publisher,
+            http: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap(),
+            rules: Mutex::new(Self::with_state(rules)),
+            lightning: Mutex::new(LightningTracker::new(lightning_params)),
+            storm: Mutex::new((Trend::new(), None)),
+            errors: Arc::new(LastError::default()),
+            clock,
+        }
+    }
+
+    // Where webhook errors are recorded, for `/debug/state`.
+    pub fn errors(&self) -> Arc<LastError> {
+        self.errors.clone()
+    }
+
+    fn with_state(rules: Vec<AlertRule>) -> Vec<(AlertRule, Trigger)> {
+        rules
+            .into_iter()
+            .map(|rule| (rule, Trigger::default()))
+            .collect()
+    }
+
+    // Replaces the rules if they changed, which resets whether each alert is firing.
+    pub fn reconfigure(&self, new_rules: Vec<AlertRule>, lightning_params: LightningParams) {
+        let mut rules = self.rules.lock().unwrap();
+        if !rules.iter().map(|(rule, _)| rule).eq(new_rules.iter()) {
+            info!("Alert rules changed");
+            *rules = Self::with_state(new_rules);
+        }
+        self.lightning.lock().unwrap().reconfigure(lightning_params);
+    }
+
+    pub fn handle_report(&self, msg: &TempestMsg) {
+        let level = self.lightning.lock().unwrap().update(msg, self.clock.utc());
+        if let Some(level) = level {
+            info!("Lightning alert level {}", level.as_str());
+            self.publisher
+                .publish_alert("lightning", level.as_str().to_string());
+        }
+        if let Some(storm) = self.update_storm(msg) {
+            info!("Storm warning {}", if storm { "raised" } else { "cleared" });
+            self.publisher.publish_alert("storm", storm.to_string());
+        }
+
+        let station_params = self.station_params.read().unwrap();
+        let mut rules = self.rules.lock().unwrap();
+        let now = self.clock.now();
+        for (rule, trigger) in rules.iter_mut() {
+            let value = match rule.quantity.value(msg, &station_params) {
+                Some(value) => value,
+                None => continue,
+            };
+            let alert_state =
+                match trigger.update(rule.threshold, rule.hysteresis, rule.cooldown, value, now) {
+                    Some(alert_state) => alert_state,
+                    None => continue,
+                };
+            self.notify(
+                rule,
+                alert_state,
+                value,
+                msg.timestamp(),
+                &station_params.metadata,
+            );
+        }
+    }
+
+    // Returns whether a storm is signalled when that changes.
+    fn update_storm(&self, msg: &TempestMsg) -> Option<bool> {
+        let obs = match msg {
+            TempestMsg::Observation(obs) => obs,
+            _ => return None,
+        };
+        let params = self.exporter_params.read().unwrap().storm.clone();
+        let mut storm = self.storm.lock().unwrap();
+        let (trend, signalled) = &mut *storm;
+        trend.push(obs.timestamp, obs.station_pressure?.0, params.window);
+        let now = params.signalled_by(trend.change(params.window)?);
+        if *signalled == Some(now) {
+            return None;
+        }
+        *signalled = Some(now);
+        Some(now)
+    }
+
+    fn notify(
+        &self,
+        rule: &AlertRule,
+        state: AlertState,
+        value: f64,
+        timestamp: DateTime<Utc>,
+        station: &StationMetadata,
+    ) {
+        info!("Alert {} {:?} at {}", rule.name, state, value);
+        let (above, below) = match rule.threshold {
+            Threshold::Above(limit) => (Some(limit), None),
+            Threshold::Below(limit) => (None, Some(limit)),
+        };
+        let notification = Notification {
+            alert: &rule.name,
+            state,
+            value,
+            above,
+            below,
+            timestamp,
+            station,
+        };
+        let mut notification = serde_json::to_value(&notification).unwrap();
+        self.exporter_params
+            .read()
+            .unwrap()
+            .precision
+            .round_json(&mut notification);
+        let payload = schema::versioned(&notification);
+        self.publisher.publish_alert(&rule.name, payload.clone());
+        if let Some(webhook) = &rule.webhook {
+            let request = self
+                .http
+                .post(webhook)
+                .header("content-type", "application/json")
+                .body(payload);
+            let name = rule.name.clone();
+            let errors = self.errors.clone();
+            tokio::spawn(async move {
+                if let Err(e) = request.send().await.and_then(|r| r.error_for_status()) {
+                    error!("Alert {} webhook failed: {}", name, e);
+                    errors.record(format!("Alert {} webhook failed: {}", name, e));
+                }
+            });
+        }
+    }
+}
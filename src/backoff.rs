@@ -0,0 +1,34 @@
+//! Delays between reconnection attempts, doubling with each failure up to [`BACKOFF_MAX`].
+
+use std::time::Duration;
+
+use rand::Rng;
+
+pub const BACKOFF_INITIAL: Duration = Duration::from_secs(1);
+pub const BACKOFF_MAX: Duration = Duration::from_secs(300);
+
+/// Counts consecutive failed attempts. Each delay is drawn at random from the upper half of its
+/// ceiling, so that many exporters losing the same broker don't all retry in step once it comes
+/// back.
+#[derive(Default)]
+pub struct Backoff {
+    failures: u32,
+}
+
+impl Backoff {
+    /// Records a failed attempt, returning the number of consecutive failures and how long to
+    /// wait before the next attempt.
+    pub fn fail(&mut self) -> (u32, Duration) {
+        self.failures = self.failures.saturating_add(1);
+        let ceiling = BACKOFF_INITIAL
+            .checked_mul(1 << (self.failures - 1).min(16))
+            .map_or(BACKOFF_MAX, |delay| delay.min(BACKOFF_MAX));
+        let delay = ceiling.mul_f64(rand::thread_rng().gen_range(0.5..=1.0));
+        (self.failures, delay)
+    }
+
+    /// Records a successful connection, returning the number of failures before it, if any.
+    pub fn reset(&mut self) -> Option<u32> {
+        Some(std::mem::take(&mut self.failures)).filter(|&failures| failures > 0)
+    }
+}
@@ -0,0 +1,24 @@
+use futures_core::stream::Stream;
+use tokio_stream::StreamExt;
+
+use crate::config::{Shared, StationParams};
+use crate::decoder::TempestMsg;
+
+pub fn new<DEC: Stream<Item = TempestMsg>>(
+    decoder: DEC,
+    station_params: Shared<StationParams>,
+) -> impl Stream<Item = TempestMsg> {
+    decoder.map(move |msg| calibrate(msg, &station_params.read().unwrap()))
+}
+
+pub fn calibrate(msg: TempestMsg, station_params: &StationParams) -> TempestMsg {
+    match msg {
+        TempestMsg::Observation(mut obs) => {
+            let calibration = station_params.calibration_for(&obs.serial_number);
+            obs.calibrate(calibration.temp_offset, calibration.rh_offset);
+            obs.apply_floors(calibration.illuminance_floor, calibration.uv_floor);
+            TempestMsg::Observation(obs)
+        }
+        other => other,
+    }
+}
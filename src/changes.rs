@@ -0,0 +1,37 @@
+//! Publishing only the readings that changed, for consumers that would rather not be sent the
+//! same retained value with every report.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// The payload last published to each topic and when, deciding whether the next needs
+/// publishing: only if it differs, or the same was last published `refresh` or longer ago, so
+/// that retained values are still refreshed now and then.
+#[derive(Debug)]
+pub struct ChangesOnly {
+    refresh: Duration,
+    published: HashMap<Arc<str>, (String, Instant)>,
+}
+
+impl ChangesOnly {
+    pub fn new(refresh: Duration) -> Self {
+        Self {
+            refresh,
+            published: HashMap::new(),
+        }
+    }
+
+    /// Whether `payload` needs publishing to `topic` at `now`.
+    pub fn is_due(&self, topic: &str, payload: &str, now: Instant) -> bool {
+        match self.published.get(topic) {
+            Some((last, at)) => last != payload || now.duration_since(*at) >= self.refresh,
+            None => true,
+        }
+    }
+
+    /// Records that `payload` was published to `topic` at `now`.
+    pub fn record(&mut self, topic: Arc<str>, payload: String, now: Instant) {
+        self.published.insert(topic, (payload, now));
+    }
+}
@@ -0,0 +1,115 @@
+//! Aggregate state kept across restarts in a small JSON file.
+//!
+//! Counters and histograms would otherwise restart from zero, which Prometheus copes with but
+//! which loses totals for anything reading the exposition directly. A [`Checkpoint`] is taken
+//! from an [`Exporter`](crate::exporter::Exporter) periodically and restored into a fresh one at
+//! startup. It also carries the latest observation and status reports as received, from the
+//! [`StationState`](crate::state::StationState), so that metrics don't go missing until devices
+//! next report, which can take minutes.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::rain::{RainCounter, SeasonTotal};
+use crate::reader::RawTempestMsg;
+
+/// Format version written to new checkpoints. Checkpoints of any other version are ignored.
+pub const VERSION: u32 = 1;
+
+/// Snapshot of the state kept across restarts.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub version: u32,
+    pub saved_at: DateTime<Utc>,
+    /// Messages received, keyed by message type.
+    pub messages_received: BTreeMap<String, u64>,
+    /// Messages dropped by a sink queue, keyed by sink.
+    pub sink_queue_dropped: BTreeMap<String, u64>,
+    /// Hub reboots, keyed by reset reason.
+    #[serde(default)]
+    pub hub_reboots: BTreeMap<String, u64>,
+    /// Device restarts.
+    #[serde(default)]
+    pub station_restarts: u64,
+    /// Times the lightning sensor started detecting noise.
+    #[serde(default)]
+    pub lightning_noise_events: u64,
+    /// Times the lightning sensor started detecting disturbers.
+    #[serde(default)]
+    pub lightning_disturber_events: u64,
+    /// Time of the latest lightning strike.
+    #[serde(default)]
+    pub last_strike: Option<DateTime<Utc>>,
+    /// Time rain was last seen, whether starting or in an observation.
+    #[serde(default)]
+    pub last_rain: Option<DateTime<Utc>>,
+    /// Rain over the current rain season.
+    #[serde(default)]
+    pub rain_season: Option<SeasonTotal>,
+    /// Rain counted for Domoticz rain devices, so that their counter doesn't go backwards.
+    #[serde(default)]
+    pub domoticz_rain: RainCounter,
+    /// Histograms, keyed by metric name.
+    pub histograms: BTreeMap<String, HistogramState>,
+    /// Latest observation and status reports, as received.
+    #[serde(default)]
+    pub last_reports: Vec<RawTempestMsg>,
+}
+
+/// Cumulative state of one histogram.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct HistogramState {
+    pub upper_bounds: Vec<f64>,
+    /// Cumulative count at each upper bound, excluding +Inf.
+    pub bucket_counts: Vec<u64>,
+    pub sample_count: u64,
+    pub sample_sum: f64,
+}
+
+/// Reads a checkpoint, returning `None` if there is none. A checkpoint that is unreadable,
+/// corrupt, or of another version is logged and ignored rather than preventing startup.
+pub fn load(path: &Path) -> Option<Checkpoint> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == ErrorKind::NotFound => return None,
+        Err(e) => {
+            warn!("Ignoring unreadable state file {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    let checkpoint: Checkpoint = match serde_json::from_str(&text) {
+        Ok(checkpoint) => checkpoint,
+        Err(e) => {
+            warn!("Ignoring corrupt state file {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    if checkpoint.version != VERSION {
+        warn!(
+            "Ignoring state file {} of unsupported version {}",
+            path.display(),
+            checkpoint.version
+        );
+        return None;
+    }
+    Some(checkpoint)
+}
+
+/// Writes a checkpoint, replacing the previous one atomically so that a crash mid-write leaves
+/// the previous checkpoint intact.
+pub fn save(path: &Path, checkpoint: &Checkpoint) -> anyhow::Result<()> {
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    fs::write(&temp_path, serde_json::to_vec_pretty(checkpoint)?)
+        .with_context(|| format!("Writing state file {:?}", temp_path))?;
+    fs::rename(&temp_path, path)
+        .with_context(|| format!("Replacing state file {}", path.display()))?;
+    Ok(())
+}
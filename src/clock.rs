@@ -0,0 +1,76 @@
+//! Where the exporter gets the time from.
+//!
+//! Metrics expire and windows roll over by the clock rather than by reports arriving, so an
+//! [`exporter::Exporter`](crate::exporter::Exporter) reads the time from a [`Clock`]. The
+//! [`SystemClock`] is the real one; a [`ManualClock`] only moves when told to, for testing expiry
+//! and windowing without waiting.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+
+/// A source of the current time.
+pub trait Clock: Send + Sync {
+    /// Monotonic time, for how long ago something happened and when it expires.
+    fn now(&self) -> Instant;
+
+    /// Wall-clock time, for comparing with the timestamps of reports.
+    fn utc(&self) -> DateTime<Utc>;
+}
+
+/// A clock shared by everything that keeps time for one exporter.
+pub type SharedClock = Arc<dyn Clock>;
+
+/// The system's clocks.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn utc(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// The system clock, shared.
+pub fn system() -> SharedClock {
+    Arc::new(SystemClock)
+}
+
+/// A clock that stands still until advanced.
+#[derive(Debug)]
+pub struct ManualClock {
+    started: Instant,
+    started_utc: DateTime<Utc>,
+    elapsed: Mutex<Duration>,
+}
+
+impl ManualClock {
+    /// A clock reading `utc` as the wall-clock time.
+    pub fn new(utc: DateTime<Utc>) -> Self {
+        ManualClock {
+            started: Instant::now(),
+            started_utc: utc,
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    /// Moves both the monotonic and wall-clock time forward.
+    pub fn advance(&self, by: Duration) {
+        *self.elapsed.lock().unwrap() += by;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.started + *self.elapsed.lock().unwrap()
+    }
+
+    fn utc(&self) -> DateTime<Utc> {
+        self.started_utc + chrono::Duration::from_std(*self.elapsed.lock().unwrap()).unwrap()
+    }
+}
@@ -0,0 +1,1516 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context};
+use serde::Deserialize;
+use structopt::StructOpt;
+use tempest_exporter::derived::DerivedMetric;
+use tempest_exporter::exporter;
+use tempest_exporter::filter::PublishFilter;
+use tempest_exporter::history;
+use tempest_exporter::hubs::HubPreference;
+use tempest_exporter::lightning::LightningParams;
+use tempest_exporter::precision::Precision;
+use tempest_exporter::rain_check::RainCheckParams;
+use tempest_exporter::reader::DecodeMode;
+use tempest_exporter::receiver;
+use tempest_exporter::reference::ReferenceParams;
+use tempest_exporter::smoothing::Smoothing;
+use tempest_exporter::threshold::Threshold;
+use tempest_exporter::topic::TopicTemplate;
+
+pub use tempest_exporter::params::{
+    shared, ApparentTemperatureFormula, Calibration, ExporterParams, NameMode, Shared,
+    StationMetadata, StationParams, StormParams,
+};
+
+use crate::alerts::{AlertRule, Quantity};
+use crate::domoticz::DomoticzParams;
+use crate::ecowitt::{self, EcowittParams};
+use crate::grafana_live::GrafanaLiveParams;
+use crate::knx::{self, KnxParams};
+use crate::modbus::{ModbusParams, Register, RegisterFormat};
+use crate::nmea::NmeaParams;
+use crate::once::OutputFormat;
+use crate::signalk::{SignalKParams, WindConvention};
+use crate::simulator::Scenario;
+use crate::sink_queue::{QueueOptions, SINK_NAMES};
+
+// Configuration is layered: command line flags take precedence over `TEMPEST_*` environment
+// variables, which take precedence over values from the configuration file, which take precedence
+// over built-in defaults. The same option structs are used for both the command line and the
+// configuration file, and each layer is merged field-by-field with `or`.
+
+#[derive(StructOpt, Debug)]
+pub struct Opt {
+    /// Path to TOML configuration file
+    #[structopt(long, env = "TEMPEST_CONFIG", parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    /// Run the full pipeline but publish to a dummy sink instead of the MQTT broker
+    #[structopt(long, env = "TEMPEST_DRY_RUN", require_equals = true)]
+    dry_run: Option<Option<bool>>,
+
+    /// Run as a Windows service (used in the service's registered command line)
+    #[cfg(windows)]
+    #[structopt(long)]
+    pub windows_service: bool,
+
+    #[structopt(flatten)]
+    options: Options,
+
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum Command {
+    /// Validate and print the effective configuration, then exit
+    CheckConfig {
+        /// Also check that the MQTT broker accepts a connection
+        #[structopt(long)]
+        connect: bool,
+    },
+
+    /// Print every received message, raw and decoded, without exporting or publishing
+    Listen {
+        /// Print one JSON document per message instead of human-readable output
+        #[structopt(long)]
+        json: bool,
+    },
+
+    /// Send synthetic Tempest messages, for testing without station hardware
+    Simulate {
+        /// Weather scenario: calm, storm, or sensor-failure
+        #[structopt(long, default_value = "calm")]
+        scenario: Scenario,
+
+        /// Address to send messages to (may be a broadcast address)
+        #[structopt(long, default_value = "127.0.0.1:50222")]
+        target: SocketAddr,
+
+        /// Serial number of the simulated station
+        #[structopt(long, default_value = "ST-00000001")]
+        serial_number: String,
+
+        /// Serial number of the simulated hub
+        #[structopt(long, default_value = "HB-00000001")]
+        hub_serial_number: String,
+    },
+
+    /// Probe the running exporter's health endpoint, exiting non-zero unless it is healthy
+    Healthcheck {
+        /// Probe readiness (/readyz), which also requires data to have arrived, instead of liveness
+        #[structopt(long)]
+        ready: bool,
+
+        /// Seconds to wait for an answer
+        #[structopt(long, default_value = "5")]
+        timeout: u64,
+    },
+
+    /// Wait for the next observation, print it with derived values, then exit
+    Once {
+        /// Output format: text, json, or prom
+        #[structopt(long, default_value = "text")]
+        format: OutputFormat,
+    },
+}
+
+#[derive(StructOpt, Deserialize, Clone, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct Options {
+    /// Log verbosity level, overridden by `RUST_LOG` filter directives if set [default: info]
+    #[structopt(long, env = "TEMPEST_LOG_LEVEL")]
+    log_level: Option<log::LevelFilter>,
+
+    /// OpenTelemetry collector endpoint to export traces to over OTLP/gRPC
+    #[structopt(long, env = "TEMPEST_OTLP_ENDPOINT")]
+    otlp_endpoint: Option<String>,
+
+    /// Port to bind the Prometheus metrics server [default: 8080]
+    #[structopt(long, env = "TEMPEST_METRICS_PORT")]
+    metrics_port: Option<u16>,
+
+    /// Serve metrics, health checks and the debug API over HTTP [default: true]
+    #[structopt(long, env = "TEMPEST_ENABLE_PROMETHEUS")]
+    enable_prometheus: Option<bool>,
+
+    /// Publish reports over MQTT, or log what would be published if no broker is set
+    /// [default: true]
+    #[structopt(long, env = "TEMPEST_ENABLE_MQTT")]
+    enable_mqtt: Option<bool>,
+
+    /// Evaluate alert rules and lightning alerts [default: true]
+    #[structopt(long, env = "TEMPEST_ENABLE_ALERTS")]
+    enable_alerts: Option<bool>,
+
+    /// UDP port to receive hub broadcasts on [default: 50222]
+    #[structopt(long, env = "TEMPEST_API_PORT")]
+    api_port: Option<u16>,
+
+    /// Serve HTTP on this inherited listening socket instead of binding the metrics port. Sockets
+    /// passed by systemd socket activation are picked up without it
+    #[structopt(long, env = "TEMPEST_LISTEN_FD")]
+    listen_fd: Option<i32>,
+
+    /// Path prefix to serve metrics, health and API routes under, e.g. "tempest" for
+    /// `/tempest/metrics` [default: none]
+    #[structopt(long, env = "TEMPEST_ROUTE_PREFIX")]
+    route_prefix: Option<String>,
+
+    /// HTTP connections served at once; more wait to be accepted until one closes [default: 16]
+    #[structopt(long, env = "TEMPEST_HTTP_MAX_CONNECTIONS")]
+    http_max_connections: Option<usize>,
+
+    /// Seconds an HTTP client has to send a request, or to read a response, before it is
+    /// disconnected. Also how long an idle connection is kept open [default: 10]
+    #[structopt(long, env = "TEMPEST_HTTP_REQUEST_TIMEOUT")]
+    http_request_timeout: Option<u64>,
+
+    /// How to treat datagrams that stray from the documented format: strict drops them, lenient
+    /// repairs what it can and drops only what it can't [default: lenient]
+    #[structopt(long, env = "TEMPEST_DECODE_MODE")]
+    decode_mode: Option<DecodeMode>,
+
+    /// Seconds instantaneous wind metrics are exported after the last report [default: 15]
+    #[structopt(long, env = "TEMPEST_INSTANT_WIND_TTL")]
+    instant_wind_ttl: Option<u64>,
+
+    /// Seconds of instantaneous wind summarized at each scrape, which should be at least the
+    /// scrape interval so that no gust goes unseen [default: 60]
+    #[structopt(long, env = "TEMPEST_INSTANT_WIND_WINDOW")]
+    instant_wind_window: Option<u64>,
+
+    /// Seconds observation metrics are exported after the last report [default: 180]
+    #[structopt(long, env = "TEMPEST_OBSERVATION_TTL")]
+    observation_ttl: Option<u64>,
+
+    /// Minutes over which a pressure drop signals a storm [default: 180]
+    #[structopt(long, env = "TEMPEST_STORM_WINDOW")]
+    storm_window: Option<u64>,
+
+    /// Station pressure drop within the storm window that signals a storm (hPa) [default: 3]
+    #[structopt(long, env = "TEMPEST_STORM_PRESSURE_DROP")]
+    storm_pressure_drop: Option<f64>,
+
+    /// Minutes without rain after which a rain event ends [default: 30]
+    #[structopt(long, env = "TEMPEST_RAIN_EVENT_DRY_MINUTES")]
+    rain_event_dry_minutes: Option<u64>,
+
+    /// Month and day the rain season totalled since its start begins each year, as MM-DD, such
+    /// as 10-01 for a water year [default: 10-01]
+    #[structopt(long, env = "TEMPEST_RAIN_SEASON_START")]
+    rain_season_start: Option<String>,
+
+    /// Hours of minute-by-minute observations kept for `/api/v1/recent`, at most 48
+    /// [default: 24]
+    #[structopt(long, env = "TEMPEST_RECENT_HOURS")]
+    recent_hours: Option<u64>,
+
+    /// Seconds to wait for sinks to flush pending messages on shutdown [default: 5]
+    #[structopt(long, env = "TEMPEST_SHUTDOWN_TIMEOUT")]
+    shutdown_timeout: Option<u64>,
+
+    /// Whether to wait for the first message before serving metrics ("wait"), or serve
+    /// immediately and report not ready until data arrives ("serve") [default: wait]
+    #[structopt(long, env = "TEMPEST_STARTUP_MODE")]
+    startup_mode: Option<StartupMode>,
+
+    /// Seconds to wait for the first message before giving up [default: wait forever]
+    #[structopt(long, env = "TEMPEST_FIRST_DATA_TIMEOUT")]
+    first_data_timeout: Option<u64>,
+
+    /// File to keep metric totals and the latest reports in across restarts [default: not kept]
+    #[structopt(long, env = "TEMPEST_STATE_FILE")]
+    state_file: Option<PathBuf>,
+
+    /// Seconds between checkpoints of the state file [default: 60]
+    #[structopt(long, env = "TEMPEST_CHECKPOINT_INTERVAL")]
+    checkpoint_interval: Option<u64>,
+
+    /// Bearer token required to read internal state from `/debug/state` [default: disabled]
+    #[structopt(long, env = "TEMPEST_DEBUG_TOKEN", hide_env_values = true)]
+    debug_token: Option<String>,
+
+    /// File containing the `/debug/state` bearer token
+    #[structopt(long, env = "TEMPEST_DEBUG_TOKEN_FILE", parse(from_os_str))]
+    debug_token_file: Option<PathBuf>,
+
+    /// MQTT parameters
+    #[structopt(flatten)]
+    mqtt: MqttOptions,
+
+    /// Station parameters
+    #[structopt(flatten)]
+    station: StationOptions,
+
+    /// Lightning alert parameters
+    #[structopt(flatten)]
+    lightning: LightningOptions,
+
+    /// WeatherFlow Rain Check reconciliation parameters
+    #[structopt(flatten)]
+    rain_check: RainCheckOptions,
+
+    /// Reference station comparison parameters
+    #[structopt(flatten)]
+    reference: ReferenceOptions,
+
+    /// Per-device parameters, keyed by device serial number (configuration file only)
+    #[structopt(skip)]
+    devices: HashMap<String, DeviceOptions>,
+
+    /// Alert rules (configuration file only)
+    #[structopt(skip)]
+    alerts: Vec<AlertOptions>,
+
+    /// Derived metrics, keyed by name, as arithmetic expressions over observation fields
+    /// (configuration file only)
+    #[structopt(skip)]
+    derived: BTreeMap<String, String>,
+
+    /// Time constants (seconds) for exponential smoothing, keyed by observation field as named in
+    /// derived metrics (configuration file only)
+    #[structopt(skip)]
+    smoothing: BTreeMap<String, u64>,
+
+    /// Names to expose renamed metrics under during their deprecation window: current, legacy or
+    /// both, keyed by either name (configuration file only) [default: both]
+    #[structopt(skip)]
+    metric_names: HashMap<String, NameMode>,
+
+    /// MQTT topics and Prometheus metrics to publish or suppress, as glob patterns (configuration
+    /// file only) [default: everything]
+    #[structopt(skip)]
+    publish: Option<PublishOptions>,
+
+    /// Decimal places to round values to in MQTT payloads and JSON output, by category
+    /// (configuration file only) [default: full precision]
+    #[structopt(skip)]
+    precision: Option<Precision>,
+
+    /// Domoticz virtual devices to update over MQTT (configuration file only) [default: none]
+    #[structopt(skip)]
+    domoticz: Option<DomoticzOptions>,
+
+    /// KNX group addresses to write observations to over KNXnet/IP routing (configuration file
+    /// only) [default: none]
+    #[structopt(skip)]
+    knx: Option<KnxOptions>,
+
+    /// Modbus TCP server exposing observations as registers (configuration file only)
+    /// [default: not served]
+    #[structopt(skip)]
+    modbus: Option<ModbusOptions>,
+
+    /// Signal K server to send deltas to over UDP (configuration file only) [default: none]
+    #[structopt(skip)]
+    signalk: Option<SignalKOptions>,
+
+    /// NMEA 0183 weather sentences to send over UDP or serve over TCP (configuration file only)
+    /// [default: none]
+    #[structopt(skip)]
+    nmea: Option<NmeaOptions>,
+
+    /// Server to upload observations to in the Ecowitt or Ambient Weather custom server protocol
+    /// (configuration file only) [default: none]
+    #[structopt(skip)]
+    ecowitt: Option<EcowittOptions>,
+
+    /// Grafana to push rapid wind and observations to over Grafana Live (configuration file only)
+    /// [default: none]
+    #[structopt(skip)]
+    grafana_live: Option<GrafanaLiveOptions>,
+
+    /// Capacity and overflow policy (drop-oldest, drop-newest or block) of the queues feeding
+    /// sinks, keyed by sink name: exporter, history, mqtt, alerts, knx, signalk, nmea, ecowitt,
+    /// grafana_live or modbus. A blocking queue holds up every sink while it is full
+    /// (configuration file only) [default: 256, dropping the newest for mqtt and alerts and the
+    /// oldest otherwise]
+    #[structopt(skip)]
+    queues: BTreeMap<String, QueueOptions>,
+
+    /// Stations to run separate pipelines for, keyed by name, each with options overriding those
+    /// given here. Their metrics are served together, labelled with the station name
+    /// (configuration file only) [default: a single unnamed station]
+    #[structopt(skip)]
+    stations: BTreeMap<String, Options>,
+}
+
+impl Options {
+    fn or(self, other: Self) -> Self {
+        let (debug_token, debug_token_file) = or_secret(
+            (self.debug_token, self.debug_token_file),
+            (other.debug_token, other.debug_token_file),
+        );
+        let mut devices = other.devices;
+        devices.extend(self.devices);
+        let mut derived = other.derived;
+        derived.extend(self.derived);
+        let mut smoothing = other.smoothing;
+        smoothing.extend(self.smoothing);
+        let mut metric_names = other.metric_names;
+        metric_names.extend(self.metric_names);
+        let mut queues = other.queues;
+        queues.extend(self.queues);
+        let mut stations = other.stations;
+        stations.extend(self.stations);
+        Self {
+            log_level: self.log_level.or(other.log_level),
+            otlp_endpoint: self.otlp_endpoint.or(other.otlp_endpoint),
+            metrics_port: self.metrics_port.or(other.metrics_port),
+            enable_prometheus: self.enable_prometheus.or(other.enable_prometheus),
+            enable_mqtt: self.enable_mqtt.or(other.enable_mqtt),
+            enable_alerts: self.enable_alerts.or(other.enable_alerts),
+            api_port: self.api_port.or(other.api_port),
+            listen_fd: self.listen_fd.or(other.listen_fd),
+            route_prefix: self.route_prefix.or(other.route_prefix),
+            http_max_connections: self.http_max_connections.or(other.http_max_connections),
+            http_request_timeout: self.http_request_timeout.or(other.http_request_timeout),
+            decode_mode: self.decode_mode.or(other.decode_mode),
+            instant_wind_ttl: self.instant_wind_ttl.or(other.instant_wind_ttl),
+            instant_wind_window: self.instant_wind_window.or(other.instant_wind_window),
+            observation_ttl: self.observation_ttl.or(other.observation_ttl),
+            storm_window: self.storm_window.or(other.storm_window),
+            storm_pressure_drop: self.storm_pressure_drop.or(other.storm_pressure_drop),
+            rain_event_dry_minutes: self.rain_event_dry_minutes.or(other.rain_event_dry_minutes),
+            rain_season_start: self.rain_season_start.or(other.rain_season_start),
+            recent_hours: self.recent_hours.or(other.recent_hours),
+            shutdown_timeout: self.shutdown_timeout.or(other.shutdown_timeout),
+            startup_mode: self.startup_mode.or(other.startup_mode),
+            first_data_timeout: self.first_data_timeout.or(other.first_data_timeout),
+            state_file: self.state_file.or(other.state_file),
+            checkpoint_interval: self.checkpoint_interval.or(other.checkpoint_interval),
+            debug_token,
+            debug_token_file,
+            mqtt: self.mqtt.or(other.mqtt),
+            station: self.station.or(other.station),
+            lightning: self.lightning.or(other.lightning),
+            rain_check: self.rain_check.or(other.rain_check),
+            reference: self.reference.or(other.reference),
+            devices,
+            alerts: if self.alerts.is_empty() {
+                other.alerts
+            } else {
+                self.alerts
+            },
+            derived,
+            smoothing,
+            metric_names,
+            queues,
+            publish: self.publish.or(other.publish),
+            precision: self.precision.or(other.precision),
+            domoticz: self.domoticz.or(other.domoticz),
+            knx: self.knx.or(other.knx),
+            modbus: self.modbus.or(other.modbus),
+            signalk: self.signalk.or(other.signalk),
+            nmea: self.nmea.or(other.nmea),
+            ecowitt: self.ecowitt.or(other.ecowitt),
+            grafana_live: self.grafana_live.or(other.grafana_live),
+            stations,
+        }
+    }
+
+    // Options applying to the whole exporter rather than one station, which stations can't
+    // override.
+    fn process_wide(&self) -> Vec<&'static str> {
+        [
+            ("log_level", self.log_level.is_some()),
+            ("otlp_endpoint", self.otlp_endpoint.is_some()),
+            ("metrics_port", self.metrics_port.is_some()),
+            ("enable_prometheus", self.enable_prometheus.is_some()),
+            ("listen_fd", self.listen_fd.is_some()),
+            ("route_prefix", self.route_prefix.is_some()),
+            ("http_max_connections", self.http_max_connections.is_some()),
+            ("http_request_timeout", self.http_request_timeout.is_some()),
+            ("shutdown_timeout", self.shutdown_timeout.is_some()),
+            ("startup_mode", self.startup_mode.is_some()),
+            ("first_data_timeout", self.first_data_timeout.is_some()),
+            ("checkpoint_interval", self.checkpoint_interval.is_some()),
+            ("debug_token", self.debug_token.is_some()),
+            ("debug_token_file", self.debug_token_file.is_some()),
+            ("stations", !self.stations.is_empty()),
+        ]
+        .into_iter()
+        .filter_map(|(name, set)| set.then_some(name))
+        .collect()
+    }
+}
+
+#[derive(StructOpt, Deserialize, Clone, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct MqttOptions {
+    /// Port to use for MQTT broker [default: 1883]
+    #[structopt(long = "mqtt-port", env = "TEMPEST_MQTT_PORT")]
+    port: Option<u16>,
+
+    /// Address of MQTT broker
+    #[structopt(long = "mqtt-broker", env = "TEMPEST_MQTT_BROKER")]
+    broker: Option<String>,
+
+    /// MQTT username
+    #[structopt(long = "mqtt-username", env = "TEMPEST_MQTT_USERNAME")]
+    username: Option<String>,
+
+    /// MQTT password
+    #[structopt(
+        long = "mqtt-password",
+        env = "TEMPEST_MQTT_PASSWORD",
+        hide_env_values = true
+    )]
+    password: Option<String>,
+
+    /// File containing the MQTT password, e.g. a mounted container secret
+    #[structopt(
+        long = "mqtt-password-file",
+        env = "TEMPEST_MQTT_PASSWORD_FILE",
+        parse(from_os_str)
+    )]
+    password_file: Option<PathBuf>,
+
+    /// Prefix of all published MQTT topics [default: tempest]
+    #[structopt(long = "mqtt-topic-prefix", env = "TEMPEST_MQTT_TOPIC_PREFIX")]
+    topic_prefix: Option<String>,
+
+    /// Template for published topics, with placeholders {prefix}, {serial}, {topic}, {category}
+    /// and {field}, e.g. {prefix}/{serial}/{category}/{field}. The availability topic is always
+    /// <prefix>/status [default: {prefix}/{topic}]
+    #[structopt(long = "mqtt-topic-template", env = "TEMPEST_MQTT_TOPIC_TEMPLATE")]
+    topic_template: Option<String>,
+
+    /// Only publish readings that changed since last published [default: false]
+    #[structopt(long = "mqtt-changes-only", env = "TEMPEST_MQTT_CHANGES_ONLY")]
+    changes_only: Option<bool>,
+
+    /// Seconds after which unchanged readings are published again, when publishing changes only
+    /// [default: 900]
+    #[structopt(long = "mqtt-refresh-interval", env = "TEMPEST_MQTT_REFRESH_INTERVAL")]
+    refresh_interval: Option<u64>,
+
+    /// Forward debug and diagnostic messages as received to debug/<type> [default: true]
+    #[structopt(
+        long = "mqtt-forward-diagnostics",
+        env = "TEMPEST_MQTT_FORWARD_DIAGNOSTICS"
+    )]
+    forward_diagnostics: Option<bool>,
+
+    /// Messages queued for the MQTT client while the broker is slow or unreachable, beyond which
+    /// new ones are dropped [default: 1024]
+    #[structopt(long = "mqtt-queue-capacity", env = "TEMPEST_MQTT_QUEUE_CAPACITY")]
+    queue_capacity: Option<usize>,
+
+    /// Requests the MQTT client queues for its connection, which should hold all of one
+    /// report's publishes [default: 64]
+    #[structopt(long = "mqtt-request-capacity", env = "TEMPEST_MQTT_REQUEST_CAPACITY")]
+    request_capacity: Option<usize>,
+}
+
+impl MqttOptions {
+    fn or(self, other: Self) -> Self {
+        let (password, password_file) = or_secret(
+            (self.password, self.password_file),
+            (other.password, other.password_file),
+        );
+        Self {
+            port: self.port.or(other.port),
+            broker: self.broker.or(other.broker),
+            username: self.username.or(other.username),
+            password,
+            password_file,
+            topic_prefix: self.topic_prefix.or(other.topic_prefix),
+            topic_template: self.topic_template.or(other.topic_template),
+            changes_only: self.changes_only.or(other.changes_only),
+            refresh_interval: self.refresh_interval.or(other.refresh_interval),
+            forward_diagnostics: self.forward_diagnostics.or(other.forward_diagnostics),
+            queue_capacity: self.queue_capacity.or(other.queue_capacity),
+            request_capacity: self.request_capacity.or(other.request_capacity),
+        }
+    }
+}
+
+#[derive(StructOpt, Deserialize, Clone, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct RainCheckOptions {
+    /// WeatherFlow station ID to fetch Rain Check corrected daily rain for [default: not fetched]
+    #[structopt(long = "rain-check-station-id", env = "TEMPEST_RAIN_CHECK_STATION_ID")]
+    station_id: Option<u64>,
+
+    /// WeatherFlow personal access token for fetching corrected daily rain
+    #[structopt(
+        long = "rain-check-token",
+        env = "TEMPEST_RAIN_CHECK_TOKEN",
+        hide_env_values = true
+    )]
+    token: Option<String>,
+
+    /// File containing the WeatherFlow personal access token, e.g. a mounted container secret
+    #[structopt(
+        long = "rain-check-token-file",
+        env = "TEMPEST_RAIN_CHECK_TOKEN_FILE",
+        parse(from_os_str)
+    )]
+    token_file: Option<PathBuf>,
+
+    /// Hours between fetches of corrected daily rain [default: 24]
+    #[structopt(long = "rain-check-interval", env = "TEMPEST_RAIN_CHECK_INTERVAL")]
+    interval: Option<u64>,
+}
+
+impl RainCheckOptions {
+    fn or(self, other: Self) -> Self {
+        let (token, token_file) = or_secret(
+            (self.token, self.token_file),
+            (other.token, other.token_file),
+        );
+        Self {
+            station_id: self.station_id.or(other.station_id),
+            token,
+            token_file,
+            interval: self.interval.or(other.interval),
+        }
+    }
+}
+
+#[derive(StructOpt, Deserialize, Clone, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct ReferenceOptions {
+    /// US National Weather Service observation station to compare temperature and pressure with,
+    /// such as the METAR station KSEA [default: not compared]
+    #[structopt(long = "reference-station", env = "TEMPEST_REFERENCE_STATION")]
+    station: Option<String>,
+
+    /// Minutes between fetches of the reference station's latest observation [default: 60]
+    // Named apart from the Rain Check interval, which clap would otherwise take it for.
+    #[structopt(
+        name = "reference-interval",
+        long = "reference-interval",
+        env = "TEMPEST_REFERENCE_INTERVAL"
+    )]
+    interval: Option<u64>,
+}
+
+impl ReferenceOptions {
+    fn or(self, other: Self) -> Self {
+        Self {
+            station: self.station.or(other.station),
+            interval: self.interval.or(other.interval),
+        }
+    }
+}
+
+#[derive(StructOpt, Deserialize, Clone, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct StationOptions {
+    /// Station elevation in meters - used to compute barometric pressure.
+    #[structopt(long = "station-elevation", env = "TEMPEST_STATION_ELEVATION")]
+    elevation: Option<f64>,
+
+    /// Calibration offset added to measured air temperature (°C) [default: 0]
+    #[structopt(
+        long = "calibration-temp-offset",
+        env = "TEMPEST_CALIBRATION_TEMP_OFFSET"
+    )]
+    calibration_temp_offset: Option<f64>,
+
+    /// Calibration offset added to measured relative humidity (%) [default: 0]
+    #[structopt(long = "calibration-rh-offset", env = "TEMPEST_CALIBRATION_RH_OFFSET")]
+    calibration_rh_offset: Option<f64>,
+
+    /// Illuminance below which it reads as zero (lux), to hide night-time sensor noise and
+    /// moonlight from "is it dark" automations [default: 0]
+    #[structopt(long = "illuminance-floor", env = "TEMPEST_ILLUMINANCE_FLOOR")]
+    illuminance_floor: Option<f64>,
+
+    /// UV index below which it reads as zero [default: 0]
+    #[structopt(long = "uv-floor", env = "TEMPEST_UV_FLOOR")]
+    uv_floor: Option<f64>,
+
+    /// Formula for apparent temperature: steadman, heat-index-windchill, australian-at or
+    /// wbgt-estimate [default: steadman]
+    #[structopt(
+        long = "apparent-temperature-formula",
+        env = "TEMPEST_APPARENT_TEMPERATURE_FORMULA"
+    )]
+    apparent_temperature_formula: Option<ApparentTemperatureFormula>,
+
+    /// Station name, identifying the station in exported metadata
+    #[structopt(long = "station-name", env = "TEMPEST_STATION_NAME")]
+    name: Option<String>,
+
+    /// Station latitude in decimal degrees, positive north
+    #[structopt(long = "station-latitude", env = "TEMPEST_STATION_LATITUDE")]
+    latitude: Option<f64>,
+
+    /// Station longitude in decimal degrees, positive east
+    #[structopt(long = "station-longitude", env = "TEMPEST_STATION_LONGITUDE")]
+    longitude: Option<f64>,
+
+    /// Station timezone, as an IANA name such as Europe/London. Daily totals start over at its
+    /// midnight [default: the system timezone]
+    #[structopt(long = "station-timezone", env = "TEMPEST_STATION_TIMEZONE")]
+    timezone: Option<String>,
+
+    /// Serial number of the hub whose copy of each report to use when more than one hub relays
+    /// the station's reports [default: the hub hearing the station the strongest]
+    #[structopt(long = "primary-hub", env = "TEMPEST_PRIMARY_HUB")]
+    primary_hub: Option<String>,
+}
+
+impl StationOptions {
+    fn or(self, other: Self) -> Self {
+        Self {
+            elevation: self.elevation.or(other.elevation),
+            calibration_temp_offset: self
+                .calibration_temp_offset
+                .or(other.calibration_temp_offset),
+            calibration_rh_offset: self.calibration_rh_offset.or(other.calibration_rh_offset),
+            illuminance_floor: self.illuminance_floor.or(other.illuminance_floor),
+            uv_floor: self.uv_floor.or(other.uv_floor),
+            apparent_temperature_formula: self
+                .apparent_temperature_formula
+                .or(other.apparent_temperature_formula),
+            name: self.name.or(other.name),
+            latitude: self.latitude.or(other.latitude),
+            longitude: self.longitude.or(other.longitude),
+            timezone: self.timezone.or(other.timezone),
+            primary_hub: self.primary_hub.or(other.primary_hub),
+        }
+    }
+}
+
+#[derive(StructOpt, Deserialize, Clone, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct LightningOptions {
+    /// Minutes of strikes considered for the lightning alert level [default: 15]
+    #[structopt(long = "lightning-window", env = "TEMPEST_LIGHTNING_WINDOW")]
+    window: Option<u64>,
+
+    /// Strikes needed within the window to raise the lightning alert level [default: 2]
+    #[structopt(long = "lightning-min-strikes", env = "TEMPEST_LIGHTNING_MIN_STRIKES")]
+    min_strikes: Option<usize>,
+
+    /// Strike distance within which lightning is near (km) [default: 20]
+    #[structopt(
+        long = "lightning-near-distance",
+        env = "TEMPEST_LIGHTNING_NEAR_DISTANCE"
+    )]
+    near_distance: Option<f64>,
+
+    /// Strike distance within which lightning is overhead (km) [default: 5]
+    #[structopt(
+        long = "lightning-overhead-distance",
+        env = "TEMPEST_LIGHTNING_OVERHEAD_DISTANCE"
+    )]
+    overhead_distance: Option<f64>,
+}
+
+impl LightningOptions {
+    fn or(self, other: Self) -> Self {
+        Self {
+            window: self.window.or(other.window),
+            min_strikes: self.min_strikes.or(other.min_strikes),
+            near_distance: self.near_distance.or(other.near_distance),
+            overhead_distance: self.overhead_distance.or(other.overhead_distance),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct DeviceOptions {
+    calibration_temp_offset: Option<f64>,
+    calibration_rh_offset: Option<f64>,
+    illuminance_floor: Option<f64>,
+    uv_floor: Option<f64>,
+}
+
+#[derive(Deserialize, Clone, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct DomoticzOptions {
+    // Topic Domoticz subscribes to for device updates [default: domoticz/in]
+    topic: Option<String>,
+    // Virtual device indices, keyed by device type.
+    devices: BTreeMap<String, u64>,
+}
+
+#[derive(Deserialize, Clone, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct KnxOptions {
+    // KNX IP router or interface to send to [default: the KNXnet/IP routing multicast group]
+    gateway: Option<SocketAddr>,
+    // Individual address telegrams are sent from [default: 15.15.250]
+    source_address: Option<String>,
+    groups: Vec<KnxGroupOptions>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+struct KnxGroupOptions {
+    address: String,
+    value: Quantity,
+}
+
+impl KnxOptions {
+    fn resolve(self) -> anyhow::Result<KnxParams> {
+        if self.groups.is_empty() {
+            bail!("KNX output needs at least one group address");
+        }
+        Ok(KnxParams {
+            gateway: self
+                .gateway
+                .unwrap_or_else(|| knx::ROUTING_MULTICAST.parse().unwrap()),
+            source_address: self
+                .source_address
+                .as_deref()
+                .unwrap_or("15.15.250")
+                .parse()?,
+            groups: self
+                .groups
+                .into_iter()
+                .map(|group| Ok((group.address.parse()?, group.value)))
+                .collect::<anyhow::Result<_>>()?,
+        })
+    }
+}
+
+#[derive(Deserialize, Clone, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct ModbusOptions {
+    // TCP port to serve on [default: 502]
+    port: Option<u16>,
+    registers: Vec<ModbusRegisterOptions>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+struct ModbusRegisterOptions {
+    address: u16,
+    value: Quantity,
+    #[serde(default = "unit_scale")]
+    scale: f64,
+    #[serde(default)]
+    format: RegisterFormat,
+}
+
+fn unit_scale() -> f64 {
+    1.0
+}
+
+impl ModbusOptions {
+    fn resolve(self) -> anyhow::Result<ModbusParams> {
+        if self.registers.is_empty() {
+            bail!("Modbus server needs at least one register");
+        }
+        let params = ModbusParams {
+            port: self.port.unwrap_or(502),
+            registers: self
+                .registers
+                .into_iter()
+                .map(|register| Register {
+                    address: register.address,
+                    quantity: register.value,
+                    scale: register.scale,
+                    format: register.format,
+                })
+                .collect(),
+        };
+        params.validate()?;
+        Ok(params)
+    }
+}
+
+#[derive(Deserialize, Clone, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct PublishOptions {
+    // Topics and metrics to publish, matched with `*` wildcards, which also name the readings
+    // other outputs send. Alerts are published either way [default: all]
+    include: Vec<String>,
+    // Topics and metrics to suppress, even if included
+    exclude: Vec<String>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+struct SignalKOptions {
+    target: SocketAddr,
+    // Whether wind is reported as apparent or true [default: apparent]
+    #[serde(default)]
+    wind: WindConvention,
+}
+
+#[derive(Deserialize, Clone, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct NmeaOptions {
+    // [default: WI]
+    talker: Option<String>,
+    udp_target: Option<SocketAddr>,
+    tcp_port: Option<u16>,
+    // Whether wind is reported as apparent or true [default: apparent]
+    wind: WindConvention,
+    // Seconds between MWV sentences, and between MDA and XDR sentences [default: every report]
+    wind_interval: u64,
+    observation_interval: u64,
+}
+
+impl NmeaOptions {
+    fn resolve(self) -> anyhow::Result<NmeaParams> {
+        let params = NmeaParams {
+            talker: self.talker.unwrap_or_else(|| "WI".to_string()),
+            udp_target: self.udp_target,
+            tcp_port: self.tcp_port,
+            wind: self.wind,
+            wind_interval: Duration::from_secs(self.wind_interval),
+            observation_interval: Duration::from_secs(self.observation_interval),
+        };
+        params.validate()?;
+        Ok(params)
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+struct EcowittOptions {
+    url: String,
+    // Upload format, ecowitt or ambient [default: ecowitt]
+    #[serde(default)]
+    protocol: ecowitt::Protocol,
+    // Ecowitt PASSKEY or Ambient MAC the receiver knows the station by, given directly or read
+    // from a file [default: the Tempest's serial number]
+    station_key: Option<String>,
+    station_key_file: Option<PathBuf>,
+    // Seconds between uploads [default: every observation]
+    #[serde(default)]
+    interval: u64,
+}
+
+impl EcowittOptions {
+    fn resolve(self) -> anyhow::Result<EcowittParams> {
+        Ok(EcowittParams {
+            url: self
+                .url
+                .parse()
+                .with_context(|| format!("Invalid Ecowitt upload URL {}", self.url))?,
+            protocol: self.protocol,
+            station_key: secret(self.station_key, self.station_key_file)?,
+            interval: Duration::from_secs(self.interval),
+        })
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+struct GrafanaLiveOptions {
+    // Grafana's base URL, e.g. http://grafana:3000/
+    url: String,
+    // Service account token with permission to push, given directly or read from a file
+    token: Option<String>,
+    token_file: Option<PathBuf>,
+    // Stream ID to push to [default: tempest]
+    stream: Option<String>,
+}
+
+impl GrafanaLiveOptions {
+    fn resolve(self) -> anyhow::Result<GrafanaLiveParams> {
+        let url: reqwest::Url = self
+            .url
+            .parse()
+            .with_context(|| format!("Invalid Grafana URL {}", self.url))?;
+        let stream = self.stream.unwrap_or_else(|| "tempest".to_string());
+        if stream.is_empty()
+            || !stream
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            bail!(
+                "Grafana Live stream {:?} must be letters, digits, - and _",
+                stream
+            );
+        }
+        // Joined relative to the base, keeping any path Grafana is served under.
+        let base = if url.path().ends_with('/') {
+            url
+        } else {
+            format!("{}/", url).parse()?
+        };
+        Ok(GrafanaLiveParams {
+            push_url: base.join(&format!("api/live/push/{}", stream))?,
+            token: secret(self.token, self.token_file)?
+                .ok_or_else(|| anyhow!("Grafana Live needs a token"))?,
+        })
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+struct AlertOptions {
+    name: String,
+    value: Quantity,
+    above: Option<f64>,
+    below: Option<f64>,
+    #[serde(default)]
+    hysteresis: f64,
+    #[serde(default)]
+    cooldown: u64,
+    webhook: Option<String>,
+}
+
+impl AlertOptions {
+    fn resolve(self) -> anyhow::Result<AlertRule> {
+        let threshold = match (self.above, self.below) {
+            (Some(above), None) => Threshold::Above(above),
+            (None, Some(below)) => Threshold::Below(below),
+            _ => bail!("Alert {} must set exactly one of above or below", self.name),
+        };
+        if self.name == "lightning" || self.name == "storm" {
+            bail!("Alert name {} is reserved for a built-in alert", self.name);
+        }
+        if self.hysteresis < 0.0 {
+            bail!("Alert {} hysteresis must not be negative", self.name);
+        }
+        Ok(AlertRule {
+            name: self.name,
+            quantity: self.value,
+            threshold,
+            hysteresis: self.hysteresis,
+            cooldown: Duration::from_secs(self.cooldown),
+            webhook: self.webhook,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Config {
+    pub log_level: log::LevelFilter,
+    pub otlp_endpoint: Option<String>,
+    pub enable_prometheus: bool,
+    pub metrics_port: u16,
+    pub listen_fd: Option<i32>,
+    /// Path segments routes are served under.
+    pub route_prefix: Vec<String>,
+    pub http_max_connections: usize,
+    pub http_request_timeout: Duration,
+    pub shutdown_timeout: Duration,
+    pub startup_mode: StartupMode,
+    pub first_data_timeout: Option<Duration>,
+    pub checkpoint_interval: Duration,
+    pub debug_api: Option<DebugApiParams>,
+    /// Stations in name order, or a single unnamed one if none are configured.
+    pub stations: Vec<StationConfig>,
+}
+
+// Configuration of one station's pipeline, from the hub's broadcasts to the sinks.
+#[derive(Debug)]
+pub struct StationConfig {
+    pub name: Option<String>,
+    pub api_port: u16,
+    pub enable_mqtt: bool,
+    pub enable_alerts: bool,
+    pub state_file: Option<PathBuf>,
+    pub exporter_params: ExporterParams,
+    pub mqtt_params: MqttParams,
+    pub station_params: StationParams,
+    pub alert_rules: Vec<AlertRule>,
+    pub lightning_params: LightningParams,
+    pub rain_check_params: Option<RainCheckParams>,
+    pub reference_params: Option<ReferenceParams>,
+    pub knx_params: Option<KnxParams>,
+    pub modbus_params: Option<ModbusParams>,
+    pub signalk_params: Option<SignalKParams>,
+    pub nmea_params: Option<NmeaParams>,
+    pub ecowitt_params: Option<EcowittParams>,
+    pub grafana_live_params: Option<GrafanaLiveParams>,
+    pub hub_preference: HubPreference,
+    pub history_retention: Duration,
+    pub decode_mode: DecodeMode,
+    pub queues: BTreeMap<String, QueueOptions>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StartupMode {
+    Wait,
+    Serve,
+}
+
+impl FromStr for StartupMode {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wait" => Ok(StartupMode::Wait),
+            "serve" => Ok(StartupMode::Serve),
+            other => bail!("Unrecognized startup mode {}", other),
+        }
+    }
+}
+
+// Access to `/debug/state`, which is only served when a token is configured.
+#[derive(Clone, PartialEq)]
+pub struct DebugApiParams {
+    pub token: String,
+}
+
+// Keeps the token out of logs and `check-config` output.
+impl fmt::Debug for DebugApiParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DebugApiParams")
+            .field("token", &"<redacted>")
+            .finish()
+    }
+}
+
+// Keeps the password out of logs and `check-config` output.
+impl fmt::Debug for MqttParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MqttParams")
+            .field("mqtt_port", &self.mqtt_port)
+            .field("mqtt_broker", &self.mqtt_broker)
+            .field("mqtt_username", &self.mqtt_username)
+            .field(
+                "mqtt_password",
+                &self.mqtt_password.as_ref().map(|_| "<redacted>"),
+            )
+            .field("mqtt_topic_prefix", &self.mqtt_topic_prefix)
+            .field("mqtt_topic_template", &self.mqtt_topic_template.to_string())
+            .field("mqtt_client_id", &self.mqtt_client_id)
+            .field("mqtt_changes_only", &self.mqtt_changes_only)
+            .field("mqtt_forward_diagnostics", &self.mqtt_forward_diagnostics)
+            .field("mqtt_queue_capacity", &self.mqtt_queue_capacity)
+            .field("mqtt_request_capacity", &self.mqtt_request_capacity)
+            .field("domoticz", &self.domoticz)
+            .finish()
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub struct MqttParams {
+    pub mqtt_port: u16,
+    pub mqtt_broker: Option<String>,
+    pub mqtt_username: Option<String>,
+    pub mqtt_password: Option<String>,
+    pub mqtt_topic_prefix: String,
+    pub mqtt_topic_template: TopicTemplate,
+    pub mqtt_client_id: String,
+    // Interval unchanged readings are refreshed at, if only changes are published.
+    pub mqtt_changes_only: Option<Duration>,
+    pub mqtt_forward_diagnostics: bool,
+    pub mqtt_queue_capacity: usize,
+    pub mqtt_request_capacity: usize,
+    pub domoticz: Option<DomoticzParams>,
+}
+
+impl Config {
+    pub fn load(opt: &Opt) -> anyhow::Result<Self> {
+        let options = match &opt.config {
+            Some(path) => {
+                let text = std::fs::read_to_string(path)
+                    .with_context(|| format!("Reading config file {}", path.display()))?;
+                let file_options: Options = toml::from_str(&text)
+                    .with_context(|| format!("Parsing config file {}", path.display()))?;
+                opt.options.clone().or(file_options)
+            }
+            None => opt.options.clone(),
+        };
+        let mut config = Self::resolve(options)?;
+        // A bare `--dry-run` is a flag; `--dry-run=false` and the environment variable say.
+        if matches!(opt.dry_run, Some(None | Some(true))) {
+            for station in &mut config.stations {
+                station.mqtt_params.mqtt_broker = None;
+            }
+        }
+        Ok(config)
+    }
+
+    // The station of a single-station configuration, or the first.
+    pub fn station(&self) -> &StationConfig {
+        &self.stations[0]
+    }
+
+    fn resolve(mut options: Options) -> anyhow::Result<Self> {
+        let stations = if options.stations.is_empty() {
+            vec![StationConfig::resolve(None, options.clone())?]
+        } else {
+            let stations = std::mem::take(&mut options.stations);
+            let mut ports = HashMap::new();
+            stations
+                .into_iter()
+                .map(|(name, station)| {
+                    if let Some(option) = station.process_wide().first() {
+                        bail!(
+                            "Station {} sets {}, which applies to all stations",
+                            name,
+                            option
+                        );
+                    }
+                    let own_state_file = station.state_file.is_some();
+                    let own_topic_prefix = station.mqtt.topic_prefix.is_some();
+                    let mut config =
+                        StationConfig::resolve(Some(name.clone()), station.or(options.clone()))
+                            .with_context(|| format!("Station {}", name))?;
+                    // Stations sharing the top-level settings get their own state file and topics.
+                    if !own_state_file {
+                        config.state_file =
+                            config.state_file.map(|path| station_file(&path, &name));
+                    }
+                    if !own_topic_prefix {
+                        config.mqtt_params.mqtt_topic_prefix =
+                            format!("{}/{}", config.mqtt_params.mqtt_topic_prefix, name);
+                    }
+                    if let Some(other) = ports.insert(config.api_port, name.clone()) {
+                        bail!(
+                            "Stations {} and {} both receive on UDP port {}",
+                            other,
+                            name,
+                            config.api_port
+                        );
+                    }
+                    Ok(config)
+                })
+                .collect::<anyhow::Result<_>>()?
+        };
+        let debug_api = secret(options.debug_token, options.debug_token_file)?
+            .map(|token| DebugApiParams { token });
+        if matches!(&debug_api, Some(params) if params.token.is_empty()) {
+            bail!("Debug token must not be empty");
+        }
+        let enable_prometheus = options.enable_prometheus.unwrap_or(true);
+        if debug_api.is_some() && !enable_prometheus {
+            bail!("The debug API is served over HTTP, which is disabled");
+        }
+        let http_max_connections = options.http_max_connections.unwrap_or(16);
+        if http_max_connections == 0 {
+            bail!("HTTP connection limit must be at least 1");
+        }
+        if options.http_request_timeout == Some(0) {
+            bail!("HTTP request timeout must be at least 1 second");
+        }
+        if options.checkpoint_interval == Some(0) {
+            bail!("Checkpoint interval must be at least 1 second");
+        }
+        if options.otlp_endpoint.is_some() && !cfg!(feature = "otlp") {
+            bail!("OTLP trace export requires building with the otlp feature");
+        }
+        Ok(Self {
+            log_level: options.log_level.unwrap_or(log::LevelFilter::Info),
+            otlp_endpoint: options.otlp_endpoint,
+            enable_prometheus,
+            metrics_port: options.metrics_port.unwrap_or(8080),
+            listen_fd: options.listen_fd,
+            route_prefix: options
+                .route_prefix
+                .unwrap_or_default()
+                .split('/')
+                .filter(|segment| !segment.is_empty())
+                .map(str::to_string)
+                .collect(),
+            http_max_connections,
+            http_request_timeout: Duration::from_secs(options.http_request_timeout.unwrap_or(10)),
+            shutdown_timeout: Duration::from_secs(options.shutdown_timeout.unwrap_or(5)),
+            startup_mode: options.startup_mode.unwrap_or(StartupMode::Wait),
+            first_data_timeout: options.first_data_timeout.map(Duration::from_secs),
+            checkpoint_interval: Duration::from_secs(options.checkpoint_interval.unwrap_or(60)),
+            debug_api,
+            stations,
+        })
+    }
+}
+
+// A station's own variant of a file shared by all stations, e.g. `state-cabin.json` for
+// `state.json`.
+fn station_file(path: &Path, station: &str) -> PathBuf {
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push("-");
+    name.push(station);
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    path.with_file_name(name)
+}
+
+impl StationConfig {
+    fn resolve(name: Option<String>, options: Options) -> anyhow::Result<Self> {
+        let calibration = Calibration {
+            temp_offset: options.station.calibration_temp_offset.unwrap_or(0.0),
+            rh_offset: options.station.calibration_rh_offset.unwrap_or(0.0),
+            illuminance_floor: options.station.illuminance_floor.unwrap_or(0.0),
+            uv_floor: options.station.uv_floor.unwrap_or(0.0),
+        };
+        let devices = options
+            .devices
+            .into_iter()
+            .map(|(serial_number, device)| {
+                let device_calibration = Calibration {
+                    temp_offset: device
+                        .calibration_temp_offset
+                        .unwrap_or(calibration.temp_offset),
+                    rh_offset: device
+                        .calibration_rh_offset
+                        .unwrap_or(calibration.rh_offset),
+                    illuminance_floor: device
+                        .illuminance_floor
+                        .unwrap_or(calibration.illuminance_floor),
+                    uv_floor: device.uv_floor.unwrap_or(calibration.uv_floor),
+                };
+                (serial_number, device_calibration)
+            })
+            .collect();
+        if let Some(latitude) = options.station.latitude {
+            if !(-90.0..=90.0).contains(&latitude) {
+                bail!("Station latitude must be between -90 and 90 degrees");
+            }
+        }
+        if let Some(longitude) = options.station.longitude {
+            if !(-180.0..=180.0).contains(&longitude) {
+                bail!("Station longitude must be between -180 and 180 degrees");
+            }
+        }
+        let metadata = StationMetadata {
+            name: options.station.name,
+            latitude: options.station.latitude,
+            longitude: options.station.longitude,
+            timezone: options
+                .station
+                .timezone
+                .map(|tz| {
+                    tz.parse()
+                        .map_err(|_| anyhow!("Unrecognized station timezone {}", tz))
+                })
+                .transpose()?,
+        };
+        let derived = options
+            .derived
+            .iter()
+            .map(|(name, expression)| DerivedMetric::new(name, expression))
+            .collect::<anyhow::Result<_>>()?;
+        let smoothing = options
+            .smoothing
+            .iter()
+            .map(|(name, secs)| Smoothing::new(name, Duration::from_secs(*secs)))
+            .collect::<anyhow::Result<_>>()?;
+        let metric_names = options
+            .metric_names
+            .into_iter()
+            .map(|(name, mode)| match exporter::renamed_metric(&name) {
+                Some(current) => Ok((current.to_string(), mode)),
+                None => Err(anyhow!("Metric {} has not been renamed", name)),
+            })
+            .collect::<anyhow::Result<_>>()?;
+        let rain_check_params = match options.rain_check.station_id {
+            Some(station_id) if options.rain_check.interval == Some(0) => {
+                bail!(
+                    "Rain Check interval for station {} must be at least an hour",
+                    station_id
+                )
+            }
+            Some(station_id) => Some(RainCheckParams {
+                station_id,
+                token: secret(options.rain_check.token, options.rain_check.token_file)?
+                    .ok_or_else(|| anyhow!("Rain Check needs a WeatherFlow access token"))?,
+                interval: Duration::from_secs(options.rain_check.interval.unwrap_or(24) * 3600),
+            }),
+            None => None,
+        };
+        let reference_params = match options.reference.station {
+            Some(station) if options.reference.interval == Some(0) => {
+                bail!(
+                    "Reference interval for {} must be at least a minute",
+                    station
+                )
+            }
+            // The station ID goes into the path of the NWS API URL as it is.
+            Some(station)
+                if station.is_empty() || !station.chars().all(|c| c.is_ascii_alphanumeric()) =>
+            {
+                bail!(
+                    "Reference station {:?} is not a station ID such as KSEA",
+                    station
+                )
+            }
+            Some(station) => Some(ReferenceParams {
+                station,
+                interval: Duration::from_secs(options.reference.interval.unwrap_or(60) * 60),
+            }),
+            None => None,
+        };
+        let alert_rules = options
+            .alerts
+            .into_iter()
+            .map(AlertOptions::resolve)
+            .collect::<anyhow::Result<_>>()?;
+        let lightning_params = LightningParams {
+            window: Duration::from_secs(options.lightning.window.unwrap_or(15) * 60),
+            min_strikes: options.lightning.min_strikes.unwrap_or(2),
+            near_distance: options.lightning.near_distance.unwrap_or(20.0),
+            overhead_distance: options.lightning.overhead_distance.unwrap_or(5.0),
+        };
+        if lightning_params.overhead_distance > lightning_params.near_distance {
+            bail!("Lightning overhead distance must not exceed near distance");
+        }
+        let history_retention = match options.recent_hours {
+            None => history::DEFAULT_RETENTION,
+            Some(hours)
+                if hours > 0 && Duration::from_secs(hours * 3600) <= history::MAX_RETENTION =>
+            {
+                Duration::from_secs(hours * 3600)
+            }
+            Some(_) => bail!("Recent observations must be kept for between 1 and 48 hours"),
+        };
+        for (name, queue) in &options.queues {
+            if !SINK_NAMES.contains(&name.as_str()) {
+                bail!("Unknown sink {} in queue configuration", name);
+            }
+            if queue.capacity == Some(0) {
+                bail!("Queue capacity of sink {} must be at least 1", name);
+            }
+        }
+        let mqtt_queue_capacity = options.mqtt.queue_capacity.unwrap_or(1024);
+        let mqtt_request_capacity = options.mqtt.request_capacity.unwrap_or(64);
+        if mqtt_queue_capacity == 0 || mqtt_request_capacity == 0 {
+            bail!("MQTT queue capacities must be at least 1");
+        }
+        // Brokers disconnect a client when another connects with the same ID.
+        let mqtt_client_id = match &name {
+            Some(name) => format!("tempest-exporter-{}", name),
+            None => "tempest-exporter".to_string(),
+        };
+        let enable_mqtt = options.enable_mqtt.unwrap_or(true);
+        let domoticz = match options.domoticz {
+            Some(domoticz) if domoticz.devices.is_empty() => {
+                bail!("Domoticz output needs at least one device index")
+            }
+            Some(domoticz) => Some(DomoticzParams {
+                topic: domoticz.topic.unwrap_or_else(|| "domoticz/in".to_string()),
+                devices: domoticz
+                    .devices
+                    .iter()
+                    .map(|(device, idx)| Ok((device.parse()?, *idx)))
+                    .collect::<anyhow::Result<_>>()?,
+            }),
+            None => None,
+        };
+        Ok(Self {
+            name,
+            api_port: options.api_port.unwrap_or(receiver::API_PORT),
+            enable_mqtt,
+            enable_alerts: options.enable_alerts.unwrap_or(true),
+            state_file: options.state_file,
+            exporter_params: ExporterParams {
+                instant_wind_ttl: Duration::from_secs(options.instant_wind_ttl.unwrap_or(15)),
+                instant_wind_window: Duration::from_secs(options.instant_wind_window.unwrap_or(60)),
+                observation_ttl: Duration::from_secs(options.observation_ttl.unwrap_or(3 * 60)),
+                storm: StormParams {
+                    window: Duration::from_secs(options.storm_window.unwrap_or(180) * 60),
+                    pressure_drop: options.storm_pressure_drop.unwrap_or(3.0),
+                },
+                rain_event_dry_period: Duration::from_secs(
+                    options.rain_event_dry_minutes.unwrap_or(30) * 60,
+                ),
+                rain_season_start: options
+                    .rain_season_start
+                    .map(|start| start.parse())
+                    .transpose()?
+                    .unwrap_or_default(),
+                metric_names,
+                smoothing,
+                publish_filter: options
+                    .publish
+                    .map(|publish| PublishFilter::new(publish.include, publish.exclude))
+                    .unwrap_or_default(),
+                precision: options.precision.unwrap_or_default(),
+            },
+            mqtt_params: MqttParams {
+                mqtt_port: options.mqtt.port.unwrap_or(1883),
+                // Alerts are still published through the dummy sink, which only logs them.
+                mqtt_broker: options.mqtt.broker.filter(|_| enable_mqtt),
+                mqtt_username: options.mqtt.username,
+                mqtt_password: secret(options.mqtt.password, options.mqtt.password_file)?,
+                mqtt_topic_prefix: options
+                    .mqtt
+                    .topic_prefix
+                    .unwrap_or_else(|| "tempest".to_string()),
+                mqtt_topic_template: options
+                    .mqtt
+                    .topic_template
+                    .map(|template| template.parse())
+                    .transpose()?
+                    .unwrap_or_default(),
+                mqtt_client_id,
+                mqtt_changes_only: options
+                    .mqtt
+                    .changes_only
+                    .unwrap_or(false)
+                    .then(|| Duration::from_secs(options.mqtt.refresh_interval.unwrap_or(900))),
+                mqtt_forward_diagnostics: options.mqtt.forward_diagnostics.unwrap_or(true),
+                mqtt_queue_capacity,
+                mqtt_request_capacity,
+                domoticz,
+            },
+            station_params: StationParams {
+                elevation: options.station.elevation.ok_or_else(|| {
+                    anyhow!("Station elevation must be set with --station-elevation or in config")
+                })?,
+                metadata,
+                apparent_temperature_formula: options
+                    .station
+                    .apparent_temperature_formula
+                    .unwrap_or_default(),
+                calibration,
+                devices,
+                derived,
+            },
+            alert_rules,
+            lightning_params,
+            rain_check_params,
+            reference_params,
+            knx_params: options.knx.map(KnxOptions::resolve).transpose()?,
+            modbus_params: options.modbus.map(ModbusOptions::resolve).transpose()?,
+            signalk_params: options.signalk.map(|signalk| SignalKParams {
+                target: signalk.target,
+                wind: signalk.wind,
+            }),
+            nmea_params: options.nmea.map(NmeaOptions::resolve).transpose()?,
+            ecowitt_params: options.ecowitt.map(EcowittOptions::resolve).transpose()?,
+            grafana_live_params: options
+                .grafana_live
+                .map(GrafanaLiveOptions::resolve)
+                .transpose()?,
+            hub_preference: match options.station.primary_hub {
+                Some(hub) => HubPreference::Primary(hub),
+                None => HubPreference::StrongestSignal,
+            },
+            history_retention,
+            decode_mode: options.decode_mode.unwrap_or_default(),
+            queues: options.queues,
+        })
+    }
+}
+
+// A secret given directly or as a file in one layer overrides both in the layers below, so that
+// a password file on the command line beats a password in the configuration file.
+type SecretOptions = (Option<String>, Option<PathBuf>);
+
+fn or_secret(secret: SecretOptions, other: SecretOptions) -> SecretOptions {
+    match secret {
+        (None, None) => other,
+        secret => secret,
+    }
+}
+
+// Secrets may be given directly or read from a file, in which case surrounding whitespace (such as
+// a trailing newline) is ignored. A directly given value takes precedence.
+fn secret(value: Option<String>, file: Option<PathBuf>) -> anyhow::Result<Option<String>> {
+    match (value, file) {
+        (Some(value), _) => Ok(Some(value)),
+        (None, Some(path)) => {
+            let text = std::fs::read_to_string(&path)
+                .with_context(|| format!("Reading secret file {}", path.display()))?;
+            Ok(Some(text.trim().to_string()))
+        }
+        (None, None) => Ok(None),
+    }
+}
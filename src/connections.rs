@@ -0,0 +1,153 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{AcquireError, OwnedSemaphorePermit, Semaphore};
+use tokio::time::{Instant, Sleep};
+use tokio_stream::Stream;
+use tracing::warn;
+
+// How long to wait after failing to accept a connection before trying again. Errors such as
+// running out of file descriptors tend to persist for a moment, and retrying at once would spin.
+// Other servers' accept loops wait as long.
+pub const ACCEPT_BACKOFF: Duration = Duration::from_secs(1);
+
+type Acquiring = Pin<Box<dyn Future<Output = Result<OwnedSemaphorePermit, AcquireError>> + Send>>;
+
+// Connections accepted from the HTTP listener, at most `max_connections` open at once. Further
+// clients wait in the listen backlog until one closes, rather than each costing memory and a task.
+// Failing to accept a connection is logged and retried rather than ending the stream, since the
+// server stops at the first error it is given.
+pub struct Connections {
+    listener: TcpListener,
+    semaphore: Arc<Semaphore>,
+    acquiring: Option<Acquiring>,
+    permit: Option<OwnedSemaphorePermit>,
+    backoff: Option<Pin<Box<Sleep>>>,
+    timeout: Duration,
+}
+
+impl Connections {
+    pub fn new(listener: TcpListener, max_connections: usize, timeout: Duration) -> Self {
+        Connections {
+            listener,
+            semaphore: Arc::new(Semaphore::new(max_connections)),
+            acquiring: None,
+            permit: None,
+            backoff: None,
+            timeout,
+        }
+    }
+}
+
+impl Stream for Connections {
+    type Item = io::Result<Connection>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        if let Some(backoff) = &mut this.backoff {
+            if backoff.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            this.backoff = None;
+        }
+        if this.permit.is_none() {
+            let semaphore = &this.semaphore;
+            let acquiring = this
+                .acquiring
+                .get_or_insert_with(|| Box::pin(semaphore.clone().acquire_owned()));
+            match acquiring.as_mut().poll(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(permit) => {
+                    this.acquiring = None;
+                    this.permit = Some(permit.expect("connection semaphore is never closed"));
+                }
+            }
+        }
+        loop {
+            match this.listener.poll_accept(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(e)) => {
+                    warn!("Failed to accept HTTP connection: {}", e);
+                    let mut backoff = Box::pin(tokio::time::sleep(ACCEPT_BACKOFF));
+                    if backoff.as_mut().poll(cx).is_pending() {
+                        this.backoff = Some(backoff);
+                        return Poll::Pending;
+                    }
+                }
+                Poll::Ready(Ok((stream, _))) => {
+                    return Poll::Ready(Some(Ok(Connection {
+                        stream,
+                        _permit: this.permit.take(),
+                        deadline: Box::pin(tokio::time::sleep(this.timeout)),
+                        timeout: this.timeout,
+                    })))
+                }
+            }
+        }
+    }
+}
+
+// An HTTP connection whose client must get a response out of it within the timeout of connecting
+// or of the last response data being written, so that one trickling a request in or reading a
+// response slowly, or a keep-alive connection left idle, is dropped. Holds its place among the
+// open connections until closed.
+pub struct Connection {
+    stream: TcpStream,
+    _permit: Option<OwnedSemaphorePermit>,
+    deadline: Pin<Box<Sleep>>,
+    timeout: Duration,
+}
+
+impl Connection {
+    fn check_deadline(&mut self, cx: &mut Context<'_>) -> io::Result<()> {
+        match self.deadline.as_mut().poll(cx) {
+            Poll::Ready(()) => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "HTTP client took too long",
+            )),
+            Poll::Pending => Ok(()),
+        }
+    }
+}
+
+impl AsyncRead for Connection {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.check_deadline(cx)?;
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Connection {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.check_deadline(cx)?;
+        let written = Pin::new(&mut self.stream).poll_write(cx, buf);
+        if matches!(written, Poll::Ready(Ok(n)) if n > 0) {
+            let renewed = Instant::now() + self.timeout;
+            self.deadline.as_mut().reset(renewed);
+        }
+        written
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.check_deadline(cx)?;
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
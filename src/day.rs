@@ -0,0 +1,84 @@
+//! Calendar days in the station's local time, which daily totals and extremes start over at.
+//!
+//! A day runs from one local midnight to the next in the station's timezone, or the system's if
+//! the station's isn't set, so it is 23 or 25 hours long across a daylight saving change. Where a
+//! change skips midnight altogether, the day starts at the first local time there is.
+
+use chrono::{DateTime, Local, LocalResult, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+// Steps to look ahead from a skipped midnight for the first local time there is; no timezone
+// skips more than a few hours.
+const GAP_STEP_MINUTES: i64 = 15;
+const GAP_STEPS: i64 = 4 * 24;
+
+/// The timezone whose midnight starts a new day.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DayZone {
+    /// The system's local time.
+    #[default]
+    System,
+    Named(Tz),
+}
+
+impl From<Option<Tz>> for DayZone {
+    fn from(timezone: Option<Tz>) -> Self {
+        timezone.map_or(DayZone::System, DayZone::Named)
+    }
+}
+
+impl DayZone {
+    /// The local date at an instant.
+    pub fn date(&self, at: DateTime<Utc>) -> NaiveDate {
+        match self {
+            DayZone::System => at.with_timezone(&Local).naive_local().date(),
+            DayZone::Named(tz) => at.with_timezone(tz).naive_local().date(),
+        }
+    }
+
+    /// The instant a local date starts.
+    pub fn start_of(&self, date: NaiveDate) -> DateTime<Utc> {
+        let midnight = date.and_hms_opt(0, 0, 0).unwrap();
+        (0..=GAP_STEPS)
+            .map(|step| midnight + chrono::Duration::minutes(step * GAP_STEP_MINUTES))
+            .find_map(|local| self.earliest(&local))
+            .expect("local time resumes within a day")
+    }
+
+    // The first instant at a local time, if the local time occurs at all.
+    fn earliest(&self, local: &NaiveDateTime) -> Option<DateTime<Utc>> {
+        fn utc<Z: TimeZone>(result: LocalResult<DateTime<Z>>) -> Option<DateTime<Utc>> {
+            result.earliest().map(|at| at.with_timezone(&Utc))
+        }
+        match self {
+            DayZone::System => utc(Local.from_local_datetime(local)),
+            DayZone::Named(tz) => utc(tz.from_local_datetime(local)),
+        }
+    }
+}
+
+/// A value accumulated over a local day, starting over from the default when a reading falls on
+/// another day than the one before.
+#[derive(Clone, Debug, Default)]
+pub struct Daily<T> {
+    day: Option<NaiveDate>,
+    value: T,
+}
+
+impl<T: Default> Daily<T> {
+    /// Applies a reading taken at `at` and returns the value for its day.
+    pub fn update(&mut self, zone: DayZone, at: DateTime<Utc>, f: impl FnOnce(&mut T)) -> &T {
+        let day = zone.date(at);
+        if self.day != Some(day) {
+            self.day = Some(day);
+            self.value = T::default();
+        }
+        f(&mut self.value);
+        &self.value
+    }
+
+    /// The day the value is for, if there has been a reading.
+    pub fn day(&self) -> Option<NaiveDate> {
+        self.day
+    }
+}
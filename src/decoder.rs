@@ -1,16 +1,26 @@
+//! Decoding of raw API messages into typed reports with physical units and derived values.
+
 use std::convert::TryFrom;
+use std::fmt;
 use std::str::FromStr;
 
-use anyhow::{anyhow, bail};
-use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use anyhow::bail;
+use chrono::{DateTime, Duration, TimeZone, Utc};
 use futures_core::stream::Stream;
-use log::warn;
-use serde::Serialize;
+use serde::ser::{SerializeStruct, Serializer};
+use serde::{Deserialize, Serialize};
 use tokio_stream::StreamExt;
+use tracing::{trace_span, warn};
 
 use crate::reader::{self, RawTempestMsg};
+use crate::solar;
+use crate::units::{Celsius, HectoPascal, MetersPerSecond, Millimeters};
 
-#[derive(Debug)]
+/// A decoded message from the Tempest local UDP API.
+///
+/// Serializes as an object tagged with `"type"`, whose value matches [`TempestMsg::kind`].
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum TempestMsg {
     PrecipEvent(PrecipEvent),
     StrikeEvent(StrikeEvent),
@@ -18,17 +28,107 @@ pub enum TempestMsg {
     Observation(Observation),
     DeviceStatus(DeviceStatus),
     HubStatus(HubStatus),
+    Debug(DebugMessage),
+}
+
+impl TempestMsg {
+    /// Short snake_case name of the message type, suitable for metric labels.
+    pub fn kind(&self) -> &'static str {
+        use TempestMsg as TM;
+        match self {
+            TM::PrecipEvent(_) => "precip_event",
+            TM::StrikeEvent(_) => "strike_event",
+            TM::RapidWind(_) => "rapid_wind",
+            TM::Observation(_) => "observation",
+            TM::DeviceStatus(_) => "device_status",
+            TM::HubStatus(_) => "hub_status",
+            TM::Debug(_) => "debug",
+        }
+    }
+
+    /// Time the report was made, according to the device.
+    pub fn timestamp(&self) -> DateTime<Utc> {
+        use TempestMsg as TM;
+        match self {
+            TM::PrecipEvent(pe) => pe.timestamp,
+            TM::StrikeEvent(se) => se.timestamp,
+            TM::RapidWind(rw) => rw.timestamp,
+            TM::Observation(obs) => obs.timestamp,
+            TM::DeviceStatus(ds) => ds.timestamp,
+            TM::HubStatus(hs) => hs.timestamp,
+            TM::Debug(dm) => dm.timestamp,
+        }
+    }
+}
+
+/// Why a raw message could not be decoded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// A value the report can't be decoded without is missing, such as an observation's timestamp.
+    MissingField(&'static str),
+    /// A value isn't one of those documented for its field, such as a precipitation type added by
+    /// newer firmware.
+    UnknownValue { field: &'static str, value: String },
+    /// A Unix timestamp (s) is beyond the dates that can be represented.
+    InvalidTimestamp(i64),
+    /// A status report's Unix timestamp (s) is from before any device could have sent it, as
+    /// when it is really the uptime.
+    ImplausibleTimestamp(i64),
+    /// A duration (s) is beyond those that can be represented.
+    InvalidDuration(i64),
+}
+
+impl DecodeError {
+    /// Short snake_case name of the kind of error, suitable for metric labels.
+    pub fn category(&self) -> &'static str {
+        match self {
+            DecodeError::MissingField(_) => "missing_field",
+            DecodeError::UnknownValue { .. } => "unknown_value",
+            DecodeError::InvalidTimestamp(_) => "invalid_timestamp",
+            DecodeError::ImplausibleTimestamp(_) => "implausible_timestamp",
+            DecodeError::InvalidDuration(_) => "invalid_duration",
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::MissingField(field) => write!(f, "Missing {}", field),
+            DecodeError::UnknownValue { field, value } => {
+                write!(f, "Unrecognized {} {}", field, value)
+            }
+            DecodeError::InvalidTimestamp(unix_sec) => {
+                write!(f, "Timestamp {} out of range", unix_sec)
+            }
+            DecodeError::ImplausibleTimestamp(unix_sec) => {
+                write!(f, "Timestamp {} predates the device", unix_sec)
+            }
+            DecodeError::InvalidDuration(secs) => write!(f, "Duration of {} s out of range", secs),
+        }
+    }
 }
 
+impl std::error::Error for DecodeError {}
+
 impl TryFrom<RawTempestMsg> for TempestMsg {
-    type Error = (RawTempestMsg, anyhow::Error);
+    type Error = (RawTempestMsg, DecodeError);
     fn try_from(msg: RawTempestMsg) -> Result<TempestMsg, Self::Error> {
         use RawTempestMsg as RM;
         use TempestMsg as TM;
         match msg {
-            RM::PrecipEvent(rpe) => Ok(TM::PrecipEvent(rpe.into())),
-            RM::StrikeEvent(rse) => Ok(TM::StrikeEvent(rse.into())),
-            RM::RapidWind(rrw) => Ok(TM::RapidWind(rrw.into())),
+            RM::PrecipEvent(rpe) => rpe
+                .try_into()
+                .map_err(|(rpe, e)| (RM::PrecipEvent(rpe), e))
+                .map(TM::PrecipEvent),
+            RM::StrikeEvent(rse) => rse
+                .try_into()
+                .map_err(|(rse, e)| (RM::StrikeEvent(rse), e))
+                .map(TM::StrikeEvent),
+            RM::RapidWind(rrw) => rrw
+                .try_into()
+                .map_err(|(rrw, e)| (RM::RapidWind(rrw), e))
+                .map(TM::RapidWind),
             RM::Observation(ro) => ro
                 .try_into()
                 .map_err(|(ro, e)| (RM::Observation(ro), e))
@@ -37,47 +137,74 @@ impl TryFrom<RawTempestMsg> for TempestMsg {
                 .try_into()
                 .map_err(|(rhs, e)| (RM::HubStatus(rhs), e))
                 .map(TM::HubStatus),
-            RM::DeviceStatus(rds) => Ok(TM::DeviceStatus(rds.into())),
+            RM::DeviceStatus(rds) => rds
+                .try_into()
+                .map_err(|(rds, e)| (RM::DeviceStatus(rds), e))
+                .map(TM::DeviceStatus),
+            RM::Debug(rdm) => Ok(TM::Debug(rdm.into())),
         }
     }
 }
 
-#[derive(Debug)]
+/// Onset of precipitation.
+#[derive(Debug, Serialize)]
 pub struct PrecipEvent {
     pub timestamp: DateTime<Utc>,
 }
 
-impl From<reader::RawPrecipEvent> for PrecipEvent {
-    fn from(raw: reader::RawPrecipEvent) -> Self {
-        Self {
-            timestamp: DateTime::from_utc(NaiveDateTime::from_timestamp(raw.evt.0, 0), Utc),
+impl TryFrom<reader::RawPrecipEvent> for PrecipEvent {
+    type Error = (reader::RawPrecipEvent, DecodeError);
+    fn try_from(raw: reader::RawPrecipEvent) -> Result<Self, Self::Error> {
+        match unix_timestamp(raw.evt.0) {
+            Ok(timestamp) => Ok(Self { timestamp }),
+            Err(e) => Err((raw, e)),
         }
     }
 }
 
+/// A single lightning strike.
 #[derive(Debug, Serialize)]
 pub struct StrikeEvent {
     pub timestamp: DateTime<Utc>,
+    /// Estimated distance to the strike (km).
     pub distance: f64,
+    /// Relative strike energy (unitless).
     pub energy: f64,
 }
 
-impl From<reader::RawStrikeEvent> for StrikeEvent {
-    fn from(raw: reader::RawStrikeEvent) -> Self {
-        Self {
-            timestamp: DateTime::from_utc(NaiveDateTime::from_timestamp(raw.evt.0, 0), Utc),
-            distance: raw.evt.1,
-            energy: raw.evt.2,
+impl TryFrom<reader::RawStrikeEvent> for StrikeEvent {
+    type Error = (reader::RawStrikeEvent, DecodeError);
+    fn try_from(raw: reader::RawStrikeEvent) -> Result<Self, Self::Error> {
+        match unix_timestamp(raw.evt.0) {
+            Ok(timestamp) => Ok(Self {
+                timestamp,
+                distance: raw.evt.1,
+                energy: raw.evt.2,
+            }),
+            Err(e) => Err((raw, e)),
         }
     }
 }
 
+/// A wind vector, as speed (m/s) and the compass direction it blows from (degrees).
 #[derive(Debug)]
 pub struct Wind {
     speed_magnitude: f64,
     source_direction: f64,
 }
 
+impl Serialize for Wind {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (north, east) = self.component_velocity();
+        let mut s = serializer.serialize_struct("Wind", 4)?;
+        s.serialize_field("speed_magnitude_m_per_s", &self.speed_magnitude)?;
+        s.serialize_field("source_direction_deg", &self.source_direction)?;
+        s.serialize_field("north_velocity_m_per_s", &north)?;
+        s.serialize_field("east_velocity_m_per_s", &east)?;
+        s.end()
+    }
+}
+
 impl Wind {
     pub fn new(speed: f64, dir: f64) -> Self {
         Self {
@@ -85,40 +212,48 @@ impl Wind {
             source_direction: dir,
         }
     }
-    pub fn speed_magnitude(&self) -> f64 {
-        self.speed_magnitude
+    pub fn speed_magnitude(&self) -> MetersPerSecond {
+        MetersPerSecond(self.speed_magnitude)
     }
     pub fn source_direction(&self) -> f64 {
         self.source_direction
     }
+    /// Unit vector of the source direction as (north, east) components.
     pub fn component_direction(&self) -> (f64, f64) {
         (
             self.source_direction.to_radians().cos(),
             self.source_direction.to_radians().sin(),
         )
     }
+    /// Velocity as (north, east) components (m/s), pointing toward the source direction.
     pub fn component_velocity(&self) -> (f64, f64) {
         let (north, east) = self.component_direction();
         (self.speed_magnitude * north, self.speed_magnitude * east)
     }
 }
 
-#[derive(Debug)]
+/// Instantaneous wind, reported every few seconds.
+#[derive(Debug, Serialize)]
 pub struct RapidWind {
     pub timestamp: DateTime<Utc>,
     pub wind: Wind,
 }
 
-impl From<reader::RawRapidWind> for RapidWind {
-    fn from(raw: reader::RawRapidWind) -> Self {
-        Self {
-            timestamp: DateTime::from_utc(NaiveDateTime::from_timestamp(raw.ob.0, 0), Utc),
-            wind: Wind::new(raw.ob.1, raw.ob.2),
+impl TryFrom<reader::RawRapidWind> for RapidWind {
+    type Error = (reader::RawRapidWind, DecodeError);
+    fn try_from(raw: reader::RawRapidWind) -> Result<Self, Self::Error> {
+        match unix_timestamp(raw.ob.0) {
+            Ok(timestamp) => Ok(Self {
+                timestamp,
+                wind: Wind::new(raw.ob.1, raw.ob.2),
+            }),
+            Err(e) => Err((raw, e)),
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum PrecipKind {
     None,
     Rain,
@@ -126,39 +261,90 @@ pub enum PrecipKind {
     RainHail,
 }
 
-#[derive(Debug)]
+/// Phase that precipitation would likely fall as, which the Tempest cannot sense: its haptic rain
+/// sensor reports snow as no precipitation at all.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PrecipPhase {
+    Rain,
+    Mixed,
+    Snow,
+}
+
+impl PrecipPhase {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PrecipPhase::Rain => "rain",
+            PrecipPhase::Mixed => "mixed",
+            PrecipPhase::Snow => "snow",
+        }
+    }
+}
+
+/// How humid the air feels, by dew point, the usual measure of mugginess.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Comfort {
+    Comfortable,
+    Humid,
+    Oppressive,
+}
+
+impl Comfort {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Comfort::Comfortable => "comfortable",
+            Comfort::Humid => "humid",
+            Comfort::Oppressive => "oppressive",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
 pub struct WindObservation {
     pub lull: Wind,
     pub avg: Wind,
     pub gust: Wind,
+    #[serde(rename = "interval_sec", serialize_with = "seconds")]
     pub interval: Duration,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct SolarObservation {
+    #[serde(rename = "illuminance_lux")]
     pub illuminance: f64,
+    #[serde(rename = "uv_index")]
     pub ultraviolet_index: f64,
+    #[serde(rename = "irradiance_w_per_m2")]
     pub irradiance: f64,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct PrecipObservation {
-    pub quantity_last_minute: f64,
+    #[serde(rename = "previous_minute_rain_mm")]
+    pub quantity_last_minute: Millimeters,
     pub kind: PrecipKind,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct LightningObservation {
+    #[serde(rename = "average_distance_km")]
     pub average_distance: f64,
     pub count: i64,
 }
 
+/// Periodic observation from all station sensors. Sensor readings are `None` when the sensor
+/// reported no value.
+///
+/// Serialization includes the derived values that need no station parameters; barometric pressure
+/// depends on station elevation and is left out.
 #[derive(Debug)]
 pub struct Observation {
+    pub serial_number: String,
     pub timestamp: DateTime<Utc>,
     pub wind: Option<WindObservation>,
-    pub station_pressure: Option<f64>,
-    pub air_temperature: Option<f64>,
+    pub station_pressure: Option<HectoPascal>,
+    pub air_temperature: Option<Celsius>,
     pub relative_humidity: Option<f64>,
     pub solar: Option<SolarObservation>,
     pub precip: Option<PrecipObservation>,
@@ -194,60 +380,371 @@ const STEADMAN_CQ: f64 = 0.70;
 const STEADMAN_OWS: f64 = 10.0;
 const STEADMAN_B: f64 = -4.25;
 
+// Black globe thermometer heat balance for wet bulb globe temperature: a standard 150 mm globe
+// absorbs sunlight over its cross-section, a quarter of its surface, and sheds heat by radiation
+// and by forced convection per ISO 7726. Convection is floored at a light air, as the formula
+// breaks down in still air.
+const GLOBE_DIAMETER: f64 = 0.15; // m
+const GLOBE_ABSORPTIVITY: f64 = 0.95;
+const GLOBE_EMISSIVITY: f64 = 0.95;
+const GLOBE_MIN_WIND: f64 = 0.5; // m s^-1
+const STEFAN_BOLTZMANN: f64 = 5.670374e-8; // W m^-2 K^-4
+
+// Precipitation phase by wet bulb temperature, which unlike air temperature accounts for falling
+// snow being cooled by evaporation in dry air. Above the air temperature limit snow melts before
+// reaching the ground however dry the air.
+const SNOW_MAX_WET_BULB: f64 = 0.5; // °C
+const RAIN_MIN_WET_BULB: f64 = 1.5; // °C
+const RAIN_MIN_AIR_TEMPERATURE: f64 = 6.0; // °C
+
+// Wind chill applies in cold and wind, per Environment Canada and the US National Weather Service
+// (°C and km h^-1).
+const WIND_CHILL_MAX_TEMPERATURE: f64 = 10.0;
+const WIND_CHILL_MIN_WIND: f64 = 4.8;
+const WIND_CHILL_A: f64 = 13.12;
+const WIND_CHILL_B: f64 = 0.6215;
+const WIND_CHILL_C: f64 = -11.37;
+const WIND_CHILL_D: f64 = 0.3965;
+const WIND_CHILL_EXP: f64 = 0.16;
+
+// Heat index applies in heat, per the US National Weather Service, whose Rothfusz regression
+// works in °F.
+const HEAT_INDEX_MIN_TEMPERATURE: f64 = 26.7; // °C
+const ROTHFUSZ: [f64; 9] = [
+    -42.379,
+    2.04901523,
+    10.14333127,
+    -0.22475541,
+    -0.00683783,
+    -0.05481717,
+    0.00122874,
+    0.00085282,
+    -0.00000199,
+];
+
+// Opaque constants for the Australian Bureau of Meteorology apparent temperature, without
+// radiation.
+const AUSTRALIAN_AT_CE: f64 = 0.33;
+const AUSTRALIAN_AT_CWS: f64 = -0.70;
+const AUSTRALIAN_AT_B: f64 = -4.00;
+
+// Opaque constants for the Australian Bureau of Meteorology estimate of shaded wet bulb globe
+// temperature from temperature and humidity alone.
+const WBGT_ESTIMATE_CT: f64 = 0.567;
+const WBGT_ESTIMATE_CE: f64 = 0.393;
+const WBGT_ESTIMATE_B: f64 = 3.94;
+
+// Radiative cooling heuristic for frost risk: without sunshine, exposed surfaces radiate heat to
+// the sky and fall below air temperature, by more in still air (which doesn't mix warmer air
+// back down) than in a breeze.
+const FROST_NO_SUN_IRRADIANCE: f64 = 50.0; // W m^-2
+const FROST_CALM_WIND: f64 = 2.0; // m s^-1
+const FROST_BREEZY_WIND: f64 = 4.0; // m s^-1
+const FROST_CALM_COOLING: f64 = 4.0; // K
+const FROST_BREEZY_COOLING: f64 = 2.0; // K
+
+// Dew points from which the air feels humid, and then oppressive.
+const COMFORT_MAX_DEW_POINT: f64 = 16.0; // °C
+const OPPRESSIVE_MIN_DEW_POINT: f64 = 21.0; // °C
+
+// Below this solar elevation the clear-sky model is unreliable and what light there is mostly
+// diffuse, so the clear-sky index says little about cloud.
+const CLEAR_SKY_MIN_ELEVATION: f64 = 10.0; // deg
+
 impl Observation {
-    pub fn barometric_pressure(&self, station_elevation: f64) -> Option<f64> {
-        let t_kelvin = self.air_temperature.unwrap_or(0.0) + ZERO_C_KELVIN;
+    /// Corrects raw sensor readings so that every derived value is computed from calibrated
+    /// inputs. Relative humidity is clamped to 0–100 %.
+    pub fn calibrate(&mut self, temp_offset: f64, rh_offset: f64) {
+        self.air_temperature = self.air_temperature.map(|t| Celsius(t.0 + temp_offset));
+        self.relative_humidity = self
+            .relative_humidity
+            .map(|rh| (rh + rh_offset).clamp(0.0, 100.0));
+    }
+
+    /// Zeroes illuminance and UV index readings below the given floors, such as the few lux the
+    /// light sensor reports at night, so that darkness reads as exactly zero.
+    pub fn apply_floors(&mut self, illuminance_floor: f64, uv_floor: f64) {
+        if let Some(solar) = &mut self.solar {
+            if solar.illuminance < illuminance_floor {
+                solar.illuminance = 0.0;
+            }
+            if solar.ultraviolet_index < uv_floor {
+                solar.ultraviolet_index = 0.0;
+            }
+        }
+    }
+
+    /// Station pressure reduced to mean sea level (hPa), given station elevation (m).
+    pub fn barometric_pressure(&self, station_elevation: f64) -> Option<HectoPascal> {
+        let t_kelvin = self.air_temperature.unwrap_or_default().kelvin();
         let ratio = (1.0 + (LAMBDA * station_elevation) / (t_kelvin - LAMBDA * station_elevation))
             .powf(-G_OVER_RD_LAMBDA);
-        Some(self.station_pressure? * ratio)
+        Some(HectoPascal(self.station_pressure?.0 * ratio))
     }
 
-    pub fn vapor_pressure_saturated(&self) -> Option<f64> {
-        let t = self.air_temperature?;
-        Some(ARDEN_BUCK_A * ((ARDEN_BUCK_B - t / ARDEN_BUCK_D) * (t / (ARDEN_BUCK_C + t))).exp())
+    /// Saturated vapor pressure at air temperature (hPa), per Arden Buck.
+    pub fn vapor_pressure_saturated(&self) -> Option<HectoPascal> {
+        let t = self.air_temperature?.0;
+        Some(HectoPascal(
+            ARDEN_BUCK_A * ((ARDEN_BUCK_B - t / ARDEN_BUCK_D) * (t / (ARDEN_BUCK_C + t))).exp(),
+        ))
     }
 
-    pub fn vapor_pressure_actual(&self) -> Option<f64> {
-        Some(self.vapor_pressure_saturated()? * (self.relative_humidity? / 100.0))
+    /// Actual vapor pressure (hPa).
+    pub fn vapor_pressure_actual(&self) -> Option<HectoPascal> {
+        Some(HectoPascal(
+            self.vapor_pressure_saturated()?.0 * (self.relative_humidity? / 100.0),
+        ))
     }
 
-    pub fn dew_point(&self) -> Option<f64> {
-        let ln_pa_t_over_a = (self.vapor_pressure_actual()? / ARDEN_BUCK_A).ln();
-        Some(ARDEN_BUCK_C * ln_pa_t_over_a / (ARDEN_BUCK_B - ln_pa_t_over_a))
+    /// Dew point (°C).
+    pub fn dew_point(&self) -> Option<Celsius> {
+        let ln_pa_t_over_a = (self.vapor_pressure_actual()?.0 / ARDEN_BUCK_A).ln();
+        Some(Celsius(
+            ARDEN_BUCK_C * ln_pa_t_over_a / (ARDEN_BUCK_B - ln_pa_t_over_a),
+        ))
     }
 
-    pub fn wet_bulb_temperature(&self) -> Option<f64> {
-        let t = self.air_temperature?;
+    /// Wet bulb temperature (°C), per Stull.
+    pub fn wet_bulb_temperature(&self) -> Option<Celsius> {
+        let t = self.air_temperature?.0;
         let rh = self.relative_humidity?;
-        Some(
+        Some(Celsius(
             t * (STULL_A * (rh + STULL_B).sqrt()).atan() + (t + rh).atan() - (rh + STULL_C).atan()
                 + STULL_D * rh.powf(3.0 / 2.0) * (STULL_E * rh).atan()
                 + STULL_F,
-        )
+        ))
     }
 
-    pub fn apparent_temperature(&self) -> Option<f64> {
-        let ta = self.air_temperature?;
-        let e = self.vapor_pressure_actual()?;
-        let ws = self.wind.as_ref()?.avg.speed_magnitude();
+    /// Apparent temperature (°C), per Steadman including solar radiation.
+    pub fn apparent_temperature(&self) -> Option<Celsius> {
+        let ta = self.air_temperature?.0;
+        let e = self.vapor_pressure_actual()?.0;
+        let ws = self.wind.as_ref()?.avg.speed_magnitude().0;
         let q = self.solar.as_ref()?.irradiance;
-        Some(
+        Some(Celsius(
             ta + STEADMAN_CE * e
                 + STEADMAN_CWS * ws
                 + (STEADMAN_CQ * q) / (ws + STEADMAN_OWS)
                 + STEADMAN_B,
+        ))
+    }
+
+    /// Apparent temperature (°C) by the given formula.
+    pub fn apparent_temperature_by(&self, formula: ApparentTemperatureFormula) -> Option<Celsius> {
+        match formula {
+            ApparentTemperatureFormula::Steadman => self.apparent_temperature(),
+            ApparentTemperatureFormula::HeatIndexWindChill => self.heat_index_wind_chill(),
+            ApparentTemperatureFormula::AustralianAt => {
+                let ws = self.wind.as_ref()?.avg.speed_magnitude().0;
+                Some(Celsius(
+                    self.air_temperature?.0
+                        + AUSTRALIAN_AT_CE * self.vapor_pressure_actual()?.0
+                        + AUSTRALIAN_AT_CWS * ws
+                        + AUSTRALIAN_AT_B,
+                ))
+            }
+            ApparentTemperatureFormula::WbgtEstimate => Some(Celsius(
+                WBGT_ESTIMATE_CT * self.air_temperature?.0
+                    + WBGT_ESTIMATE_CE * self.vapor_pressure_actual()?.0
+                    + WBGT_ESTIMATE_B,
+            )),
+        }
+    }
+
+    /// Phase that precipitation would likely fall as, estimated from wet bulb and air
+    /// temperature. Estimated whether or not any is falling, since snow goes undetected.
+    pub fn estimated_precip_phase(&self) -> Option<PrecipPhase> {
+        let tw = self.wet_bulb_temperature()?.0;
+        Some(
+            if self.air_temperature?.0 >= RAIN_MIN_AIR_TEMPERATURE || tw >= RAIN_MIN_WET_BULB {
+                PrecipPhase::Rain
+            } else if tw > SNOW_MAX_WET_BULB {
+                PrecipPhase::Mixed
+            } else {
+                PrecipPhase::Snow
+            },
         )
     }
+
+    /// How humid the air feels, from the dew point.
+    pub fn comfort(&self) -> Option<Comfort> {
+        let dew_point = self.dew_point()?.0;
+        Some(if dew_point >= OPPRESSIVE_MIN_DEW_POINT {
+            Comfort::Oppressive
+        } else if dew_point >= COMFORT_MAX_DEW_POINT {
+            Comfort::Humid
+        } else {
+            Comfort::Comfortable
+        })
+    }
+
+    /// Estimated temperature of a black globe thermometer in sun and wind (°C), from a heat balance
+    /// in which it absorbs sunlight and loses heat to surroundings at air temperature.
+    pub fn globe_temperature(&self) -> Option<Celsius> {
+        let ta = self.air_temperature?.kelvin();
+        let ws = self
+            .wind
+            .as_ref()?
+            .avg
+            .speed_magnitude()
+            .0
+            .max(GLOBE_MIN_WIND);
+        let absorbed = GLOBE_ABSORPTIVITY * self.solar.as_ref()?.irradiance.max(0.0) / 4.0;
+        let convection = 6.3 * ws.powf(0.6) / GLOBE_DIAMETER.powf(0.4);
+        let net_loss = |tg: f64| {
+            GLOBE_EMISSIVITY * STEFAN_BOLTZMANN * (tg.powi(4) - ta.powi(4)) + convection * (tg - ta)
+        };
+        // Heat loss grows with globe temperature, and convection alone would shed the absorbed
+        // heat by the upper bound, so bisect between the two.
+        let (mut low, mut high) = (ta, ta + absorbed / convection);
+        for _ in 0..50 {
+            let mid = (low + high) / 2.0;
+            if net_loss(mid) < absorbed {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        Some(Celsius((low + high) / 2.0 - ZERO_C_KELVIN))
+    }
+
+    /// Estimated wet bulb globe temperature (°C), the outdoor heat stress index, from the
+    /// psychrometric wet bulb standing in for the natural wet bulb, the estimated globe
+    /// temperature, and air temperature. The natural wet bulb reads somewhat higher in strong
+    /// sun, so this errs low there.
+    pub fn wet_bulb_globe_temperature(&self) -> Option<Celsius> {
+        Some(Celsius(
+            0.7 * self.wet_bulb_temperature()?.0
+                + 0.2 * self.globe_temperature()?.0
+                + 0.1 * self.air_temperature?.0,
+        ))
+    }
+
+    /// Wind chill in cold and wind, heat index in heat, and otherwise air temperature (°C), per
+    /// the US National Weather Service.
+    pub fn heat_index_wind_chill(&self) -> Option<Celsius> {
+        let t = self.air_temperature?.0;
+        if t <= WIND_CHILL_MAX_TEMPERATURE {
+            let wind_kmh = self
+                .wind
+                .as_ref()?
+                .avg
+                .speed_magnitude()
+                .kilometers_per_hour();
+            if wind_kmh <= WIND_CHILL_MIN_WIND {
+                return Some(Celsius(t));
+            }
+            let v = wind_kmh.powf(WIND_CHILL_EXP);
+            return Some(Celsius(
+                WIND_CHILL_A + WIND_CHILL_B * t + WIND_CHILL_C * v + WIND_CHILL_D * t * v,
+            ));
+        }
+        if t < HEAT_INDEX_MIN_TEMPERATURE {
+            return Some(Celsius(t));
+        }
+        let rh = self.relative_humidity?;
+        let f = Celsius(t).fahrenheit();
+        let [c1, c2, c3, c4, c5, c6, c7, c8, c9] = ROTHFUSZ;
+        let mut hi = c1
+            + c2 * f
+            + c3 * rh
+            + c4 * f * rh
+            + c5 * f * f
+            + c6 * rh * rh
+            + c7 * f * f * rh
+            + c8 * f * rh * rh
+            + c9 * f * f * rh * rh;
+        // The regression's own corrections for very dry and for very humid heat.
+        if rh < 13.0 && (80.0..=112.0).contains(&f) {
+            hi -= (13.0 - rh) / 4.0 * ((17.0 - (f - 95.0).abs()) / 17.0).sqrt();
+        } else if rh > 85.0 && (80.0..=87.0).contains(&f) {
+            hi += (rh - 85.0) / 10.0 * (87.0 - f) / 5.0;
+        }
+        Some(Celsius((hi - 32.0) * 5.0 / 9.0))
+    }
+
+    /// Estimated temperature of exposed surfaces such as plants (°C). Surfaces cool below the air
+    /// when there is no sun to warm them, but not much below the dew point, where condensation
+    /// releases heat. Missing wind or solar readings are taken as calm and dark.
+    pub fn surface_temperature(&self) -> Option<Celsius> {
+        let t = self.air_temperature?.0;
+        let sunny = self.sunlit();
+        let wind = self
+            .wind
+            .as_ref()
+            .map_or(0.0, |wind| wind.avg.speed_magnitude().0);
+        let cooling = if sunny {
+            0.0
+        } else if wind < FROST_CALM_WIND {
+            FROST_CALM_COOLING
+        } else if wind < FROST_BREEZY_WIND {
+            FROST_BREEZY_COOLING
+        } else {
+            0.0
+        };
+        Some(Celsius((t - cooling).max(self.dew_point()?.0.min(t))))
+    }
+
+    /// Whether there is sunshine enough to keep exposed surfaces from cooling below the air. Taken
+    /// as not without solar readings.
+    pub fn sunlit(&self) -> bool {
+        matches!(&self.solar, Some(solar) if solar.irradiance >= FROST_NO_SUN_IRRADIANCE)
+    }
+
+    /// Whether exposed surfaces are likely at or below freezing, so that frost can form.
+    pub fn frost_risk(&self) -> Option<bool> {
+        Some(self.surface_temperature()?.0 <= 0.0)
+    }
+
+    /// Ratio of measured irradiance to that modelled for a cloudless sky, given station latitude
+    /// and longitude (decimal degrees): near 1 when clear, and lower as cloud thickens. `None`
+    /// while the sun is too low for the ratio to mean much.
+    pub fn clear_sky_index(&self, latitude: f64, longitude: f64) -> Option<f64> {
+        let irradiance = self.solar.as_ref()?.irradiance;
+        let zenith_cosine = solar::zenith_cosine(self.timestamp, latitude, longitude);
+        if zenith_cosine < CLEAR_SKY_MIN_ELEVATION.to_radians().sin() {
+            return None;
+        }
+        Some(irradiance / solar::clear_sky_irradiance(zenith_cosine))
+    }
+}
+
+impl Serialize for Observation {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("Observation", 18)?;
+        s.serialize_field("serial_number", &self.serial_number)?;
+        s.serialize_field("timestamp", &self.timestamp)?;
+        s.serialize_field("wind", &self.wind)?;
+        s.serialize_field("station_pressure_hpa", &self.station_pressure)?;
+        s.serialize_field("temperature_deg_c", &self.air_temperature)?;
+        s.serialize_field("relative_humidity_pct", &self.relative_humidity)?;
+        s.serialize_field("dew_point_deg_c", &self.dew_point())?;
+        s.serialize_field("wet_bulb_temperature_deg_c", &self.wet_bulb_temperature())?;
+        s.serialize_field(
+            "wet_bulb_globe_temperature_deg_c",
+            &self.wet_bulb_globe_temperature(),
+        )?;
+        s.serialize_field("apparent_temperature_deg_c", &self.apparent_temperature())?;
+        s.serialize_field("vapor_pressure_hpa", &self.vapor_pressure_actual())?;
+        s.serialize_field("frost_risk", &self.frost_risk())?;
+        s.serialize_field("solar", &self.solar)?;
+        s.serialize_field("precip", &self.precip)?;
+        s.serialize_field("estimated_precip_phase", &self.estimated_precip_phase())?;
+        s.serialize_field("lightning", &self.lightning)?;
+        s.serialize_field("battery_volts", &self.battery_volts)?;
+        s.serialize_field("report_interval_sec", &self.report_interval.num_seconds())?;
+        s.end()
+    }
 }
 
 impl TryFrom<reader::RawObservation> for Observation {
-    type Error = (reader::RawObservation, anyhow::Error);
+    type Error = (reader::RawObservation, DecodeError);
     fn try_from(raw: reader::RawObservation) -> Result<Self, Self::Error> {
-        let timestamp = match raw.obs[0][0] {
-            Some(unix_sec) => {
-                DateTime::from_utc(NaiveDateTime::from_timestamp(unix_sec as i64, 0), Utc)
-            }
-            None => return Err((raw, anyhow!("Missing observation timestamp"))),
+        let timestamp = match raw.obs[0][0].map(|unix_sec| unix_timestamp(unix_sec as i64)) {
+            Some(Ok(timestamp)) => timestamp,
+            Some(Err(e)) => return Err((raw, e)),
+            None => return Err((raw, DecodeError::MissingField("observation timestamp"))),
         };
 
         let wind: Option<WindObservation> = (|| {
@@ -256,7 +753,7 @@ impl TryFrom<reader::RawObservation> for Observation {
                 lull: Wind::new(raw.obs[0][1]?, wind_dir),
                 avg: Wind::new(raw.obs[0][2]?, wind_dir),
                 gust: Wind::new(raw.obs[0][3]?, wind_dir),
-                interval: Duration::seconds(raw.obs[0][5]? as i64),
+                interval: duration_seconds(raw.obs[0][5]? as i64).ok()?,
             })
         })();
 
@@ -271,13 +768,22 @@ impl TryFrom<reader::RawObservation> for Observation {
         let precip_obs: Option<(f64, i64)> = (|| Some((raw.obs[0][12]?, raw.obs[0][13]? as i64)))();
         let precip = if let Some((qty, kind_raw)) = precip_obs {
             Some(PrecipObservation {
-                quantity_last_minute: qty,
+                quantity_last_minute: Millimeters(qty),
                 kind: match kind_raw {
                     0 => PrecipKind::None,
                     1 => PrecipKind::Rain,
                     2 => PrecipKind::Hail,
                     3 => PrecipKind::RainHail,
-                    other => return Err((raw, anyhow!("Unrecognized precip type {}", other))),
+                    other => {
+                        let value = other.to_string();
+                        return Err((
+                            raw,
+                            DecodeError::UnknownValue {
+                                field: "precip type",
+                                value,
+                            },
+                        ));
+                    }
                 },
             })
         } else {
@@ -294,25 +800,29 @@ impl TryFrom<reader::RawObservation> for Observation {
         Ok(Self {
             timestamp,
             wind,
-            station_pressure: raw.obs[0][6],
-            air_temperature: raw.obs[0][7],
+            station_pressure: raw.obs[0][6].map(HectoPascal),
+            air_temperature: raw.obs[0][7].map(Celsius),
             relative_humidity: raw.obs[0][8],
             solar,
             precip,
             lightning,
             battery_volts: match raw.obs[0][16] {
                 Some(volts) => volts,
-                None => return Err((raw, anyhow!("Missing battery voltage"))),
+                None => return Err((raw, DecodeError::MissingField("battery voltage"))),
             },
-            report_interval: match raw.obs[0][17] {
-                Some(interval) => Duration::minutes(interval as i64),
-                None => return Err((raw, anyhow!("Missing report interval"))),
+            report_interval: match raw.obs[0][17]
+                .map(|minutes| duration_seconds((minutes * 60.0) as i64))
+            {
+                Some(Ok(interval)) => interval,
+                Some(Err(e)) => return Err((raw, e)),
+                None => return Err((raw, DecodeError::MissingField("report interval"))),
             },
+            serial_number: raw.serial_number,
         })
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct SensorStatus {
     pub lightning_failure: bool,
     pub lightning_noise: bool,
@@ -345,38 +855,88 @@ impl From<u32> for SensorStatus {
     }
 }
 
-#[derive(Debug)]
+/// Station health and radio status.
+#[derive(Debug, Serialize)]
 pub struct DeviceStatus {
     pub serial_number: String,
     pub hub_serial_number: String,
     pub timestamp: DateTime<Utc>,
+    #[serde(rename = "uptime_sec", serialize_with = "seconds")]
     pub uptime: Duration,
+    #[serde(rename = "battery_volts")]
     pub voltage: f64,
     pub firmware_revision: i32,
+    #[serde(rename = "rssi_dbm")]
     pub rssi: f64,
+    #[serde(rename = "hub_rssi_dbm")]
     pub hub_rssi: f64,
     pub sensor_status: SensorStatus,
     pub debug: bool,
 }
 
-impl From<reader::RawDeviceStatus> for DeviceStatus {
-    fn from(raw: reader::RawDeviceStatus) -> Self {
-        Self {
+impl TryFrom<reader::RawDeviceStatus> for DeviceStatus {
+    type Error = (reader::RawDeviceStatus, DecodeError);
+    fn try_from(raw: reader::RawDeviceStatus) -> Result<Self, Self::Error> {
+        let (timestamp, uptime) = match status_times(raw.timestamp, raw.uptime) {
+            Ok(times) => times,
+            Err(e) => return Err((raw, e)),
+        };
+        Ok(Self {
             serial_number: raw.serial_number,
             hub_serial_number: raw.hub_sn,
-            timestamp: DateTime::from_utc(NaiveDateTime::from_timestamp(raw.timestamp, 0), Utc),
-            uptime: Duration::seconds(raw.uptime),
+            timestamp,
+            uptime,
             voltage: raw.voltage,
             firmware_revision: raw.firmware_revision,
             rssi: raw.rssi,
             hub_rssi: raw.hub_rssi,
             sensor_status: raw.sensor_status.into(),
             debug: raw.debug == 1,
+        })
+    }
+}
+
+/// Formula for the apparent temperature, conventions for which differ between regions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ApparentTemperatureFormula {
+    /// Steadman, including solar radiation.
+    #[default]
+    Steadman,
+    /// Wind chill or heat index, per the US National Weather Service.
+    #[serde(rename = "heat-index-windchill")]
+    HeatIndexWindChill,
+    /// Australian Bureau of Meteorology, without radiation.
+    AustralianAt,
+    /// Australian Bureau of Meteorology estimate of wet bulb globe temperature in shade.
+    WbgtEstimate,
+}
+
+impl ApparentTemperatureFormula {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Steadman => "steadman",
+            Self::HeatIndexWindChill => "heat-index-windchill",
+            Self::AustralianAt => "australian-at",
+            Self::WbgtEstimate => "wbgt-estimate",
+        }
+    }
+}
+
+impl FromStr for ApparentTemperatureFormula {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "steadman" => Ok(Self::Steadman),
+            "heat-index-windchill" => Ok(Self::HeatIndexWindChill),
+            "australian-at" => Ok(Self::AustralianAt),
+            "wbgt-estimate" => Ok(Self::WbgtEstimate),
+            other => bail!("Unrecognized apparent temperature formula {}", other),
         }
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Serialize)]
 pub struct ResetFlags {
     pub brownout: bool,
     pub pin: bool,
@@ -389,10 +949,10 @@ pub struct ResetFlags {
 }
 
 impl FromStr for ResetFlags {
-    type Err = anyhow::Error;
+    type Err = DecodeError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut new = Self::default();
-        for label in s.split(',') {
+        for label in s.split(',').filter(|label| !label.is_empty()) {
             match label {
                 "BOR" => new.brownout = true,
                 "PIN" => new.pin = true,
@@ -402,18 +962,26 @@ impl FromStr for ResetFlags {
                 "WWD" => new.window_watchdog = true,
                 "LPW" => new.low_power = true,
                 "HRDFLT" => new.hard_fault = true,
-                label => bail!("Unrecognized reset flag label {}", label),
+                label => {
+                    return Err(DecodeError::UnknownValue {
+                        field: "reset flag label",
+                        value: label.to_string(),
+                    })
+                }
             }
         }
         Ok(new)
     }
 }
 
-#[derive(Debug)]
+/// Hub health and radio status.
+#[derive(Debug, Serialize)]
 pub struct HubStatus {
     pub serial_number: String,
     pub firmware_revision: String,
+    #[serde(rename = "uptime_sec", serialize_with = "seconds")]
     pub uptime: Duration,
+    #[serde(rename = "rssi_dbm")]
     pub rssi: f64,
     pub timestamp: DateTime<Utc>,
     pub reset_flags: ResetFlags,
@@ -421,30 +989,113 @@ pub struct HubStatus {
 }
 
 impl TryFrom<reader::RawHubStatus> for HubStatus {
-    type Error = (reader::RawHubStatus, anyhow::Error);
+    type Error = (reader::RawHubStatus, DecodeError);
     fn try_from(raw: reader::RawHubStatus) -> Result<Self, Self::Error> {
         let reset_flags = match raw.reset_flags.parse() {
             Ok(v) => v,
             Err(e) => return Err((raw, e)),
         };
+        let (timestamp, uptime) = match status_times(raw.timestamp, raw.uptime) {
+            Ok(times) => times,
+            Err(e) => return Err((raw, e)),
+        };
         Ok(Self {
             serial_number: raw.serial_number,
             firmware_revision: raw.firmware_revision,
-            uptime: Duration::seconds(raw.uptime),
+            uptime,
             rssi: raw.rssi,
-            timestamp: DateTime::from_utc(NaiveDateTime::from_timestamp(raw.uptime, 0), Utc),
+            timestamp,
             reset_flags,
             seq: raw.seq,
         })
     }
 }
 
+/// A debug or diagnostic message, passed along undecoded but for the device and time it came from.
+#[derive(Debug, Serialize)]
+pub struct DebugMessage {
+    /// Message type, such as `light_debug`, `hub_debug` or `radio_stats`.
+    pub kind: String,
+    pub serial_number: Option<String>,
+    /// When the message was made if it says, and otherwise when it was decoded.
+    pub timestamp: DateTime<Utc>,
+    pub fields: serde_json::Map<String, serde_json::Value>,
+}
+
+impl From<reader::RawDebugMessage> for DebugMessage {
+    fn from(raw: reader::RawDebugMessage) -> Self {
+        let serial_number = raw
+            .fields
+            .get("serial_number")
+            .and_then(|sn| sn.as_str())
+            .map(str::to_string);
+        // Reports are timestamped either in a field of their own or first in their `ob` array.
+        let timestamp = raw
+            .fields
+            .get("timestamp")
+            .or_else(|| raw.fields.get("ob").and_then(|ob| ob.get(0)))
+            .and_then(|ts| ts.as_i64())
+            .and_then(|ts| unix_timestamp(ts).ok())
+            .unwrap_or_else(Utc::now);
+        Self {
+            kind: raw.kind,
+            serial_number,
+            timestamp,
+            fields: raw.fields,
+        }
+    }
+}
+
+// Range-checked conversions of hub-supplied values, which chrono would otherwise panic on.
+fn unix_timestamp(unix_sec: i64) -> Result<DateTime<Utc>, DecodeError> {
+    Utc.timestamp_opt(unix_sec, 0)
+        .single()
+        .ok_or(DecodeError::InvalidTimestamp(unix_sec))
+}
+
+fn duration_seconds(secs: i64) -> Result<Duration, DecodeError> {
+    secs.checked_mul(1000)
+        .map(Duration::milliseconds)
+        .ok_or(DecodeError::InvalidDuration(secs))
+}
+
+// Status reports carry both the time they were made and the device's uptime, in seconds alike, so
+// one is easily taken for the other. No device of the API predates 2016, so a status time before
+// then can only be such a mix-up.
+const MIN_STATUS_TIMESTAMP: i64 = 1_451_606_400; // 2016-01-01T00:00:00Z
+
+// Time and uptime of a status report, the time checked for plausibility.
+fn status_times(unix_sec: i64, uptime_secs: i64) -> Result<(DateTime<Utc>, Duration), DecodeError> {
+    let timestamp = unix_timestamp(unix_sec)?;
+    let uptime = duration_seconds(uptime_secs)?;
+    if unix_sec < MIN_STATUS_TIMESTAMP {
+        return Err(DecodeError::ImplausibleTimestamp(unix_sec));
+    }
+    Ok((timestamp, uptime))
+}
+
+fn seconds<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_i64(duration.num_seconds())
+}
+
+/// Decodes a stream of raw messages, logging and dropping any that fail to decode.
 pub fn new<RD: Stream<Item = RawTempestMsg>>(reader: RD) -> impl Stream<Item = TempestMsg> {
-    reader.filter_map(|raw| {
-        raw.try_into()
+    with_drop_hook(reader, |_| {})
+}
+
+/// Like [`new`], also passing the error for each dropped message to `dropped`, such as to count
+/// them by [`DecodeError::category`].
+pub fn with_drop_hook<RD: Stream<Item = RawTempestMsg>>(
+    reader: RD,
+    mut dropped: impl FnMut(&DecodeError),
+) -> impl Stream<Item = TempestMsg> {
+    reader.filter_map(move |raw| {
+        let _span = trace_span!("decode").entered();
+        TempestMsg::try_from(raw)
             .map_err(|(raw, e)| {
                 warn!("Dropped undecodable message: {:?}", raw);
-                warn!(".. error was: {}", e);
+                warn!(".. error was ({}): {}", e.category(), e);
+                dropped(&e);
             })
             .ok()
     })
@@ -0,0 +1,428 @@
+//! User-defined derived metrics: simple arithmetic expressions over observation fields, given in
+//! the configuration file, such as `temperature * 1.8 + 32`.
+//!
+//! Expressions support numbers, the fields named by [`Variable`], `+ - * / ^`, parentheses, and
+//! the functions `abs`, `sqrt`, `exp`, `ln`, `min` and `max`. An expression evaluates to `None`
+//! when a field it uses is missing from the observation, or when the result is not finite.
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail};
+
+use crate::decoder::Observation;
+use crate::params::StationParams;
+
+/// A derived metric, exported and published under its name.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DerivedMetric {
+    pub name: String,
+    /// The expression as written in the configuration.
+    pub source: String,
+    pub expression: Expr,
+}
+
+impl DerivedMetric {
+    /// Parses the expression, and checks that the name is usable as part of a metric name and an
+    /// MQTT topic.
+    pub fn new(name: &str, expression: &str) -> anyhow::Result<Self> {
+        let mut chars = name.chars();
+        let valid = matches!(chars.next(), Some('a'..='z' | '_'))
+            && chars.all(|c| matches!(c, 'a'..='z' | '0'..='9' | '_'));
+        if !valid {
+            bail!(
+                "Derived metric name {:?} must be lowercase letters, digits and underscores",
+                name
+            );
+        }
+        Ok(Self {
+            name: name.to_string(),
+            source: expression.to_string(),
+            expression: expression
+                .parse()
+                .map_err(|e| anyhow!("Derived metric {}: {}", name, e))?,
+        })
+    }
+
+    pub fn evaluate(&self, obs: &Observation, station_params: &StationParams) -> Option<f64> {
+        self.expression.evaluate(obs, station_params)
+    }
+}
+
+/// An observation field usable in expressions, in the same units as the built-in metrics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Variable {
+    Temperature,
+    RelativeHumidity,
+    DewPoint,
+    WetBulbTemperature,
+    ApparentTemperature,
+    StationPressure,
+    BarometricPressure,
+    WindLull,
+    WindAvg,
+    WindGust,
+    WindDirection,
+    Illuminance,
+    Irradiance,
+    UvIndex,
+    Rain,
+    LightningCount,
+    LightningDistance,
+    BatteryVolts,
+}
+
+const VARIABLES: &[(&str, Variable)] = &[
+    ("temperature", Variable::Temperature),
+    ("relative_humidity", Variable::RelativeHumidity),
+    ("dew_point", Variable::DewPoint),
+    ("wet_bulb_temperature", Variable::WetBulbTemperature),
+    ("apparent_temperature", Variable::ApparentTemperature),
+    ("station_pressure", Variable::StationPressure),
+    ("barometric_pressure", Variable::BarometricPressure),
+    ("wind_lull", Variable::WindLull),
+    ("wind_avg", Variable::WindAvg),
+    ("wind_gust", Variable::WindGust),
+    ("wind_direction", Variable::WindDirection),
+    ("illuminance", Variable::Illuminance),
+    ("irradiance", Variable::Irradiance),
+    ("uv_index", Variable::UvIndex),
+    ("rain", Variable::Rain),
+    ("lightning_count", Variable::LightningCount),
+    ("lightning_distance", Variable::LightningDistance),
+    ("battery_volts", Variable::BatteryVolts),
+];
+
+impl Variable {
+    pub fn from_name(name: &str) -> Option<Self> {
+        VARIABLES
+            .iter()
+            .find(|(variable, _)| *variable == name)
+            .map(|(_, variable)| *variable)
+    }
+
+    pub fn name(&self) -> &'static str {
+        VARIABLES
+            .iter()
+            .find(|(_, variable)| variable == self)
+            .map(|(name, _)| *name)
+            .unwrap()
+    }
+
+    /// Value of the field in the observation, if present.
+    pub fn value(&self, obs: &Observation, station_params: &StationParams) -> Option<f64> {
+        use Variable as V;
+        match self {
+            V::Temperature => obs.air_temperature.map(f64::from),
+            V::RelativeHumidity => obs.relative_humidity,
+            V::DewPoint => obs.dew_point().map(f64::from),
+            V::WetBulbTemperature => obs.wet_bulb_temperature().map(f64::from),
+            V::ApparentTemperature => obs
+                .apparent_temperature_by(station_params.apparent_temperature_formula)
+                .map(f64::from),
+            V::StationPressure => obs.station_pressure.map(f64::from),
+            V::BarometricPressure => obs
+                .barometric_pressure(station_params.elevation)
+                .map(f64::from),
+            V::WindLull => Some(obs.wind.as_ref()?.lull.speed_magnitude().0),
+            V::WindAvg => Some(obs.wind.as_ref()?.avg.speed_magnitude().0),
+            V::WindGust => Some(obs.wind.as_ref()?.gust.speed_magnitude().0),
+            V::WindDirection => Some(obs.wind.as_ref()?.avg.source_direction()),
+            V::Illuminance => Some(obs.solar.as_ref()?.illuminance),
+            V::Irradiance => Some(obs.solar.as_ref()?.irradiance),
+            V::UvIndex => Some(obs.solar.as_ref()?.ultraviolet_index),
+            V::Rain => Some(obs.precip.as_ref()?.quantity_last_minute.0),
+            V::LightningCount => Some(obs.lightning.as_ref()?.count as f64),
+            V::LightningDistance => Some(obs.lightning.as_ref()?.average_distance),
+            V::BatteryVolts => Some(obs.battery_volts),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Function {
+    Abs,
+    Sqrt,
+    Exp,
+    Ln,
+    Min,
+    Max,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "abs" => Function::Abs,
+            "sqrt" => Function::Sqrt,
+            "exp" => Function::Exp,
+            "ln" => Function::Ln,
+            "min" => Function::Min,
+            "max" => Function::Max,
+            _ => return None,
+        })
+    }
+
+    // Whether the function takes the given number of arguments.
+    fn accepts(&self, count: usize) -> bool {
+        match self {
+            Function::Min | Function::Max => count >= 1,
+            _ => count == 1,
+        }
+    }
+
+    fn apply(&self, args: &[f64]) -> f64 {
+        match self {
+            Function::Abs => args[0].abs(),
+            Function::Sqrt => args[0].sqrt(),
+            Function::Exp => args[0].exp(),
+            Function::Ln => args[0].ln(),
+            Function::Min => args.iter().copied().fold(f64::INFINITY, f64::min),
+            Function::Max => args.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Power,
+}
+
+/// A parsed expression.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    Variable(Variable),
+    Negate(Box<Expr>),
+    Binary(Operator, Box<Expr>, Box<Expr>),
+    Call(Function, Vec<Expr>),
+}
+
+impl Expr {
+    pub fn evaluate(&self, obs: &Observation, station_params: &StationParams) -> Option<f64> {
+        let value = self.value(obs, station_params)?;
+        if value.is_finite() {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    fn value(&self, obs: &Observation, station_params: &StationParams) -> Option<f64> {
+        Some(match self {
+            Expr::Number(n) => *n,
+            Expr::Variable(v) => v.value(obs, station_params)?,
+            Expr::Negate(e) => -e.value(obs, station_params)?,
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (a.value(obs, station_params)?, b.value(obs, station_params)?);
+                match op {
+                    Operator::Add => a + b,
+                    Operator::Subtract => a - b,
+                    Operator::Multiply => a * b,
+                    Operator::Divide => a / b,
+                    Operator::Power => a.powf(b),
+                }
+            }
+            Expr::Call(f, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| arg.value(obs, station_params))
+                    .collect::<Option<Vec<_>>>()?;
+                f.apply(&args)
+            }
+        })
+    }
+}
+
+impl FromStr for Expr {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            position: 0,
+        };
+        let expr = parser.expr()?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(token) => bail!("Unexpected {} in expression", token),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Symbol(char),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "number {}", n),
+            Token::Ident(name) => write!(f, "name {}", name),
+            Token::Symbol(c) => write!(f, "'{}'", c),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> anyhow::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut number = String::new();
+            while let Some(&c) = chars.peek() {
+                // Exponents in scientific notation, such as 1e-3, may be signed.
+                let signed_exponent =
+                    matches!(c, '+' | '-') && matches!(number.chars().last(), Some('e' | 'E'));
+                if c.is_ascii_alphanumeric() || c == '.' || signed_exponent {
+                    number.push(c);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            let value = number
+                .parse()
+                .map_err(|_| anyhow!("Invalid number {} in expression", number))?;
+            tokens.push(Token::Number(value));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let mut name = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_ascii_alphanumeric() || c == '_' {
+                    name.push(c);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            tokens.push(Token::Ident(name));
+        } else if "+-*/^(),".contains(c) {
+            tokens.push(Token::Symbol(c));
+            chars.next();
+        } else {
+            bail!("Unexpected character '{}' in expression", c);
+        }
+    }
+    Ok(tokens)
+}
+
+// Recursive descent, from lowest precedence to highest: sums, products, negation, powers (right
+// associative), then numbers, fields, calls and parenthesized expressions.
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn eat(&mut self, symbol: char) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: char) -> anyhow::Result<()> {
+        match self.next() {
+            Some(Token::Symbol(c)) if c == symbol => Ok(()),
+            Some(token) => bail!("Expected '{}' but found {} in expression", symbol, token),
+            None => bail!("Expected '{}' at end of expression", symbol),
+        }
+    }
+
+    fn expr(&mut self) -> anyhow::Result<Expr> {
+        let mut expr = self.term()?;
+        loop {
+            let op = if self.eat('+') {
+                Operator::Add
+            } else if self.eat('-') {
+                Operator::Subtract
+            } else {
+                return Ok(expr);
+            };
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.term()?));
+        }
+    }
+
+    fn term(&mut self) -> anyhow::Result<Expr> {
+        let mut expr = self.unary()?;
+        loop {
+            let op = if self.eat('*') {
+                Operator::Multiply
+            } else if self.eat('/') {
+                Operator::Divide
+            } else {
+                return Ok(expr);
+            };
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> anyhow::Result<Expr> {
+        if self.eat('-') {
+            Ok(Expr::Negate(Box::new(self.unary()?)))
+        } else {
+            self.power()
+        }
+    }
+
+    fn power(&mut self) -> anyhow::Result<Expr> {
+        let base = self.atom()?;
+        if self.eat('^') {
+            Ok(Expr::Binary(
+                Operator::Power,
+                Box::new(base),
+                Box::new(self.unary()?),
+            ))
+        } else {
+            Ok(base)
+        }
+    }
+
+    fn atom(&mut self) -> anyhow::Result<Expr> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Expr::Number(n)),
+            Some(Token::Ident(name)) if self.eat('(') => {
+                let function = Function::from_name(&name)
+                    .ok_or_else(|| anyhow!("Unknown function {} in expression", name))?;
+                let mut args = vec![self.expr()?];
+                while self.eat(',') {
+                    args.push(self.expr()?);
+                }
+                self.expect(')')?;
+                if !function.accepts(args.len()) {
+                    bail!("Wrong number of arguments to {} in expression", name);
+                }
+                Ok(Expr::Call(function, args))
+            }
+            Some(Token::Ident(name)) => Variable::from_name(&name)
+                .map(Expr::Variable)
+                .ok_or_else(|| anyhow!("Unknown field {} in expression", name)),
+            Some(Token::Symbol('(')) => {
+                let expr = self.expr()?;
+                self.expect(')')?;
+                Ok(expr)
+            }
+            Some(token) => bail!("Unexpected {} in expression", token),
+            None => bail!("Unexpected end of expression"),
+        }
+    }
+}
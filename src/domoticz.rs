@@ -0,0 +1,150 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use anyhow::bail;
+use serde::Serialize;
+use tempest_exporter::decoder::Observation;
+use tempest_exporter::params::StationParams;
+
+// Domoticz virtual device types that observations can update. Each takes its readings combined in
+// one `svalue`, in the formats Domoticz documents for its `domoticz/in` MQTT topic.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Device {
+    Temperature,
+    Humidity,
+    Barometer,
+    TempHum,
+    TempHumBaro,
+    Wind,
+    Rain,
+    Uv,
+    Illuminance,
+    SolarRadiation,
+}
+
+impl FromStr for Device {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "temperature" => Device::Temperature,
+            "humidity" => Device::Humidity,
+            "barometer" => Device::Barometer,
+            "temp_hum" => Device::TempHum,
+            "temp_hum_baro" => Device::TempHumBaro,
+            "wind" => Device::Wind,
+            "rain" => Device::Rain,
+            "uv" => Device::Uv,
+            "illuminance" => Device::Illuminance,
+            "solar_radiation" => Device::SolarRadiation,
+            other => bail!("Unrecognized Domoticz device type {}", other),
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DomoticzParams {
+    pub topic: String,
+    // Index of the virtual device to update, by device type.
+    pub devices: BTreeMap<Device, u64>,
+}
+
+#[derive(Serialize)]
+struct Update {
+    idx: u64,
+    nvalue: i64,
+    svalue: String,
+}
+
+const COMPASS_POINTS: [&str; 16] = [
+    "N", "NNE", "NE", "ENE", "E", "ESE", "SE", "SSE", "S", "SSW", "SW", "WSW", "W", "WNW", "NW",
+    "NNW",
+];
+
+// Forecast codes meaning "no forecast", for the temperature/humidity/barometer and the barometer
+// device types respectively.
+const NO_FORECAST_THB: u8 = 0;
+const NO_FORECAST_BARO: u8 = 5;
+
+// Humidity status, which Domoticz displays alongside the humidity.
+fn humidity_status(relative_humidity: f64) -> u8 {
+    match relative_humidity {
+        rh if rh < 30.0 => 2, // Dry
+        rh if rh > 70.0 => 3, // Wet
+        _ => 1,               // Comfortable
+    }
+}
+
+// Payloads updating each configured device from an observation, skipping devices whose readings
+// are missing from it. `rain_total` is the rain counted since startup (mm), which Domoticz turns
+// into daily totals itself.
+pub fn updates(
+    params: &DomoticzParams,
+    obs: &Observation,
+    station_params: &StationParams,
+    rain_total: f64,
+) -> Vec<String> {
+    let temp = obs.air_temperature;
+    let hum = obs.relative_humidity;
+    let baro = obs.barometric_pressure(station_params.elevation);
+    params
+        .devices
+        .iter()
+        .filter_map(|(device, &idx)| {
+            let (nvalue, svalue) = match device {
+                Device::Temperature => (0, format!("{:.1}", temp?)),
+                Device::Humidity => (hum?.round() as i64, humidity_status(hum?).to_string()),
+                Device::Barometer => (0, format!("{:.1};{}", baro?, NO_FORECAST_BARO)),
+                Device::TempHum => (
+                    0,
+                    format!("{:.1};{:.0};{}", temp?, hum?, humidity_status(hum?)),
+                ),
+                Device::TempHumBaro => (
+                    0,
+                    format!(
+                        "{:.1};{:.0};{};{:.1};{}",
+                        temp?,
+                        hum?,
+                        humidity_status(hum?),
+                        baro?,
+                        NO_FORECAST_THB
+                    ),
+                ),
+                Device::Wind => {
+                    let wind = obs.wind.as_ref()?;
+                    let bearing = wind.avg.source_direction();
+                    let point = (bearing.rem_euclid(360.0) / 22.5).round() as usize % 16;
+                    (
+                        0,
+                        format!(
+                            "{:.0};{};{:.0};{:.0};{:.1};{:.1}",
+                            bearing,
+                            COMPASS_POINTS[point],
+                            // Speeds in tenths of a m/s.
+                            wind.avg.speed_magnitude().0 * 10.0,
+                            wind.gust.speed_magnitude().0 * 10.0,
+                            temp?,
+                            obs.heat_index_wind_chill()?
+                        ),
+                    )
+                }
+                Device::Rain => {
+                    let precip = obs.precip.as_ref()?;
+                    // Rate in hundredths of a mm/h.
+                    let rate = precip.quantity_last_minute.0 * 60.0 * 100.0;
+                    (0, format!("{:.0};{:.1}", rate, rain_total))
+                }
+                Device::Uv => (0, format!("{:.1};0", obs.solar.as_ref()?.ultraviolet_index)),
+                Device::Illuminance => (0, format!("{:.0}", obs.solar.as_ref()?.illuminance)),
+                Device::SolarRadiation => (0, format!("{:.0}", obs.solar.as_ref()?.irradiance)),
+            };
+            Some(
+                serde_json::to_string(&Update {
+                    idx,
+                    nvalue,
+                    svalue,
+                })
+                .unwrap(),
+            )
+        })
+        .collect()
+}
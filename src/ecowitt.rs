@@ -0,0 +1,251 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use crate::config::{ExporterParams, Shared, StationParams};
+use crate::day::Daily;
+use crate::decoder::{Observation, TempestMsg};
+use crate::publisher::{WIND_AVG_TOPICS, WIND_GUST_TOPICS};
+use crate::sink_queue::LastError;
+use crate::units::{Celsius, HectoPascal, Millimeters};
+use tempest_exporter::clock::SharedClock;
+use tempest_exporter::filter::PublishFilter;
+
+// Which personal weather station upload format observations are sent in. Ecowitt consoles POST a
+// form to their "customized" server; Ambient Weather consoles send the same kind of fields as a
+// GET query string.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Protocol {
+    #[default]
+    Ecowitt,
+    Ambient,
+}
+
+#[derive(Clone, PartialEq)]
+pub struct EcowittParams {
+    pub url: reqwest::Url,
+    pub protocol: Protocol,
+    // Identifies the station to the receiver, as the Ecowitt PASSKEY or Ambient MAC. The Tempest's
+    // serial number if not set.
+    pub station_key: Option<String>,
+    // Least time between uploads.
+    pub interval: Duration,
+}
+
+// Keeps the station key out of logs and `check-config` output.
+impl std::fmt::Debug for EcowittParams {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EcowittParams")
+            .field("url", &self.url)
+            .field("protocol", &self.protocol)
+            .field(
+                "station_key",
+                &self.station_key.as_ref().map(|_| "<redacted>"),
+            )
+            .field("interval", &self.interval)
+            .finish()
+    }
+}
+
+// Upload fields for an observation, in the imperial units both protocols use, leaving out those
+// the filter drops by the MQTT topic of their reading. `daily_rain` is the rain since local
+// midnight.
+fn fields(
+    params: &EcowittParams,
+    obs: &Observation,
+    station_params: &StationParams,
+    filter: &PublishFilter,
+    daily_rain: Millimeters,
+) -> Vec<(&'static str, String)> {
+    let station_key = params
+        .station_key
+        .clone()
+        .unwrap_or_else(|| obs.serial_number.clone());
+    let mut fields = match params.protocol {
+        Protocol::Ecowitt => vec![
+            ("PASSKEY", station_key),
+            (
+                "stationtype",
+                format!("tempest-exporter_{}", env!("CARGO_PKG_VERSION")),
+            ),
+        ],
+        Protocol::Ambient => vec![("MAC", station_key)],
+    };
+    fields.push((
+        "dateutc",
+        obs.timestamp.format("%Y-%m-%d %H:%M:%S").to_string(),
+    ));
+    let mut push = |name, topic: &str, value: Option<f64>, precision: usize| {
+        if let Some(value) = value.filter(|_| filter.allows(topic)) {
+            fields.push((name, format!("{:.*}", precision, value)));
+        }
+    };
+    push(
+        "tempf",
+        "observation/thermal/temperature_deg_c",
+        obs.air_temperature.map(Celsius::fahrenheit),
+        1,
+    );
+    push(
+        "humidity",
+        "observation/thermal/relative_humidity_pct",
+        obs.relative_humidity,
+        0,
+    );
+    push(
+        "baromrelin",
+        "observation/pressure/barometric_hpa",
+        obs.barometric_pressure(station_params.elevation)
+            .map(HectoPascal::inches_of_mercury),
+        3,
+    );
+    push(
+        "baromabsin",
+        "observation/pressure/station_hpa",
+        obs.station_pressure.map(HectoPascal::inches_of_mercury),
+        3,
+    );
+    if let Some(wind) = &obs.wind {
+        push(
+            "winddir",
+            WIND_AVG_TOPICS.source_direction,
+            Some(wind.avg.source_direction().rem_euclid(360.0)),
+            0,
+        );
+        push(
+            "windspeedmph",
+            WIND_AVG_TOPICS.speed_magnitude,
+            Some(wind.avg.speed_magnitude().miles_per_hour()),
+            1,
+        );
+        push(
+            "windgustmph",
+            WIND_GUST_TOPICS.speed_magnitude,
+            Some(wind.gust.speed_magnitude().miles_per_hour()),
+            1,
+        );
+    }
+    if let Some(solar) = &obs.solar {
+        push(
+            "solarradiation",
+            "observation/solar/irradiance_w_per_m2",
+            Some(solar.irradiance),
+            1,
+        );
+        push(
+            "uv",
+            "observation/solar/uv_index",
+            Some(solar.ultraviolet_index),
+            0,
+        );
+    }
+    if let Some(precip) = &obs.precip {
+        let rate = Millimeters(precip.quantity_last_minute.0 * 60.0).inches();
+        let rate_field = match params.protocol {
+            Protocol::Ecowitt => "rainratein",
+            Protocol::Ambient => "hourlyrainin",
+        };
+        let topic = "observation/precip/previous_minute_rain_mm";
+        push(rate_field, topic, Some(rate), 3);
+        push("dailyrainin", topic, Some(daily_rain.inches()), 3);
+    }
+    fields
+}
+
+// Uploads observations in the Ecowitt or Ambient Weather custom server protocol, for software and
+// devices (such as irrigation controllers) that only take readings from those consoles.
+pub struct EcowittSink {
+    station_params: Shared<StationParams>,
+    exporter_params: Shared<ExporterParams>,
+    params: Mutex<EcowittParams>,
+    http: reqwest::Client,
+    last_upload: Mutex<Option<Instant>>,
+    // Rain counted on the station's local day so far (mm).
+    daily_rain: Mutex<Daily<f64>>,
+    errors: Arc<LastError>,
+    clock: SharedClock,
+}
+
+impl EcowittSink {
+    pub fn new(
+        station_params: Shared<StationParams>,
+        exporter_params: Shared<ExporterParams>,
+        params: EcowittParams,
+        clock: SharedClock,
+    ) -> Self {
+        Self {
+            station_params,
+            exporter_params,
+            params: Mutex::new(params),
+            http: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap(),
+            last_upload: Mutex::new(None),
+            daily_rain: Mutex::new(Daily::default()),
+            errors: Arc::new(LastError::default()),
+            clock,
+        }
+    }
+
+    // Where upload errors are recorded, for `/debug/state`.
+    pub fn errors(&self) -> Arc<LastError> {
+        self.errors.clone()
+    }
+
+    pub fn reconfigure(&self, new_params: EcowittParams) {
+        let mut params = self.params.lock().unwrap();
+        if *params != new_params {
+            info!("Ecowitt upload parameters changed");
+            *params = new_params;
+        }
+    }
+
+    pub fn handle_report(&self, msg: &TempestMsg) {
+        let obs = match msg {
+            TempestMsg::Observation(obs) => obs,
+            _ => return,
+        };
+        let station_params = self.station_params.read().unwrap();
+        let daily_rain = self.count_rain(obs, &station_params);
+
+        let params = self.params.lock().unwrap();
+        let mut last_upload = self.last_upload.lock().unwrap();
+        let now = self.clock.now();
+        if matches!(*last_upload, Some(last) if now < last + params.interval) {
+            return;
+        }
+        *last_upload = Some(now);
+
+        let filter = &self.exporter_params.read().unwrap().publish_filter;
+        let fields = fields(&params, obs, &station_params, filter, daily_rain);
+        let request = match params.protocol {
+            Protocol::Ecowitt => self.http.post(params.url.clone()).form(&fields),
+            Protocol::Ambient => self.http.get(params.url.clone()).query(&fields),
+        };
+        let errors = self.errors.clone();
+        tokio::spawn(async move {
+            // The URL of an Ambient upload carries the station key.
+            let result = request.send().await.and_then(|r| r.error_for_status());
+            if let Err(e) = result.map_err(|e| e.without_url()) {
+                warn!("Ecowitt upload failed: {}", e);
+                errors.record(format!("Upload failed: {}", e));
+            }
+        });
+    }
+
+    // Adds the observation's rain to the day's total, starting over at the station's local
+    // midnight, and returns the total.
+    fn count_rain(&self, obs: &Observation, station_params: &StationParams) -> Millimeters {
+        let rain = obs
+            .precip
+            .as_ref()
+            .map_or(0.0, |p| p.quantity_last_minute.0);
+        let mut daily_rain = self.daily_rain.lock().unwrap();
+        let zone = station_params.metadata.day_zone();
+        Millimeters(*daily_rain.update(zone, obs.timestamp, |total| *total += rain))
+    }
+}
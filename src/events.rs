@@ -0,0 +1,163 @@
+//! Notable changes in the station's devices and hubs, kept so that what happened overnight can be
+//! read off a list rather than pieced together from metric history.
+//!
+//! Events are spotted from consecutive status reports: a firmware revision changing, a sensor
+//! failing or recovering, a device restarting and a hub rebooting. Only the latest [`CAPACITY`]
+//! are kept.
+
+use std::collections::{HashMap, VecDeque};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::decoder::{DeviceStatus, HubStatus, SensorStatus};
+
+/// Events kept, oldest dropped first.
+pub const CAPACITY: usize = 100;
+
+/// Something notable that happened to a device or hub.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Event {
+    /// Time of the status report that revealed it.
+    pub at: DateTime<Utc>,
+    /// The device or hub it happened to.
+    pub serial_number: String,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+/// What happened. Serializes with its name under `event`, alongside its details.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum EventKind {
+    FirmwareChanged { from: String, to: String },
+    SensorFailed { sensor: &'static str },
+    SensorRecovered { sensor: &'static str },
+    DeviceRestarted,
+    HubRebooted { reason: &'static str },
+}
+
+impl EventKind {
+    /// Name of the kind of event, as serialized.
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventKind::FirmwareChanged { .. } => "firmware_changed",
+            EventKind::SensorFailed { .. } => "sensor_failed",
+            EventKind::SensorRecovered { .. } => "sensor_recovered",
+            EventKind::DeviceRestarted => "device_restarted",
+            EventKind::HubRebooted { .. } => "hub_rebooted",
+        }
+    }
+}
+
+/// The latest events, and what each device and hub last reported to spot the next ones by.
+#[derive(Debug, Default)]
+pub struct EventLog {
+    events: VecDeque<Event>,
+    firmware: HashMap<String, String>,
+    failed: HashMap<String, Vec<&'static str>>,
+}
+
+impl EventLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the events revealed by a device status, given whether the device was seen to have
+    /// restarted since its previous one, and returns them. Sensors already failed in the first
+    /// status seen from a device count as failing then.
+    pub fn device_status(&mut self, ds: &DeviceStatus, restarted: bool) -> Vec<Event> {
+        let mut kinds = Vec::new();
+        if restarted {
+            kinds.push(EventKind::DeviceRestarted);
+        }
+        kinds.extend(self.firmware_changed(&ds.serial_number, &ds.firmware_revision.to_string()));
+        let failed = failed_sensors(&ds.sensor_status);
+        let before = self
+            .failed
+            .insert(ds.serial_number.clone(), failed.clone())
+            .unwrap_or_default();
+        kinds.extend(
+            failed
+                .iter()
+                .filter(|sensor| !before.contains(sensor))
+                .map(|&sensor| EventKind::SensorFailed { sensor }),
+        );
+        kinds.extend(
+            before
+                .iter()
+                .filter(|sensor| !failed.contains(sensor))
+                .map(|&sensor| EventKind::SensorRecovered { sensor }),
+        );
+        self.record(ds.timestamp, &ds.serial_number, kinds)
+    }
+
+    /// Records the events revealed by a hub status, given the reason for a reboot if the hub was
+    /// seen to have rebooted since its previous one, and returns them.
+    pub fn hub_status(
+        &mut self,
+        hs: &HubStatus,
+        reboot_reason: Option<&'static str>,
+    ) -> Vec<Event> {
+        let mut kinds: Vec<_> = reboot_reason
+            .map(|reason| EventKind::HubRebooted { reason })
+            .into_iter()
+            .collect();
+        kinds.extend(self.firmware_changed(&hs.serial_number, &hs.firmware_revision));
+        self.record(hs.timestamp, &hs.serial_number, kinds)
+    }
+
+    /// The events kept, oldest first.
+    pub fn events(&self) -> impl Iterator<Item = &Event> {
+        self.events.iter()
+    }
+
+    fn firmware_changed(&mut self, serial_number: &str, revision: &str) -> Option<EventKind> {
+        let previous = self
+            .firmware
+            .insert(serial_number.to_string(), revision.to_string())?;
+        (previous != revision).then(|| EventKind::FirmwareChanged {
+            from: previous,
+            to: revision.to_string(),
+        })
+    }
+
+    fn record(
+        &mut self,
+        at: DateTime<Utc>,
+        serial_number: &str,
+        kinds: Vec<EventKind>,
+    ) -> Vec<Event> {
+        let events: Vec<_> = kinds
+            .into_iter()
+            .map(|kind| Event {
+                at,
+                serial_number: serial_number.to_string(),
+                kind,
+            })
+            .collect();
+        for event in &events {
+            if self.events.len() == CAPACITY {
+                self.events.pop_front();
+            }
+            self.events.push_back(event.clone());
+        }
+        events
+    }
+}
+
+// Sensors the status flags as failed.
+fn failed_sensors(status: &SensorStatus) -> Vec<&'static str> {
+    [
+        (status.lightning_failure, "lightning"),
+        (status.pressure_failed, "pressure"),
+        (status.temperature_failed, "temperature"),
+        (status.humidity_failed, "humidity"),
+        (status.wind_failed, "wind"),
+        (status.precip_failed, "precip"),
+        (status.irradiance_failed, "irradiance"),
+    ]
+    .into_iter()
+    .filter_map(|(failed, sensor)| failed.then_some(sensor))
+    .collect()
+}
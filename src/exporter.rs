@@ -1,62 +1,643 @@
+//! Prometheus metrics built from decoded reports.
+
+mod compat;
+mod exemplars;
+mod interference;
+mod openmetrics;
+mod overnight;
+mod resets;
 mod wind_metrics;
 
-use std::time::Duration;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
+use chrono::{DateTime, Utc};
+use prometheus::core::Collector;
+use prometheus::proto::{LabelPair, MetricFamily};
 use prometheus::{
-    Encoder, Gauge, Histogram, HistogramOpts, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
-    TextEncoder,
+    Encoder, Gauge, GaugeVec, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec,
+    IntGauge, IntGaugeVec, Opts, Registry, TextEncoder,
 };
+use serde_json::json;
 
+use crate::checkpoint::{self, Checkpoint};
+use crate::clock::{self, SharedClock};
 use crate::decoder;
-use crate::perishable::Perishable;
-use crate::StationParams;
-use wind_metrics::WindMetrics;
+use crate::events::{Event, EventLog};
+use crate::fire::{self, FireDanger};
+use crate::forecast;
+use crate::hubs;
+use crate::leaf;
+use crate::mold::MoldRisk;
+use crate::params::{ExporterParams, Shared, StationParams};
+use crate::perishable::{Perishable, PerishableMap};
+use crate::quality::Quality;
+use crate::rain::{RainCounter, RainEvents, RainSeason};
+use crate::rain_check::DailyRain;
+use crate::reader::RawTempestMsg;
+use crate::reference::ReferenceObservation;
+use crate::smoothing::Ewma;
+use crate::trend::{Trend, RAIN_RATE_WINDOWS};
+use exemplars::ExemplarHistogram;
+use interference::InterferenceTracker;
+use overnight::OvernightMinimum;
+use resets::ResetTracker;
+use wind_metrics::{WindMetrics, WindSpeedSummary, WindSpeedWindow};
 
-const INSTANT_WIND_VALID: Duration = Duration::from_secs(15);
-const OBS_VALID: Duration = Duration::from_secs(3 * 60);
+pub use compat::current_name as renamed_metric;
 
+/// Holds the current metric values, updated from each report and rendered on scrape.
 pub struct Exporter {
     metrics: ExportedMetrics,
-    station_params: StationParams,
+    station_params: Shared<StationParams>,
+    exporter_params: Shared<ExporterParams>,
 }
 
 impl Exporter {
-    pub fn new(station_params: StationParams) -> Self {
-        let metrics = ExportedMetrics::new();
+    pub fn new(
+        station_params: Shared<StationParams>,
+        exporter_params: Shared<ExporterParams>,
+    ) -> Self {
+        Self::with_clock(station_params, exporter_params, clock::system())
+    }
+
+    /// An exporter that times expiry and windows by `clock` rather than the system clock.
+    pub fn with_clock(
+        station_params: Shared<StationParams>,
+        exporter_params: Shared<ExporterParams>,
+        clock: SharedClock,
+    ) -> Self {
+        let metrics = ExportedMetrics::new(clock);
         Self {
             metrics,
             station_params,
+            exporter_params,
         }
     }
 
+    /// Renders all fresh metrics in the Prometheus text exposition format.
     pub fn encode(&self) -> Vec<u8> {
+        encode_text(&self.gather_with_legacy_names())
+    }
+
+    /// Renders all fresh metrics in the OpenMetrics text format, which unlike the Prometheus
+    /// format carries exemplars naming the report behind notable histogram observations.
+    pub fn encode_openmetrics(&self) -> Vec<u8> {
+        let exemplars: HashMap<_, _> = self
+            .metrics
+            .histograms()
+            .into_iter()
+            .map(|h| (h.name(), h.exemplars()))
+            .collect();
+        openmetrics::encode(&self.gather(), &exemplars)
+    }
+
+    // OpenMetrics always named counters with `_total`, so only the Prometheus format has legacy
+    // names.
+    fn gather_with_legacy_names(&self) -> Vec<MetricFamily> {
+        compat::apply(
+            self.gather(),
+            &self.exporter_params.read().unwrap().metric_names,
+        )
+    }
+
+    // Collects fresh metrics, with cumulative ones including the counts restored at startup.
+    fn gather(&self) -> Vec<MetricFamily> {
         let mut registry = Registry::new();
+        self.metrics.summarize_instant_wind(
+            self.metrics.clock.utc(),
+            self.exporter_params.read().unwrap().instant_wind_window,
+        );
         self.metrics.register_all(&mut registry);
-        let metric_families = registry.gather();
+        let station_params = self.station_params.read().unwrap();
+        // As of the scrape, so that the total starts over when the season does even if no rain is
+        // observed then.
+        if let Some(total) = self.metrics.rain_season.lock().unwrap().total_at(
+            station_params.metadata.day_zone(),
+            self.exporter_params.read().unwrap().rain_season_start,
+            self.metrics.clock.utc(),
+        ) {
+            let gauge = &self.metrics.station_rain_season_total;
+            gauge.set(total);
+            registry.register(Box::new(gauge.clone())).unwrap();
+        }
+        let formula = Opts::new(
+            "apparent_temperature_formula_info",
+            "Formula used for apparent temperature",
+        )
+        .namespace("tempest")
+        .subsystem("station")
+        .const_label(
+            "formula",
+            station_params.apparent_temperature_formula.as_str(),
+        );
+        let formula = IntGauge::with_opts(formula).unwrap();
+        formula.set(1);
+        registry.register(Box::new(formula)).unwrap();
+        let metadata = station_params.metadata.labels();
+        if !metadata.is_empty() {
+            let labels = metadata
+                .into_iter()
+                .map(|(label, value)| (label.to_string(), value))
+                .collect();
+            let info = Opts::new("info", "Station site metadata")
+                .namespace("tempest")
+                .subsystem("station")
+                .const_labels(labels);
+            let info = IntGauge::with_opts(info).unwrap();
+            info.set(1);
+            registry.register(Box::new(info)).unwrap();
+        }
+        let mut metric_families = registry.gather();
+        let filter = &self.exporter_params.read().unwrap().publish_filter;
+        metric_families.retain(|family| filter.allows(family.get_name()));
+        for histogram in self.metrics.histograms() {
+            let name = histogram.name();
+            if let Some(family) = metric_families.iter_mut().find(|f| f.get_name() == name) {
+                histogram.add_baseline(family);
+            }
+        }
+        metric_families
+    }
+
+    /// Snapshot of the cumulative metrics, to be restored after a restart.
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            version: checkpoint::VERSION,
+            saved_at: self.metrics.clock.utc(),
+            messages_received: counter_values(&self.metrics.exporter_messages_received),
+            sink_queue_dropped: counter_values(&self.metrics.exporter_sink_queue_dropped),
+            hub_reboots: counter_values(&self.metrics.hub_reboots),
+            station_restarts: self.metrics.station_restarts.get(),
+            lightning_noise_events: self.metrics.station_lightning_noise_events.get(),
+            lightning_disturber_events: self.metrics.station_lightning_disturber_events.get(),
+            last_strike: *self.metrics.last_strike.lock().unwrap(),
+            last_rain: *self.metrics.last_rain.lock().unwrap(),
+            rain_season: self.metrics.rain_season.lock().unwrap().current().cloned(),
+            domoticz_rain: RainCounter::default(),
+            histograms: self
+                .metrics
+                .histograms()
+                .into_iter()
+                .map(|h| (h.name(), h.state()))
+                .collect(),
+            last_reports: Vec::new(),
+        }
+    }
+
+    /// Adds the cumulative metrics from a checkpoint taken before a restart. Call this once,
+    /// before any reports are handled.
+    pub fn restore(&self, checkpoint: &Checkpoint) {
+        for (kind, count) in &checkpoint.messages_received {
+            self.metrics
+                .exporter_messages_received
+                .with_label_values(&[kind])
+                .inc_by(*count);
+        }
+        for (sink, count) in &checkpoint.sink_queue_dropped {
+            self.metrics
+                .exporter_sink_queue_dropped
+                .with_label_values(&[sink])
+                .inc_by(*count);
+        }
+        for (reason, count) in &checkpoint.hub_reboots {
+            self.metrics
+                .hub_reboots
+                .with_label_values(&[reason])
+                .inc_by(*count);
+        }
+        self.metrics
+            .station_restarts
+            .inc_by(checkpoint.station_restarts);
+        self.metrics
+            .station_lightning_noise_events
+            .inc_by(checkpoint.lightning_noise_events);
+        self.metrics
+            .station_lightning_disturber_events
+            .inc_by(checkpoint.lightning_disturber_events);
+        if let Some(at) = checkpoint.last_strike {
+            record_latest(&self.metrics.last_strike, at);
+        }
+        if let Some(at) = checkpoint.last_rain {
+            record_latest(&self.metrics.last_rain, at);
+        }
+        if let Some(total) = &checkpoint.rain_season {
+            self.metrics
+                .rain_season
+                .lock()
+                .unwrap()
+                .restore(total.clone());
+        }
+        for histogram in self.metrics.histograms() {
+            if let Some(state) = checkpoint.histograms.get(&histogram.name()) {
+                histogram.restore(state);
+            }
+        }
+    }
 
-        let mut buffer = vec![];
-        let encoder = TextEncoder::new();
-        encoder.encode(&metric_families, &mut buffer).unwrap();
-        buffer
+    /// The latest notable events, oldest first.
+    pub fn events(&self) -> Vec<Event> {
+        self.metrics
+            .events
+            .lock()
+            .unwrap()
+            .events()
+            .cloned()
+            .collect()
     }
 
+    /// Sets yesterday's rain as reported by the WeatherFlow cloud.
+    pub fn set_daily_rain(&self, rain: &DailyRain) {
+        let gauges = &self.metrics.station_daily_rain;
+        gauges.with_label_values(&["raw"]).set(rain.raw);
+        match rain.corrected {
+            Some(corrected) => gauges.with_label_values(&["corrected"]).set(corrected),
+            None => {
+                gauges.remove_label_values(&["corrected"]).ok();
+            }
+        }
+    }
+
+    /// Sets the latest observation from the reference station, and how much the station's own
+    /// readings at the time of the observation differ from it. Readings the reference didn't
+    /// report, or the station has no reading from within a few minutes of the observation to
+    /// compare with, are removed.
+    pub fn set_reference(&self, reference: &ReferenceObservation) {
+        let metrics = &self.metrics;
+        let labels = &[reference.station.as_str()];
+        metrics
+            .reference_observation_timestamp
+            .with_label_values(labels)
+            .set(reference.timestamp.timestamp());
+        for (value, readings, gauges, deltas) in [
+            (
+                reference.temperature.map(f64::from),
+                &metrics.reference_temperature_readings,
+                &metrics.reference_temperature,
+                &metrics.reference_temperature_delta,
+            ),
+            (
+                reference.barometric_pressure.map(f64::from),
+                &metrics.reference_barometric_pressure_readings,
+                &metrics.reference_barometric_pressure,
+                &metrics.reference_barometric_pressure_delta,
+            ),
+        ] {
+            match value {
+                Some(value) => gauges.with_label_values(labels).set(value),
+                None => {
+                    gauges.remove_label_values(labels).ok();
+                }
+            }
+            let own = readings
+                .lock()
+                .unwrap()
+                .at(reference.timestamp, REFERENCE_MATCH);
+            match (value, own) {
+                (Some(value), Some(own)) => deltas.with_label_values(labels).set(own - value),
+                _ => {
+                    deltas.remove_label_values(labels).ok();
+                }
+            }
+        }
+    }
+
+    /// Internal state for troubleshooting: when each group of perishable metrics was updated and
+    /// expires, and what is being tracked across reports.
+    pub fn debug_state(&self) -> serde_json::Value {
+        let now = self.metrics.clock.now();
+        let perishable: serde_json::Map<_, _> = AGE_GROUPS
+            .iter()
+            .zip(self.metrics.freshness())
+            .map(|((group, _), (last_update, expires_at))| {
+                let expires_in = match expires_at.checked_duration_since(now) {
+                    Some(remaining) => remaining.as_secs_f64(),
+                    None => -now.duration_since(expires_at).as_secs_f64(),
+                };
+                let state = json!({
+                    "age_sec": last_update.map(|at| now.duration_since(at).as_secs_f64()),
+                    "expires_in_sec": last_update.map(|_| expires_in),
+                });
+                (group.to_string(), state)
+            })
+            .collect();
+        let metrics = &self.metrics;
+        json!({
+            "perishable": perishable,
+            "pressure_trend": *metrics.pressure_trend.lock().unwrap(),
+            "rain_history": *metrics.rain_history.lock().unwrap(),
+            "temperature_history": *metrics.temperature_history.lock().unwrap(),
+            "mold_risk": *metrics.mold_risk.lock().unwrap(),
+            "fire_danger": *metrics.fire_danger.lock().unwrap(),
+            "rain_events": *metrics.rain_events.lock().unwrap(),
+            "rain_season": *metrics.rain_season.lock().unwrap(),
+            "last_rain": *metrics.last_rain.lock().unwrap(),
+            "last_strike": *metrics.last_strike.lock().unwrap(),
+            "firmware_revisions": *metrics.firmware_revisions.lock().unwrap(),
+            "debug_messages": *metrics.debug_messages.lock().unwrap(),
+        })
+    }
+
+    /// Depth gauge and drop counter for the queue feeding the named sink.
+    pub fn queue_metrics(&self, sink: &str) -> (IntGauge, IntCounter) {
+        (
+            self.metrics
+                .exporter_sink_queue_depth
+                .with_label_values(&[sink]),
+            self.metrics
+                .exporter_sink_queue_dropped
+                .with_label_values(&[sink]),
+        )
+    }
+
+    /// Connection metrics for the MQTT publisher, exported from the first call on.
+    pub fn publisher_metrics(&self) -> PublisherMetrics {
+        self.metrics
+            .publisher
+            .lock()
+            .unwrap()
+            .get_or_insert_with(PublisherMetrics::new)
+            .clone()
+    }
+
+    /// Updates metrics from a decoded report.
     pub fn handle_report(&self, msg: &decoder::TempestMsg) {
+        let ep = &*self.exporter_params.read().unwrap();
+        self.export(msg, ep);
+        self.accumulate(msg, ep);
+    }
+
+    /// Counts a message dropped at a pipeline `stage` (`read`, `decode` or `dedup`) for `reason`,
+    /// such as a [`decoder::DecodeError::category`].
+    pub fn handle_dropped(&self, stage: &str, reason: &str) {
+        self.metrics
+            .exporter_messages_dropped
+            .with_label_values(&[stage, reason])
+            .inc();
+    }
+
+    /// Counts a copy of a report relayed by a hub other than the preferred one, which is otherwise
+    /// ignored except for the link quality of status reports.
+    pub fn handle_ignored_copy(&self, raw: &RawTempestMsg) {
+        self.handle_dropped("dedup", "hub_copy");
+        if let Some(hub) = hubs::relaying_hub(raw) {
+            self.metrics
+                .exporter_hub_copies_ignored
+                .with_label_values(&[hub])
+                .inc();
+        }
+        if let RawTempestMsg::DeviceStatus(rds) = raw {
+            if let Ok(ds) = decoder::DeviceStatus::try_from(rds.clone()) {
+                self.metrics.link_quality(&ds);
+            }
+        }
+    }
+
+    /// Updates metrics from a report saved before a restart, as if it had just been received
+    /// except that it expires as long after it was made as a live report would. Cumulative metrics
+    /// are left alone, since the report was already counted in them. Returns whether the report
+    /// was fresh enough to use.
+    pub fn restore_report(&self, msg: &decoder::TempestMsg) -> bool {
         use decoder::TempestMsg as TM;
+        // However old, a status report is the baseline for spotting a reboot or restart while we
+        // were down.
+        {
+            let mut resets = self.metrics.resets.lock().unwrap();
+            match msg {
+                TM::HubStatus(hs) => {
+                    resets.hub_rebooted(hs);
+                    self.metrics.events.lock().unwrap().hub_status(hs, None);
+                }
+                TM::DeviceStatus(ds) => {
+                    resets.device_restarted(ds);
+                    self.metrics.interference.lock().unwrap().detected(ds);
+                    self.metrics.events.lock().unwrap().device_status(ds, false);
+                }
+                _ => {}
+            }
+        }
+
+        let ep = self.exporter_params.read().unwrap().clone();
+        let age = match (self.metrics.clock.utc() - msg.timestamp()).to_std() {
+            Ok(age) => age,
+            // Made in the future by our clock, so as fresh as can be.
+            Err(_) => Duration::ZERO,
+        };
+        if age >= ep.observation_ttl {
+            return false;
+        }
+        let ep = ExporterParams {
+            instant_wind_ttl: ep.instant_wind_ttl.saturating_sub(age),
+            observation_ttl: ep.observation_ttl - age,
+            ..ep
+        };
+        self.export(msg, &ep);
+        true
+    }
+
+    fn export(&self, msg: &decoder::TempestMsg, ep: &ExporterParams) {
+        use decoder::TempestMsg as TM;
+        let sp = &*self.station_params.read().unwrap();
+        match msg {
+            TM::PrecipEvent(pe) => pe.export_to(&self.metrics, sp, ep),
+            TM::StrikeEvent(se) => se.export_to(&self.metrics, sp, ep),
+            TM::RapidWind(rw) => rw.export_to(&self.metrics, sp, ep),
+            TM::Observation(obs) => obs.export_to(&self.metrics, sp, ep),
+            TM::DeviceStatus(ds) => ds.export_to(&self.metrics, sp, ep),
+            TM::HubStatus(hs) => hs.export_to(&self.metrics, sp, ep),
+            TM::Debug(dm) => dm.export_to(&self.metrics, sp, ep),
+        }
+    }
+
+    fn accumulate(&self, msg: &decoder::TempestMsg, ep: &ExporterParams) {
+        use decoder::TempestMsg as TM;
+        let sp = &*self.station_params.read().unwrap();
         match msg {
-            TM::PrecipEvent(pe) => pe.export_to(&self.metrics, &self.station_params),
-            TM::StrikeEvent(se) => se.export_to(&self.metrics, &self.station_params),
-            TM::RapidWind(rw) => rw.export_to(&self.metrics, &self.station_params),
-            TM::Observation(obs) => obs.export_to(&self.metrics, &self.station_params),
-            TM::DeviceStatus(ds) => ds.export_to(&self.metrics, &self.station_params),
-            TM::HubStatus(hs) => hs.export_to(&self.metrics, &self.station_params),
+            TM::PrecipEvent(pe) => pe.accumulate(&self.metrics, sp, ep),
+            TM::StrikeEvent(se) => se.accumulate(&self.metrics, sp, ep),
+            TM::RapidWind(rw) => rw.accumulate(&self.metrics, sp, ep),
+            TM::Observation(obs) => obs.accumulate(&self.metrics, sp, ep),
+            TM::DeviceStatus(ds) => ds.accumulate(&self.metrics, sp, ep),
+            TM::HubStatus(hs) => hs.accumulate(&self.metrics, sp, ep),
+            TM::Debug(dm) => dm.accumulate(&self.metrics, sp, ep),
         }
     }
 }
 
+/// Renders the fresh metrics of several stations together in the Prometheus text exposition
+/// format, each series labelled with the name of the station it belongs to.
+pub fn encode_stations(stations: &[(&str, &Exporter)]) -> Vec<u8> {
+    encode_text(&merge_stations(stations.iter().map(
+        |(station, exporter)| (*station, exporter.gather_with_legacy_names()),
+    )))
+}
+
+/// Renders the fresh metrics of several stations together in the OpenMetrics text format. Unlike
+/// `Exporter::encode_openmetrics` this carries no exemplars, which are kept per station rather
+/// than per series.
+pub fn encode_stations_openmetrics(stations: &[(&str, &Exporter)]) -> Vec<u8> {
+    let families = merge_stations(
+        stations
+            .iter()
+            .map(|(station, exporter)| (*station, exporter.gather())),
+    );
+    openmetrics::encode(&families, &HashMap::new())
+}
+
+/// Health of the MQTT publisher's connection to its broker.
+#[derive(Clone)]
+pub struct PublisherMetrics {
+    /// 1 while connected to the broker, 0 otherwise.
+    pub connected: IntGauge,
+    /// Connections established after the first.
+    pub reconnects: IntCounter,
+    /// When the broker last acknowledged a publish.
+    pub last_publish: Gauge,
+    /// Round trip from a publish being sent to the broker to it being acknowledged.
+    pub publish_latency: Histogram,
+    /// Messages dropped because the publisher's queue was full.
+    pub dropped: IntCounter,
+}
+
+impl PublisherMetrics {
+    /// Metrics that are not exported by any exporter, for a publisher whose health isn't of
+    /// interest.
+    pub fn new() -> Self {
+        let publisher = |name, help| {
+            Opts::new(name, help)
+                .namespace("tempest")
+                .subsystem("exporter")
+        };
+        Self {
+            connected: IntGauge::with_opts(publisher(
+                "publisher_connected",
+                "Whether the MQTT publisher is connected to its broker",
+            ))
+            .unwrap(),
+            reconnects: IntCounter::with_opts(publisher(
+                "publisher_reconnects_total",
+                "Times the MQTT publisher reconnected to its broker",
+            ))
+            .unwrap(),
+            last_publish: Gauge::with_opts(publisher(
+                "publisher_last_publish_timestamp_unix_sec",
+                "When the broker last acknowledged a publish (Unix time)",
+            ))
+            .unwrap(),
+            publish_latency: Histogram::with_opts(
+                HistogramOpts::from(publisher(
+                    "publisher_publish_latency_sec",
+                    "Delay from a publish being sent to the broker to it being acknowledged",
+                ))
+                .buckets(vec![
+                    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+                ]),
+            )
+            .unwrap(),
+            dropped: IntCounter::with_opts(publisher(
+                "publisher_messages_dropped_total",
+                "Messages the MQTT publisher dropped because its queue was full",
+            ))
+            .unwrap(),
+        }
+    }
+}
+
+impl Default for PublisherMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn encode_text(metric_families: &[MetricFamily]) -> Vec<u8> {
+    let mut buffer = vec![];
+    let encoder = TextEncoder::new();
+    encoder.encode(metric_families, &mut buffer).unwrap();
+    buffer
+}
+
+// Merges each station's metric families into one set, adding a `station` label to every series.
+fn merge_stations<'a>(
+    stations: impl Iterator<Item = (&'a str, Vec<MetricFamily>)>,
+) -> Vec<MetricFamily> {
+    let mut merged: BTreeMap<String, MetricFamily> = BTreeMap::new();
+    for (station, families) in stations {
+        for mut family in families {
+            let mut metrics = family.take_metric();
+            for metric in metrics.iter_mut() {
+                let mut label = LabelPair::default();
+                label.set_name("station".to_string());
+                label.set_value(station.to_string());
+                let labels = metric.mut_label();
+                labels.push(label);
+                labels.sort_by(|a, b| a.get_name().cmp(b.get_name()));
+            }
+            let family = merged
+                .entry(family.get_name().to_string())
+                .or_insert(family);
+            for metric in metrics {
+                family.mut_metric().push(metric);
+            }
+        }
+    }
+    merged.into_values().collect()
+}
+
+// Values of a single-label counter vector, keyed by label value.
+fn counter_values(counters: &IntCounterVec) -> BTreeMap<String, u64> {
+    counters
+        .collect()
+        .iter()
+        .flat_map(|family| family.get_metric())
+        .map(|m| {
+            (
+                m.get_label()[0].get_value().to_string(),
+                m.get_counter().get_value() as u64,
+            )
+        })
+        .collect()
+}
+
+// Groups of perishable metrics updated together, each with an age gauge so that staleness can be
+// seen before the group expires and vanishes. Ages come from `ExportedMetrics::last_updates`.
+const AGE_GROUPS: [(&str, &str); 7] = [
+    ("instant_wind", "instantaneous wind"),
+    ("observation_wind", "observed wind"),
+    ("observation_pressure", "observed pressure"),
+    ("observation_temperature", "observed temperature"),
+    ("observation_humidity", "observed humidity"),
+    ("observation_solar", "observed light"),
+    ("observation_precip", "observed precipitation"),
+];
+
+// Window for the mean temperature, as used to reduce station pressure to sea level.
+const TEMPERATURE_MEAN_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+// How far back the station's readings are kept to compare with the reference station's, whose
+// latest observation is often an hour or more old, and how close in time a reading must be to the
+// observation to be compared with it.
+const REFERENCE_WINDOW: Duration = Duration::from_secs(3 * 60 * 60);
+const REFERENCE_MATCH: Duration = Duration::from_secs(5 * 60);
+
 pub struct ExportedMetrics {
     exporter_messages_received: IntCounterVec,
+    exporter_messages_dropped: IntCounterVec,
+    exporter_message_latency: HistogramVec,
+    exporter_sink_queue_depth: IntGaugeVec,
+    exporter_sink_queue_dropped: IntCounterVec,
+    exporter_hub_copies_ignored: IntCounterVec,
+    publisher: Mutex<Option<PublisherMetrics>>,
+
+    // One per group in AGE_GROUPS.
+    perishable_ages: [Gauge; 7],
 
     instant_wind: Perishable<WindMetrics>,
+    instant_wind_samples: IntCounter,
+    instant_wind_interval: Perishable<GaugeVec>,
+    instant_wind_summary: Mutex<WindSpeedSummary>,
+    instant_wind_window: GaugeVec,
+    instant_wind_speeds: Mutex<WindSpeedWindow>,
 
     observation_timestamp: IntGauge,
     observation_wind_lull: Perishable<WindMetrics>,
@@ -65,26 +646,107 @@ pub struct ExportedMetrics {
     observation_station_pressure: Perishable<Gauge>,
     observation_barometric_pressure: Perishable<Gauge>,
     observation_temperature: Perishable<Gauge>,
+    observation_temperature_mean: Perishable<Gauge>,
+    temperature_history: Mutex<Trend>,
     observation_relative_humidity: Perishable<Gauge>,
     observation_dew_point: Perishable<Gauge>,
     observation_wet_bulb_temperature: Perishable<Gauge>,
+    observation_wet_bulb_globe_temperature: Perishable<Gauge>,
     observation_apparent_temperature: Perishable<Gauge>,
+    observation_frost_risk: Perishable<IntGauge>,
+    observation_grass_temperature: Perishable<Gauge>,
+    observation_grass_temperature_min: Perishable<Gauge>,
+    grass_minimum: Mutex<OvernightMinimum>,
+    observation_humid_hours: Perishable<Gauge>,
+    observation_mold_risk: Perishable<IntGauge>,
+    mold_risk: Mutex<MoldRisk>,
+    observation_drought_factor: Perishable<Gauge>,
+    observation_fire_danger: Perishable<Gauge>,
+    fire_danger: Mutex<FireDanger>,
+    observation_leaf_wetness: Perishable<Gauge>,
+    observation_leaf_wet: Perishable<IntGauge>,
+    observation_pressure_change: Perishable<Gauge>,
+    observation_storm_warning: Perishable<IntGauge>,
+    observation_forecast: Perishable<IntGaugeVec>,
+    pressure_trend: Mutex<Trend>,
     observation_illuminance: Perishable<Gauge>,
     observation_irradiance: Perishable<Gauge>,
     observation_uv_index: Perishable<Gauge>,
-    observation_rain: Histogram,
+    observation_clear_sky_index: Perishable<Gauge>,
+    observation_precip_phase: Perishable<IntGaugeVec>,
+    observation_comfort: Perishable<IntGaugeVec>,
+    // User-defined derived metrics by name, with the expression each gauge was created for.
+    observation_derived: Mutex<BTreeMap<String, (String, Perishable<Gauge>)>>,
+    observation_smoothed: Mutex<BTreeMap<&'static str, (Ewma, Perishable<Gauge>)>>,
+    observation_rain: ExemplarHistogram,
+    // One per window in RAIN_RATE_WINDOWS.
+    observation_rain_rate: [Perishable<Gauge>; 2],
+    rain_history: Mutex<Trend>,
+    observation_rain_event_active: Perishable<IntGauge>,
+    observation_rain_event_duration: Perishable<Gauge>,
+    observation_rain_event_total: Perishable<Gauge>,
+    rain_events: Mutex<RainEvents>,
+    station_rain_season_total: Gauge,
+    rain_season: Mutex<RainSeason>,
+    station_daily_rain: GaugeVec,
+    station_time_since_rain: IntGauge,
+    last_rain: Mutex<Option<DateTime<Utc>>>,
+    station_time_since_strike: IntGauge,
+    last_strike: Mutex<Option<DateTime<Utc>>>,
+    observation_gust: ExemplarHistogram,
 
     station_battery_volts: Gauge,
+    station_rssi: ExemplarHistogram,
+    station_hub_rssi: ExemplarHistogram,
+    station_hub_link_rssi: GaugeVec,
     station_sensor_status: IntGaugeVec,
+    station_firmware: IntGaugeVec,
+    station_quality_score: PerishableMap<String, Gauge>,
+    quality: Mutex<Quality>,
+    station_restarts: IntCounter,
+    station_lightning_noise_events: IntCounter,
+    station_lightning_disturber_events: IntCounter,
+    interference: Mutex<InterferenceTracker>,
+    station_debug_enabled: IntGauge,
+    station_debug_messages: IntCounterVec,
+    // Latest debug message of each type.
+    debug_messages: Mutex<BTreeMap<String, serde_json::Value>>,
+    station_events: IntCounterVec,
+    events: Mutex<EventLog>,
+
+    hub_reboots: IntCounterVec,
+    hub_firmware: IntGaugeVec,
+    resets: Mutex<ResetTracker>,
+
+    reference_observation_timestamp: IntGaugeVec,
+    reference_temperature: GaugeVec,
+    reference_temperature_delta: GaugeVec,
+    reference_barometric_pressure: GaugeVec,
+    reference_barometric_pressure_delta: GaugeVec,
+    // The station's readings over the reference window.
+    reference_temperature_readings: Mutex<Trend>,
+    reference_barometric_pressure_readings: Mutex<Trend>,
+
+    // Last firmware revision seen from each hub and device, by serial number.
+    firmware_revisions: Mutex<HashMap<String, String>>,
+
+    // What expiry, ages and windows are timed by.
+    clock: SharedClock,
 }
 
 impl ExportedMetrics {
-    fn new() -> Self {
+    fn new(clock: SharedClock) -> Self {
         let station = |name, help| {
             Opts::new(name, help)
                 .namespace("tempest")
                 .subsystem("station")
         };
+        let hub = |name, help| Opts::new(name, help).namespace("tempest").subsystem("hub");
+        let reference = |name, help| {
+            Opts::new(name, help)
+                .namespace("tempest")
+                .subsystem("reference")
+        };
         let exporter = |name, help| {
             Opts::new(name, help)
                 .namespace("tempest")
@@ -92,36 +754,123 @@ impl ExportedMetrics {
         };
         Self {
             exporter_messages_received: IntCounterVec::new(
-                exporter("messages_received", "API messages received"),
+                exporter("messages_received_total", "API messages received"),
+                &["type"],
+            )
+            .unwrap(),
+            exporter_messages_dropped: IntCounterVec::new(
+                exporter(
+                    "messages_dropped_total",
+                    "API messages dropped before being handled, by pipeline stage and reason",
+                ),
+                &["stage", "reason"],
+            )
+            .unwrap(),
+            exporter_message_latency: HistogramVec::new(
+                HistogramOpts::from(exporter(
+                    "message_latency_sec",
+                    "Delay from a report being made to it being received",
+                ))
+                .buckets(vec![0.5, 1.0, 2.0, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0]),
                 &["type"],
             )
             .unwrap(),
+            exporter_sink_queue_depth: IntGaugeVec::new(
+                exporter(
+                    "sink_queue_depth",
+                    "Messages waiting to be handled by a sink",
+                ),
+                &["sink"],
+            )
+            .unwrap(),
+            exporter_sink_queue_dropped: IntCounterVec::new(
+                exporter(
+                    "sink_queue_dropped_total",
+                    "Messages dropped because a sink's queue was full",
+                ),
+                &["sink"],
+            )
+            .unwrap(),
+            exporter_hub_copies_ignored: IntCounterVec::new(
+                exporter(
+                    "hub_copies_ignored_total",
+                    "Reports ignored as copies relayed by a hub other than the preferred one, by \
+                     hub",
+                ),
+                &["hub_serial_number"],
+            )
+            .unwrap(),
+            publisher: Mutex::new(None),
 
-            instant_wind: Perishable::new(WindMetrics::new("instant_wind", "Instantaneous wind")),
+            perishable_ages: AGE_GROUPS.map(|(group, descr)| {
+                Gauge::with_opts(
+                    Opts::new(
+                        format!("{}_age_sec", group),
+                        format!("Time since {} metrics were last updated (s)", descr),
+                    )
+                    .namespace("tempest")
+                    .subsystem("station"),
+                )
+                .unwrap()
+            }),
+
+            instant_wind: Perishable::new(
+                WindMetrics::new("instant_wind", "Instantaneous wind"),
+                &clock,
+            ),
+            instant_wind_samples: IntCounter::with_opts(station(
+                "instant_wind_samples_total",
+                "Instantaneous wind reports received",
+            ))
+            .unwrap(),
+            instant_wind_interval: Perishable::new(
+                GaugeVec::new(
+                    station(
+                        "instant_wind_interval_speed_magnitude_m_per_s",
+                        "Instantaneous wind speed magnitude over the last observation interval \
+                         (m·s^-1)",
+                    ),
+                    &["stat"],
+                )
+                .unwrap(),
+                &clock,
+            ),
+            instant_wind_summary: Mutex::new(WindSpeedSummary::default()),
+            instant_wind_window: GaugeVec::new(
+                station(
+                    "instant_wind_window_speed_magnitude_m_per_s",
+                    "Instantaneous wind speed magnitude over the window before the scrape \
+                     (m·s^-1)",
+                ),
+                &["stat"],
+            )
+            .unwrap(),
+            instant_wind_speeds: Mutex::new(WindSpeedWindow::default()),
 
             observation_timestamp: IntGauge::with_opts(station(
                 "observation_timestamp_unix_sec",
                 "Current observation Unix timestamp (s)",
             ))
             .unwrap(),
-            observation_wind_lull: Perishable::new(WindMetrics::new(
-                "observation_wind_lull",
-                "3-minute wind lull",
-            )),
-            observation_wind_avg: Perishable::new(WindMetrics::new(
-                "observation_wind_avg",
-                "3-minute wind average",
-            )),
-            observation_wind_gust: Perishable::new(WindMetrics::new(
-                "observation_wind_gust",
-                "3-minute wind gust",
-            )),
+            observation_wind_lull: Perishable::new(
+                WindMetrics::new("observation_wind_lull", "3-minute wind lull"),
+                &clock,
+            ),
+            observation_wind_avg: Perishable::new(
+                WindMetrics::new("observation_wind_avg", "3-minute wind average"),
+                &clock,
+            ),
+            observation_wind_gust: Perishable::new(
+                WindMetrics::new("observation_wind_gust", "3-minute wind gust"),
+                &clock,
+            ),
             observation_station_pressure: Perishable::new(
                 Gauge::with_opts(station(
                     "observation_station_pressure_hpa",
                     "Current station pressure (hPa)",
                 ))
                 .unwrap(),
+                &clock,
             ),
             observation_barometric_pressure: Perishable::new(
                 Gauge::with_opts(station(
@@ -129,6 +878,7 @@ impl ExportedMetrics {
                     "Current barometric pressure, mean sea level (hPa)",
                 ))
                 .unwrap(),
+                &clock,
             ),
             observation_temperature: Perishable::new(
                 Gauge::with_opts(station(
@@ -136,13 +886,24 @@ impl ExportedMetrics {
                     "Current temperature (°C)",
                 ))
                 .unwrap(),
+                &clock,
+            ),
+            observation_temperature_mean: Perishable::new(
+                Gauge::with_opts(station(
+                    "observation_temperature_mean_1h_deg_c",
+                    "Time-weighted mean temperature over the last hour (°C)",
+                ))
+                .unwrap(),
+                &clock,
             ),
+            temperature_history: Mutex::new(Trend::new()),
             observation_relative_humidity: Perishable::new(
                 Gauge::with_opts(station(
                     "observation_relative_humidity_pct",
                     "Current relative humidity (%)",
                 ))
                 .unwrap(),
+                &clock,
             ),
             observation_dew_point: Perishable::new(
                 Gauge::with_opts(station(
@@ -150,6 +911,7 @@ impl ExportedMetrics {
                     "Current dew point (°C)",
                 ))
                 .unwrap(),
+                &clock,
             ),
             observation_wet_bulb_temperature: Perishable::new(
                 Gauge::with_opts(station(
@@ -157,20 +919,137 @@ impl ExportedMetrics {
                     "Current wet bulb temperature (°C)",
                 ))
                 .unwrap(),
+                &clock,
+            ),
+            observation_wet_bulb_globe_temperature: Perishable::new(
+                Gauge::with_opts(station(
+                    "observation_wet_bulb_globe_temperature_deg_c",
+                    "Current estimated wet bulb globe temperature (°C)",
+                ))
+                .unwrap(),
+                &clock,
             ),
             observation_apparent_temperature: Perishable::new(
                 Gauge::with_opts(station(
                     "observation_apparent_temperature_deg_c",
-                    "Current apparent temperature, Steadman formula (°C)",
+                    "Current apparent temperature, by the formula in \
+                     apparent_temperature_formula_info (°C)",
+                ))
+                .unwrap(),
+                &clock,
+            ),
+            observation_frost_risk: Perishable::new(
+                IntGauge::with_opts(station(
+                    "observation_frost_risk",
+                    "Whether exposed surfaces are likely cold enough for frost (boolean)",
+                ))
+                .unwrap(),
+                &clock,
+            ),
+            observation_grass_temperature: Perishable::new(
+                Gauge::with_opts(station(
+                    "observation_grass_temperature_deg_c",
+                    "Estimated temperature of exposed grass and plants, cooling below the air on \
+                     clear calm nights (°C)",
+                ))
+                .unwrap(),
+                &clock,
+            ),
+            observation_grass_temperature_min: Perishable::new(
+                Gauge::with_opts(station(
+                    "observation_grass_temperature_min_deg_c",
+                    "Lowest estimated grass temperature of the latest night (°C)",
+                ))
+                .unwrap(),
+                &clock,
+            ),
+            grass_minimum: Mutex::new(OvernightMinimum::default()),
+            observation_humid_hours: Perishable::new(
+                Gauge::with_opts(station(
+                    "observation_humid_hours_24h",
+                    "Hours in the last day humid enough for mold to grow (h)",
                 ))
                 .unwrap(),
+                &clock,
             ),
+            observation_mold_risk: Perishable::new(
+                IntGauge::with_opts(station(
+                    "observation_mold_risk",
+                    "Mold and condensation risk from recent humidity, from 0 (none) to 3 (high)",
+                ))
+                .unwrap(),
+                &clock,
+            ),
+            mold_risk: Mutex::new(MoldRisk::new()),
+            observation_drought_factor: Perishable::new(
+                Gauge::with_opts(station(
+                    "observation_drought_factor",
+                    "Estimated fuel dryness from rain over the last 20 days, from 0 to 10",
+                ))
+                .unwrap(),
+                &clock,
+            ),
+            observation_fire_danger: Perishable::new(
+                Gauge::with_opts(station(
+                    "observation_fire_danger_index",
+                    "Estimated McArthur forest fire danger index",
+                ))
+                .unwrap(),
+                &clock,
+            ),
+            fire_danger: Mutex::new(FireDanger::new()),
+            observation_leaf_wetness: Perishable::new(
+                Gauge::with_opts(station(
+                    "observation_leaf_wetness",
+                    "Estimated leaf wetness from dew and recent rain, from 0 (dry) to 1 (wet)",
+                ))
+                .unwrap(),
+                &clock,
+            ),
+            observation_leaf_wet: Perishable::new(
+                IntGauge::with_opts(station(
+                    "observation_leaf_wet",
+                    "Whether leaves are likely wet from dew or recent rain (boolean)",
+                ))
+                .unwrap(),
+                &clock,
+            ),
+            observation_pressure_change: Perishable::new(
+                Gauge::with_opts(station(
+                    "observation_pressure_change_hpa",
+                    "Station pressure change over the storm detection window (hPa)",
+                ))
+                .unwrap(),
+                &clock,
+            ),
+            observation_storm_warning: Perishable::new(
+                IntGauge::with_opts(station(
+                    "observation_storm_warning",
+                    "Whether pressure is falling fast enough to signal a storm (boolean)",
+                ))
+                .unwrap(),
+                &clock,
+            ),
+            observation_forecast: Perishable::new(
+                IntGaugeVec::new(
+                    station(
+                        "observation_forecast",
+                        "Zambretti forecast from pressure, its trend, wind and season, by letter \
+                         from A (settled fine) to Z (stormy)",
+                    ),
+                    &["code", "text"],
+                )
+                .unwrap(),
+                &clock,
+            ),
+            pressure_trend: Mutex::new(Trend::new()),
             observation_illuminance: Perishable::new(
                 Gauge::with_opts(station(
                     "observation_illuminance_lux",
                     "Current photometric illuminance (lux)",
                 ))
                 .unwrap(),
+                &clock,
             ),
             observation_irradiance: Perishable::new(
                 Gauge::with_opts(station(
@@ -178,12 +1057,48 @@ impl ExportedMetrics {
                     "Current radiometric irradiance (W·m^-2)",
                 ))
                 .unwrap(),
+                &clock,
             ),
             observation_uv_index: Perishable::new(
                 Gauge::with_opts(station("observation_uv_index", "Current ultraviolet index"))
                     .unwrap(),
+                &clock,
             ),
-            observation_rain: Histogram::with_opts(
+            observation_clear_sky_index: Perishable::new(
+                Gauge::with_opts(station(
+                    "observation_clear_sky_index",
+                    "Current ratio of measured to modelled clear-sky irradiance, a cloudiness \
+                     proxy",
+                ))
+                .unwrap(),
+                &clock,
+            ),
+            observation_precip_phase: Perishable::new(
+                IntGaugeVec::new(
+                    station(
+                        "observation_precip_phase",
+                        "Phase precipitation would likely fall as, estimated from wet bulb \
+                         temperature (boolean)",
+                    ),
+                    &["phase"],
+                )
+                .unwrap(),
+                &clock,
+            ),
+            observation_comfort: Perishable::new(
+                IntGaugeVec::new(
+                    station(
+                        "observation_comfort",
+                        "How humid the air feels, by dew point (boolean)",
+                    ),
+                    &["level"],
+                )
+                .unwrap(),
+                &clock,
+            ),
+            observation_derived: Mutex::new(BTreeMap::new()),
+            observation_smoothed: Mutex::new(BTreeMap::new()),
+            observation_rain: ExemplarHistogram::with_opts(
                 HistogramOpts::from(station("observation_rain", "Rain observed (mm·min^-1)"))
                     .buckets(
                         prometheus::exponential_buckets(1.00, 10.0f64.powf(0.2), 17)
@@ -192,28 +1107,396 @@ impl ExportedMetrics {
                             .map(|v| v.round() / 1000.0)
                             .collect(),
                     ),
+            ),
+            observation_rain_rate: RAIN_RATE_WINDOWS.map(|(suffix, _)| {
+                Perishable::new(
+                    Gauge::with_opts(
+                        Opts::new(
+                            format!("observation_rain_rate_mm_per_h_{}", suffix),
+                            format!("Rain rate over the last {} (mm·h^-1)", suffix),
+                        )
+                        .namespace("tempest")
+                        .subsystem("station"),
+                    )
+                    .unwrap(),
+                    &clock,
+                )
+            }),
+            rain_history: Mutex::new(Trend::new()),
+            observation_rain_event_active: Perishable::new(
+                IntGauge::with_opts(station(
+                    "observation_rain_event_active",
+                    "Whether a rain event is ongoing (boolean)",
+                ))
+                .unwrap(),
+                &clock,
+            ),
+            observation_rain_event_duration: Perishable::new(
+                Gauge::with_opts(station(
+                    "observation_rain_event_duration_sec",
+                    "Duration of the ongoing rain event, or of the last one if none is (s)",
+                ))
+                .unwrap(),
+                &clock,
+            ),
+            observation_rain_event_total: Perishable::new(
+                Gauge::with_opts(station(
+                    "observation_rain_event_total_mm",
+                    "Rain accumulated over the ongoing rain event, or the last one if none is (mm)",
+                ))
+                .unwrap(),
+                &clock,
+            ),
+            rain_events: Mutex::new(RainEvents::new()),
+            station_rain_season_total: Gauge::with_opts(station(
+                "rain_season_total_mm",
+                "Rain since the rain season started, in station local time (mm)",
+            ))
+            .unwrap(),
+            rain_season: Mutex::new(RainSeason::new()),
+            station_daily_rain: GaugeVec::new(
+                station(
+                    "daily_rain_mm",
+                    "Rain yesterday in station local time, as measured (raw) and as corrected by \
+                     WeatherFlow Rain Check (corrected) (mm)",
+                ),
+                &["source"],
             )
             .unwrap(),
+            station_time_since_rain: IntGauge::with_opts(station(
+                "time_since_rain_sec",
+                "Time since rain was last seen, whether starting or in an observation (s)",
+            ))
+            .unwrap(),
+            last_rain: Mutex::new(None),
+            station_time_since_strike: IntGauge::with_opts(station(
+                "time_since_strike_sec",
+                "Time since the latest lightning strike (s)",
+            ))
+            .unwrap(),
+            last_strike: Mutex::new(None),
+            // Upper bounds of Beaufort forces 0 through 11.
+            observation_gust: ExemplarHistogram::with_opts(
+                HistogramOpts::from(station("observation_gust", "Wind gust observed (m·s^-1)"))
+                    .buckets(vec![
+                        0.5, 1.5, 3.3, 5.5, 7.9, 10.7, 13.8, 17.1, 20.7, 24.4, 28.4, 32.6,
+                    ]),
+            ),
 
             station_battery_volts: Gauge::with_opts(station(
                 "status_battery_volts",
                 "Station battery voltage (V)",
             ))
             .unwrap(),
+            // Every status report is sampled, so that a link that only drops out now and then
+            // shows up in the tail rather than being missed between scrapes.
+            station_rssi: ExemplarHistogram::with_opts(
+                HistogramOpts::from(station(
+                    "status_rssi_dbm",
+                    "Radio signal strength at the device (dBm)",
+                ))
+                .buckets(rssi_buckets()),
+            ),
+            station_hub_rssi: ExemplarHistogram::with_opts(
+                HistogramOpts::from(station(
+                    "status_hub_rssi_dbm",
+                    "Radio signal strength of the device at the hub (dBm)",
+                ))
+                .buckets(rssi_buckets()),
+            ),
+            station_hub_link_rssi: GaugeVec::new(
+                station(
+                    "status_hub_link_rssi_dbm",
+                    "Radio signal strength of the device at each hub relaying its reports (dBm)",
+                ),
+                &["hub_serial_number"],
+            )
+            .unwrap(),
             station_sensor_status: IntGaugeVec::new(
                 station("status_sensors", "Station sensor status flags (boolean)"),
                 &["condition"],
             )
             .unwrap(),
+            station_firmware: IntGaugeVec::new(
+                station("status_firmware_info", "Device firmware revision"),
+                &["serial_number", "revision"],
+            )
+            .unwrap(),
+            station_quality_score: PerishableMap::new(&clock),
+            quality: Mutex::new(Quality::new()),
+            station_restarts: IntCounter::with_opts(station(
+                "restarts_total",
+                "Device restarts, seen as uptime going backwards",
+            ))
+            .unwrap(),
+            station_lightning_noise_events: IntCounter::with_opts(station(
+                "lightning_noise_events_total",
+                "Times the lightning sensor started detecting electrical noise",
+            ))
+            .unwrap(),
+            station_lightning_disturber_events: IntCounter::with_opts(station(
+                "lightning_disturber_events_total",
+                "Times the lightning sensor started detecting disturbers, man-made signals \
+                 resembling lightning",
+            ))
+            .unwrap(),
+            interference: Mutex::new(InterferenceTracker::default()),
+            station_debug_enabled: IntGauge::with_opts(station(
+                "status_debug_enabled",
+                "Whether debugging is enabled on the device (boolean)",
+            ))
+            .unwrap(),
+            station_debug_messages: IntCounterVec::new(
+                station(
+                    "debug_messages_total",
+                    "Debug messages received while debugging is enabled, by type",
+                ),
+                &["type"],
+            )
+            .unwrap(),
+            debug_messages: Mutex::new(BTreeMap::new()),
+            station_events: IntCounterVec::new(
+                station(
+                    "events_total",
+                    "Notable device and hub events: firmware changes, sensor failures and \
+                     recoveries, restarts and reboots",
+                ),
+                &["serial_number", "event"],
+            )
+            .unwrap(),
+            events: Mutex::new(EventLog::new()),
+
+            hub_reboots: IntCounterVec::new(
+                hub(
+                    "reboots_total",
+                    "Hub reboots, seen as status sequence or uptime going backwards, by reset \
+                     reason",
+                ),
+                &["reason"],
+            )
+            .unwrap(),
+            hub_firmware: IntGaugeVec::new(
+                hub("firmware_info", "Hub firmware revision"),
+                &["serial_number", "revision"],
+            )
+            .unwrap(),
+            resets: Mutex::new(ResetTracker::default()),
+
+            reference_observation_timestamp: IntGaugeVec::new(
+                reference(
+                    "observation_timestamp_seconds",
+                    "Time of the reference station's latest observation (s)",
+                ),
+                &["station"],
+            )
+            .unwrap(),
+            reference_temperature: GaugeVec::new(
+                reference(
+                    "temperature_deg_c",
+                    "Temperature at the reference station (°C)",
+                ),
+                &["station"],
+            )
+            .unwrap(),
+            reference_temperature_delta: GaugeVec::new(
+                reference(
+                    "temperature_delta_deg_c",
+                    "Temperature here minus at the reference station, as of its latest \
+                     observation (°C)",
+                ),
+                &["station"],
+            )
+            .unwrap(),
+            reference_barometric_pressure: GaugeVec::new(
+                reference(
+                    "barometric_pressure_hpa",
+                    "Barometric pressure at the reference station, mean sea level (hPa)",
+                ),
+                &["station"],
+            )
+            .unwrap(),
+            reference_barometric_pressure_delta: GaugeVec::new(
+                reference(
+                    "barometric_pressure_delta_hpa",
+                    "Barometric pressure here minus at the reference station, as of its latest \
+                     observation (hPa)",
+                ),
+                &["station"],
+            )
+            .unwrap(),
+            reference_temperature_readings: Mutex::new(Trend::new()),
+            reference_barometric_pressure_readings: Mutex::new(Trend::new()),
+
+            firmware_revisions: Mutex::new(HashMap::new()),
+
+            clock,
         }
     }
 
+    // Counts the radio link between the device and the hub that relayed the status report.
+    fn link_quality(&self, ds: &decoder::DeviceStatus) {
+        self.station_rssi
+            .observe(ds.rssi, &ds.serial_number, ds.timestamp);
+        self.station_hub_rssi
+            .observe(ds.hub_rssi, &ds.serial_number, ds.timestamp);
+        self.station_hub_link_rssi
+            .with_label_values(&[&ds.hub_serial_number])
+            .set(ds.hub_rssi);
+    }
+
+    // Summarizes the instantaneous wind within `window` before `now`, leaving the summary out when
+    // none was reported.
+    fn summarize_instant_wind(&self, now: DateTime<Utc>, window: Duration) {
+        self.instant_wind_window.reset();
+        let summary = self
+            .instant_wind_speeds
+            .lock()
+            .unwrap()
+            .summary(now, window);
+        if let Some((min, mean, max)) = summary {
+            self.instant_wind_window
+                .with_label_values(&["min"])
+                .set(min);
+            self.instant_wind_window
+                .with_label_values(&["mean"])
+                .set(mean);
+            self.instant_wind_window
+                .with_label_values(&["max"])
+                .set(max);
+        }
+    }
+
+    // Counts a report received live, and how long after it was made. Device clocks are synced by
+    // the hub, so a report from the future is taken as received without delay.
+    fn received(&self, kind: &str, timestamp: DateTime<Utc>) {
+        self.exporter_messages_received
+            .with_label_values(&[kind])
+            .inc();
+        let latency = (self.clock.utc() - timestamp).to_std().unwrap_or_default();
+        self.exporter_message_latency
+            .with_label_values(&[kind])
+            .observe(latency.as_secs_f64());
+    }
+
+    // Sets a device's quality score, which expires with the device's observations.
+    fn set_quality_score(&self, serial_number: &str, score: f64, valid_duration: Duration) {
+        self.station_quality_score
+            .freshen(serial_number.to_string(), valid_duration, |serial_number| {
+                let opts = Opts::new(
+                    "status_quality_score",
+                    "Device data quality score, from sensor failures, signal strength, battery \
+                     mode and missing fields (0–100)",
+                )
+                .namespace("tempest")
+                .subsystem("station")
+                .const_label("serial_number", serial_number);
+                Gauge::with_opts(opts).unwrap()
+            })
+            .set(score);
+    }
+
+    fn count_events(&self, events: &[Event]) {
+        for event in events {
+            self.station_events
+                .with_label_values(&[&event.serial_number, event.kind.name()])
+                .inc();
+        }
+    }
+
+    // Sets the info metric for the revision, removing the one for any revision it replaces.
+    fn set_firmware(&self, info: &IntGaugeVec, serial_number: &str, revision: &str) {
+        let previous = self
+            .firmware_revisions
+            .lock()
+            .unwrap()
+            .insert(serial_number.to_string(), revision.to_string());
+        if let Some(previous) = previous.filter(|previous| previous != revision) {
+            info.remove_label_values(&[serial_number, &previous]).ok();
+        }
+        info.with_label_values(&[serial_number, revision]).set(1);
+    }
+
+    fn histograms(&self) -> [&ExemplarHistogram; 4] {
+        [
+            &self.observation_rain,
+            &self.observation_gust,
+            &self.station_rssi,
+            &self.station_hub_rssi,
+        ]
+    }
+
+    // When each group in AGE_GROUPS was last updated and when it expires, going by a metric in
+    // the group that every update freshens.
+    fn freshness(&self) -> [(Option<Instant>, Instant); 7] {
+        fn of<T>(perishable: &Perishable<T>) -> (Option<Instant>, Instant) {
+            (perishable.last_update(), perishable.expires_at())
+        }
+        [
+            of(&self.instant_wind),
+            of(&self.observation_wind_avg),
+            of(&self.observation_station_pressure),
+            of(&self.observation_temperature),
+            of(&self.observation_relative_humidity),
+            of(&self.observation_illuminance),
+            of(&self.observation_rain_rate[0]),
+        ]
+    }
+
     fn register_all(&self, registry: &mut Registry) {
         registry
             .register(Box::new(self.exporter_messages_received.clone()))
             .unwrap();
+        registry
+            .register(Box::new(self.exporter_messages_dropped.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(self.exporter_message_latency.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(self.exporter_sink_queue_depth.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(self.exporter_sink_queue_dropped.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(self.exporter_hub_copies_ignored.clone()))
+            .unwrap();
+        if let Some(publisher) = &*self.publisher.lock().unwrap() {
+            registry
+                .register(Box::new(publisher.connected.clone()))
+                .unwrap();
+            registry
+                .register(Box::new(publisher.reconnects.clone()))
+                .unwrap();
+            registry
+                .register(Box::new(publisher.last_publish.clone()))
+                .unwrap();
+            registry
+                .register(Box::new(publisher.publish_latency.clone()))
+                .unwrap();
+            registry
+                .register(Box::new(publisher.dropped.clone()))
+                .unwrap();
+        }
+
+        // Exported even once the group has expired, but not before it was ever updated.
+        let now = self.clock.now();
+        for (gauge, (last_update, _)) in self.perishable_ages.iter().zip(self.freshness()) {
+            if let Some(last_update) = last_update {
+                gauge.set(now.duration_since(last_update).as_secs_f64());
+                registry.register(Box::new(gauge.clone())).unwrap();
+            }
+        }
 
         self.instant_wind.map(|m| m.register_all(registry));
+        registry
+            .register(Box::new(self.instant_wind_samples.clone()))
+            .unwrap();
+        self.instant_wind_interval
+            .map(|m| registry.register(Box::new(m.clone())).unwrap());
+        registry
+            .register(Box::new(self.instant_wind_window.clone()))
+            .unwrap();
 
         registry
             .register(Box::new(self.observation_timestamp.clone()))
@@ -227,155 +1510,612 @@ impl ExportedMetrics {
             .map(|m| registry.register(Box::new(m.clone())).unwrap());
         self.observation_temperature
             .map(|m| registry.register(Box::new(m.clone())).unwrap());
+        self.observation_temperature_mean
+            .map(|m| registry.register(Box::new(m.clone())).unwrap());
         self.observation_relative_humidity
             .map(|m| registry.register(Box::new(m.clone())).unwrap());
         self.observation_dew_point
             .map(|m| registry.register(Box::new(m.clone())).unwrap());
         self.observation_wet_bulb_temperature
             .map(|m| registry.register(Box::new(m.clone())).unwrap());
+        self.observation_wet_bulb_globe_temperature
+            .map(|m| registry.register(Box::new(m.clone())).unwrap());
         self.observation_apparent_temperature
             .map(|m| registry.register(Box::new(m.clone())).unwrap());
+        self.observation_frost_risk
+            .map(|m| registry.register(Box::new(m.clone())).unwrap());
+        self.observation_grass_temperature
+            .map(|m| registry.register(Box::new(m.clone())).unwrap());
+        self.observation_grass_temperature_min
+            .map(|m| registry.register(Box::new(m.clone())).unwrap());
+        self.observation_humid_hours
+            .map(|m| registry.register(Box::new(m.clone())).unwrap());
+        self.observation_mold_risk
+            .map(|m| registry.register(Box::new(m.clone())).unwrap());
+        self.observation_drought_factor
+            .map(|m| registry.register(Box::new(m.clone())).unwrap());
+        self.observation_fire_danger
+            .map(|m| registry.register(Box::new(m.clone())).unwrap());
+        self.observation_leaf_wetness
+            .map(|m| registry.register(Box::new(m.clone())).unwrap());
+        self.observation_leaf_wet
+            .map(|m| registry.register(Box::new(m.clone())).unwrap());
+        self.observation_pressure_change
+            .map(|m| registry.register(Box::new(m.clone())).unwrap());
+        self.observation_storm_warning
+            .map(|m| registry.register(Box::new(m.clone())).unwrap());
+        self.observation_forecast
+            .map(|m| registry.register(Box::new(m.clone())).unwrap());
         self.observation_illuminance
             .map(|m| registry.register(Box::new(m.clone())).unwrap());
         self.observation_irradiance
             .map(|m| registry.register(Box::new(m.clone())).unwrap());
         self.observation_uv_index
             .map(|m| registry.register(Box::new(m.clone())).unwrap());
+        self.observation_clear_sky_index
+            .map(|m| registry.register(Box::new(m.clone())).unwrap());
+        self.observation_precip_phase
+            .map(|m| registry.register(Box::new(m.clone())).unwrap());
+        self.observation_comfort
+            .map(|m| registry.register(Box::new(m.clone())).unwrap());
+        for (_, gauge) in self.observation_derived.lock().unwrap().values() {
+            gauge.map(|m| registry.register(Box::new(m.clone())).unwrap());
+        }
+        for (_, gauge) in self.observation_smoothed.lock().unwrap().values() {
+            gauge.map(|m| registry.register(Box::new(m.clone())).unwrap());
+        }
+        self.observation_rain.register(registry);
+        for gauge in &self.observation_rain_rate {
+            gauge.map(|m| registry.register(Box::new(m.clone())).unwrap());
+        }
+        self.observation_rain_event_active
+            .map(|m| registry.register(Box::new(m.clone())).unwrap());
+        self.observation_rain_event_duration
+            .map(|m| registry.register(Box::new(m.clone())).unwrap());
+        self.observation_rain_event_total
+            .map(|m| registry.register(Box::new(m.clone())).unwrap());
         registry
-            .register(Box::new(self.observation_rain.clone()))
+            .register(Box::new(self.station_daily_rain.clone()))
             .unwrap();
+        // Elapsed as of the scrape, and only once there has been rain or a strike to time from.
+        for (latest, gauge) in [
+            (&self.last_rain, &self.station_time_since_rain),
+            (&self.last_strike, &self.station_time_since_strike),
+        ] {
+            if let Some(at) = *latest.lock().unwrap() {
+                gauge.set((self.clock.utc() - at).num_seconds().max(0));
+                registry.register(Box::new(gauge.clone())).unwrap();
+            }
+        }
+        self.observation_gust.register(registry);
 
         registry
             .register(Box::new(self.station_battery_volts.clone()))
             .unwrap();
+        self.station_rssi.register(registry);
+        self.station_hub_rssi.register(registry);
+        registry
+            .register(Box::new(self.station_hub_link_rssi.clone()))
+            .unwrap();
         registry
             .register(Box::new(self.station_sensor_status.clone()))
             .unwrap();
+        registry
+            .register(Box::new(self.station_firmware.clone()))
+            .unwrap();
+        for gauge in self.station_quality_score.fresh() {
+            registry.register(Box::new(gauge)).unwrap();
+        }
+        registry
+            .register(Box::new(self.station_restarts.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(self.station_lightning_noise_events.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(self.station_lightning_disturber_events.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(self.station_debug_enabled.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(self.station_debug_messages.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(self.station_events.clone()))
+            .unwrap();
+
+        registry
+            .register(Box::new(self.hub_reboots.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(self.hub_firmware.clone()))
+            .unwrap();
+
+        for gauges in [
+            &self.reference_temperature,
+            &self.reference_temperature_delta,
+            &self.reference_barometric_pressure,
+            &self.reference_barometric_pressure_delta,
+        ] {
+            registry.register(Box::new(gauges.clone())).unwrap();
+        }
+        registry
+            .register(Box::new(self.reference_observation_timestamp.clone()))
+            .unwrap();
+    }
+}
+
+// Records the time of an event, unless a later one has already been recorded.
+fn record_latest(latest: &Mutex<Option<DateTime<Utc>>>, at: DateTime<Utc>) {
+    let mut latest = latest.lock().unwrap();
+    if !matches!(*latest, Some(previous) if previous >= at) {
+        *latest = Some(at);
     }
 }
 
+// From a barely usable link to a strong one, in 10 dB steps.
+fn rssi_buckets() -> Vec<f64> {
+    prometheus::linear_buckets(-100.0, 10.0, 7).unwrap()
+}
+
 trait ExportTo {
-    fn export_to(&self, metrics: &ExportedMetrics, station_params: &StationParams);
+    fn export_to(
+        &self,
+        metrics: &ExportedMetrics,
+        station_params: &StationParams,
+        exporter_params: &ExporterParams,
+    );
+
+    // Counts the report in cumulative metrics, which a restored report must not count again.
+    fn accumulate(
+        &self,
+        metrics: &ExportedMetrics,
+        station_params: &StationParams,
+        exporter_params: &ExporterParams,
+    );
 }
 
 impl ExportTo for decoder::PrecipEvent {
-    fn export_to(&self, metrics: &ExportedMetrics, _station_params: &StationParams) {
-        metrics
-            .exporter_messages_received
-            .with_label_values(&["precip_event"])
-            .inc();
+    fn export_to(
+        &self,
+        metrics: &ExportedMetrics,
+        _station_params: &StationParams,
+        _exporter_params: &ExporterParams,
+    ) {
+        record_latest(&metrics.last_rain, self.timestamp);
+    }
+
+    fn accumulate(
+        &self,
+        metrics: &ExportedMetrics,
+        _station_params: &StationParams,
+        _exporter_params: &ExporterParams,
+    ) {
+        metrics.received("precip_event", self.timestamp);
     }
 }
 
 impl ExportTo for decoder::StrikeEvent {
-    fn export_to(&self, metrics: &ExportedMetrics, _station_params: &StationParams) {
-        metrics
-            .exporter_messages_received
-            .with_label_values(&["strike_event"])
-            .inc();
+    fn export_to(
+        &self,
+        metrics: &ExportedMetrics,
+        _station_params: &StationParams,
+        _exporter_params: &ExporterParams,
+    ) {
+        record_latest(&metrics.last_strike, self.timestamp);
+    }
+
+    fn accumulate(
+        &self,
+        metrics: &ExportedMetrics,
+        _station_params: &StationParams,
+        _exporter_params: &ExporterParams,
+    ) {
+        metrics.received("strike_event", self.timestamp);
     }
 }
 
 impl ExportTo for decoder::RapidWind {
-    fn export_to(&self, metrics: &ExportedMetrics, _station_params: &StationParams) {
-        metrics
-            .exporter_messages_received
-            .with_label_values(&["instant_wind"])
-            .inc();
+    fn export_to(
+        &self,
+        metrics: &ExportedMetrics,
+        _station_params: &StationParams,
+        exporter_params: &ExporterParams,
+    ) {
         metrics
             .instant_wind
-            .freshen(INSTANT_WIND_VALID)
+            .freshen(exporter_params.instant_wind_ttl)
             .export(&self.wind);
+        metrics.instant_wind_speeds.lock().unwrap().push(
+            self.timestamp,
+            self.wind.speed_magnitude().0,
+            exporter_params.instant_wind_window,
+        );
+    }
+    fn accumulate(
+        &self,
+        metrics: &ExportedMetrics,
+        _station_params: &StationParams,
+        _exporter_params: &ExporterParams,
+    ) {
+        metrics.received("instant_wind", self.timestamp);
+        metrics.instant_wind_samples.inc();
+        metrics
+            .instant_wind_summary
+            .lock()
+            .unwrap()
+            .add(self.wind.speed_magnitude().0);
     }
 }
 
 impl ExportTo for decoder::Observation {
-    fn export_to(&self, metrics: &ExportedMetrics, station_params: &StationParams) {
-        metrics
-            .exporter_messages_received
-            .with_label_values(&["observation"])
-            .inc();
+    fn export_to(
+        &self,
+        metrics: &ExportedMetrics,
+        station_params: &StationParams,
+        exporter_params: &ExporterParams,
+    ) {
         metrics
             .observation_timestamp
             .set(self.timestamp.timestamp());
+        // Only live rapid wind is summarized, so a restored observation finds nothing to close.
+        if let Some((min, mean, max)) = metrics.instant_wind_summary.lock().unwrap().take() {
+            let interval = metrics
+                .instant_wind_interval
+                .freshen(exporter_params.observation_ttl);
+            interval.with_label_values(&["min"]).set(min);
+            interval.with_label_values(&["mean"]).set(mean);
+            interval.with_label_values(&["max"]).set(max);
+        }
         if let Some(wind) = &self.wind {
             metrics
                 .observation_wind_lull
-                .freshen(OBS_VALID)
+                .freshen(exporter_params.observation_ttl)
                 .export(&wind.lull);
             metrics
                 .observation_wind_avg
-                .freshen(OBS_VALID)
+                .freshen(exporter_params.observation_ttl)
                 .export(&wind.avg);
             metrics
                 .observation_wind_gust
-                .freshen(OBS_VALID)
+                .freshen(exporter_params.observation_ttl)
                 .export(&wind.gust);
         }
         self.station_pressure.map(|v| {
             metrics
                 .observation_station_pressure
-                .freshen(OBS_VALID)
-                .set(v)
+                .freshen(exporter_params.observation_ttl)
+                .set(v.0)
         });
-        self.barometric_pressure(station_params.elevation).map(|v| {
+        if let Some(pressure) = self.barometric_pressure(station_params.elevation) {
             metrics
                 .observation_barometric_pressure
-                .freshen(OBS_VALID)
-                .set(v)
+                .freshen(exporter_params.observation_ttl)
+                .set(pressure.0);
+            metrics
+                .reference_barometric_pressure_readings
+                .lock()
+                .unwrap()
+                .push(self.timestamp, pressure.0, REFERENCE_WINDOW);
+        }
+        self.air_temperature.map(|v| {
+            metrics
+                .observation_temperature
+                .freshen(exporter_params.observation_ttl)
+                .set(v.0)
         });
-        self.air_temperature
-            .map(|v| metrics.observation_temperature.freshen(OBS_VALID).set(v));
+        if let Some(temperature) = self.air_temperature {
+            let mut history = metrics.temperature_history.lock().unwrap();
+            history.push(self.timestamp, temperature.0, TEMPERATURE_MEAN_WINDOW);
+            metrics.reference_temperature_readings.lock().unwrap().push(
+                self.timestamp,
+                temperature.0,
+                REFERENCE_WINDOW,
+            );
+            if let Some(mean) = history.time_weighted_mean(TEMPERATURE_MEAN_WINDOW) {
+                metrics
+                    .observation_temperature_mean
+                    .freshen(exporter_params.observation_ttl)
+                    .set(mean);
+            }
+        }
         self.relative_humidity.map(|v| {
             metrics
                 .observation_relative_humidity
-                .freshen(OBS_VALID)
+                .freshen(exporter_params.observation_ttl)
                 .set(v)
         });
-        self.dew_point()
-            .map(|v| metrics.observation_dew_point.freshen(OBS_VALID).set(v));
+        self.dew_point().map(|v| {
+            metrics
+                .observation_dew_point
+                .freshen(exporter_params.observation_ttl)
+                .set(v.0)
+        });
         self.wet_bulb_temperature().map(|v| {
             metrics
                 .observation_wet_bulb_temperature
-                .freshen(OBS_VALID)
-                .set(v)
+                .freshen(exporter_params.observation_ttl)
+                .set(v.0)
         });
-        self.apparent_temperature().map(|v| {
+        self.wet_bulb_globe_temperature().map(|v| {
             metrics
-                .observation_apparent_temperature
-                .freshen(OBS_VALID)
-                .set(v)
+                .observation_wet_bulb_globe_temperature
+                .freshen(exporter_params.observation_ttl)
+                .set(v.0)
+        });
+        self.apparent_temperature_by(station_params.apparent_temperature_formula)
+            .map(|v| {
+                metrics
+                    .observation_apparent_temperature
+                    .freshen(exporter_params.observation_ttl)
+                    .set(v.0)
+            });
+        self.frost_risk().map(|v| {
+            metrics
+                .observation_frost_risk
+                .freshen(exporter_params.observation_ttl)
+                .set(v as i64)
         });
+        if let Some(grass) = self.surface_temperature().map(f64::from) {
+            metrics
+                .observation_grass_temperature
+                .freshen(exporter_params.observation_ttl)
+                .set(grass);
+            let minimum = metrics
+                .grass_minimum
+                .lock()
+                .unwrap()
+                .push(grass, self.sunlit());
+            if let Some(minimum) = minimum {
+                metrics
+                    .observation_grass_temperature_min
+                    .freshen(exporter_params.observation_ttl)
+                    .set(minimum);
+            }
+        }
+        if let (Some(t), Some(rh)) = (self.air_temperature, self.relative_humidity) {
+            let mut mold_risk = metrics.mold_risk.lock().unwrap();
+            mold_risk.push(self.timestamp, t.0, rh);
+            metrics
+                .observation_humid_hours
+                .freshen(exporter_params.observation_ttl)
+                .set(mold_risk.humid_hours());
+            metrics
+                .observation_mold_risk
+                .freshen(exporter_params.observation_ttl)
+                .set(mold_risk.level() as i64);
+        }
+        if let Some(precip) = &self.precip {
+            let mut fire_danger = metrics.fire_danger.lock().unwrap();
+            fire_danger.push(self.timestamp, precip.quantity_last_minute.0);
+            let drought_factor = fire_danger.drought_factor();
+            metrics
+                .observation_drought_factor
+                .freshen(exporter_params.observation_ttl)
+                .set(drought_factor);
+            if let Some(index) = fire::for_observation(self, drought_factor) {
+                metrics
+                    .observation_fire_danger
+                    .freshen(exporter_params.observation_ttl)
+                    .set(index);
+            }
+        }
+        if let Some(pressure) = self.station_pressure {
+            let storm = &exporter_params.storm;
+            let mut trend = metrics.pressure_trend.lock().unwrap();
+            trend.push(self.timestamp, pressure.0, storm.window);
+            if let Some(change) = trend.change(storm.window) {
+                metrics
+                    .observation_pressure_change
+                    .freshen(exporter_params.observation_ttl)
+                    .set(change);
+                metrics
+                    .observation_storm_warning
+                    .freshen(exporter_params.observation_ttl)
+                    .set(storm.signalled_by(change) as i64);
+                if let Some(forecast) =
+                    forecast::for_observation(self, station_params, change, storm.window)
+                {
+                    let gauges = metrics
+                        .observation_forecast
+                        .freshen(exporter_params.observation_ttl);
+                    gauges.reset();
+                    gauges
+                        .with_label_values(&[&forecast.code.to_string(), forecast.text])
+                        .set(1);
+                }
+            }
+        }
+        if let Some(phase) = self.estimated_precip_phase() {
+            let gauges = metrics
+                .observation_precip_phase
+                .freshen(exporter_params.observation_ttl);
+            for candidate in [
+                decoder::PrecipPhase::Rain,
+                decoder::PrecipPhase::Mixed,
+                decoder::PrecipPhase::Snow,
+            ] {
+                gauges
+                    .with_label_values(&[candidate.as_str()])
+                    .set((candidate == phase) as i64);
+            }
+        }
+        if let Some(comfort) = self.comfort() {
+            let gauges = metrics
+                .observation_comfort
+                .freshen(exporter_params.observation_ttl);
+            for candidate in [
+                decoder::Comfort::Comfortable,
+                decoder::Comfort::Humid,
+                decoder::Comfort::Oppressive,
+            ] {
+                gauges
+                    .with_label_values(&[candidate.as_str()])
+                    .set((candidate == comfort) as i64);
+            }
+        }
+        if let Some(precip) = &self.precip {
+            let (_, longest) = RAIN_RATE_WINDOWS[RAIN_RATE_WINDOWS.len() - 1];
+            let mut history = metrics.rain_history.lock().unwrap();
+            history.push(self.timestamp, precip.quantity_last_minute.0, longest);
+            for ((_, window), gauge) in RAIN_RATE_WINDOWS.iter().zip(&metrics.observation_rain_rate)
+            {
+                gauge
+                    .freshen(exporter_params.observation_ttl)
+                    .set(history.sum(*window) * 3600.0 / window.as_secs_f64());
+            }
+        }
+        if let Some(precip) = &self.precip {
+            if precip.quantity_last_minute.0 > 0.0 {
+                record_latest(&metrics.last_rain, self.timestamp);
+            }
+            let mut events = metrics.rain_events.lock().unwrap();
+            events.observe(
+                self.timestamp,
+                self.report_interval,
+                precip.quantity_last_minute.0,
+                exporter_params.rain_event_dry_period,
+            );
+            metrics
+                .observation_rain_event_active
+                .freshen(exporter_params.observation_ttl)
+                .set(events.current().is_some() as i64);
+            if let Some(event) = events.latest() {
+                metrics
+                    .observation_rain_event_duration
+                    .freshen(exporter_params.observation_ttl)
+                    .set(event.duration().num_seconds() as f64);
+                metrics
+                    .observation_rain_event_total
+                    .freshen(exporter_params.observation_ttl)
+                    .set(event.total);
+            }
+        }
+        if let Some(wetness) = leaf::for_observation(self, *metrics.last_rain.lock().unwrap()) {
+            metrics
+                .observation_leaf_wetness
+                .freshen(exporter_params.observation_ttl)
+                .set(wetness);
+            metrics
+                .observation_leaf_wet
+                .freshen(exporter_params.observation_ttl)
+                .set((wetness >= leaf::WET_SCORE) as i64);
+        }
         if let Some(solar) = &self.solar {
             metrics
                 .observation_illuminance
-                .freshen(OBS_VALID)
+                .freshen(exporter_params.observation_ttl)
                 .set(solar.illuminance);
             metrics
                 .observation_irradiance
-                .freshen(OBS_VALID)
+                .freshen(exporter_params.observation_ttl)
                 .set(solar.irradiance);
             metrics
                 .observation_uv_index
-                .freshen(OBS_VALID)
+                .freshen(exporter_params.observation_ttl)
                 .set(solar.ultraviolet_index);
         }
-        if let Some(precip) = &self.precip {
-            metrics
-                .observation_rain
-                .observe(precip.quantity_last_minute);
+        station_params
+            .metadata
+            .location()
+            .and_then(|(latitude, longitude)| self.clear_sky_index(latitude, longitude))
+            .map(|v| {
+                metrics
+                    .observation_clear_sky_index
+                    .freshen(exporter_params.observation_ttl)
+                    .set(v)
+            });
+        let mut derived_gauges = metrics.observation_derived.lock().unwrap();
+        for derived in &station_params.derived {
+            let value = match derived.evaluate(self, station_params) {
+                Some(value) => value,
+                None => continue,
+            };
+            // A reload may have changed the expression, and with it the help text.
+            let stale = !matches!(
+                derived_gauges.get(&derived.name),
+                Some((source, _)) if *source == derived.source
+            );
+            if stale {
+                let opts = Opts::new(
+                    format!("derived_{}", derived.name),
+                    format!("Derived from the observation as {}", derived.source),
+                )
+                .namespace("tempest")
+                .subsystem("station");
+                let gauge = Perishable::new(Gauge::with_opts(opts).unwrap(), &metrics.clock);
+                derived_gauges.insert(derived.name.clone(), (derived.source.clone(), gauge));
+            }
+            derived_gauges[&derived.name]
+                .1
+                .freshen(exporter_params.observation_ttl)
+                .set(value);
+        }
+        let mut smoothed_gauges = metrics.observation_smoothed.lock().unwrap();
+        for smoothing in &exporter_params.smoothing {
+            let value = match smoothing.variable.value(self, station_params) {
+                Some(value) => value,
+                None => continue,
+            };
+            let name = smoothing.variable.name();
+            let (ewma, gauge) = smoothed_gauges.entry(name).or_insert_with(|| {
+                let opts = Opts::new(
+                    format!("smoothed_{}", name),
+                    format!("Observed {} with exponential smoothing", name),
+                )
+                .namespace("tempest")
+                .subsystem("station");
+                (
+                    Ewma::new(self.timestamp, value),
+                    Perishable::new(Gauge::with_opts(opts).unwrap(), &metrics.clock),
+                )
+            });
+            gauge
+                .freshen(exporter_params.observation_ttl)
+                .set(ewma.update(self.timestamp, value, smoothing.time_constant));
         }
-
         metrics.station_battery_volts.set(self.battery_volts);
+        let score = metrics.quality.lock().unwrap().update_observation(self);
+        metrics.set_quality_score(&self.serial_number, score, exporter_params.observation_ttl);
+    }
+    fn accumulate(
+        &self,
+        metrics: &ExportedMetrics,
+        station_params: &StationParams,
+        exporter_params: &ExporterParams,
+    ) {
+        metrics.received("observation", self.timestamp);
+        if let Some(wind) = &self.wind {
+            metrics.observation_gust.observe(
+                wind.gust.speed_magnitude().0,
+                &self.serial_number,
+                self.timestamp,
+            );
+        }
+        if let Some(precip) = &self.precip {
+            metrics.observation_rain.observe(
+                precip.quantity_last_minute.0,
+                &self.serial_number,
+                self.timestamp,
+            );
+            metrics.rain_season.lock().unwrap().observe(
+                station_params.metadata.day_zone(),
+                exporter_params.rain_season_start,
+                self.timestamp,
+                precip.quantity_last_minute.0,
+            );
+        }
     }
 }
 
 impl ExportTo for decoder::DeviceStatus {
-    fn export_to(&self, metrics: &ExportedMetrics, _station_params: &StationParams) {
-        metrics
-            .exporter_messages_received
-            .with_label_values(&["device_status"])
-            .inc();
+    fn export_to(
+        &self,
+        metrics: &ExportedMetrics,
+        _station_params: &StationParams,
+        exporter_params: &ExporterParams,
+    ) {
         let sss = &metrics.station_sensor_status;
         sss.with_label_values(&["lightning_failure"])
             .set(self.sensor_status.lightning_failure as i64);
@@ -399,14 +2139,105 @@ impl ExportTo for decoder::DeviceStatus {
             .set(self.sensor_status.power_booster_depleted as i64);
         sss.with_label_values(&["power_booster_shore_power"])
             .set(self.sensor_status.power_booster_shore_power as i64);
+        metrics.station_debug_enabled.set(self.debug as i64);
+        metrics.set_firmware(
+            &metrics.station_firmware,
+            &self.serial_number,
+            &self.firmware_revision.to_string(),
+        );
+        let score = metrics.quality.lock().unwrap().update_status(self);
+        metrics.set_quality_score(&self.serial_number, score, exporter_params.observation_ttl);
+    }
+    fn accumulate(
+        &self,
+        metrics: &ExportedMetrics,
+        _station_params: &StationParams,
+        _exporter_params: &ExporterParams,
+    ) {
+        metrics.received("device_status", self.timestamp);
+        metrics.link_quality(self);
+        let restarted = metrics.resets.lock().unwrap().device_restarted(self);
+        if restarted {
+            metrics.station_restarts.inc();
+        }
+        let events = metrics
+            .events
+            .lock()
+            .unwrap()
+            .device_status(self, restarted);
+        metrics.count_events(&events);
+        let (noise, disturber) = metrics.interference.lock().unwrap().detected(self);
+        if noise {
+            metrics.station_lightning_noise_events.inc();
+        }
+        if disturber {
+            metrics.station_lightning_disturber_events.inc();
+        }
     }
 }
 
 impl ExportTo for decoder::HubStatus {
-    fn export_to(&self, metrics: &ExportedMetrics, _station_params: &StationParams) {
+    fn export_to(
+        &self,
+        metrics: &ExportedMetrics,
+        _station_params: &StationParams,
+        _exporter_params: &ExporterParams,
+    ) {
+        metrics.set_firmware(
+            &metrics.hub_firmware,
+            &self.serial_number,
+            &self.firmware_revision,
+        );
+    }
+
+    fn accumulate(
+        &self,
+        metrics: &ExportedMetrics,
+        _station_params: &StationParams,
+        _exporter_params: &ExporterParams,
+    ) {
+        metrics.received("hub_status", self.timestamp);
+        let reboot_reason = metrics
+            .resets
+            .lock()
+            .unwrap()
+            .hub_rebooted(self)
+            .then(|| resets::reboot_reason(&self.reset_flags));
+        if let Some(reason) = reboot_reason {
+            metrics.hub_reboots.with_label_values(&[reason]).inc();
+        }
+        let events = metrics
+            .events
+            .lock()
+            .unwrap()
+            .hub_status(self, reboot_reason);
+        metrics.count_events(&events);
+    }
+}
+
+impl ExportTo for decoder::DebugMessage {
+    fn export_to(
+        &self,
+        metrics: &ExportedMetrics,
+        _station_params: &StationParams,
+        _exporter_params: &ExporterParams,
+    ) {
+        metrics
+            .debug_messages
+            .lock()
+            .unwrap()
+            .insert(self.kind.clone(), serde_json::to_value(self).unwrap());
+    }
+
+    fn accumulate(
+        &self,
+        metrics: &ExportedMetrics,
+        _station_params: &StationParams,
+        _exporter_params: &ExporterParams,
+    ) {
         metrics
-            .exporter_messages_received
-            .with_label_values(&["hub_status"])
+            .station_debug_messages
+            .with_label_values(&[&self.kind])
             .inc();
     }
 }
@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+
+use prometheus::proto::MetricFamily;
+
+use crate::params::NameMode;
+
+// Metrics that have been renamed, as (current name, legacy name). During a deprecation window both
+// names are exposed by default, so dashboards keep working across an upgrade; each metric's
+// `NameMode` selects otherwise. Entries are removed once the window is over.
+pub const RENAMES: &[(&str, &str)] = &[
+    // Counters gained the conventional `_total` suffix.
+    (
+        "tempest_exporter_messages_received_total",
+        "tempest_exporter_messages_received",
+    ),
+    (
+        "tempest_exporter_sink_queue_dropped_total",
+        "tempest_exporter_sink_queue_dropped",
+    ),
+];
+
+/// The current name of a renamed metric, given either its current or its legacy name.
+pub fn current_name(name: &str) -> Option<&'static str> {
+    RENAMES
+        .iter()
+        .find(|(current, legacy)| *current == name || *legacy == name)
+        .map(|(current, _)| *current)
+}
+
+// Exposes renamed metric families under their current name, legacy name or both, as configured.
+pub fn apply(families: Vec<MetricFamily>, modes: &HashMap<String, NameMode>) -> Vec<MetricFamily> {
+    let mut out = Vec::with_capacity(families.len());
+    for family in families {
+        let legacy_name = match RENAMES
+            .iter()
+            .find(|(current, _)| *current == family.get_name())
+        {
+            Some((_, legacy_name)) => *legacy_name,
+            None => {
+                out.push(family);
+                continue;
+            }
+        };
+        let mode = modes.get(family.get_name()).copied().unwrap_or_default();
+        if mode != NameMode::Current {
+            let mut legacy = family.clone();
+            legacy.set_help(format!(
+                "{} (deprecated, renamed {})",
+                family.get_help(),
+                family.get_name()
+            ));
+            legacy.set_name(legacy_name.to_string());
+            out.push(legacy);
+        }
+        if mode != NameMode::Legacy {
+            out.push(family);
+        }
+    }
+    out.sort_by(|a, b| a.get_name().cmp(b.get_name()));
+    out
+}
@@ -0,0 +1,117 @@
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use prometheus::core::{Collector, Metric};
+use prometheus::proto::MetricFamily;
+use prometheus::{Histogram, HistogramOpts, Registry};
+use tracing::warn;
+
+use crate::checkpoint::HistogramState;
+
+// The latest observation that fell into a histogram bucket, identifying the report it came from.
+#[derive(Clone, Debug)]
+pub struct Exemplar {
+    pub serial_number: String,
+    pub value: f64,
+    pub timestamp: DateTime<Utc>,
+}
+
+// A histogram that also remembers an exemplar per bucket (the last being +Inf), which only the
+// OpenMetrics exposition can carry.
+pub struct ExemplarHistogram {
+    histogram: Histogram,
+    upper_bounds: Vec<f64>,
+    exemplars: Mutex<Vec<Option<Exemplar>>>,
+    // Counts restored from a checkpoint, added to the live histogram when it is gathered.
+    baseline: Mutex<HistogramState>,
+}
+
+impl ExemplarHistogram {
+    pub fn with_opts(opts: HistogramOpts) -> Self {
+        let upper_bounds = opts.buckets.clone();
+        Self {
+            exemplars: Mutex::new(vec![None; upper_bounds.len() + 1]),
+            histogram: Histogram::with_opts(opts).unwrap(),
+            baseline: Mutex::new(HistogramState {
+                upper_bounds: upper_bounds.clone(),
+                bucket_counts: vec![0; upper_bounds.len()],
+                ..HistogramState::default()
+            }),
+            upper_bounds,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        self.histogram.desc()[0].fq_name.clone()
+    }
+
+    // Zero observations are counted but never become exemplars, as they mark nothing of interest.
+    pub fn observe(&self, value: f64, serial_number: &str, timestamp: DateTime<Utc>) {
+        self.histogram.observe(value);
+        if value == 0.0 {
+            return;
+        }
+        let bucket = self
+            .upper_bounds
+            .iter()
+            .position(|&upper_bound| value <= upper_bound)
+            .unwrap_or(self.upper_bounds.len());
+        self.exemplars.lock().unwrap()[bucket] = Some(Exemplar {
+            serial_number: serial_number.to_string(),
+            value,
+            timestamp,
+        });
+    }
+
+    pub fn exemplars(&self) -> Vec<Option<Exemplar>> {
+        self.exemplars.lock().unwrap().clone()
+    }
+
+    pub fn state(&self) -> HistogramState {
+        let live = self.histogram.metric();
+        let live = live.get_histogram();
+        let baseline = self.baseline.lock().unwrap();
+        HistogramState {
+            upper_bounds: self.upper_bounds.clone(),
+            bucket_counts: live
+                .get_bucket()
+                .iter()
+                .zip(&baseline.bucket_counts)
+                .map(|(bucket, base)| bucket.get_cumulative_count() + base)
+                .collect(),
+            sample_count: live.get_sample_count() + baseline.sample_count,
+            sample_sum: live.get_sample_sum() + baseline.sample_sum,
+        }
+    }
+
+    // Adds restored counts, unless the buckets have changed since the checkpoint was taken.
+    pub fn restore(&self, state: &HistogramState) {
+        if state.upper_bounds != self.upper_bounds
+            || state.bucket_counts.len() != self.upper_bounds.len()
+        {
+            warn!("Buckets of {} changed, not restoring it", self.name());
+            return;
+        }
+        *self.baseline.lock().unwrap() = state.clone();
+    }
+
+    pub fn add_baseline(&self, family: &mut MetricFamily) {
+        let baseline = self.baseline.lock().unwrap();
+        for metric in family.mut_metric().iter_mut() {
+            let histogram = metric.mut_histogram();
+            for (bucket, base) in histogram
+                .mut_bucket()
+                .iter_mut()
+                .zip(&baseline.bucket_counts)
+            {
+                bucket.set_cumulative_count(bucket.get_cumulative_count() + base);
+            }
+            histogram.set_sample_count(histogram.get_sample_count() + baseline.sample_count);
+            histogram.set_sample_sum(histogram.get_sample_sum() + baseline.sample_sum);
+        }
+    }
+
+    pub fn register(&self, registry: &mut Registry) {
+        registry.register(Box::new(self.histogram.clone())).unwrap();
+    }
+}
@@ -0,0 +1,30 @@
+use std::collections::HashMap;
+
+use crate::decoder::DeviceStatus;
+
+// Spots the lightning sensor detecting electrical noise or a disturber (a man-made signal
+// resembling lightning), from its status flags going from clear to set between consecutive
+// status reports from the same device. The flags only stay set while the interference lasts.
+#[derive(Default)]
+pub struct InterferenceTracker {
+    devices: HashMap<String, (bool, bool)>,
+}
+
+impl InterferenceTracker {
+    // Records the status and returns whether noise and a disturber were newly detected since the
+    // previous one. Flags set in the first status seen from a device count as new.
+    pub fn detected(&mut self, ds: &DeviceStatus) -> (bool, bool) {
+        let status = &ds.sensor_status;
+        let (noise, disturber) = self
+            .devices
+            .insert(
+                ds.serial_number.clone(),
+                (status.lightning_noise, status.lightning_disturber),
+            )
+            .unwrap_or_default();
+        (
+            status.lightning_noise && !noise,
+            status.lightning_disturber && !disturber,
+        )
+    }
+}
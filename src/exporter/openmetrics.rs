@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use prometheus::proto::{LabelPair, MetricFamily, MetricType};
+
+use super::exemplars::Exemplar;
+
+// Renders gathered metric families in the OpenMetrics text format, attaching exemplars to
+// histogram buckets. Exemplars are keyed by histogram name, one slot per bucket including +Inf.
+pub fn encode(
+    families: &[MetricFamily],
+    exemplars: &HashMap<String, Vec<Option<Exemplar>>>,
+) -> Vec<u8> {
+    let mut out = String::new();
+    for family in families {
+        let name = family.get_name();
+        match family.get_field_type() {
+            MetricType::COUNTER => {
+                let name = name.strip_suffix("_total").unwrap_or(name);
+                write_descriptor(&mut out, name, "counter", family.get_help());
+                for metric in family.get_metric() {
+                    write_sample(
+                        &mut out,
+                        &format!("{}_total", name),
+                        metric.get_label(),
+                        None,
+                        metric.get_counter().get_value(),
+                    );
+                    out.push('\n');
+                }
+            }
+            MetricType::GAUGE => {
+                write_descriptor(&mut out, name, "gauge", family.get_help());
+                for metric in family.get_metric() {
+                    write_sample(
+                        &mut out,
+                        name,
+                        metric.get_label(),
+                        None,
+                        metric.get_gauge().get_value(),
+                    );
+                    out.push('\n');
+                }
+            }
+            MetricType::HISTOGRAM => {
+                write_descriptor(&mut out, name, "histogram", family.get_help());
+                let no_exemplars = vec![];
+                let exemplars = exemplars.get(name).unwrap_or(&no_exemplars);
+                for metric in family.get_metric() {
+                    let histogram = metric.get_histogram();
+                    let buckets = histogram
+                        .get_bucket()
+                        .iter()
+                        .map(|b| (b.get_upper_bound(), b.get_cumulative_count()))
+                        .chain(std::iter::once((
+                            f64::INFINITY,
+                            histogram.get_sample_count(),
+                        )));
+                    for (i, (upper_bound, count)) in buckets.enumerate() {
+                        write_sample(
+                            &mut out,
+                            &format!("{}_bucket", name),
+                            metric.get_label(),
+                            Some(upper_bound),
+                            count as f64,
+                        );
+                        if let Some(Some(exemplar)) = exemplars.get(i) {
+                            write!(
+                                out,
+                                " # {{serial_number=\"{}\"}} {} {}",
+                                escape_label_value(&exemplar.serial_number),
+                                format_value(exemplar.value),
+                                exemplar.timestamp.timestamp()
+                            )
+                            .unwrap();
+                        }
+                        out.push('\n');
+                    }
+                    let sum_name = format!("{}_sum", name);
+                    write_sample(
+                        &mut out,
+                        &sum_name,
+                        metric.get_label(),
+                        None,
+                        histogram.get_sample_sum(),
+                    );
+                    out.push('\n');
+                    let count_name = format!("{}_count", name);
+                    let count = histogram.get_sample_count() as f64;
+                    write_sample(&mut out, &count_name, metric.get_label(), None, count);
+                    out.push('\n');
+                }
+            }
+            // Not used by this exporter.
+            _ => {}
+        }
+    }
+    out.push_str("# EOF\n");
+    out.into_bytes()
+}
+
+fn write_descriptor(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+    writeln!(out, "# HELP {} {}", name, escape_label_value(help)).unwrap();
+}
+
+fn write_sample(out: &mut String, name: &str, labels: &[LabelPair], le: Option<f64>, value: f64) {
+    out.push_str(name);
+    let mut pairs: Vec<String> = labels
+        .iter()
+        .map(|l| format!("{}=\"{}\"", l.get_name(), escape_label_value(l.get_value())))
+        .collect();
+    if let Some(le) = le {
+        pairs.push(format!("le=\"{}\"", format_value(le)));
+    }
+    if !pairs.is_empty() {
+        write!(out, "{{{}}}", pairs.join(",")).unwrap();
+    }
+    write!(out, " {}", format_value(value)).unwrap();
+}
+
+fn format_value(value: f64) -> String {
+    if value == f64::INFINITY {
+        "+Inf".to_string()
+    } else if value == f64::NEG_INFINITY {
+        "-Inf".to_string()
+    } else if value.is_nan() {
+        "NaN".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
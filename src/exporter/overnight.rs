@@ -0,0 +1,23 @@
+// Lowest reading of the night so far, or once the sun is up, of the night before. Nights are told
+// by the lack of sunshine rather than the clock, as that is what lets surfaces cool.
+#[derive(Default)]
+pub struct OvernightMinimum {
+    minimum: Option<f64>,
+    night: bool,
+}
+
+impl OvernightMinimum {
+    // Records a reading, taken in sunshine or not, and returns the minimum.
+    pub fn push(&mut self, value: f64, sunlit: bool) -> Option<f64> {
+        if sunlit {
+            self.night = false;
+        } else {
+            if !self.night {
+                self.night = true;
+                self.minimum = None;
+            }
+            self.minimum = Some(self.minimum.map_or(value, |minimum| minimum.min(value)));
+        }
+        self.minimum
+    }
+}
@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+
+use chrono::Duration;
+
+use crate::decoder::{DeviceStatus, HubStatus, ResetFlags};
+
+// Spots hub reboots and device restarts from discontinuities between consecutive status reports
+// from the same hub or device.
+#[derive(Default)]
+pub struct ResetTracker {
+    hubs: HashMap<String, (i32, Duration)>,
+    devices: HashMap<String, Duration>,
+}
+
+impl ResetTracker {
+    // Records the status and returns whether the hub rebooted since its previous one: its status
+    // sequence number or uptime went backwards.
+    pub fn hub_rebooted(&mut self, hs: &HubStatus) -> bool {
+        match self
+            .hubs
+            .insert(hs.serial_number.clone(), (hs.seq, hs.uptime))
+        {
+            Some((seq, uptime)) => hs.seq < seq || hs.uptime < uptime,
+            None => false,
+        }
+    }
+
+    // Records the status and returns whether the device restarted since its previous one: its
+    // uptime went backwards.
+    pub fn device_restarted(&mut self, ds: &DeviceStatus) -> bool {
+        match self.devices.insert(ds.serial_number.clone(), ds.uptime) {
+            Some(uptime) => ds.uptime < uptime,
+            None => false,
+        }
+    }
+}
+
+// The single most telling reason among the reset flags, so that each reboot is counted once.
+// Faults come first, then power problems, then deliberate resets; a power-on flag accompanies most
+// of the others.
+pub fn reboot_reason(flags: &ResetFlags) -> &'static str {
+    [
+        (flags.hard_fault, "hard_fault"),
+        (flags.watchdog, "watchdog"),
+        (flags.window_watchdog, "window_watchdog"),
+        (flags.low_power, "low_power"),
+        (flags.brownout, "brownout"),
+        (flags.software, "software"),
+        (flags.pin, "pin"),
+        (flags.power_on, "power_on"),
+    ]
+    .iter()
+    .find(|(set, _)| *set)
+    .map_or("unknown", |(_, reason)| *reason)
+}
@@ -1,3 +1,7 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
 use prometheus::{Gauge, Opts, Registry};
 
 use crate::decoder;
@@ -56,10 +60,82 @@ impl WindMetrics {
     }
 
     pub fn export(&self, wind: &decoder::Wind) {
-        self.speed_magnitude.set(wind.speed_magnitude());
+        self.speed_magnitude.set(wind.speed_magnitude().0);
         self.source_direction.set(wind.source_direction());
         let (north, east) = wind.component_velocity();
         self.component_velocity_north.set(north);
         self.component_velocity_east.set(east);
     }
 }
+
+// Instantaneous wind speeds seen since the last observation, so that scrapes much slower than the
+// ~3 s rapid wind reports still see how gusty the interval was.
+#[derive(Default)]
+pub struct WindSpeedSummary {
+    count: u32,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+impl WindSpeedSummary {
+    pub fn add(&mut self, speed: f64) {
+        if self.count == 0 {
+            self.min = speed;
+            self.max = speed;
+        } else {
+            self.min = self.min.min(speed);
+            self.max = self.max.max(speed);
+        }
+        self.count += 1;
+        self.sum += speed;
+    }
+
+    // Minimum, mean and maximum speed over the interval, starting a new one. `None` if no rapid
+    // wind was reported in it.
+    pub fn take(&mut self) -> Option<(f64, f64, f64)> {
+        let summary = std::mem::take(self);
+        (summary.count > 0).then(|| {
+            (
+                summary.min,
+                summary.sum / f64::from(summary.count),
+                summary.max,
+            )
+        })
+    }
+}
+
+// Instantaneous wind speeds over a sliding window, summarized when metrics are gathered so that
+// scrapes see the gusts between them, however far apart they are.
+#[derive(Default)]
+pub struct WindSpeedWindow {
+    speeds: VecDeque<(DateTime<Utc>, f64)>,
+}
+
+impl WindSpeedWindow {
+    pub fn push(&mut self, timestamp: DateTime<Utc>, speed: f64, window: Duration) {
+        if matches!(self.speeds.back(), Some((latest, _)) if *latest > timestamp) {
+            return;
+        }
+        self.speeds.push_back((timestamp, speed));
+        self.expire(timestamp, window);
+    }
+
+    // Minimum, mean and maximum speed within `window` before `now`. `None` if no rapid wind was
+    // reported in it.
+    pub fn summary(&mut self, now: DateTime<Utc>, window: Duration) -> Option<(f64, f64, f64)> {
+        self.expire(now, window);
+        let mut summary = WindSpeedSummary::default();
+        for (_, speed) in &self.speeds {
+            summary.add(*speed);
+        }
+        summary.take()
+    }
+
+    fn expire(&mut self, now: DateTime<Utc>, window: Duration) {
+        let cutoff = now - chrono::Duration::from_std(window).unwrap();
+        while matches!(self.speeds.front(), Some((at, _)) if *at <= cutoff) {
+            self.speeds.pop_front();
+        }
+    }
+}
@@ -0,0 +1,57 @@
+//! Selective publishing: include and exclude lists deciding which MQTT topics and Prometheus
+//! metrics are published, applied in one place so that every output drops the same fields.
+//! Outputs without topics of their own (KNX, Modbus, Signal K, NMEA, Ecowitt and Grafana Live)
+//! name each field by the MQTT topic it is published under, so the same patterns cover them.
+
+/// Glob patterns matched against both MQTT topics (below the prefix, such as
+/// `observation/solar/illuminance_lux`) and Prometheus metric names (such as
+/// `tempest_observation_illuminance_lux`), where `*` matches any run of characters. A pattern like
+/// `*illuminance*` thus covers a field in both.
+///
+/// A name is published if it matches an include pattern, or there are none, and matches no
+/// exclude pattern.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PublishFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl PublishFilter {
+    pub fn new(include: Vec<String>, exclude: Vec<String>) -> Self {
+        Self { include, exclude }
+    }
+
+    /// Whether the topic or metric `name` is published.
+    pub fn allows(&self, name: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|p| glob_match(p, name)))
+            && !self.excludes(name)
+    }
+
+    /// Whether an exclude pattern suppresses `name`. Topics that an include list doesn't apply
+    /// to are published unless this says otherwise.
+    pub fn excludes(&self, name: &str) -> bool {
+        self.exclude.iter().any(|p| glob_match(p, name))
+    }
+}
+
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let mut pieces = pattern.split('*');
+    // Without a `*`, the pattern is the whole name.
+    let first = pieces.next().unwrap_or("");
+    let mut rest = match name.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let pieces: Vec<_> = pieces.collect();
+    let (last, middle) = match pieces.split_last() {
+        Some(split) => split,
+        None => return rest.is_empty(),
+    };
+    for piece in middle {
+        match rest.find(piece) {
+            Some(at) => rest = &rest[at + piece.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
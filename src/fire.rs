@@ -0,0 +1,106 @@
+//! Fire danger, as the McArthur Mark 5 Forest Fire Danger Index (FFDI) estimated from the
+//! station's own readings, for local awareness in wildfire-prone areas.
+//!
+//! The index needs a drought factor for how dry the fuel is, officially derived from a soil
+//! dryness index kept over months. Here it comes from rain over the last 20 days by Griffiths'
+//! formula, with the soil taken to be dry, so it errs high after a wet season and until 20 days of
+//! rain have been seen. Wind is measured nearer the ground than the 10 m the index expects, so it
+//! errs low there. Neither makes it an official rating.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::decoder::Observation;
+use crate::units::{Celsius, MetersPerSecond};
+
+/// Period of rain the drought factor considers.
+pub const RAIN_WINDOW: Duration = Duration::from_secs(20 * 24 * 3600);
+
+// Rain within a day (mm) from which it counts as a rain event, and is discounted by.
+const EVENT_RAIN: f64 = 2.0;
+
+/// Tracks rain over the last 20 days for the drought factor.
+#[derive(Debug, Default, Serialize)]
+pub struct FireDanger {
+    rain: VecDeque<(DateTime<Utc>, f64)>,
+    latest: Option<DateTime<Utc>>,
+}
+
+impl FireDanger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an observation's rain over the previous minute (mm), forgetting rain older than the
+    /// window before it. Observations are expected in time order; one older than the latest is
+    /// ignored.
+    pub fn push(&mut self, timestamp: DateTime<Utc>, rain: f64) {
+        if matches!(self.latest, Some(latest) if latest >= timestamp) {
+            return;
+        }
+        self.latest = Some(timestamp);
+        if rain > 0.0 {
+            self.rain.push_back((timestamp, rain));
+        }
+        let cutoff = timestamp - chrono::Duration::from_std(RAIN_WINDOW).unwrap();
+        while matches!(self.rain.front(), Some((at, _)) if *at <= cutoff) {
+            self.rain.pop_front();
+        }
+    }
+
+    /// Drought factor from 0 (fuel soaked) to 10 (fuel fully dry), limited by the day of rain in
+    /// the window that wets the fuel the most for how long ago it fell.
+    pub fn drought_factor(&self) -> f64 {
+        let latest = match self.latest {
+            Some(latest) => latest,
+            None => return 10.0,
+        };
+        let mut days = [0.0; 20];
+        for (at, rain) in &self.rain {
+            if let Some(total) = days.get_mut((latest - *at).num_days() as usize) {
+                *total += rain;
+            }
+        }
+        let x = days
+            .iter()
+            .enumerate()
+            .filter(|(_, rain)| **rain > EVENT_RAIN)
+            .map(|(days_ago, rain)| {
+                // Rain within the last day counts as having fallen most of a day ago.
+                let age = (days_ago as f64).max(0.8).powf(1.3);
+                age / (age + rain - EVENT_RAIN)
+            })
+            .fold(1.0, f64::min);
+        10.0 * (41.0 * x * x + x) / (40.0 * x * x + x + 1.0)
+    }
+}
+
+/// FFDI from temperature, relative humidity (%), average wind speed and drought factor:
+/// below 12 is low to moderate, then high, very high from 25, severe from 50, extreme from 75 and
+/// catastrophic from 100.
+pub fn ffdi(
+    temperature: Celsius,
+    relative_humidity: f64,
+    wind_speed: MetersPerSecond,
+    drought_factor: f64,
+) -> f64 {
+    let wind_km_per_h = wind_speed.kilometers_per_hour();
+    2.0 * (-0.45 + 0.987 * drought_factor.ln() - 0.0345 * relative_humidity
+        + 0.0338 * temperature.0
+        + 0.0234 * wind_km_per_h)
+        .exp()
+}
+
+/// FFDI for an observation, given the drought factor. `None` if it lacks temperature, humidity or
+/// wind.
+pub fn for_observation(obs: &Observation, drought_factor: f64) -> Option<f64> {
+    Some(ffdi(
+        obs.air_temperature?,
+        obs.relative_humidity?,
+        obs.wind.as_ref()?.avg.speed_magnitude(),
+        drought_factor,
+    ))
+}
@@ -0,0 +1,153 @@
+//! Short-term local forecast from sea level pressure and its trend, after Negretti & Zambra's
+//! Zambretti forecaster.
+
+use std::time::Duration;
+
+use chrono::Datelike;
+
+use crate::decoder::Observation;
+use crate::params::StationParams;
+use crate::units::HectoPascal;
+
+/// Window pressure tendency is judged over, as on the original instrument.
+pub const TENDENCY_WINDOW: Duration = Duration::from_secs(3 * 3600);
+
+// Change over the tendency window (hPa) beyond which pressure counts as rising or falling.
+const TENDENCY_THRESHOLD: f64 = 1.6;
+
+const FORECASTS: [&str; 26] = [
+    "Settled fine",
+    "Fine weather",
+    "Becoming fine",
+    "Fine, becoming less settled",
+    "Fine, possible showers",
+    "Fairly fine, improving",
+    "Fairly fine, possible showers early",
+    "Fairly fine, showery later",
+    "Showery early, improving",
+    "Changeable, mending",
+    "Fairly fine, showers likely",
+    "Rather unsettled, clearing later",
+    "Unsettled, probably improving",
+    "Showery, bright intervals",
+    "Showery, becoming less settled",
+    "Changeable, some rain",
+    "Unsettled, short fine intervals",
+    "Unsettled, rain later",
+    "Unsettled, some rain",
+    "Mostly very unsettled",
+    "Occasional rain, worsening",
+    "Rain at times, very unsettled",
+    "Rain at frequent intervals",
+    "Rain, very unsettled",
+    "Stormy, may improve",
+    "Stormy, much rain",
+];
+
+// Forecast letters for each tendency, in order of falling adjusted pressure.
+const FALLING: &[u8] = b"ABDHORUXZ";
+const STEADY: &[u8] = b"ABEKNPSWXZ";
+const RISING: &[u8] = b"ABCFGIJLMQTYZ";
+
+// Pressure adjustment (hPa) for the wind's source direction in the northern hemisphere, by
+// 16-point compass sector starting from north.
+const WIND_ADJUSTMENT: [f64; 16] = [
+    6.0, 5.0, 5.0, 2.0, -0.5, -2.0, -5.0, -8.5, -12.0, -10.0, -6.0, -4.5, -3.0, -0.5, 1.5, 3.0,
+];
+
+// Pressure adjustment (hPa) for rising pressure in summer, and falling pressure in winter.
+const SEASON_ADJUSTMENT: f64 = 7.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PressureTendency {
+    Falling,
+    Steady,
+    Rising,
+}
+
+impl PressureTendency {
+    /// Tendency from the change in pressure over [`TENDENCY_WINDOW`] (hPa).
+    pub fn from_change(change: f64) -> Self {
+        if change <= -TENDENCY_THRESHOLD {
+            PressureTendency::Falling
+        } else if change >= TENDENCY_THRESHOLD {
+            PressureTendency::Rising
+        } else {
+            PressureTendency::Steady
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Forecast {
+    /// Letter of the forecast on the Zambretti dial, from A (settled fine) to Z (stormy).
+    pub code: char,
+    pub text: &'static str,
+}
+
+/// Forecasts from sea level pressure, its tendency, the wind's source direction (degrees,
+/// `None` when calm), the month (1 to 12) and the hemisphere.
+pub fn zambretti(
+    pressure: HectoPascal,
+    tendency: PressureTendency,
+    wind_direction: Option<f64>,
+    month: u32,
+    southern_hemisphere: bool,
+) -> Forecast {
+    let mut pressure = pressure.0;
+    if let Some(direction) = wind_direction {
+        // South of the equator, winds from the pole are southerly.
+        let direction = if southern_hemisphere {
+            direction + 180.0
+        } else {
+            direction
+        };
+        let sector = (direction.rem_euclid(360.0) / 22.5).round() as usize % 16;
+        pressure += WIND_ADJUSTMENT[sector];
+    }
+    let summer = (4..=9).contains(&month) != southern_hemisphere;
+    match tendency {
+        PressureTendency::Rising if summer => pressure += SEASON_ADJUSTMENT,
+        PressureTendency::Falling if !summer => pressure -= SEASON_ADJUSTMENT,
+        _ => {}
+    }
+    // Zambretti number, counted from the first letter for the tendency.
+    let (z, codes) = match tendency {
+        PressureTendency::Falling => (127.0 - 0.12 * pressure - 1.0, FALLING),
+        PressureTendency::Steady => (144.0 - 0.13 * pressure - 10.0, STEADY),
+        PressureTendency::Rising => (185.0 - 0.16 * pressure - 20.0, RISING),
+    };
+    let index = (z.round().max(0.0) as usize).min(codes.len() - 1);
+    let code = codes[index] as char;
+    Forecast {
+        code,
+        text: FORECASTS[(codes[index] - b'A') as usize],
+    }
+}
+
+/// Forecasts from an observation, given the change in station pressure over `window` (hPa). The
+/// station is taken to be in the northern hemisphere unless its latitude says otherwise. `None`
+/// if the observation lacks pressure.
+pub fn for_observation(
+    obs: &Observation,
+    station_params: &StationParams,
+    pressure_change: f64,
+    window: Duration,
+) -> Option<Forecast> {
+    let pressure = obs.barometric_pressure(station_params.elevation)?;
+    let change = pressure_change * TENDENCY_WINDOW.as_secs_f64() / window.as_secs_f64();
+    let wind_direction = obs
+        .wind
+        .as_ref()
+        .filter(|wind| wind.avg.speed_magnitude().0 > 0.0)
+        .map(|wind| wind.avg.source_direction());
+    let southern_hemisphere =
+        matches!(station_params.metadata.latitude, Some(latitude) if latitude < 0.0);
+    Some(zambretti(
+        pressure,
+        PressureTendency::from_change(change),
+        wind_direction,
+        obs.timestamp.month(),
+        southern_hemisphere,
+    ))
+}
@@ -0,0 +1,206 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use tracing::{info, warn};
+
+use crate::config::{ExporterParams, Shared};
+use crate::decoder::TempestMsg;
+use crate::publisher::{INSTANT_WIND_TOPICS, WIND_AVG_TOPICS, WIND_GUST_TOPICS, WIND_LULL_TOPICS};
+use crate::sink_queue::LastError;
+use tempest_exporter::filter::PublishFilter;
+
+#[derive(Clone, PartialEq)]
+pub struct GrafanaLiveParams {
+    // Push endpoint of the stream, whose measurements become channels
+    // stream/<stream>/<measurement>.
+    pub push_url: reqwest::Url,
+    // Service account or API token allowed to push.
+    pub token: String,
+}
+
+// Keeps the token out of logs and `check-config` output.
+impl std::fmt::Debug for GrafanaLiveParams {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GrafanaLiveParams")
+            .field("push_url", &self.push_url)
+            .field("token", &"<redacted>")
+            .finish()
+    }
+}
+
+// One line of InfluxDB line protocol, leaving out missing fields and those the filter drops by
+// the MQTT topic given with each. `None` if all are left out.
+fn line(
+    measurement: &str,
+    fields: &[(&str, &str, Option<f64>)],
+    timestamp: DateTime<Utc>,
+    filter: &PublishFilter,
+) -> Option<String> {
+    let fields: Vec<_> = fields
+        .iter()
+        .filter(|(_, topic, _)| filter.allows(topic))
+        .filter_map(|(name, _, value)| value.map(|value| format!("{}={}", name, value)))
+        .collect();
+    if fields.is_empty() {
+        return None;
+    }
+    let nanos = timestamp.timestamp() * 1_000_000_000 + timestamp.timestamp_subsec_nanos() as i64;
+    Some(format!("{} {} {}", measurement, fields.join(","), nanos))
+}
+
+// Measurements for a report: rapid wind as it arrives every few seconds, and observations.
+fn lines(msg: &TempestMsg, filter: &PublishFilter) -> Option<String> {
+    match msg {
+        TempestMsg::RapidWind(rw) => line(
+            "rapid_wind",
+            &[
+                (
+                    "speed_m_per_s",
+                    INSTANT_WIND_TOPICS.speed_magnitude,
+                    Some(rw.wind.speed_magnitude().0),
+                ),
+                (
+                    "direction_deg",
+                    INSTANT_WIND_TOPICS.source_direction,
+                    Some(rw.wind.source_direction()),
+                ),
+            ],
+            rw.timestamp,
+            filter,
+        ),
+        TempestMsg::Observation(obs) => {
+            let wind = obs.wind.as_ref();
+            let solar = obs.solar.as_ref();
+            line(
+                "observation",
+                &[
+                    (
+                        "temperature_deg_c",
+                        "observation/thermal/temperature_deg_c",
+                        obs.air_temperature.map(f64::from),
+                    ),
+                    (
+                        "relative_humidity_pct",
+                        "observation/thermal/relative_humidity_pct",
+                        obs.relative_humidity,
+                    ),
+                    (
+                        "dew_point_deg_c",
+                        "observation/thermal/dew_point_deg_c",
+                        obs.dew_point().map(f64::from),
+                    ),
+                    (
+                        "station_pressure_hpa",
+                        "observation/pressure/station_hpa",
+                        obs.station_pressure.map(f64::from),
+                    ),
+                    (
+                        "wind_lull_m_per_s",
+                        WIND_LULL_TOPICS.speed_magnitude,
+                        wind.map(|w| w.lull.speed_magnitude().0),
+                    ),
+                    (
+                        "wind_avg_m_per_s",
+                        WIND_AVG_TOPICS.speed_magnitude,
+                        wind.map(|w| w.avg.speed_magnitude().0),
+                    ),
+                    (
+                        "wind_gust_m_per_s",
+                        WIND_GUST_TOPICS.speed_magnitude,
+                        wind.map(|w| w.gust.speed_magnitude().0),
+                    ),
+                    (
+                        "wind_direction_deg",
+                        WIND_AVG_TOPICS.source_direction,
+                        wind.map(|w| w.avg.source_direction()),
+                    ),
+                    (
+                        "illuminance_lux",
+                        "observation/solar/illuminance_lux",
+                        solar.map(|s| s.illuminance),
+                    ),
+                    (
+                        "irradiance_w_per_m2",
+                        "observation/solar/irradiance_w_per_m2",
+                        solar.map(|s| s.irradiance),
+                    ),
+                    (
+                        "uv_index",
+                        "observation/solar/uv_index",
+                        solar.map(|s| s.ultraviolet_index),
+                    ),
+                    (
+                        "rain_mm",
+                        "observation/precip/previous_minute_rain_mm",
+                        obs.precip.as_ref().map(|p| p.quantity_last_minute.0),
+                    ),
+                    (
+                        "lightning_strike_count",
+                        "event/lightning",
+                        obs.lightning.as_ref().map(|l| l.count as f64),
+                    ),
+                ],
+                obs.timestamp,
+                filter,
+            )
+        }
+        _ => None,
+    }
+}
+
+// Pushes rapid wind and observations to Grafana Live over HTTP, for streaming panels that update
+// as reports arrive rather than at each scrape.
+pub struct GrafanaLiveSink {
+    exporter_params: Shared<ExporterParams>,
+    params: Mutex<GrafanaLiveParams>,
+    http: reqwest::Client,
+    errors: Arc<LastError>,
+}
+
+impl GrafanaLiveSink {
+    pub fn new(exporter_params: Shared<ExporterParams>, params: GrafanaLiveParams) -> Self {
+        Self {
+            exporter_params,
+            params: Mutex::new(params),
+            http: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap(),
+            errors: Arc::new(LastError::default()),
+        }
+    }
+
+    // Where push errors are recorded, for `/debug/state`.
+    pub fn errors(&self) -> Arc<LastError> {
+        self.errors.clone()
+    }
+
+    pub fn reconfigure(&self, new_params: GrafanaLiveParams) {
+        let mut params = self.params.lock().unwrap();
+        if *params != new_params {
+            info!("Grafana Live parameters changed");
+            *params = new_params;
+        }
+    }
+
+    pub fn handle_report(&self, msg: &TempestMsg) {
+        let body = match lines(msg, &self.exporter_params.read().unwrap().publish_filter) {
+            Some(body) => body,
+            None => return,
+        };
+        let params = self.params.lock().unwrap();
+        let request = self
+            .http
+            .post(params.push_url.clone())
+            .bearer_auth(&params.token)
+            .body(body);
+        let errors = self.errors.clone();
+        tokio::spawn(async move {
+            if let Err(e) = request.send().await.and_then(|r| r.error_for_status()) {
+                warn!("Grafana Live push failed: {}", e);
+                errors.record(format!("Push failed: {}", e));
+            }
+        });
+    }
+}
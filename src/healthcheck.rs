@@ -0,0 +1,31 @@
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+
+use crate::config::Config;
+
+// Probes the running exporter's health endpoint over loopback and fails unless it answers OK, so
+// that container healthchecks and exec probes don't need an HTTP client in the image.
+pub async fn run(config: Config, ready: bool, timeout: Duration) -> anyhow::Result<()> {
+    let mut url = format!("http://127.0.0.1:{}", config.metrics_port);
+    for segment in &config.route_prefix {
+        url.push('/');
+        url.push_str(segment);
+    }
+    url.push_str(if ready { "/readyz" } else { "/healthz" });
+
+    let response = reqwest::Client::builder()
+        .timeout(timeout)
+        .build()?
+        .get(&url)
+        .send()
+        .await
+        .with_context(|| format!("Probing {}", url))?;
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    if !status.is_success() {
+        bail!("{} answered {}: {}", url, status, body.trim());
+    }
+    println!("{}", body.trim());
+    Ok(())
+}
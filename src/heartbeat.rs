@@ -0,0 +1,19 @@
+use crossbeam_utils::atomic::AtomicCell;
+use std::time::{Duration, Instant};
+
+// Records when the message pump last made progress, so liveness can be judged from outside it.
+pub struct Heartbeat(AtomicCell<Instant>);
+
+impl Heartbeat {
+    pub fn new() -> Self {
+        Heartbeat(AtomicCell::new(Instant::now()))
+    }
+
+    pub fn beat(&self) {
+        self.0.store(Instant::now());
+    }
+
+    pub fn since_last(&self) -> Duration {
+        self.0.load().elapsed()
+    }
+}
@@ -0,0 +1,172 @@
+//! Recent observations at one-minute resolution, kept in memory so that simple web frontends can
+//! draw sparklines of the last day or two without a time series database.
+
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::time::Duration;
+
+use chrono::{DateTime, DurationRound, Utc};
+use serde::Serialize;
+
+use crate::decoder::Observation;
+
+/// Period of observations kept unless configured otherwise.
+pub const DEFAULT_RETENTION: Duration = Duration::from_secs(24 * 3600);
+
+/// Longest period of observations that may be kept.
+pub const MAX_RETENTION: Duration = Duration::from_secs(48 * 3600);
+
+/// One minute of observations: the latest readings within it, and the rain that fell in it.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Sample {
+    /// Start of the minute.
+    pub timestamp: DateTime<Utc>,
+    pub temperature_deg_c: Option<f64>,
+    pub relative_humidity_pct: Option<f64>,
+    pub station_pressure_hpa: Option<f64>,
+    pub wind_avg_m_per_s: Option<f64>,
+    pub wind_gust_m_per_s: Option<f64>,
+    pub wind_direction_deg: Option<f64>,
+    pub irradiance_w_per_m2: Option<f64>,
+    pub uv_index: Option<f64>,
+    pub rain_mm: Option<f64>,
+}
+
+// Column names of the CSV rendering, in field order.
+const CSV_HEADER: &str = "timestamp,temperature_deg_c,relative_humidity_pct,station_pressure_hpa,\
+                          wind_avg_m_per_s,wind_gust_m_per_s,wind_direction_deg,\
+                          irradiance_w_per_m2,uv_index,rain_mm";
+
+impl Sample {
+    fn new(obs: &Observation) -> Self {
+        let minute = chrono::Duration::minutes(1);
+        Self {
+            timestamp: obs
+                .timestamp
+                .duration_trunc(minute)
+                .unwrap_or(obs.timestamp),
+            temperature_deg_c: obs.air_temperature.map(f64::from),
+            relative_humidity_pct: obs.relative_humidity,
+            station_pressure_hpa: obs.station_pressure.map(f64::from),
+            wind_avg_m_per_s: obs.wind.as_ref().map(|wind| wind.avg.speed_magnitude().0),
+            wind_gust_m_per_s: obs.wind.as_ref().map(|wind| wind.gust.speed_magnitude().0),
+            wind_direction_deg: obs.wind.as_ref().map(|wind| wind.avg.source_direction()),
+            irradiance_w_per_m2: obs.solar.as_ref().map(|solar| solar.irradiance),
+            uv_index: obs.solar.as_ref().map(|solar| solar.ultraviolet_index),
+            rain_mm: obs
+                .precip
+                .as_ref()
+                .map(|precip| precip.quantity_last_minute.0),
+        }
+    }
+
+    // Folds a later sample within the same minute into this one.
+    fn merge(&mut self, later: Sample) {
+        let rain = match (self.rain_mm, later.rain_mm) {
+            (Some(earlier), Some(later)) => Some(earlier + later),
+            (earlier, later) => earlier.or(later),
+        };
+        *self = Sample {
+            temperature_deg_c: later.temperature_deg_c.or(self.temperature_deg_c),
+            relative_humidity_pct: later.relative_humidity_pct.or(self.relative_humidity_pct),
+            station_pressure_hpa: later.station_pressure_hpa.or(self.station_pressure_hpa),
+            wind_avg_m_per_s: later.wind_avg_m_per_s.or(self.wind_avg_m_per_s),
+            wind_gust_m_per_s: later.wind_gust_m_per_s.or(self.wind_gust_m_per_s),
+            wind_direction_deg: later.wind_direction_deg.or(self.wind_direction_deg),
+            irradiance_w_per_m2: later.irradiance_w_per_m2.or(self.irradiance_w_per_m2),
+            uv_index: later.uv_index.or(self.uv_index),
+            rain_mm: rain,
+            timestamp: self.timestamp,
+        };
+    }
+
+    fn write_csv(&self, out: &mut String) {
+        write!(out, "{}", self.timestamp.to_rfc3339()).unwrap();
+        for value in [
+            self.temperature_deg_c,
+            self.relative_humidity_pct,
+            self.station_pressure_hpa,
+            self.wind_avg_m_per_s,
+            self.wind_gust_m_per_s,
+            self.wind_direction_deg,
+            self.irradiance_w_per_m2,
+            self.uv_index,
+            self.rain_mm,
+        ] {
+            out.push(',');
+            if let Some(value) = value {
+                write!(out, "{}", value).unwrap();
+            }
+        }
+        out.push('\n');
+    }
+}
+
+/// Ring buffer of one-minute samples over the retention period, oldest first.
+#[derive(Debug)]
+pub struct History {
+    samples: VecDeque<Sample>,
+    retention: Duration,
+}
+
+impl History {
+    /// History keeping samples for `retention`, at most [`MAX_RETENTION`].
+    pub fn new(retention: Duration) -> Self {
+        Self {
+            samples: VecDeque::new(),
+            retention: retention.min(MAX_RETENTION),
+        }
+    }
+
+    /// Changes the retention period, forgetting samples now too old.
+    pub fn set_retention(&mut self, retention: Duration) {
+        self.retention = retention.min(MAX_RETENTION);
+        if let Some(latest) = self.samples.back().map(|sample| sample.timestamp) {
+            self.expire(latest);
+        }
+    }
+
+    /// Adds an observation, into the latest sample if it falls in the same minute. Observations
+    /// are expected in time order; one from before the latest minute is ignored.
+    pub fn push(&mut self, obs: &Observation) {
+        let sample = Sample::new(obs);
+        match self.samples.back_mut() {
+            Some(latest) if latest.timestamp > sample.timestamp => {}
+            Some(latest) if latest.timestamp == sample.timestamp => latest.merge(sample),
+            _ => {
+                let timestamp = sample.timestamp;
+                self.samples.push_back(sample);
+                self.expire(timestamp);
+            }
+        }
+    }
+
+    /// Samples from `since` onwards, or all of them, oldest first.
+    pub fn samples(&self, since: Option<DateTime<Utc>>) -> impl Iterator<Item = &Sample> {
+        self.samples
+            .iter()
+            .filter(move |sample| since.is_none_or(|since| sample.timestamp >= since))
+    }
+
+    /// Samples from `since` onwards as CSV, with a header row. Missing readings are left empty.
+    pub fn to_csv(&self, since: Option<DateTime<Utc>>) -> String {
+        let mut out = format!("{}\n", CSV_HEADER);
+        for sample in self.samples(since) {
+            sample.write_csv(&mut out);
+        }
+        out
+    }
+
+    fn expire(&mut self, latest: DateTime<Utc>) {
+        let cutoff = latest - chrono::Duration::from_std(self.retention).unwrap();
+        while matches!(self.samples.front(), Some(sample) if sample.timestamp <= cutoff) {
+            self.samples.pop_front();
+        }
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(DEFAULT_RETENTION)
+    }
+}
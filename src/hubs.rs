@@ -0,0 +1,120 @@
+//! Selection between copies of a device's reports relayed by more than one hub, as when two hubs
+//! are in range of the same station. Only the copy from the preferred hub is used, so that events
+//! aren't counted twice and gauges don't alternate between hubs.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::reader::RawTempestMsg;
+
+/// How long a hub can go without relaying a device's reports before copies from other hubs are
+/// used instead.
+pub const FAILOVER: Duration = Duration::from_secs(3 * 60);
+
+/// Which hub's copy of a device's reports to use.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum HubPreference {
+    /// The hub hearing the device with the strongest signal, as of its latest status report.
+    #[default]
+    StrongestSignal,
+    /// The hub with this serial number, falling back to the strongest while it relays nothing.
+    Primary(String),
+}
+
+/// Tracks which hubs relay each device's reports, and picks the copy to use.
+#[derive(Default)]
+pub struct HubSelector {
+    preference: HubPreference,
+    devices: HashMap<String, Relays>,
+}
+
+#[derive(Default)]
+struct Relays {
+    preferred: Option<String>,
+    // Each hub's last relay of the device's reports, and the device's signal strength there.
+    hubs: HashMap<String, (Instant, Option<f64>)>,
+}
+
+impl HubSelector {
+    pub fn new(preference: HubPreference) -> Self {
+        Self {
+            preference,
+            devices: HashMap::new(),
+        }
+    }
+
+    pub fn reconfigure(&mut self, preference: HubPreference) {
+        self.preference = preference;
+    }
+
+    /// Records a report received at `now` and returns whether it is the copy to use. Hub status
+    /// reports, and reports that don't say which hub relayed them, always are.
+    pub fn select(&mut self, raw: &RawTempestMsg, now: Instant) -> bool {
+        match relay(raw) {
+            Some((device, hub, rssi)) => self.select_relay(device, hub, rssi, now),
+            None => true,
+        }
+    }
+
+    fn select_relay(&mut self, device: &str, hub: &str, rssi: Option<f64>, now: Instant) -> bool {
+        let relays = self.devices.entry(device.to_string()).or_default();
+        let entry = relays.hubs.entry(hub.to_string()).or_insert((now, rssi));
+        *entry = (now, rssi.or(entry.1));
+        relays
+            .hubs
+            .retain(|_, (last, _)| now.duration_since(*last) < FAILOVER);
+
+        let signal = |hub: &str| {
+            relays
+                .hubs
+                .get(hub)
+                .and_then(|(_, rssi)| *rssi)
+                .unwrap_or(f64::NEG_INFINITY)
+        };
+        let strongest = relays
+            .hubs
+            .keys()
+            .max_by(|a, b| signal(a).total_cmp(&signal(b)))
+            .cloned();
+        let preferred = match (&self.preference, &relays.preferred) {
+            (HubPreference::Primary(primary), _) if relays.hubs.contains_key(primary) => {
+                Some(primary.clone())
+            }
+            // Only switch to a hub that is actually stronger, to not alternate between equals.
+            (_, Some(current))
+                if relays.hubs.contains_key(current)
+                    && signal(current)
+                        >= strongest.as_deref().map_or(f64::NEG_INFINITY, signal) =>
+            {
+                Some(current.clone())
+            }
+            _ => strongest,
+        };
+        let selected = preferred.as_deref() == Some(hub);
+        relays.preferred = preferred;
+        selected
+    }
+}
+
+/// The hub that relayed a report from a device, if the report says.
+pub fn relaying_hub(raw: &RawTempestMsg) -> Option<&str> {
+    relay(raw).map(|(_, hub, _)| hub)
+}
+
+// The device a report is from, the hub that relayed it, and the device's signal strength at the
+// hub if the report says.
+fn relay(raw: &RawTempestMsg) -> Option<(&str, &str, Option<f64>)> {
+    use RawTempestMsg as RTM;
+    match raw {
+        RTM::PrecipEvent(pe) => Some((&pe.serial_number, &pe.hub_sn, None)),
+        RTM::StrikeEvent(se) => Some((&se.serial_number, &se.hub_sn, None)),
+        RTM::RapidWind(rw) => Some((&rw.serial_number, &rw.hub_sn, None)),
+        RTM::Observation(obs) => Some((&obs.serial_number, &obs.hub_sn, None)),
+        RTM::DeviceStatus(ds) => Some((&ds.serial_number, &ds.hub_sn, Some(ds.hub_rssi))),
+        RTM::HubStatus(_) => None,
+        RTM::Debug(dm) => {
+            let field = |name| dm.fields.get(name).and_then(|v| v.as_str());
+            Some((field("serial_number")?, field("hub_sn")?, None))
+        }
+    }
+}
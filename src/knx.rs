@@ -0,0 +1,190 @@
+use std::net::{SocketAddr, UdpSocket};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Context};
+use tracing::{debug, info, warn};
+
+use crate::alerts::Quantity;
+use crate::config::{ExporterParams, Shared, StationParams};
+use crate::decoder::TempestMsg;
+use crate::sink_queue::LastError;
+
+// KNXnet/IP routing multicast group, which KNX IP routers and IP interfaces listen on.
+pub const ROUTING_MULTICAST: &str = "224.0.23.12:3671";
+
+// A three-level (main/middle/sub) or two-level (main/sub) KNX group address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GroupAddress(u16);
+
+impl FromStr for GroupAddress {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s
+            .split('/')
+            .map(u16::from_str)
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("Invalid KNX group address {}", s))?;
+        match parts[..] {
+            [main, middle, sub] if main < 32 && middle < 8 && sub < 256 => {
+                Ok(Self(main << 11 | middle << 8 | sub))
+            }
+            [main, sub] if main < 32 && sub < 2048 => Ok(Self(main << 11 | sub)),
+            _ => bail!("Invalid KNX group address {}", s),
+        }
+    }
+}
+
+// A KNX individual (area.line.device) address, which telegrams are sent from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IndividualAddress(u16);
+
+impl FromStr for IndividualAddress {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s
+            .split('.')
+            .map(u16::from_str)
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("Invalid KNX individual address {}", s))?;
+        match parts[..] {
+            [area, line, device] if area < 16 && line < 16 && device < 256 => {
+                Ok(Self(area << 12 | line << 8 | device))
+            }
+            _ => bail!("Invalid KNX individual address {}", s),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct KnxParams {
+    pub gateway: SocketAddr,
+    pub source_address: IndividualAddress,
+    pub groups: Vec<(GroupAddress, Quantity)>,
+}
+
+// Datapoint type a quantity is written as, with the factor taking it to the datapoint's unit.
+enum Datapoint {
+    // DPT 9, 2-byte float, as used for temperatures (9.001), wind speeds (9.005), illuminance
+    // (9.004), humidity (9.007), irradiance (9.022) and voltages in mV (9.020).
+    Float16(f64),
+    // DPT 14, 4-byte float, for pressures in Pa (14.058), which 2-byte floats can't hold to
+    // better than 1 hPa.
+    Float32(f64),
+}
+
+fn datapoint(quantity: Quantity) -> Datapoint {
+    match quantity {
+        Quantity::StationPressure | Quantity::BarometricPressure => Datapoint::Float32(100.0),
+        Quantity::BatteryVolts => Datapoint::Float16(1000.0),
+        _ => Datapoint::Float16(1.0),
+    }
+}
+
+// Encodes a DPT 9 value: a sign, a 4-bit exponent and an 11-bit two's complement mantissa in
+// hundredths. `None` if the value is out of range.
+fn float16(value: f64) -> Option<[u8; 2]> {
+    let mut mantissa = value * 100.0;
+    let mut exponent: u16 = 0;
+    while !(-2048.0..=2047.0).contains(&mantissa.round()) {
+        if exponent == 15 {
+            return None;
+        }
+        mantissa /= 2.0;
+        exponent += 1;
+    }
+    let mantissa = mantissa.round() as i16;
+    let sign = if mantissa < 0 { 0x8000 } else { 0 };
+    Some((sign | exponent << 11 | (mantissa as u16 & 0x07ff)).to_be_bytes())
+}
+
+// A KNXnet/IP routing indication carrying a group value write.
+fn routing_indication(source: IndividualAddress, group: GroupAddress, data: &[u8]) -> Vec<u8> {
+    let mut cemi = vec![
+        0x29, // L_Data.ind
+        0x00, // No additional info
+        0xbc, // Standard frame, not repeated, broadcast, low priority
+        0xe0, // Group destination, hop count 6
+    ];
+    cemi.extend(source.0.to_be_bytes());
+    cemi.extend(group.0.to_be_bytes());
+    cemi.push(data.len() as u8 + 1);
+    cemi.extend([0x00, 0x80]); // GroupValue_Write
+    cemi.extend(data);
+    let mut frame = vec![0x06, 0x10, 0x05, 0x30]; // Header length, version, ROUTING_INDICATION
+    frame.extend((cemi.len() as u16 + 6).to_be_bytes());
+    frame.extend(cemi);
+    frame
+}
+
+// Writes the mapped quantities from each report to KNX group addresses, through a KNX IP router
+// or interface using KNXnet/IP routing.
+pub struct KnxBridge {
+    station_params: Shared<StationParams>,
+    exporter_params: Shared<ExporterParams>,
+    params: Mutex<KnxParams>,
+    socket: UdpSocket,
+    errors: Arc<LastError>,
+}
+
+impl KnxBridge {
+    pub fn new(
+        station_params: Shared<StationParams>,
+        exporter_params: Shared<ExporterParams>,
+        params: KnxParams,
+    ) -> anyhow::Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0").context("Binding KNX socket")?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            station_params,
+            exporter_params,
+            params: Mutex::new(params),
+            socket,
+            errors: Arc::new(LastError::default()),
+        })
+    }
+
+    // Where send errors are recorded, for `/debug/state`.
+    pub fn errors(&self) -> Arc<LastError> {
+        self.errors.clone()
+    }
+
+    pub fn reconfigure(&self, new_params: KnxParams) {
+        let mut params = self.params.lock().unwrap();
+        if *params != new_params {
+            info!("KNX group addresses changed");
+            *params = new_params;
+        }
+    }
+
+    pub fn handle_report(&self, msg: &TempestMsg) {
+        let params = self.params.lock().unwrap();
+        let station_params = self.station_params.read().unwrap();
+        let filter = &self.exporter_params.read().unwrap().publish_filter;
+        for &(group, quantity) in &params.groups {
+            if !filter.allows(quantity.topic()) {
+                continue;
+            }
+            let value = match quantity.value(msg, &station_params) {
+                Some(value) if value.is_finite() => value,
+                _ => continue,
+            };
+            let data = match datapoint(quantity) {
+                Datapoint::Float16(scale) => float16(value * scale).map(Vec::from),
+                Datapoint::Float32(scale) => Some(((value * scale) as f32).to_be_bytes().to_vec()),
+            };
+            let data = match data {
+                Some(data) => data,
+                None => {
+                    debug!("{:?} value {} is out of KNX range", quantity, value);
+                    continue;
+                }
+            };
+            let frame = routing_indication(params.source_address, group, &data);
+            if let Err(e) = self.socket.send_to(&frame, params.gateway) {
+                warn!("KNX send failed: {}", e);
+                self.errors.record(format!("Send failed: {}", e));
+            }
+        }
+    }
+}
@@ -0,0 +1,43 @@
+//! Leaf wetness, estimated from the air being near saturation (dew forming) or rain having fallen
+//! recently, for blight and other plant disease models that count hours of wet foliage.
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::decoder::Observation;
+
+// Dew point depressions (°C) at and below which leaves are taken to be fully wet from dew, and at
+// and above which dew plays no part.
+const DEW_DEPRESSION: (f64, f64) = (1.0, 4.0);
+
+// How long after rain leaves are taken to dry out (minutes).
+const DRYING_MINUTES: i64 = 120;
+
+/// Wetness score at and above which leaves are taken to be wet.
+pub const WET_SCORE: f64 = 0.5;
+
+/// Wetness score from 0 (dry) to 1 (wet), whichever of dew and rain makes leaves wetter: dew as the
+/// dew point depression (°C) closes to within a degree, and rain fading out over the two hours
+/// after it last fell.
+pub fn wetness(dew_point_depression: Option<f64>, since_rain: Option<Duration>) -> f64 {
+    let (wet, dry) = DEW_DEPRESSION;
+    let dew = dew_point_depression.map_or(0.0, |depression| {
+        ((dry - depression) / (dry - wet)).clamp(0.0, 1.0)
+    });
+    let rain = since_rain.map_or(0.0, |since| {
+        (1.0 - since.num_minutes() as f64 / DRYING_MINUTES as f64).clamp(0.0, 1.0)
+    });
+    dew.max(rain)
+}
+
+/// Wetness score for an observation, given when rain last fell. `None` if it lacks both
+/// temperature and rain readings to go by.
+pub fn for_observation(obs: &Observation, last_rain: Option<DateTime<Utc>>) -> Option<f64> {
+    let depression = obs
+        .air_temperature
+        .zip(obs.dew_point())
+        .map(|(t, dew_point)| t.0 - dew_point.0);
+    if depression.is_none() && obs.precip.is_none() {
+        return None;
+    }
+    Some(wetness(depression, last_rain.map(|at| obs.timestamp - at)))
+}
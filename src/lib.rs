@@ -0,0 +1,54 @@
+//! Receiving and decoding of the WeatherFlow Tempest local UDP API.
+//!
+//! The pipeline is a chain of streams: [`receiver::Receiver`] yields JSON datagrams,
+//! [`reader::new`] parses them into [`reader::RawTempestMsg`], and [`decoder::new`] turns those
+//! into [`decoder::TempestMsg`] reports with derived values such as dew point and barometric
+//! pressure. An [`exporter::Exporter`] turns those reports into Prometheus metrics.
+//!
+//! ```no_run
+//! use tempest_exporter::{decoder, reader, receiver};
+//! use tokio_stream::StreamExt;
+//!
+//! # async fn example() -> anyhow::Result<()> {
+//! let rx = receiver::Receiver::new().await?;
+//! let mut messages = Box::pin(decoder::new(reader::new(rx)));
+//! while let Some(msg) = messages.next().await {
+//!     println!("{:?}", msg);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod backoff;
+pub mod changes;
+pub mod checkpoint;
+pub mod clock;
+pub mod day;
+pub mod decoder;
+pub mod derived;
+pub mod events;
+pub mod exporter;
+pub mod filter;
+pub mod fire;
+pub mod forecast;
+pub mod history;
+pub mod hubs;
+pub mod leaf;
+pub mod lightning;
+pub mod mold;
+pub mod params;
+mod perishable;
+pub mod precision;
+pub mod quality;
+pub mod rain;
+pub mod rain_check;
+pub mod reader;
+pub mod receiver;
+pub mod reference;
+pub mod smoothing;
+pub mod solar;
+pub mod state;
+pub mod threshold;
+pub mod topic;
+pub mod trend;
+pub mod units;
@@ -0,0 +1,95 @@
+//! Lightning alert levels, graded from the strikes detected within a rolling window.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::decoder::TempestMsg;
+
+/// How strikes are graded: how far back they count, how many it takes to raise the level at all,
+/// and the distances (km) within which lightning is near and overhead.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LightningParams {
+    pub window: Duration,
+    pub min_strikes: usize,
+    pub near_distance: f64,
+    pub overhead_distance: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LightningLevel {
+    None,
+    Distant,
+    Near,
+    Overhead,
+}
+
+impl LightningLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LightningLevel::None => "none",
+            LightningLevel::Distant => "distant",
+            LightningLevel::Near => "near",
+            LightningLevel::Overhead => "overhead",
+        }
+    }
+}
+
+/// Grades lightning activity from the strikes within a rolling window. Enough strikes must fall
+/// within the window to raise the level at all, so that a lone false detection doesn't; the level
+/// is then set by the closest of them.
+pub struct LightningTracker {
+    params: LightningParams,
+    strikes: VecDeque<(DateTime<Utc>, f64)>,
+    level: Option<LightningLevel>,
+}
+
+impl LightningTracker {
+    pub fn new(params: LightningParams) -> Self {
+        Self {
+            params,
+            strikes: VecDeque::new(),
+            level: None,
+        }
+    }
+
+    /// Grades by `params` from now on, keeping the strikes seen so far.
+    pub fn reconfigure(&mut self, params: LightningParams) {
+        self.params = params;
+    }
+
+    /// Takes in a report received at `now`, returning the new level when it changes. Any report
+    /// re-evaluates the level, so that it falls as strikes leave the window even when there are
+    /// no new ones.
+    pub fn update(&mut self, msg: &TempestMsg, now: DateTime<Utc>) -> Option<LightningLevel> {
+        if let TempestMsg::StrikeEvent(se) = msg {
+            self.strikes.push_back((se.timestamp, se.distance));
+        }
+        let window = chrono::Duration::from_std(self.params.window).unwrap();
+        let cutoff = now - window;
+        self.strikes.retain(|(timestamp, _)| *timestamp > cutoff);
+
+        let level = if self.strikes.len() < self.params.min_strikes.max(1) {
+            LightningLevel::None
+        } else {
+            let closest = self
+                .strikes
+                .iter()
+                .map(|(_, distance)| *distance)
+                .fold(f64::INFINITY, f64::min);
+            if closest <= self.params.overhead_distance {
+                LightningLevel::Overhead
+            } else if closest <= self.params.near_distance {
+                LightningLevel::Near
+            } else {
+                LightningLevel::Distant
+            }
+        };
+        if self.level == Some(level) {
+            return None;
+        }
+        self.level = Some(level);
+        Some(level)
+    }
+}
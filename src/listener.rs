@@ -0,0 +1,43 @@
+use std::convert::TryFrom;
+
+use serde_json::json;
+use tokio_stream::StreamExt;
+
+use crate::decoder::TempestMsg;
+use crate::reader;
+use crate::receiver::Receiver;
+
+pub async fn run(port: u16, json: bool) -> anyhow::Result<()> {
+    let mut rx = Receiver::bind(port).await?;
+    while let Some(datagram) = rx.next().await {
+        let datagram = String::from_utf8_lossy(&datagram);
+        if json {
+            println!("{}", to_json(&datagram));
+        } else {
+            print_human(&datagram);
+        }
+    }
+    Ok(())
+}
+
+fn decode(datagram: &str) -> Result<TempestMsg, String> {
+    let raw = reader::parse(datagram).map_err(|e| format!("unreadable: {}", e))?;
+    TempestMsg::try_from(raw).map_err(|(_, e)| format!("undecodable: {}", e))
+}
+
+fn print_human(datagram: &str) {
+    println!("{}", datagram);
+    match decode(datagram) {
+        Ok(msg) => println!("{:#?}", msg),
+        Err(e) => println!("Dropped {}", e),
+    }
+    println!();
+}
+
+fn to_json(datagram: &str) -> serde_json::Value {
+    let raw = serde_json::from_str(datagram).unwrap_or_else(|_| json!(datagram));
+    match decode(datagram) {
+        Ok(msg) => json!({ "raw": raw, "decoded": msg }),
+        Err(e) => json!({ "raw": raw, "error": e }),
+    }
+}
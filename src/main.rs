@@ -1,157 +1,635 @@
-mod decoder;
-mod exporter;
-mod perishable;
+mod alerts;
+mod calibrator;
+mod config;
+mod connections;
+mod domoticz;
+mod ecowitt;
+mod grafana_live;
+mod healthcheck;
+mod heartbeat;
+mod knx;
+mod listener;
+mod modbus;
+mod nmea;
+mod once;
+mod pipeline;
 mod publisher;
-mod reader;
-mod receiver;
+mod schema;
+mod scrape_cache;
+#[cfg(windows)]
+mod service;
+mod shutdown;
+mod signalk;
+mod simulator;
+mod sink_queue;
+mod sockets;
+mod systemd;
+mod telemetry;
 
-use std::sync::Arc;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
+use std::time::Duration;
 
-use anyhow::{bail, Context};
-use log::{error, info};
-use simple_logger::SimpleLogger;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
 use structopt::StructOpt;
-use tokio::signal;
-use tokio::sync::oneshot;
-use tokio_stream::StreamExt;
+use tokio::sync::{oneshot, watch, Notify};
+use tracing::{error, info, warn};
 use warp::Filter;
 
-#[derive(StructOpt, Clone, Debug)]
-pub struct StationParams {
-    /// Station elevation in meters - used to compute barometric pressure.
-    #[structopt(long = "station-elevation")]
-    pub elevation: f64,
-}
-
-#[derive(StructOpt, Debug)]
-pub struct MqttParams {
-    /// Port to use for MQTT broker
-    #[structopt(long, default_value = "1883")]
-    mqtt_port: u16,
+use tempest_exporter::{
+    checkpoint, clock, day, decoder, exporter, history, hubs, rain_check, reader, receiver,
+    reference, state, units,
+};
 
-    /// Address of MQTT broker
-    #[structopt(long)]
-    mqtt_broker: Option<String>,
+use config::{Command, Config, Opt, StartupMode};
 
-    /// MQTT username
-    #[structopt(long)]
-    mqtt_username: Option<String>,
-
-    /// MQTT password
-    #[structopt(long)]
-    mqtt_password: Option<String>,
+fn main() -> anyhow::Result<()> {
+    let opt = Opt::from_args();
+    #[cfg(windows)]
+    if opt.windows_service {
+        return service::start();
+    }
+    tokio::runtime::Runtime::new()?.block_on(dispatch(opt))
 }
 
-#[derive(StructOpt, Debug)]
-struct Opt {
-    /// Log verbosity level
-    #[structopt(long, default_value = "info")]
-    log_level: log::LevelFilter,
-
-    /// Port to bind the Prometheus metrics server
-    #[structopt(long, default_value = "8080")]
-    metrics_port: u16,
-
-    /// MQTT parameters
-    #[structopt(flatten)]
-    mqtt_params: MqttParams,
+async fn dispatch(opt: Opt) -> anyhow::Result<()> {
+    match opt.command {
+        Some(Command::CheckConfig { connect }) => check_config(&opt, connect).await,
+        Some(Command::Healthcheck { ready, timeout }) => {
+            healthcheck::run(Config::load(&opt)?, ready, Duration::from_secs(timeout)).await
+        }
+        Some(Command::Listen { json }) => {
+            // Only the first station's port is listened on.
+            listener::run(Config::load(&opt)?.station().api_port, json).await
+        }
+        Some(Command::Once { format }) => once::run(Config::load(&opt)?, format).await,
+        Some(Command::Simulate {
+            scenario,
+            target,
+            serial_number,
+            hub_serial_number,
+        }) => {
+            telemetry::init(log::LevelFilter::Info, None)?;
+            simulator::Simulator::new(scenario, target, serial_number, hub_serial_number)
+                .await?
+                .run()
+                .await
+        }
+        None => run(opt, Arc::new(Notify::new())).await,
+    }
+}
 
-    /// Station parameters
-    #[structopt(flatten)]
-    station_params: StationParams,
+async fn check_config(opt: &Opt, connect: bool) -> anyhow::Result<()> {
+    let config = Config::load(opt)?;
+    println!("{:#?}", config);
+    if connect {
+        for station in config.stations {
+            publisher::Publisher::check_connection(station.mqtt_params).await?;
+        }
+        println!("MQTT broker connection succeeded");
+    }
+    Ok(())
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let opt = Opt::from_args();
+// Runs the exporter until it is asked to stop by a signal or through `service_stop`.
+async fn run(opt: Opt, service_stop: Arc<Notify>) -> anyhow::Result<()> {
+    let config = Config::load(&opt)?;
 
-    SimpleLogger::new()
-        .with_level(opt.log_level)
-        .with_utc_timestamps()
-        .init()
-        .context("Logging setup failed")
-        .unwrap();
+    telemetry::init(config.log_level, config.otlp_endpoint.as_deref())?;
     info!("Starting Tempest exporter");
 
-    let rx = receiver::Receiver::new().await?;
-    let rdr = reader::new(rx);
-    let mut dec = decoder::new(rdr);
-
-    let exporter = Arc::new(exporter::Exporter::new(opt.station_params.clone()));
-    let publisher = Arc::new(publisher::Publisher::new(
-        opt.station_params.clone(),
-        opt.mqtt_params,
-    ));
-
-    match dec.next().await {
-        Some(msg) => {
-            exporter.handle_report(&msg);
-            publisher.handle_report(&msg);
-            info!("Tempest API is alive");
+    // Taken before anything else runs, as doing so clears the environment systemd passes them in.
+    let activated = systemd::activated_sockets()?;
+    let mut inherited = sockets::api_sockets(activated.udp)?;
+    // A lone station takes a lone socket whatever its port, otherwise they are matched by port.
+    let lone = config.stations.len() == 1 && inherited.len() == 1;
+    let mut handover = sockets::Handover::default();
+    let mut pipelines = Vec::new();
+    for station in config.stations {
+        let socket = if lone {
+            inherited.pop()
+        } else {
+            sockets::take_for_port(&mut inherited, station.api_port)
+        };
+        pipelines.push(
+            pipeline::Pipeline::start(
+                station,
+                socket,
+                config.checkpoint_interval,
+                config.enable_prometheus,
+                &mut handover,
+            )
+            .await?,
+        );
+    }
+    if !inherited.is_empty() {
+        warn!("Ignoring inherited UDP sockets on ports no station receives on");
+    }
+
+    let readiness = Arc::new(pipeline::Readiness::new(pipelines.len()));
+    systemd::notify_status("Waiting for first message");
+    if config.startup_mode == StartupMode::Wait {
+        for pipeline in &mut pipelines {
+            pipeline
+                .wait_for_data(config.first_data_timeout, &readiness)
+                .await?;
         }
-        None => bail!("Decoder stream never returned anything"),
     }
 
-    let server_filter_chain = warp::path("healthz")
+    let exporters: Vec<_> = pipelines
+        .iter()
+        .map(|pipeline| (pipeline.name.clone(), pipeline.exporter.clone()))
+        .collect();
+    let routes = warp::path("healthz")
         .map(|| "ok")
-        .or(warp::path("metrics").map({
-            let exporter = exporter.clone();
+        .or(warp::path("readyz").map({
+            let readiness = readiness.clone();
             move || {
-                http::Response::builder()
-                    .header("content-type", "text/plain; charset=utf-8")
-                    .body(exporter.encode())
-            }
-        }));
-    let (server_shutdown_tx, server_shutdown_rx) = oneshot::channel();
-    let server = tokio::spawn(
-        warp::serve(server_filter_chain)
-            .bind_with_graceful_shutdown(([0, 0, 0, 0], opt.metrics_port), async move {
-                server_shutdown_rx.await.ok();
-                info!("Web server stopping");
-            })
-            .1,
-    );
-
-    let (message_pump_shutdown_tx, mut message_pump_shutdown_rx) = oneshot::channel();
-    let message_pump = tokio::spawn({
-        let publisher = publisher.clone();
-        async move {
-            loop {
-                if let Some(msg) = dec.next().await {
-                    exporter.handle_report(&msg);
-                    publisher.handle_report(&msg);
+                if readiness.is_ready() {
+                    warp::reply::with_status("ok", http::StatusCode::OK)
                 } else {
-                    break;
-                }
-                if message_pump_shutdown_rx.try_recv().is_ok() {
-                    info!("Message pump stopping");
-                    break;
+                    warp::reply::with_status("no data yet", http::StatusCode::SERVICE_UNAVAILABLE)
                 }
             }
-        }
-    });
+        }))
+        .or(warp::path("metrics")
+            .and(warp::path::end())
+            .and(warp::header::optional::<String>("accept"))
+            .and(warp::header::optional::<String>("accept-encoding"))
+            .map({
+                let cache = Arc::new(scrape_cache::ScrapeCache::new());
+                move |accept: Option<String>, accept_encoding: Option<String>| {
+                    // Exemplars are only carried by OpenMetrics, so serve it to scrapers asking.
+                    let openmetrics = accept
+                        .unwrap_or_default()
+                        .contains("application/openmetrics-text");
+                    let gzip =
+                        accept_encoding.is_some_and(|header| scrape_cache::accepts_gzip(&header));
+                    let body = cache.get(openmetrics, gzip, || {
+                        encode_metrics(&exporters, openmetrics)
+                    });
+                    let content_type = if openmetrics {
+                        "application/openmetrics-text; version=1.0.0; charset=utf-8"
+                    } else {
+                        "text/plain; charset=utf-8"
+                    };
+                    let mut response = http::Response::builder()
+                        .header("content-type", content_type)
+                        .header(
+                            "cache-control",
+                            format!("max-age={}", scrape_cache::MAX_AGE.as_secs()),
+                        )
+                        .header("vary", "accept, accept-encoding");
+                    if gzip {
+                        response = response.header("content-encoding", "gzip");
+                    }
+                    response.body(body)
+                }
+            }))
+        .or(warp::path!("api" / "v1" / "schema").map(|| warp::reply::json(&schema::document())))
+        .or(warp::path!("api" / "v1" / "recent")
+            .and(warp::query::<RecentQuery>())
+            .map({
+                let histories: Vec<_> = pipelines
+                    .iter()
+                    .map(|pipeline| (pipeline.name.clone(), pipeline.history.clone()))
+                    .collect();
+                move |query: RecentQuery| recent(&histories, query)
+            }))
+        .or(warp::path!("api" / "v1" / "current")
+            .and(warp::query::<StationQuery>())
+            .map({
+                let states: Vec<_> = pipelines
+                    .iter()
+                    .map(|pipeline| (pipeline.name.clone(), pipeline.state.clone()))
+                    .collect();
+                move |query: StationQuery| current(&states, query)
+            }))
+        .or(warp::path!("api" / "v1" / "events")
+            .and(warp::query::<StationQuery>())
+            .map({
+                let exporters: Vec<_> = pipelines
+                    .iter()
+                    .map(|pipeline| (pipeline.name.clone(), pipeline.exporter.clone()))
+                    .collect();
+                move |query: StationQuery| events(&exporters, query)
+            }))
+        .or(warp::path!("debug" / "state")
+            .and(warp::header::optional::<String>("authorization"))
+            .map({
+                let debug_api = config.debug_api.clone();
+                let states: Vec<_> = pipelines
+                    .iter()
+                    .map(|pipeline| {
+                        let exporter = pipeline.exporter.clone();
+                        let sinks = pipeline.sinks.clone();
+                        (pipeline.name.clone(), exporter, sinks)
+                    })
+                    .collect();
+                move |authorization: Option<String>| {
+                    let status = match &debug_api {
+                        None => http::StatusCode::NOT_FOUND,
+                        Some(params)
+                            if !bearer_matches(authorization.as_deref(), &params.token) =>
+                        {
+                            http::StatusCode::UNAUTHORIZED
+                        }
+                        Some(_) => {
+                            let state = debug_state(&states);
+                            return http::Response::builder()
+                                .header("content-type", "application/json")
+                                .body(serde_json::to_vec_pretty(&state).unwrap());
+                        }
+                    };
+                    http::Response::builder().status(status).body(Vec::new())
+                }
+            }));
+    let route_prefix = config
+        .route_prefix
+        .iter()
+        .fold(warp::any().boxed(), |prefix, segment| {
+            prefix.and(warp::path(segment.clone())).boxed()
+        });
+    let server_filter_chain = route_prefix.and(routes);
+    let mut server = None;
+    if config.enable_prometheus {
+        // Activated sockets win, as after an in-place upgrade a configured descriptor is long
+        // closed.
+        let http_listener =
+            sockets::http_listener(config.metrics_port, activated.http.or(config.listen_fd))?;
+        handover.keep(&http_listener)?;
+        let (server_shutdown_tx, server_shutdown_rx) = oneshot::channel();
+        let task = tokio::spawn(
+            warp::serve(server_filter_chain).serve_incoming_with_graceful_shutdown(
+                connections::Connections::new(
+                    http_listener,
+                    config.http_max_connections,
+                    config.http_request_timeout,
+                ),
+                async move {
+                    server_shutdown_rx.await.ok();
+                    info!("Web server stopping");
+                },
+            ),
+        );
+        server = Some((task, server_shutdown_tx));
+    }
+
+    for pipeline in &mut pipelines {
+        pipeline.spawn_pump(config.first_data_timeout, readiness.clone());
+    }
 
+    let shutdown_timeout = config.shutdown_timeout;
+    #[cfg(unix)]
+    spawn_reloader(
+        opt,
+        config.log_level,
+        config.metrics_port,
+        pipelines
+            .iter()
+            .map(pipeline::Pipeline::reconfigurable)
+            .collect(),
+    );
+
+    let mut outcome = Ok(());
+    let mut upgrade = false;
     tokio::select! {
-        result = server => match result {
+        result = async {
+            match server.as_mut() {
+                Some((task, _)) => task.await,
+                None => std::future::pending().await,
+            }
+        } => match result {
             Err(e) => error!("Server task panic: {}", e),
             Ok(()) => info!("Server task exited"),
         },
-        result = message_pump => match result {
+        result = first_finished(&mut pipelines) => match result {
             Err(e) => error!("Exporter task panic: {}", e),
-            Ok(()) => info!("Exporter task exited"),
+            Ok(Err(e)) => outcome = Err(e),
+            Ok(Ok(())) => info!("Exporter task exited"),
         },
-        result = signal::ctrl_c() => match result {
-            Err(e) => error!("Interrupt signal handling failure: {}", e),
-            Ok(()) => info!("Terminating on interrupt signal"),
+        result = shutdown::requested(service_stop) => match result {
+            Err(e) => error!("Signal handling failure: {}", e),
+            Ok(reason) => info!("Terminating on {}", reason),
         },
+        _ = shutdown::upgrade_requested() => {
+            info!("Upgrading in place on user-defined signal 2");
+            upgrade = true;
+        }
     }
 
-    server_shutdown_tx.send(()).ok();
-    message_pump_shutdown_tx.send(()).ok();
-    publisher.shutdown();
+    if upgrade {
+        systemd::notify_reloading();
+    } else {
+        systemd::notify_stopping();
+    }
+    if let Some((_, server_shutdown_tx)) = server {
+        server_shutdown_tx.send(()).ok();
+    }
+    for pipeline in &mut pipelines {
+        pipeline.stop_pump(upgrade);
+    }
     info!("Shutdown initiated");
-    tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+    let stopping: Vec<_> = pipelines
+        .into_iter()
+        .map(|pipeline| tokio::spawn(pipeline.shutdown(shutdown_timeout, upgrade)))
+        .collect();
+    for stopped in stopping {
+        stopped.await.ok();
+    }
 
+    if upgrade {
+        info!("Re-executing");
+        telemetry::shutdown();
+        return Err(handover.exec());
+    }
     info!("Terminating");
-    Ok(())
+    telemetry::shutdown();
+    outcome
+}
+
+// Renders every station's metrics. A single unnamed station's are served unlabelled, as they
+// were before stations could be named.
+fn encode_metrics(
+    exporters: &[(Option<String>, Arc<exporter::Exporter>)],
+    openmetrics: bool,
+) -> Vec<u8> {
+    match exporters {
+        [(None, exporter)] if openmetrics => exporter.encode_openmetrics(),
+        [(None, exporter)] => exporter.encode(),
+        _ => {
+            let stations: Vec<_> = exporters
+                .iter()
+                .map(|(name, exporter)| (name.as_deref().unwrap_or_default(), &**exporter))
+                .collect();
+            if openmetrics {
+                exporter::encode_stations_openmetrics(&stations)
+            } else {
+                exporter::encode_stations(&stations)
+            }
+        }
+    }
+}
+
+type DebugSources = (
+    Option<String>,
+    Arc<exporter::Exporter>,
+    Arc<pipeline::Sinks>,
+);
+
+// Internal state for `/debug/state`, keyed by station name when stations are named.
+fn debug_state(sources: &[DebugSources]) -> serde_json::Value {
+    let state = |exporter: &exporter::Exporter, sinks: &pipeline::Sinks| {
+        serde_json::json!({
+            "exporter": exporter.debug_state(),
+            "sinks": sinks.queues().iter().map(|q| q.state()).collect::<Vec<_>>(),
+        })
+    };
+    match sources {
+        [(None, exporter, sinks)] => state(exporter, sinks),
+        _ => sources
+            .iter()
+            .map(|(name, exporter, sinks)| {
+                (name.clone().unwrap_or_default(), state(exporter, sinks))
+            })
+            .collect::<serde_json::Map<_, _>>()
+            .into(),
+    }
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum RecentFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(Deserialize)]
+struct RecentQuery {
+    #[serde(default)]
+    format: RecentFormat,
+    since: Option<DateTime<Utc>>,
+    station: Option<String>,
+}
+
+// Recent observations of the station asked for, or as JSON of every station keyed by name when
+// there are several and none was asked for.
+fn recent(
+    histories: &[(Option<String>, Arc<Mutex<history::History>>)],
+    query: RecentQuery,
+) -> http::Response<Vec<u8>> {
+    let reply = |status, content_type, body: Vec<u8>| {
+        http::Response::builder()
+            .status(status)
+            .header("content-type", content_type)
+            .body(body)
+            .unwrap()
+    };
+    let history = match (&query.station, histories) {
+        (None, [(_, history)]) => history,
+        (Some(station), _) => match histories
+            .iter()
+            .find(|(name, _)| name.as_deref().unwrap_or_default() == station)
+        {
+            Some((_, history)) => history,
+            None => {
+                return reply(
+                    http::StatusCode::NOT_FOUND,
+                    "text/plain; charset=utf-8",
+                    format!("No station named {}", station).into_bytes(),
+                )
+            }
+        },
+        (None, _) if query.format == RecentFormat::Csv => {
+            return reply(
+                http::StatusCode::BAD_REQUEST,
+                "text/plain; charset=utf-8",
+                b"Choose a station with ?station=".to_vec(),
+            )
+        }
+        (None, _) => {
+            let locked: Vec<_> = histories
+                .iter()
+                .map(|(name, history)| {
+                    (name.as_deref().unwrap_or_default(), history.lock().unwrap())
+                })
+                .collect();
+            let stations: std::collections::BTreeMap<_, Vec<_>> = locked
+                .iter()
+                .map(|(name, history)| (*name, history.samples(query.since).collect()))
+                .collect();
+            return reply(
+                http::StatusCode::OK,
+                "application/json",
+                serde_json::to_vec(&stations).unwrap(),
+            );
+        }
+    };
+    let history = history.lock().unwrap();
+    match query.format {
+        RecentFormat::Json => reply(
+            http::StatusCode::OK,
+            "application/json",
+            serde_json::to_vec(&history.samples(query.since).collect::<Vec<_>>()).unwrap(),
+        ),
+        RecentFormat::Csv => reply(
+            http::StatusCode::OK,
+            "text/csv; charset=utf-8",
+            history.to_csv(query.since).into_bytes(),
+        ),
+    }
+}
+
+#[derive(Deserialize)]
+struct StationQuery {
+    station: Option<String>,
+}
+
+// The latest reports of the station asked for, or of every station keyed by name when there are
+// several and none was asked for.
+fn current(
+    states: &[(Option<String>, watch::Receiver<state::StationState>)],
+    query: StationQuery,
+) -> http::Response<Vec<u8>> {
+    by_station(states, query, |state| state.borrow().clone())
+}
+
+// The latest notable events of the station asked for, oldest first, or of every station keyed by
+// name.
+fn events(
+    exporters: &[(Option<String>, Arc<exporter::Exporter>)],
+    query: StationQuery,
+) -> http::Response<Vec<u8>> {
+    by_station(exporters, query, |exporter| exporter.events())
+}
+
+// A JSON view of the station asked for, or of every station keyed by name when there are several
+// and none was asked for.
+fn by_station<T, V: serde::Serialize>(
+    stations: &[(Option<String>, T)],
+    query: StationQuery,
+    view: impl Fn(&T) -> V,
+) -> http::Response<Vec<u8>> {
+    let body = match (&query.station, stations) {
+        (None, [(_, source)]) => serde_json::to_vec(&view(source)),
+        (Some(station), _) => match stations
+            .iter()
+            .find(|(name, _)| name.as_deref().unwrap_or_default() == station)
+        {
+            Some((_, source)) => serde_json::to_vec(&view(source)),
+            None => {
+                return http::Response::builder()
+                    .status(http::StatusCode::NOT_FOUND)
+                    .header("content-type", "text/plain; charset=utf-8")
+                    .body(format!("No station named {}", station).into_bytes())
+                    .unwrap()
+            }
+        },
+        (None, _) => {
+            let views: std::collections::BTreeMap<_, _> = stations
+                .iter()
+                .map(|(name, source)| (name.as_deref().unwrap_or_default(), view(source)))
+                .collect();
+            serde_json::to_vec(&views)
+        }
+    };
+    http::Response::builder()
+        .header("content-type", "application/json")
+        .body(body.unwrap())
+        .unwrap()
+}
+
+// Resolves with the outcome of the first message pump to finish.
+async fn first_finished(
+    pipelines: &mut [pipeline::Pipeline],
+) -> Result<anyhow::Result<()>, tokio::task::JoinError> {
+    std::future::poll_fn(|cx| {
+        for pipeline in pipelines.iter_mut() {
+            if let Poll::Ready(result) = Pin::new(pipeline.pump()).poll(cx) {
+                return Poll::Ready(result);
+            }
+        }
+        Poll::Pending
+    })
+    .await
+}
+
+// Whether an `Authorization` header carries the bearer token, compared in constant time so that
+// response timing doesn't give the token away. Every byte of the token is compared whatever was
+// given, so that the time taken doesn't give away its length either.
+fn bearer_matches(authorization: Option<&str>, token: &str) -> bool {
+    match authorization.and_then(|value| value.strip_prefix("Bearer ")) {
+        Some(given) => {
+            let given = given.as_bytes();
+            let diff = token
+                .bytes()
+                .enumerate()
+                .fold(given.len() ^ token.len(), |diff, (i, b)| {
+                    diff | usize::from(given.get(i).copied().unwrap_or(0) ^ b)
+                });
+            diff == 0
+        }
+        None => false,
+    }
+}
+
+// Reloads configuration on SIGHUP, applying it to the running pipelines in place.
+#[cfg(unix)]
+fn spawn_reloader(
+    opt: Opt,
+    log_level: log::LevelFilter,
+    metrics_port: u16,
+    stations: Vec<Arc<pipeline::Reconfigurable>>,
+) {
+    use tokio::signal::unix::{signal, SignalKind};
+    tokio::spawn(async move {
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(hangup) => hangup,
+            Err(e) => {
+                error!("Hangup signal handling failure: {}", e);
+                return;
+            }
+        };
+        while hangup.recv().await.is_some() {
+            info!("Reloading configuration on hangup signal");
+            let mut new_config = match Config::load(&opt) {
+                Ok(new_config) => new_config,
+                Err(e) => {
+                    error!("Configuration reload failed: {:#}", e);
+                    continue;
+                }
+            };
+            if new_config.log_level != log_level || new_config.metrics_port != metrics_port {
+                warn!(
+                    "Log level and metrics port changes require a restart or an in-place \
+                     upgrade (SIGUSR2)"
+                );
+            }
+            for station in &stations {
+                match new_config
+                    .stations
+                    .iter()
+                    .position(|new| new.name == station.name)
+                {
+                    Some(i) => {
+                        station
+                            .apply(
+                                new_config.stations.swap_remove(i),
+                                new_config.shutdown_timeout,
+                            )
+                            .await
+                    }
+                    None => warn!("Removing a station requires a restart"),
+                }
+            }
+            if !new_config.stations.is_empty() {
+                warn!("Adding a station requires a restart");
+            }
+            info!("Configuration reloaded");
+        }
+    });
 }
@@ -0,0 +1,259 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Context};
+use serde::Deserialize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::{JoinHandle, JoinSet};
+use tracing::{debug, info, info_span, warn, Instrument};
+
+use crate::alerts::Quantity;
+use crate::config::{ExporterParams, Shared, StationParams};
+use crate::connections::ACCEPT_BACKOFF;
+use crate::decoder::TempestMsg;
+
+// How a value is held in registers, as fixed point after scaling. 32-bit values take two
+// registers, high word first.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RegisterFormat {
+    #[default]
+    I16,
+    U16,
+    I32,
+    U32,
+}
+
+impl RegisterFormat {
+    fn width(&self) -> usize {
+        match self {
+            RegisterFormat::I16 | RegisterFormat::U16 => 1,
+            RegisterFormat::I32 | RegisterFormat::U32 => 2,
+        }
+    }
+
+    // The value as registers, saturating at the format's limits. Before a value is first received
+    // registers hold the sentinel the format can't otherwise reach: its minimum if signed, or its
+    // maximum if not.
+    fn encode(&self, value: Option<f64>) -> Vec<u16> {
+        match (self, value) {
+            (RegisterFormat::I16, Some(v)) => vec![(v.round() as i16).max(i16::MIN + 1) as u16],
+            (RegisterFormat::I16, None) => vec![i16::MIN as u16],
+            (RegisterFormat::U16, Some(v)) => vec![(v.round() as u16).min(u16::MAX - 1)],
+            (RegisterFormat::U16, None) => vec![u16::MAX],
+            (RegisterFormat::I32, v) => {
+                let v = v.map_or(i32::MIN, |v| (v.round() as i32).max(i32::MIN + 1)) as u32;
+                vec![(v >> 16) as u16, v as u16]
+            }
+            (RegisterFormat::U32, v) => {
+                let v = v.map_or(u32::MAX, |v| (v.round() as u32).min(u32::MAX - 1));
+                vec![(v >> 16) as u16, v as u16]
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Register {
+    pub address: u16,
+    pub quantity: Quantity,
+    // Factor the value is multiplied by before rounding, e.g. 10 for tenths.
+    pub scale: f64,
+    pub format: RegisterFormat,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModbusParams {
+    pub port: u16,
+    pub registers: Vec<Register>,
+}
+
+impl ModbusParams {
+    // Checks that no two values share a register, and that all fit in the address space.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let mut used = std::collections::BTreeMap::new();
+        for register in &self.registers {
+            for offset in 0..register.format.width() {
+                let address = register.address as usize + offset;
+                if address > u16::MAX as usize {
+                    bail!("Modbus register {} is out of range", address);
+                }
+                if let Some(other) = used.insert(address, register.quantity) {
+                    bail!(
+                        "Modbus register {} holds both {:?} and {:?}",
+                        address,
+                        other,
+                        register.quantity
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Register contents, by address from zero to the highest mapped.
+fn initial_registers(params: &ModbusParams) -> Vec<u16> {
+    let len = params
+        .registers
+        .iter()
+        .map(|register| register.address as usize + register.format.width())
+        .max()
+        .unwrap_or(0);
+    let mut registers = vec![0; len];
+    for register in &params.registers {
+        let start = register.address as usize;
+        registers[start..start + register.format.width()]
+            .copy_from_slice(&register.format.encode(None));
+    }
+    registers
+}
+
+// Serves the latest value of each mapped quantity as holding (and input) registers, for devices
+// that only speak Modbus TCP. Registers are read-only.
+pub struct ModbusServer {
+    station_params: Shared<StationParams>,
+    exporter_params: Shared<ExporterParams>,
+    state: Mutex<(ModbusParams, Vec<u16>)>,
+}
+
+const READ_HOLDING_REGISTERS: u8 = 0x03;
+const READ_INPUT_REGISTERS: u8 = 0x04;
+const ILLEGAL_FUNCTION: u8 = 0x01;
+const ILLEGAL_DATA_ADDRESS: u8 = 0x02;
+const ILLEGAL_DATA_VALUE: u8 = 0x03;
+// Most registers one request may read.
+const MAX_READ: u16 = 125;
+
+impl ModbusServer {
+    pub fn new(
+        station_params: Shared<StationParams>,
+        exporter_params: Shared<ExporterParams>,
+        params: ModbusParams,
+    ) -> Self {
+        let registers = initial_registers(&params);
+        Self {
+            station_params,
+            exporter_params,
+            state: Mutex::new((params, registers)),
+        }
+    }
+
+    pub fn port(&self) -> u16 {
+        self.state.lock().unwrap().0.port
+    }
+
+    // Binds the server's port and serves connections until the returned task is aborted.
+    pub async fn spawn(self: &Arc<Self>) -> anyhow::Result<JoinHandle<()>> {
+        let addr = SocketAddr::from(([0, 0, 0, 0], self.port()));
+        let listener = TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("Binding Modbus TCP port {}", addr.port()))?;
+        info!("Serving Modbus TCP on {}", addr);
+        let server = self.clone();
+        Ok(tokio::spawn(
+            async move {
+                // Owned by this task, so that aborting it drops clients too rather than leaving
+                // them served registers that no longer change.
+                let mut connections = JoinSet::new();
+                loop {
+                    tokio::select! {
+                        accepted = listener.accept() => match accepted {
+                            Ok((stream, peer)) => {
+                                let server = server.clone();
+                                connections.spawn(async move {
+                                    if let Err(e) = server.serve(stream).await {
+                                        debug!("Modbus connection from {} closed: {}", peer, e);
+                                    }
+                                });
+                            }
+                            Err(e) => {
+                                warn!("Failed to accept Modbus connection: {}", e);
+                                tokio::time::sleep(ACCEPT_BACKOFF).await;
+                            }
+                        },
+                        // Reaps connections that have closed.
+                        Some(_) = connections.join_next() => {}
+                    }
+                }
+            }
+            .instrument(info_span!("modbus")),
+        ))
+    }
+
+    pub fn reconfigure(&self, params: ModbusParams) {
+        let mut state = self.state.lock().unwrap();
+        if params.registers != state.0.registers {
+            info!("Modbus register map changed");
+            *state = (params.clone(), initial_registers(&params));
+        }
+    }
+
+    // Filtered out quantities hold the sentinel for no value, so that a filter changed on reload
+    // takes effect.
+    pub fn handle_report(&self, msg: &TempestMsg) {
+        let station_params = self.station_params.read().unwrap();
+        let filter = &self.exporter_params.read().unwrap().publish_filter;
+        let mut state = self.state.lock().unwrap();
+        let (params, registers) = &mut *state;
+        for register in &params.registers {
+            let value = match register.quantity.value(msg, &station_params) {
+                Some(_) if !filter.allows(register.quantity.topic()) => None,
+                Some(value) if value.is_finite() => Some(value * register.scale),
+                _ => continue,
+            };
+            let start = register.address as usize;
+            registers[start..start + register.format.width()]
+                .copy_from_slice(&register.format.encode(value));
+        }
+    }
+
+    // Answers requests on one connection until the client hangs up.
+    async fn serve(&self, mut stream: TcpStream) -> anyhow::Result<()> {
+        loop {
+            // MBAP header: transaction ID, protocol ID, length of what follows, unit ID.
+            let mut header = [0; 7];
+            stream.read_exact(&mut header).await?;
+            let length = u16::from_be_bytes([header[4], header[5]]) as usize;
+            if header[2..4] != [0, 0] || !(2..=254).contains(&length) {
+                bail!("Malformed Modbus request");
+            }
+            let mut pdu = vec![0; length - 1];
+            stream.read_exact(&mut pdu).await?;
+            let response = self.respond(&pdu);
+            let mut frame = Vec::with_capacity(7 + response.len());
+            frame.extend(&header[..4]);
+            frame.extend((response.len() as u16 + 1).to_be_bytes());
+            frame.push(header[6]);
+            frame.extend(response);
+            stream.write_all(&frame).await?;
+        }
+    }
+
+    fn respond(&self, pdu: &[u8]) -> Vec<u8> {
+        let function = pdu[0];
+        let exception = |code| vec![function | 0x80, code];
+        if function != READ_HOLDING_REGISTERS && function != READ_INPUT_REGISTERS {
+            return exception(ILLEGAL_FUNCTION);
+        }
+        if pdu.len() != 5 {
+            return exception(ILLEGAL_DATA_VALUE);
+        }
+        let start = u16::from_be_bytes([pdu[1], pdu[2]]) as usize;
+        let count = u16::from_be_bytes([pdu[3], pdu[4]]);
+        if !(1..=MAX_READ).contains(&count) {
+            return exception(ILLEGAL_DATA_VALUE);
+        }
+        let state = self.state.lock().unwrap();
+        let values = match state.1.get(start..start + count as usize) {
+            Some(values) => values,
+            None => return exception(ILLEGAL_DATA_ADDRESS),
+        };
+        let mut response = vec![function, (count * 2) as u8];
+        for value in values {
+            response.extend(value.to_be_bytes());
+        }
+        response
+    }
+}
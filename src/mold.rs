@@ -0,0 +1,53 @@
+//! Mold and condensation risk, from how long the air has recently stayed humid enough for mold to
+//! grow. For users watching sheds, greenhouses and crawl spaces that share the station's air.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::trend::Trend;
+
+/// Period over which humid hours are counted.
+pub const WINDOW: Duration = Duration::from_secs(24 * 3600);
+
+/// Relative humidity from which mold can grow on most surfaces (%).
+pub const HUMID_RH: f64 = 80.0;
+
+// Temperatures between which mold grows (°C).
+const GROWTH_TEMPERATURES: (f64, f64) = (0.0, 50.0);
+
+// Humid hours within the window from which each risk level applies.
+const LEVEL_HOURS: [f64; 3] = [6.0, 12.0, 18.0];
+
+/// Tracks the time spent in conditions conducive to mold over the last day.
+#[derive(Debug, Default, Serialize)]
+pub struct MoldRisk {
+    conducive: Trend,
+}
+
+impl MoldRisk {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an observation's temperature (°C) and relative humidity (%).
+    pub fn push(&mut self, timestamp: DateTime<Utc>, temperature: f64, relative_humidity: f64) {
+        let (min, max) = GROWTH_TEMPERATURES;
+        let conducive = relative_humidity >= HUMID_RH && (min..=max).contains(&temperature);
+        self.conducive
+            .push(timestamp, conducive as u8 as f64, WINDOW);
+    }
+
+    /// Hours spent at or above [`HUMID_RH`], at temperatures mold grows at, within the last day.
+    pub fn humid_hours(&self) -> f64 {
+        self.conducive.integral(WINDOW) / 3600.0
+    }
+
+    /// Risk from 0 (none) through 1 (low) and 2 (moderate) to 3 (high), as humid hours in the last
+    /// day reach 6, 12 and 18.
+    pub fn level(&self) -> u8 {
+        let hours = self.humid_hours();
+        LEVEL_HOURS.iter().filter(|&&from| hours >= from).count() as u8
+    }
+}
@@ -0,0 +1,331 @@
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, info_span, warn, Instrument};
+
+use crate::config::{ExporterParams, Shared, StationParams};
+use crate::connections::ACCEPT_BACKOFF;
+use crate::decoder::{Observation, TempestMsg, Wind};
+use crate::publisher::{INSTANT_WIND_TOPICS, WIND_AVG_TOPICS};
+use crate::signalk::WindConvention;
+use crate::sink_queue::LastError;
+use crate::units::{HectoPascal, MetersPerSecond};
+use tempest_exporter::clock::SharedClock;
+use tempest_exporter::filter::PublishFilter;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NmeaParams {
+    // Two-letter talker ID sentences are sent as, e.g. WI for weather instruments.
+    pub talker: String,
+    pub udp_target: Option<SocketAddr>,
+    // Port to serve sentences on to any TCP client that connects.
+    pub tcp_port: Option<u16>,
+    pub wind: WindConvention,
+    // Least time between MWV sentences, and between MDA and XDR sentences.
+    pub wind_interval: Duration,
+    pub observation_interval: Duration,
+}
+
+impl NmeaParams {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.talker.len() != 2 || !self.talker.bytes().all(|b| b.is_ascii_uppercase()) {
+            bail!("NMEA talker ID must be two uppercase letters");
+        }
+        if self.udp_target.is_none() && self.tcp_port.is_none() {
+            bail!("NMEA output needs a UDP target or a TCP port");
+        }
+        Ok(())
+    }
+}
+
+// Completes a sentence from its talker ID, type and fields, with its checksum.
+fn sentence(talker: &str, kind: &str, fields: &[String]) -> String {
+    let body = format!("{}{},{}", talker, kind, fields.join(","));
+    let checksum = body.bytes().fold(0, |sum, b| sum ^ b);
+    format!("${}*{:02X}\r\n", body, checksum)
+}
+
+fn field(value: Option<f64>, precision: usize) -> String {
+    value.map_or(String::new(), |v| format!("{:.*}", precision, v))
+}
+
+// Wind speed and angle, relative to the bow if apparent.
+fn mwv(talker: &str, wind: &Wind, convention: WindConvention) -> String {
+    let reference = match convention {
+        WindConvention::Apparent => "R",
+        WindConvention::True => "T",
+    };
+    sentence(
+        talker,
+        "MWV",
+        &[
+            format!("{:.1}", wind.source_direction().rem_euclid(360.0)),
+            reference.to_string(),
+            format!("{:.1}", wind.speed_magnitude()),
+            "M".to_string(),
+            "A".to_string(),
+        ],
+    )
+}
+
+// Meteorological composite, leaving blank the readings the filter drops by their MQTT topic. Wind
+// direction is only given when it is true.
+fn mda(
+    talker: &str,
+    obs: &Observation,
+    station_params: &StationParams,
+    filter: &PublishFilter,
+    convention: WindConvention,
+) -> String {
+    let pressure = obs
+        .barometric_pressure(station_params.elevation)
+        .filter(|_| filter.allows("observation/pressure/barometric_hpa"));
+    let temperature = obs
+        .air_temperature
+        .filter(|_| filter.allows("observation/thermal/temperature_deg_c"));
+    let humidity = obs
+        .relative_humidity
+        .filter(|_| filter.allows("observation/thermal/relative_humidity_pct"));
+    let dew_point = obs
+        .dew_point()
+        .filter(|_| filter.allows("observation/thermal/dew_point_deg_c"));
+    let wind = obs.wind.as_ref().map(|wind| &wind.avg);
+    let direction = wind
+        .filter(|_| convention == WindConvention::True)
+        .filter(|_| filter.allows(WIND_AVG_TOPICS.source_direction))
+        .map(|wind| wind.source_direction().rem_euclid(360.0));
+    let speed = wind
+        .filter(|_| filter.allows(WIND_AVG_TOPICS.speed_magnitude))
+        .map(|wind| wind.speed_magnitude());
+    sentence(
+        talker,
+        "MDA",
+        &[
+            field(pressure.map(HectoPascal::inches_of_mercury), 2),
+            "I".to_string(),
+            field(pressure.map(HectoPascal::bars), 4),
+            "B".to_string(),
+            field(temperature.map(f64::from), 1),
+            "C".to_string(),
+            String::new(), // Water temperature
+            "C".to_string(),
+            field(humidity, 1),
+            String::new(), // Absolute humidity
+            field(dew_point.map(f64::from), 1),
+            "C".to_string(),
+            field(direction, 1),
+            "T".to_string(),
+            String::new(), // Magnetic direction
+            "M".to_string(),
+            field(speed.map(MetersPerSecond::knots), 1),
+            "N".to_string(),
+            field(speed.map(f64::from), 1),
+            "M".to_string(),
+        ],
+    )
+}
+
+// Transducer measurements, for plotters that don't read MDA, leaving out those the filter drops
+// by their MQTT topic. `None` if there are none.
+fn xdr(
+    talker: &str,
+    obs: &Observation,
+    station_params: &StationParams,
+    filter: &PublishFilter,
+) -> Option<String> {
+    let transducers = [
+        (
+            "observation/pressure/barometric_hpa",
+            "P",
+            obs.barometric_pressure(station_params.elevation)
+                .map(|p| format!("{:.4}", p.bars())),
+            "B",
+            "Barometer",
+        ),
+        (
+            "observation/thermal/temperature_deg_c",
+            "C",
+            obs.air_temperature.map(|t| format!("{:.1}", t)),
+            "C",
+            "AirTemp",
+        ),
+        (
+            "observation/thermal/relative_humidity_pct",
+            "H",
+            obs.relative_humidity.map(|rh| format!("{:.1}", rh)),
+            "P",
+            "Humidity",
+        ),
+    ];
+    let fields: Vec<_> = transducers
+        .into_iter()
+        .filter(|(topic, ..)| filter.allows(topic))
+        .filter_map(|(_, kind, value, unit, name)| {
+            Some([kind.to_string(), value?, unit.to_string(), name.to_string()])
+        })
+        .flatten()
+        .collect();
+    (!fields.is_empty()).then(|| sentence(talker, "XDR", &fields))
+}
+
+// Sends NMEA 0183 weather sentences for chartplotters and navigation software: MWV from rapid
+// wind, and MDA and XDR from observations.
+pub struct NmeaSink {
+    station_params: Shared<StationParams>,
+    exporter_params: Shared<ExporterParams>,
+    params: Mutex<NmeaParams>,
+    socket: UdpSocket,
+    clients: broadcast::Sender<String>,
+    // When each kind of sentence was last sent, to hold to the configured rates.
+    last_wind: Mutex<Option<Instant>>,
+    last_observation: Mutex<Option<Instant>>,
+    errors: Arc<LastError>,
+    clock: SharedClock,
+}
+
+// Sentences buffered for a slow TCP client before it starts missing them.
+const CLIENT_BACKLOG: usize = 64;
+
+impl NmeaSink {
+    pub fn new(
+        station_params: Shared<StationParams>,
+        exporter_params: Shared<ExporterParams>,
+        params: NmeaParams,
+        clock: SharedClock,
+    ) -> anyhow::Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0").context("Binding NMEA socket")?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            station_params,
+            exporter_params,
+            params: Mutex::new(params),
+            socket,
+            clients: broadcast::channel(CLIENT_BACKLOG).0,
+            last_wind: Mutex::new(None),
+            last_observation: Mutex::new(None),
+            errors: Arc::new(LastError::default()),
+            clock,
+        })
+    }
+
+    // Where send errors are recorded, for `/debug/state`.
+    pub fn errors(&self) -> Arc<LastError> {
+        self.errors.clone()
+    }
+
+    pub fn tcp_port(&self) -> Option<u16> {
+        self.params.lock().unwrap().tcp_port
+    }
+
+    // Binds the TCP port, if one is configured, and serves sentences to clients until the
+    // returned task is aborted.
+    pub async fn spawn(&self) -> anyhow::Result<Option<JoinHandle<()>>> {
+        let port = match self.tcp_port() {
+            Some(port) => port,
+            None => return Ok(None),
+        };
+        let addr = SocketAddr::from(([0, 0, 0, 0], port));
+        let listener = TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("Binding NMEA TCP port {}", port))?;
+        info!("Serving NMEA 0183 on {}", addr);
+        let clients = self.clients.clone();
+        Ok(Some(tokio::spawn(
+            async move {
+                loop {
+                    let (mut stream, peer) = match listener.accept().await {
+                        Ok(accepted) => accepted,
+                        Err(e) => {
+                            warn!("Failed to accept NMEA connection: {}", e);
+                            tokio::time::sleep(ACCEPT_BACKOFF).await;
+                            continue;
+                        }
+                    };
+                    let mut sentences = clients.subscribe();
+                    tokio::spawn(async move {
+                        loop {
+                            match sentences.recv().await {
+                                Ok(sentence) => {
+                                    if let Err(e) = stream.write_all(sentence.as_bytes()).await {
+                                        debug!("NMEA client {} disconnected: {}", peer, e);
+                                        break;
+                                    }
+                                }
+                                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                                Err(broadcast::error::RecvError::Closed) => break,
+                            }
+                        }
+                    });
+                }
+            }
+            .instrument(info_span!("nmea")),
+        )))
+    }
+
+    pub fn reconfigure(&self, new_params: NmeaParams) {
+        let mut params = self.params.lock().unwrap();
+        if *params != new_params {
+            info!("NMEA parameters changed");
+            *params = new_params;
+        }
+    }
+
+    pub fn handle_report(&self, msg: &TempestMsg) {
+        let params = self.params.lock().unwrap();
+        let exporter_params = self.exporter_params.read().unwrap();
+        let filter = &exporter_params.publish_filter;
+        let now = self.clock.now();
+        let sentences = match msg {
+            TempestMsg::RapidWind(rw) if due(&self.last_wind, params.wind_interval, now) => {
+                // MWV has no use without both its angle and its speed.
+                if !filter.allows(INSTANT_WIND_TOPICS.source_direction)
+                    || !filter.allows(INSTANT_WIND_TOPICS.speed_magnitude)
+                {
+                    return;
+                }
+                vec![mwv(&params.talker, &rw.wind, params.wind)]
+            }
+            TempestMsg::Observation(obs)
+                if due(&self.last_observation, params.observation_interval, now) =>
+            {
+                let station_params = self.station_params.read().unwrap();
+                let mut sentences = vec![mda(
+                    &params.talker,
+                    obs,
+                    &station_params,
+                    filter,
+                    params.wind,
+                )];
+                sentences.extend(xdr(&params.talker, obs, &station_params, filter));
+                sentences
+            }
+            _ => return,
+        };
+        for sentence in sentences {
+            if let Some(target) = params.udp_target {
+                if let Err(e) = self.socket.send_to(sentence.as_bytes(), target) {
+                    warn!("NMEA send failed: {}", e);
+                    self.errors.record(format!("Send failed: {}", e));
+                }
+            }
+            // Fails only when no client is connected.
+            self.clients.send(sentence).ok();
+        }
+    }
+}
+
+// Whether at least `interval` has passed between `last` and `now`, updating it if so.
+fn due(last: &Mutex<Option<Instant>>, interval: Duration, now: Instant) -> bool {
+    let mut last = last.lock().unwrap();
+    if matches!(*last, Some(last) if now < last + interval) {
+        return false;
+    }
+    *last = Some(now);
+    true
+}
@@ -0,0 +1,161 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail};
+use serde_json::{json, Map, Value};
+use tokio_stream::StreamExt;
+
+use crate::config::{self, Config, StationParams};
+use crate::decoder::{self, Observation, TempestMsg};
+use crate::exporter::Exporter;
+use crate::{calibrator, reader, receiver};
+
+#[derive(Clone, Copy, Debug)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Prom,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "prom" => Ok(OutputFormat::Prom),
+            other => bail!("Unrecognized output format {}", other),
+        }
+    }
+}
+
+pub async fn run(config: Config, format: OutputFormat) -> anyhow::Result<()> {
+    // Only the first station is watched.
+    let station = config.station();
+    let station_params = config::shared(station.station_params.clone());
+    let rx = receiver::Receiver::bind(station.api_port).await?;
+    let dec = decoder::new(reader::with_mode(rx, station.decode_mode));
+    let mut dec = calibrator::new(dec, station_params.clone());
+
+    let msg = loop {
+        match dec.next().await {
+            Some(msg @ TempestMsg::Observation(_)) => break msg,
+            Some(_) => continue,
+            None => {
+                return Err(anyhow!(
+                    "Decoder stream ended before an observation arrived"
+                ))
+            }
+        }
+    };
+    let obs = match &msg {
+        TempestMsg::Observation(obs) => obs,
+        _ => unreachable!(),
+    };
+
+    match format {
+        OutputFormat::Text => {
+            println!("{} {}", obs.serial_number, obs.timestamp.to_rfc3339());
+            for (name, value) in fields(obs, &station.station_params) {
+                if let Some(value) = value {
+                    println!("{:<32} {}", name, value);
+                }
+            }
+            for derived in &station.station_params.derived {
+                if let Some(value) = derived.evaluate(obs, &station.station_params) {
+                    println!("{:<32} {}", derived.name, value);
+                }
+            }
+        }
+        OutputFormat::Json => {
+            let mut doc = Map::new();
+            doc.insert("serial_number".into(), json!(obs.serial_number));
+            doc.insert("timestamp".into(), json!(obs.timestamp.to_rfc3339()));
+            if !station.station_params.metadata.is_empty() {
+                doc.insert("station".into(), json!(station.station_params.metadata));
+            }
+            for (name, value) in fields(obs, &station.station_params) {
+                doc.insert(name.into(), json!(value));
+            }
+            if !station.station_params.derived.is_empty() {
+                let derived: Map<_, _> = station
+                    .station_params
+                    .derived
+                    .iter()
+                    .map(|d| {
+                        (
+                            d.name.clone(),
+                            json!(d.evaluate(obs, &station.station_params)),
+                        )
+                    })
+                    .collect();
+                doc.insert("derived".into(), Value::Object(derived));
+            }
+            let mut doc = Value::Object(doc);
+            station.exporter_params.precision.round_json(&mut doc);
+            println!("{}", doc);
+        }
+        OutputFormat::Prom => {
+            let exporter = Exporter::new(
+                station_params,
+                config::shared(station.exporter_params.clone()),
+            );
+            exporter.handle_report(&msg);
+            print!("{}", String::from_utf8(exporter.encode())?);
+        }
+    }
+    Ok(())
+}
+
+fn fields(obs: &Observation, station_params: &StationParams) -> Vec<(&'static str, Option<f64>)> {
+    let wind = obs.wind.as_ref();
+    let solar = obs.solar.as_ref();
+    vec![
+        (
+            "wind_lull_m_per_s",
+            wind.map(|w| w.lull.speed_magnitude().0),
+        ),
+        ("wind_avg_m_per_s", wind.map(|w| w.avg.speed_magnitude().0)),
+        (
+            "wind_gust_m_per_s",
+            wind.map(|w| w.gust.speed_magnitude().0),
+        ),
+        ("wind_direction_deg", wind.map(|w| w.avg.source_direction())),
+        ("station_pressure_hpa", obs.station_pressure.map(f64::from)),
+        (
+            "barometric_pressure_hpa",
+            obs.barometric_pressure(station_params.elevation)
+                .map(f64::from),
+        ),
+        ("temperature_deg_c", obs.air_temperature.map(f64::from)),
+        ("relative_humidity_pct", obs.relative_humidity),
+        ("dew_point_deg_c", obs.dew_point().map(f64::from)),
+        (
+            "wet_bulb_temperature_deg_c",
+            obs.wet_bulb_temperature().map(f64::from),
+        ),
+        (
+            "wet_bulb_globe_temperature_deg_c",
+            obs.wet_bulb_globe_temperature().map(f64::from),
+        ),
+        (
+            "apparent_temperature_deg_c",
+            obs.apparent_temperature_by(station_params.apparent_temperature_formula)
+                .map(f64::from),
+        ),
+        ("illuminance_lux", solar.map(|s| s.illuminance)),
+        ("irradiance_w_per_m2", solar.map(|s| s.irradiance)),
+        ("uv_index", solar.map(|s| s.ultraviolet_index)),
+        (
+            "clear_sky_index",
+            station_params
+                .metadata
+                .location()
+                .and_then(|(latitude, longitude)| obs.clear_sky_index(latitude, longitude)),
+        ),
+        (
+            "previous_minute_rain_mm",
+            obs.precip.as_ref().map(|p| p.quantity_last_minute.0),
+        ),
+        ("battery_volts", Some(obs.battery_volts)),
+    ]
+}
@@ -0,0 +1,186 @@
+//! Parameters that tune decoding and export, which may change at runtime.
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use chrono_tz::Tz;
+
+use crate::day::DayZone;
+pub use crate::decoder::ApparentTemperatureFormula;
+use crate::derived::DerivedMetric;
+use crate::filter::PublishFilter;
+use crate::precision::Precision;
+use crate::rain::SeasonStart;
+use crate::smoothing::Smoothing;
+use serde::{Deserialize, Serialize, Serializer};
+
+/// Parameters shared between pipeline stages, replaced in place when configuration is reloaded.
+pub type Shared<T> = Arc<RwLock<T>>;
+
+pub fn shared<T>(t: T) -> Shared<T> {
+    Arc::new(RwLock::new(t))
+}
+
+/// How long metrics remain exported after the report that last updated them, and how derived
+/// signals are judged.
+#[derive(Clone, Debug)]
+pub struct ExporterParams {
+    pub instant_wind_ttl: Duration,
+    /// Period of instantaneous wind summarized each time metrics are gathered.
+    pub instant_wind_window: Duration,
+    pub observation_ttl: Duration,
+    pub storm: StormParams,
+    /// A rain event ends after this long without rain.
+    pub rain_event_dry_period: Duration,
+    /// When the rain season totalled since its start begins each year.
+    pub rain_season_start: SeasonStart,
+    /// Which names renamed metrics are exposed under, keyed by current name. Unlisted renamed
+    /// metrics are exposed under both.
+    pub metric_names: HashMap<String, NameMode>,
+    /// Observation fields also exported exponentially smoothed.
+    pub smoothing: Vec<Smoothing>,
+    /// Which MQTT topics and Prometheus metrics are published.
+    pub publish_filter: PublishFilter,
+    /// Decimal places published values are rounded to.
+    pub precision: Precision,
+}
+
+impl Default for ExporterParams {
+    /// The defaults of the corresponding options.
+    fn default() -> Self {
+        ExporterParams {
+            instant_wind_ttl: Duration::from_secs(15),
+            instant_wind_window: Duration::from_secs(60),
+            observation_ttl: Duration::from_secs(3 * 60),
+            storm: StormParams::default(),
+            rain_event_dry_period: Duration::from_secs(30 * 60),
+            rain_season_start: SeasonStart::default(),
+            metric_names: HashMap::new(),
+            smoothing: Vec::new(),
+            publish_filter: PublishFilter::default(),
+            precision: Precision::default(),
+        }
+    }
+}
+
+/// Names a renamed metric is exposed under during its deprecation window.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NameMode {
+    Current,
+    Legacy,
+    #[default]
+    Both,
+}
+
+/// A storm is signalled when station pressure falls by at least `pressure_drop` (hPa) within
+/// `window`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StormParams {
+    pub window: Duration,
+    pub pressure_drop: f64,
+}
+
+impl Default for StormParams {
+    /// A drop of 3 hPa within three hours.
+    fn default() -> Self {
+        StormParams {
+            window: Duration::from_secs(3 * 3600),
+            pressure_drop: 3.0,
+        }
+    }
+}
+
+impl StormParams {
+    /// Whether a pressure change over the window signals a storm.
+    pub fn signalled_by(&self, pressure_change: f64) -> bool {
+        pressure_change <= -self.pressure_drop
+    }
+}
+
+/// Properties of the station site and its sensors. The default is an uncalibrated station at sea
+/// level.
+#[derive(Clone, Debug, Default)]
+pub struct StationParams {
+    /// Elevation above mean sea level (m), used to reduce station pressure to sea level.
+    pub elevation: f64,
+    pub metadata: StationMetadata,
+    pub apparent_temperature_formula: ApparentTemperatureFormula,
+    pub calibration: Calibration,
+    pub devices: HashMap<String, Calibration>,
+    /// User-defined metrics derived from each observation.
+    pub derived: Vec<DerivedMetric>,
+}
+
+impl StationParams {
+    /// Device-specific calibration if configured, falling back to the station-wide calibration.
+    pub fn calibration_for(&self, serial_number: &str) -> &Calibration {
+        self.devices.get(serial_number).unwrap_or(&self.calibration)
+    }
+}
+
+/// Optional identification of the station site, attached to exported data so that stations in a
+/// fleet can be told apart without relabeling.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct StationMetadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Latitude in decimal degrees, positive north.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latitude: Option<f64>,
+    /// Longitude in decimal degrees, positive east.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub longitude: Option<f64>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "timezone_name"
+    )]
+    pub timezone: Option<Tz>,
+}
+
+impl StationMetadata {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The timezone days start over in: the station's, or the system's if it isn't set.
+    pub fn day_zone(&self) -> DayZone {
+        self.timezone.into()
+    }
+
+    /// Latitude and longitude, if both are set.
+    pub fn location(&self) -> Option<(f64, f64)> {
+        Some((self.latitude?, self.longitude?))
+    }
+
+    /// The metadata that is set, as label names and values.
+    pub fn labels(&self) -> Vec<(&'static str, String)> {
+        [
+            ("name", self.name.clone()),
+            ("latitude", self.latitude.map(|v| v.to_string())),
+            ("longitude", self.longitude.map(|v| v.to_string())),
+            ("timezone", self.timezone.map(|tz| tz.name().to_string())),
+        ]
+        .into_iter()
+        .filter_map(|(label, value)| Some((label, value?)))
+        .collect()
+    }
+}
+
+fn timezone_name<S: Serializer>(timezone: &Option<Tz>, serializer: S) -> Result<S::Ok, S::Error> {
+    match timezone {
+        Some(tz) => serializer.serialize_str(tz.name()),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Corrections to raw sensor readings: offsets added to temperature (°C) and relative humidity
+/// (%), and floors for illuminance (lux) and UV index below which they read as zero.
+#[derive(Clone, Debug, Default)]
+pub struct Calibration {
+    pub temp_offset: f64,
+    pub rh_offset: f64,
+    pub illuminance_floor: f64,
+    pub uv_floor: f64,
+}
@@ -1,21 +1,28 @@
 use crossbeam_utils::atomic::AtomicCell;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
 use std::time::{Duration, Instant};
 
-// First member holds a perishable metric T that expires at the instant given by the second member.
-pub struct Perishable<T>(T, AtomicCell<Instant>);
+use crate::clock::SharedClock;
+
+// First member holds a perishable metric T, second member the instant it was last freshened (if
+// ever) and the instant it expires, third member the clock those are read from.
+pub struct Perishable<T>(T, AtomicCell<(Option<Instant>, Instant)>, SharedClock);
 
 impl<T> Perishable<T> {
-    pub fn new(t: T) -> Self {
-        Perishable(t, AtomicCell::new(Instant::now()))
+    pub fn new(t: T, clock: &SharedClock) -> Self {
+        Perishable(t, AtomicCell::new((None, clock.now())), clock.clone())
     }
 
     pub fn freshen(&self, valid_duration: Duration) -> &T {
-        self.1.store(Instant::now() + valid_duration);
+        let now = self.2.now();
+        self.1.store((Some(now), now + valid_duration));
         &self.0
     }
 
     pub fn fresh(&self) -> Option<&T> {
-        if self.1.load() >= Instant::now() {
+        if self.expires_at() >= self.2.now() {
             Some(&self.0)
         } else {
             None
@@ -28,4 +35,45 @@ impl<T> Perishable<T> {
     {
         self.fresh().map(f)
     }
+
+    // When the metric was last freshened, or `None` if it never has been.
+    pub fn last_update(&self) -> Option<Instant> {
+        self.1.load().0
+    }
+
+    pub fn expires_at(&self) -> Instant {
+        self.1.load().1
+    }
+}
+
+// Perishable metrics keyed by their label values, such as the serial number of the device they
+// describe, each expiring on its own so that one device going quiet doesn't leave its series
+// behind or hold up the others. Metrics carry their labels as constant labels, and are
+// registered individually.
+pub struct PerishableMap<K, T>(Mutex<HashMap<K, Perishable<T>>>, SharedClock);
+
+impl<K: Eq + Hash, T: Clone> PerishableMap<K, T> {
+    pub fn new(clock: &SharedClock) -> Self {
+        PerishableMap(Mutex::new(HashMap::new()), clock.clone())
+    }
+
+    // Freshens the metric for the key, first creating it with `init` if there is none fresh.
+    pub fn freshen(&self, key: K, valid_duration: Duration, init: impl FnOnce(&K) -> T) -> T {
+        let mut metrics = self.0.lock().unwrap();
+        metrics
+            .entry(key)
+            .or_insert_with_key(|key| Perishable::new(init(key), &self.1))
+            .freshen(valid_duration)
+            .clone()
+    }
+
+    // The fresh metrics, forgetting any that have expired.
+    pub fn fresh(&self) -> Vec<T> {
+        let mut metrics = self.0.lock().unwrap();
+        metrics.retain(|_, metric| metric.fresh().is_some());
+        metrics
+            .values()
+            .filter_map(|metric| metric.fresh().cloned())
+            .collect()
+    }
 }
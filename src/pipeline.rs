@@ -0,0 +1,915 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use tokio::sync::{oneshot, watch};
+use tokio::task::JoinHandle;
+use tokio_stream::{Stream, StreamExt};
+use tracing::{debug_span, error, info, warn, Instrument};
+
+use crate::alerts::Alerter;
+use crate::clock::SharedClock;
+use crate::config::{self, ExporterParams, Shared, StationConfig, StationParams};
+use crate::decoder::TempestMsg;
+use crate::ecowitt::EcowittSink;
+use crate::exporter::PublisherMetrics;
+use crate::grafana_live::GrafanaLiveSink;
+use crate::heartbeat::Heartbeat;
+use crate::history::History;
+use crate::hubs::HubSelector;
+use crate::knx::KnxBridge;
+use crate::modbus::{ModbusParams, ModbusServer};
+use crate::nmea::{NmeaParams, NmeaSink};
+use crate::publisher::Publisher;
+use crate::signalk::SignalKSink;
+use crate::sink_queue::{DropPolicy, LastError, QueueOptions, SinkQueue};
+use crate::state::StationState;
+use crate::{
+    calibrator, checkpoint, clock, decoder, exporter, rain_check, reader, receiver, reference,
+};
+use crate::{sockets, systemd};
+
+const SINK_QUEUE_CAPACITY: usize = 256;
+
+type Decoded = Pin<Box<dyn Stream<Item = TempestMsg> + Send>>;
+
+// One station's pipeline: the reports decoded from its hub's broadcasts, fanned out to the
+// exporter, MQTT publisher and alerter that are enabled, each behind its own queue.
+pub struct Pipeline {
+    pub name: Option<String>,
+    pub exporter: Arc<exporter::Exporter>,
+    // Recent observations, kept while HTTP is served.
+    pub history: Arc<Mutex<History>>,
+    // The latest reports, updated ahead of the sinks.
+    pub state: watch::Receiver<StationState>,
+    state_tx: Arc<watch::Sender<StationState>>,
+    // The sinks running, which configuration reloads start and stop.
+    pub sinks: Arc<Sinks>,
+    publisher: Arc<Publisher>,
+    reconfigurable: Arc<Reconfigurable>,
+    checkpointer: Option<JoinHandle<()>>,
+    dec: Option<Decoded>,
+    has_data: bool,
+    pump: Option<JoinHandle<anyhow::Result<()>>>,
+    pump_shutdown_tx: Option<oneshot::Sender<()>>,
+}
+
+impl Pipeline {
+    // Restores the station's saved state and starts its sinks, receiving on `socket` if one was
+    // inherited and otherwise binding the station's port.
+    pub async fn start(
+        config: StationConfig,
+        socket: Option<std::net::UdpSocket>,
+        checkpoint_interval: Duration,
+        enable_prometheus: bool,
+        handover: &mut sockets::Handover,
+    ) -> anyhow::Result<Self> {
+        let rx = match socket {
+            Some(socket) => receiver::Receiver::from_std(socket)?,
+            None => receiver::Receiver::bind(config.api_port)
+                .await
+                .with_context(|| format!("Binding UDP port {}", config.api_port))?,
+        };
+        handover.keep(&rx)?;
+        let station_params = config::shared(config.station_params.clone());
+        let exporter_params = config::shared(config.exporter_params.clone());
+        // One clock times everything the station's reports drive.
+        let clock = clock::system();
+        let exporter = Arc::new(exporter::Exporter::with_clock(
+            station_params.clone(),
+            exporter_params.clone(),
+            clock.clone(),
+        ));
+
+        let hub_selector = Arc::new(Mutex::new(HubSelector::new(config.hub_preference.clone())));
+        let (state_tx, state) = watch::channel(StationState::default());
+        let state_tx = Arc::new(state_tx);
+        let rdr = reader::with_drop_hook(rx, config.decode_mode, {
+            let exporter = exporter.clone();
+            move |e| exporter.handle_dropped("read", reader::error_category(e))
+        })
+        .filter({
+            let hub_selector = hub_selector.clone();
+            let exporter = exporter.clone();
+            let clock = clock.clone();
+            move |raw| {
+                let selected = hub_selector.lock().unwrap().select(raw, clock.now());
+                if !selected {
+                    exporter.handle_ignored_copy(raw);
+                }
+                selected
+            }
+        })
+        .map({
+            let state_tx = state_tx.clone();
+            move |raw| {
+                // Reports as received are only read when saving, so no reader needs waking.
+                state_tx.send_if_modified(|state| {
+                    state.record(&raw);
+                    false
+                });
+                raw
+            }
+        });
+        let dec = decoder::with_drop_hook(rdr, {
+            let exporter = exporter.clone();
+            move |e| exporter.handle_dropped("decode", e.category())
+        });
+        let dec = calibrator::new(dec, station_params.clone());
+
+        let publisher = Arc::new(Publisher::new(
+            station_params.clone(),
+            exporter_params.clone(),
+            config.mqtt_params.clone(),
+            publisher_metrics(&exporter, config.enable_mqtt),
+            clock.clone(),
+        ));
+        if let Some(checkpoint) = config.state_file.as_deref().and_then(checkpoint::load) {
+            info!("Restoring state saved at {}", checkpoint.saved_at);
+            exporter.restore(&checkpoint);
+            // Before the saved reports are replayed, so that the counter they publish is the
+            // saved one rather than counting from zero.
+            publisher.restore_domoticz_rain(checkpoint.domoticz_rain);
+            restore_reports(
+                checkpoint.last_reports,
+                &station_params,
+                &exporter,
+                &publisher,
+                &state_tx,
+            );
+        }
+        let checkpointer = config.state_file.clone().map(|path| {
+            spawn_checkpointer(
+                path,
+                checkpoint_interval,
+                exporter.clone(),
+                publisher.clone(),
+                state.clone(),
+            )
+        });
+        let history = Arc::new(Mutex::new(History::new(config.history_retention)));
+        let sinks = Arc::new(Sinks::default());
+        let reconfigurable = Arc::new(Reconfigurable {
+            name: config.name.clone(),
+            api_port: config.api_port,
+            decode_mode: config.decode_mode,
+            queues: config.queues.clone(),
+            state_file: config.state_file.clone(),
+            station_params,
+            exporter_params,
+            exporter: exporter.clone(),
+            publisher: publisher.clone(),
+            clock,
+            hub_selector,
+            history: history.clone(),
+            sinks: sinks.clone(),
+            outputs: tokio::sync::Mutex::new(Outputs::default()),
+        });
+        if enable_prometheus {
+            reconfigurable.start_exporter();
+        }
+        let errors = reconfigurable.update_outputs(config).await;
+        if let Some(e) = errors.into_iter().next() {
+            return Err(e);
+        }
+
+        Ok(Self {
+            name: reconfigurable.name.clone(),
+            exporter,
+            history,
+            state,
+            state_tx,
+            sinks,
+            publisher,
+            reconfigurable,
+            checkpointer,
+            dec: Some(Box::pin(dec)),
+            has_data: false,
+            pump: None,
+            pump_shutdown_tx: None,
+        })
+    }
+
+    pub fn reconfigurable(&self) -> Arc<Reconfigurable> {
+        self.reconfigurable.clone()
+    }
+
+    // Waits for the station's first message and dispatches it, before metrics are served.
+    pub async fn wait_for_data(
+        &mut self,
+        timeout: Option<Duration>,
+        readiness: &Readiness,
+    ) -> anyhow::Result<()> {
+        let dec = self.dec.as_mut().unwrap();
+        let msg = first_message(dec, timeout).await?;
+        dispatch(&self.sinks, &self.state_tx, msg).await;
+        self.has_data = true;
+        readiness.station_ready(self.name.as_deref());
+        Ok(())
+    }
+
+    // Feeds decoded reports to the sinks until stopped, first waiting for data if
+    // `wait_for_data` didn't.
+    pub fn spawn_pump(&mut self, first_data_timeout: Option<Duration>, readiness: Arc<Readiness>) {
+        let mut dec = self.dec.take().unwrap();
+        let has_data = self.has_data;
+        let name = self.name.clone();
+        let sinks = self.sinks.clone();
+        let state_tx = self.state_tx.clone();
+        let (shutdown_tx, mut shutdown_rx) = oneshot::channel();
+        let pump = tokio::spawn(async move {
+            if !has_data {
+                let msg = first_message(&mut dec, first_data_timeout)
+                    .instrument(debug_span!("receive"))
+                    .await?;
+                dispatch(&sinks, &state_tx, msg).await;
+                readiness.station_ready(name.as_deref());
+            }
+            loop {
+                if let Some(msg) = dec.next().instrument(debug_span!("receive")).await {
+                    let span = debug_span!("message", kind = msg.kind());
+                    dispatch(&sinks, &state_tx, msg).instrument(span).await;
+                    readiness.heartbeat.beat();
+                } else {
+                    break;
+                }
+                if shutdown_rx.try_recv().is_ok() {
+                    info!("Message pump stopping");
+                    break;
+                }
+            }
+            Ok(())
+        });
+        self.pump = Some(pump);
+        self.pump_shutdown_tx = Some(shutdown_tx);
+    }
+
+    pub fn pump(&mut self) -> &mut JoinHandle<anyhow::Result<()>> {
+        self.pump.as_mut().unwrap()
+    }
+
+    // Stops the pump after the report in hand, or right away when `abort` is set, which an
+    // in-place upgrade needs as otherwise the pump, waiting on the socket, would take datagrams
+    // meant for the new exporter and then find the sinks closed.
+    pub fn stop_pump(&mut self, abort: bool) {
+        if let Some(shutdown_tx) = self.pump_shutdown_tx.take() {
+            shutdown_tx.send(()).ok();
+        }
+        if let Some(pump) = self.pump.as_ref().filter(|_| abort) {
+            pump.abort();
+        }
+    }
+
+    // Lets the sinks drain their queues, flushes MQTT, and saves state. An in-place upgrade leaves
+    // the MQTT availability online for the new exporter.
+    pub async fn shutdown(self, timeout: Duration, upgrade: bool) {
+        let mut sinks = self.sinks.close_all();
+        let drained = async {
+            for sink in &mut sinks {
+                (&mut sink.task).await.ok();
+            }
+        };
+        if tokio::time::timeout(timeout, drained).await.is_err() {
+            warn!("Sinks did not drain their queues within {:?}", timeout);
+        }
+        self.publisher.shutdown(timeout, upgrade).await;
+        for sink in sinks {
+            sink.stop_servers();
+        }
+        if let Some((checkpointer, path)) = self
+            .checkpointer
+            .zip(self.reconfigurable.state_file.as_deref())
+        {
+            checkpointer.abort();
+            save_checkpoint(path, &self.exporter, &self.publisher, &self.state);
+        }
+    }
+}
+
+async fn dispatch(sinks: &Sinks, state_tx: &watch::Sender<StationState>, msg: TempestMsg) {
+    let msg = Arc::new(msg);
+    // Only readers of reports that changed the state need waking.
+    state_tx.send_if_modified(|state| state.update(&msg));
+    for queue in sinks.queues() {
+        queue.push(msg.clone()).await;
+    }
+}
+
+// A sink that is running: its queue, the task handling what is queued, and any servers it runs
+// for clients to connect to.
+struct RunningSink {
+    queue: Arc<SinkQueue>,
+    task: JoinHandle<()>,
+    servers: Vec<JoinHandle<()>>,
+}
+
+impl RunningSink {
+    fn stop_servers(self) {
+        for server in self.servers {
+            server.abort();
+        }
+    }
+}
+
+// The sinks a station's reports are fanned out to. Configuration reloads start and stop sinks,
+// so reports go to those running when they arrive.
+#[derive(Default)]
+pub struct Sinks {
+    running: Mutex<Vec<RunningSink>>,
+}
+
+impl Sinks {
+    pub fn queues(&self) -> Vec<Arc<SinkQueue>> {
+        let running = self.running.lock().unwrap();
+        running.iter().map(|sink| sink.queue.clone()).collect()
+    }
+
+    fn add(&self, sink: RunningSink) {
+        self.running.lock().unwrap().push(sink);
+    }
+
+    // Stops the sink named `name` taking reports and serving clients. It handles what is already
+    // queued in the background.
+    fn stop(&self, name: &str) {
+        let mut running = self.running.lock().unwrap();
+        if let Some(i) = running.iter().position(|sink| sink.queue.name() == name) {
+            let sink = running.remove(i);
+            sink.queue.close();
+            sink.stop_servers();
+        }
+    }
+
+    // Stops all sinks taking reports, leaving them to handle what is already queued.
+    fn close_all(&self) -> Vec<RunningSink> {
+        let sinks = std::mem::take(&mut *self.running.lock().unwrap());
+        for sink in &sinks {
+            sink.queue.close();
+        }
+        sinks
+    }
+}
+
+// The sinks, and fetchers of reports from elsewhere, that are enabled by configuration and may
+// come and go with reloads.
+#[derive(Default)]
+struct Outputs {
+    enable_mqtt: bool,
+    alerter: Option<Arc<Alerter>>,
+    knx: Option<Arc<KnxBridge>>,
+    signalk: Option<Arc<SignalKSink>>,
+    nmea: Option<Arc<NmeaSink>>,
+    ecowitt: Option<Arc<EcowittSink>>,
+    grafana_live: Option<Arc<GrafanaLiveSink>>,
+    modbus: Option<Arc<ModbusServer>>,
+    rain_check: Option<(rain_check::RainCheckParams, JoinHandle<()>)>,
+    reference: Option<(reference::ReferenceParams, JoinHandle<()>)>,
+}
+
+// The parts of a pipeline that configuration reloads apply to.
+pub struct Reconfigurable {
+    pub name: Option<String>,
+    api_port: u16,
+    decode_mode: reader::DecodeMode,
+    queues: BTreeMap<String, QueueOptions>,
+    state_file: Option<PathBuf>,
+    station_params: Shared<StationParams>,
+    exporter_params: Shared<ExporterParams>,
+    exporter: Arc<exporter::Exporter>,
+    publisher: Arc<Publisher>,
+    clock: SharedClock,
+    hub_selector: Arc<Mutex<HubSelector>>,
+    history: Arc<Mutex<History>>,
+    sinks: Arc<Sinks>,
+    outputs: tokio::sync::Mutex<Outputs>,
+}
+
+impl Reconfigurable {
+    pub async fn apply(&self, config: StationConfig, shutdown_timeout: Duration) {
+        if config.api_port != self.api_port
+            || config.decode_mode != self.decode_mode
+            || config.queues != self.queues
+            || config.state_file != self.state_file
+        {
+            warn!(
+                "Port, decode mode, state file and sink queue changes require a restart or an \
+                 in-place upgrade (SIGUSR2)"
+            );
+        }
+        *self.station_params.write().unwrap() = config.station_params.clone();
+        *self.exporter_params.write().unwrap() = config.exporter_params.clone();
+        self.hub_selector
+            .lock()
+            .unwrap()
+            .reconfigure(config.hub_preference.clone());
+        self.history
+            .lock()
+            .unwrap()
+            .set_retention(config.history_retention);
+        self.publisher.reconfigure(
+            config.mqtt_params.clone(),
+            publisher_metrics(&self.exporter, config.enable_mqtt),
+            shutdown_timeout,
+        );
+        for e in self.update_outputs(config).await {
+            error!("Applying the configuration failed: {:#}", e);
+        }
+    }
+
+    // Starts the sinks serving metrics and history over HTTP.
+    fn start_exporter(&self) {
+        self.start_sink(
+            "exporter",
+            DropPolicy::DropOldest,
+            Arc::default(),
+            Vec::new(),
+            {
+                let exporter = self.exporter.clone();
+                move |msg| exporter.handle_report(msg)
+            },
+        );
+        self.start_sink(
+            "history",
+            DropPolicy::DropOldest,
+            Arc::default(),
+            Vec::new(),
+            {
+                let history = self.history.clone();
+                move |msg| {
+                    if let TempestMsg::Observation(obs) = msg {
+                        history.lock().unwrap().push(obs);
+                    }
+                }
+            },
+        );
+    }
+
+    // Starts a sink handling each report with `handler`, behind a queue that takes the sink's
+    // default overflow policy unless configured otherwise.
+    fn start_sink(
+        &self,
+        name: &'static str,
+        policy: DropPolicy,
+        errors: Arc<LastError>,
+        servers: Vec<JoinHandle<()>>,
+        handler: impl Fn(&TempestMsg) + Send + 'static,
+    ) {
+        let options = self.queues.get(name).copied().unwrap_or_default();
+        let queue = SinkQueue::new(
+            name,
+            options.capacity.unwrap_or(SINK_QUEUE_CAPACITY),
+            options.overflow.unwrap_or(policy),
+            self.exporter.queue_metrics(name),
+            errors,
+        );
+        self.sinks.add(RunningSink {
+            task: queue.spawn(handler),
+            queue,
+            servers,
+        });
+    }
+
+    // Starts the sinks newly enabled, stops those no longer enabled, and passes the rest their
+    // new parameters, returning what failed to start. A sink whose server's port changed is
+    // restarted.
+    async fn update_outputs(&self, config: StationConfig) -> Vec<anyhow::Error> {
+        let mut outputs = self.outputs.lock().await;
+        let mut errors = Vec::new();
+
+        if config.enable_mqtt && !outputs.enable_mqtt {
+            self.start_sink(
+                "mqtt",
+                DropPolicy::DropNewest,
+                self.publisher.errors(),
+                Vec::new(),
+                {
+                    let publisher = self.publisher.clone();
+                    move |msg| publisher.handle_report(msg)
+                },
+            );
+        } else if !config.enable_mqtt && outputs.enable_mqtt {
+            self.sinks.stop("mqtt");
+        }
+        outputs.enable_mqtt = config.enable_mqtt;
+
+        match (&outputs.alerter, config.enable_alerts) {
+            (Some(alerter), true) => {
+                alerter.reconfigure(config.alert_rules, config.lightning_params)
+            }
+            (None, true) => {
+                let alerter = Arc::new(Alerter::new(
+                    self.station_params.clone(),
+                    self.exporter_params.clone(),
+                    self.publisher.clone(),
+                    config.alert_rules,
+                    config.lightning_params,
+                    self.clock.clone(),
+                ));
+                self.start_sink(
+                    "alerts",
+                    DropPolicy::DropNewest,
+                    alerter.errors(),
+                    Vec::new(),
+                    {
+                        let alerter = alerter.clone();
+                        move |msg| alerter.handle_report(msg)
+                    },
+                );
+                outputs.alerter = Some(alerter);
+            }
+            (Some(_), false) => {
+                self.sinks.stop("alerts");
+                outputs.alerter = None;
+            }
+            (None, false) => {}
+        }
+
+        match (&outputs.knx, config.knx_params) {
+            (Some(knx), Some(params)) => knx.reconfigure(params),
+            (None, Some(params)) => {
+                let started = KnxBridge::new(
+                    self.station_params.clone(),
+                    self.exporter_params.clone(),
+                    params,
+                );
+                match started {
+                    Ok(knx) => {
+                        let knx = Arc::new(knx);
+                        self.start_sink("knx", DropPolicy::DropOldest, knx.errors(), Vec::new(), {
+                            let knx = knx.clone();
+                            move |msg| knx.handle_report(msg)
+                        });
+                        outputs.knx = Some(knx);
+                    }
+                    Err(e) => errors.push(e.context("Starting KNX bridge")),
+                }
+            }
+            (Some(_), None) => {
+                self.sinks.stop("knx");
+                outputs.knx = None;
+            }
+            (None, None) => {}
+        }
+
+        match (&outputs.signalk, config.signalk_params) {
+            (Some(signalk), Some(params)) => signalk.reconfigure(params),
+            (None, Some(params)) => {
+                let started = SignalKSink::new(
+                    self.station_params.clone(),
+                    self.exporter_params.clone(),
+                    params,
+                );
+                match started {
+                    Ok(signalk) => {
+                        let signalk = Arc::new(signalk);
+                        self.start_sink(
+                            "signalk",
+                            DropPolicy::DropOldest,
+                            signalk.errors(),
+                            Vec::new(),
+                            {
+                                let signalk = signalk.clone();
+                                move |msg| signalk.handle_report(msg)
+                            },
+                        );
+                        outputs.signalk = Some(signalk);
+                    }
+                    Err(e) => errors.push(e.context("Starting Signal K output")),
+                }
+            }
+            (Some(_), None) => {
+                self.sinks.stop("signalk");
+                outputs.signalk = None;
+            }
+            (None, None) => {}
+        }
+
+        // A new TCP port needs a new server.
+        let nmea_params = config.nmea_params;
+        let restart = matches!(
+            (&outputs.nmea, &nmea_params),
+            (Some(nmea), Some(params)) if nmea.tcp_port() != params.tcp_port
+        );
+        if restart || (outputs.nmea.is_some() && nmea_params.is_none()) {
+            self.sinks.stop("nmea");
+            outputs.nmea = None;
+        }
+        match (&outputs.nmea, nmea_params) {
+            (Some(nmea), Some(params)) => nmea.reconfigure(params),
+            (None, Some(params)) => match self.start_nmea(params).await {
+                Ok(nmea) => outputs.nmea = Some(nmea),
+                Err(e) => errors.push(e.context("Starting NMEA output")),
+            },
+            _ => {}
+        }
+
+        match (&outputs.ecowitt, config.ecowitt_params) {
+            (Some(ecowitt), Some(params)) => ecowitt.reconfigure(params),
+            (None, Some(params)) => {
+                let ecowitt = Arc::new(EcowittSink::new(
+                    self.station_params.clone(),
+                    self.exporter_params.clone(),
+                    params,
+                    self.clock.clone(),
+                ));
+                self.start_sink(
+                    "ecowitt",
+                    DropPolicy::DropOldest,
+                    ecowitt.errors(),
+                    Vec::new(),
+                    {
+                        let ecowitt = ecowitt.clone();
+                        move |msg| ecowitt.handle_report(msg)
+                    },
+                );
+                outputs.ecowitt = Some(ecowitt);
+            }
+            (Some(_), None) => {
+                self.sinks.stop("ecowitt");
+                outputs.ecowitt = None;
+            }
+            (None, None) => {}
+        }
+
+        match (&outputs.grafana_live, config.grafana_live_params) {
+            (Some(grafana_live), Some(params)) => grafana_live.reconfigure(params),
+            (None, Some(params)) => {
+                let grafana_live =
+                    Arc::new(GrafanaLiveSink::new(self.exporter_params.clone(), params));
+                self.start_sink(
+                    "grafana_live",
+                    DropPolicy::DropOldest,
+                    grafana_live.errors(),
+                    Vec::new(),
+                    {
+                        let grafana_live = grafana_live.clone();
+                        move |msg| grafana_live.handle_report(msg)
+                    },
+                );
+                outputs.grafana_live = Some(grafana_live);
+            }
+            (Some(_), None) => {
+                self.sinks.stop("grafana_live");
+                outputs.grafana_live = None;
+            }
+            (None, None) => {}
+        }
+
+        // A new port needs a new server.
+        let modbus_params = config.modbus_params;
+        let restart = matches!(
+            (&outputs.modbus, &modbus_params),
+            (Some(modbus), Some(params)) if modbus.port() != params.port
+        );
+        if restart || (outputs.modbus.is_some() && modbus_params.is_none()) {
+            self.sinks.stop("modbus");
+            outputs.modbus = None;
+        }
+        match (&outputs.modbus, modbus_params) {
+            (Some(modbus), Some(params)) => modbus.reconfigure(params),
+            (None, Some(params)) => match self.start_modbus(params).await {
+                Ok(modbus) => outputs.modbus = Some(modbus),
+                Err(e) => errors.push(e.context("Starting Modbus server")),
+            },
+            _ => {}
+        }
+
+        if outputs.rain_check.as_ref().map(|(params, _)| params)
+            != config.rain_check_params.as_ref()
+        {
+            if let Some((_, task)) = outputs.rain_check.take() {
+                task.abort();
+            }
+            outputs.rain_check = config.rain_check_params.map(|params| {
+                (
+                    params.clone(),
+                    spawn_rain_check(params, self.exporter.clone()),
+                )
+            });
+        }
+        if outputs.reference.as_ref().map(|(params, _)| params) != config.reference_params.as_ref()
+        {
+            if let Some((_, task)) = outputs.reference.take() {
+                task.abort();
+            }
+            outputs.reference = config.reference_params.map(|params| {
+                (
+                    params.clone(),
+                    spawn_reference(params, self.exporter.clone()),
+                )
+            });
+        }
+        errors
+    }
+
+    async fn start_nmea(&self, params: NmeaParams) -> anyhow::Result<Arc<NmeaSink>> {
+        let nmea = Arc::new(NmeaSink::new(
+            self.station_params.clone(),
+            self.exporter_params.clone(),
+            params,
+            self.clock.clone(),
+        )?);
+        let servers = nmea.spawn().await?.into_iter().collect();
+        self.start_sink("nmea", DropPolicy::DropOldest, nmea.errors(), servers, {
+            let nmea = nmea.clone();
+            move |msg| nmea.handle_report(msg)
+        });
+        Ok(nmea)
+    }
+
+    async fn start_modbus(&self, params: ModbusParams) -> anyhow::Result<Arc<ModbusServer>> {
+        let modbus = Arc::new(ModbusServer::new(
+            self.station_params.clone(),
+            self.exporter_params.clone(),
+            params,
+        ));
+        let servers = vec![modbus.spawn().await?];
+        self.start_sink("modbus", DropPolicy::DropOldest, Arc::default(), servers, {
+            let modbus = modbus.clone();
+            move |msg| modbus.handle_report(msg)
+        });
+        Ok(modbus)
+    }
+}
+
+// Connection metrics for the publisher. A disabled publisher's connection isn't worth exporting.
+fn publisher_metrics(exporter: &exporter::Exporter, enable_mqtt: bool) -> PublisherMetrics {
+    if enable_mqtt {
+        exporter.publisher_metrics()
+    } else {
+        Default::default()
+    }
+}
+
+// Counts the stations yet to receive their first message. The exporter is ready once none are
+// left, and only then tells the service manager so.
+pub struct Readiness {
+    waiting: AtomicUsize,
+    heartbeat: Arc<Heartbeat>,
+}
+
+impl Readiness {
+    pub fn new(stations: usize) -> Self {
+        Self {
+            waiting: AtomicUsize::new(stations),
+            heartbeat: Arc::new(Heartbeat::new()),
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.waiting.load(Ordering::Relaxed) == 0
+    }
+
+    fn station_ready(&self, station: Option<&str>) {
+        match station {
+            Some(station) => info!("Tempest API is alive for station {}", station),
+            None => info!("Tempest API is alive"),
+        }
+        self.heartbeat.beat();
+        if self.waiting.fetch_sub(1, Ordering::Relaxed) == 1 {
+            systemd::spawn_watchdog(self.heartbeat.clone());
+            systemd::notify_status("Receiving messages");
+            systemd::notify_ready();
+        }
+    }
+}
+
+// Waits for the first decoded message, giving up after `timeout` if one is set.
+async fn first_message<S>(dec: &mut S, timeout: Option<Duration>) -> anyhow::Result<TempestMsg>
+where
+    S: Stream<Item = TempestMsg> + Unpin,
+{
+    let next = async {
+        dec.next()
+            .await
+            .ok_or_else(|| anyhow!("Decoder stream never returned anything"))
+    };
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, next)
+            .await
+            .with_context(|| format!("No message from the Tempest hub within {:?}", timeout))?,
+        None => next.await,
+    }
+}
+
+// Periodically saves state to the state file, so that it survives a crash.
+fn spawn_checkpointer(
+    path: PathBuf,
+    interval: Duration,
+    exporter: Arc<exporter::Exporter>,
+    publisher: Arc<Publisher>,
+    state: watch::Receiver<StationState>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            save_checkpoint(&path, &exporter, &publisher, &state);
+        }
+    })
+}
+
+// Fetches yesterday's rain from the WeatherFlow cloud now and at every interval, since Rain Check
+// corrections are applied some time after the day ends.
+fn spawn_rain_check(
+    params: rain_check::RainCheckParams,
+    exporter: Arc<exporter::Exporter>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()
+            .unwrap();
+        let mut interval = tokio::time::interval(params.interval);
+        loop {
+            interval.tick().await;
+            match rain_check::fetch(&http, &params).await {
+                Ok(rain) => {
+                    info!(
+                        "Yesterday's rain was {} mm raw, {:?} mm corrected",
+                        rain.raw, rain.corrected
+                    );
+                    exporter.set_daily_rain(&rain);
+                }
+                Err(e) => warn!("Fetching corrected daily rain failed: {:#}", e),
+            }
+        }
+    })
+}
+
+// Fetches the reference station's latest observation now and at every interval, comparing it
+// with the station's readings at the time.
+fn spawn_reference(
+    params: reference::ReferenceParams,
+    exporter: Arc<exporter::Exporter>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        // The NWS API turns away requests that don't identify their application.
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .user_agent(concat!("tempest-exporter/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .unwrap();
+        let mut interval = tokio::time::interval(params.interval);
+        loop {
+            interval.tick().await;
+            match reference::fetch(&http, &params).await {
+                Ok(observation) => exporter.set_reference(&observation),
+                Err(e) => warn!(
+                    "Fetching reference observation from {} failed: {:#}",
+                    params.station, e
+                ),
+            }
+        }
+    })
+}
+
+fn save_checkpoint(
+    path: &Path,
+    exporter: &exporter::Exporter,
+    publisher: &Publisher,
+    state: &watch::Receiver<StationState>,
+) {
+    let checkpoint = checkpoint::Checkpoint {
+        last_reports: state.borrow().received(),
+        domoticz_rain: publisher.domoticz_rain(),
+        ..exporter.checkpoint()
+    };
+    if let Err(e) = checkpoint::save(path, &checkpoint) {
+        error!("Saving state failed: {:#}", e);
+    }
+}
+
+// Re-exports and republishes reports saved before a restart, unless they have gone stale.
+fn restore_reports(
+    reports: Vec<reader::RawTempestMsg>,
+    station_params: &Shared<StationParams>,
+    exporter: &exporter::Exporter,
+    publisher: &Publisher,
+    state_tx: &watch::Sender<StationState>,
+) {
+    let mut restored = 0;
+    for raw in reports {
+        let msg = match TempestMsg::try_from(raw.clone()) {
+            Ok(msg) => calibrator::calibrate(msg, &station_params.read().unwrap()),
+            Err((_, e)) => {
+                warn!("Dropped undecodable saved report: {:#}", e);
+                continue;
+            }
+        };
+        if exporter.restore_report(&msg) {
+            publisher.handle_report(&msg);
+            state_tx.send_modify(|state| {
+                state.record(&raw);
+                state.update(&Arc::new(msg));
+            });
+            restored += 1;
+        }
+    }
+    info!("Restored {} recent reports", restored);
+}
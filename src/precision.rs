@@ -0,0 +1,108 @@
+//! Decimal precision of published values. Readings are kept at full precision internally and only
+//! rounded when formatted for MQTT payloads and JSON output, so that subscribers see `22.34`
+//! rather than `22.340000000000003`.
+//!
+//! Which precision applies is told from the unit suffix that topic, field and key names carry,
+//! such as `_deg_c` for temperatures.
+
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Decimal places by category of reading. Unset categories fall back to `default`, and values are
+/// not rounded if that is unset too.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Precision {
+    /// Temperatures (`_deg_c`).
+    pub temperature: Option<u32>,
+    /// Relative humidity (`_pct`).
+    pub humidity: Option<u32>,
+    /// Pressures (`_hpa`).
+    pub pressure: Option<u32>,
+    /// Wind speeds (`_m_per_s`).
+    pub wind_speed: Option<u32>,
+    /// Wind directions (`_deg`).
+    pub direction: Option<u32>,
+    /// Rain amounts and rates (`_mm`, `_mm_per_h`).
+    pub rain: Option<u32>,
+    /// Illuminance and irradiance (`_lux`, `_w_per_m2`).
+    pub solar: Option<u32>,
+    /// UV index (`uv_index`).
+    pub uv: Option<u32>,
+    /// Lightning distances (`_km`).
+    pub distance: Option<u32>,
+    /// Battery voltage (`_volts`).
+    pub voltage: Option<u32>,
+    /// Everything else, such as derived metrics.
+    pub default: Option<u32>,
+}
+
+impl Precision {
+    /// Decimal places for the value named `name`, if it is to be rounded.
+    pub fn digits(&self, name: &str) -> Option<u32> {
+        let category = if name.ends_with("_deg_c") {
+            self.temperature
+        } else if name.ends_with("_pct") {
+            self.humidity
+        } else if name.ends_with("_hpa") {
+            self.pressure
+        } else if name.ends_with("_m_per_s") {
+            self.wind_speed
+        } else if name.ends_with("_deg") {
+            self.direction
+        } else if name.ends_with("_mm") || name.contains("_mm_per_h") {
+            self.rain
+        } else if name.ends_with("_lux") || name.ends_with("_w_per_m2") {
+            self.solar
+        } else if name.ends_with("uv_index") {
+            self.uv
+        } else if name.ends_with("_km") {
+            self.distance
+        } else if name.ends_with("_volts") {
+            self.voltage
+        } else {
+            None
+        };
+        category.or(self.default)
+    }
+
+    /// The value named `name` rounded to its precision.
+    pub fn round(&self, name: &str, value: f64) -> f64 {
+        match self.digits(name) {
+            Some(digits) if value.is_finite() => {
+                let scale = 10f64.powi(digits as i32);
+                (value * scale).round() / scale
+            }
+            _ => value,
+        }
+    }
+
+    /// Rounds the numbers in a JSON document by the names of the fields holding them.
+    pub fn round_json(&self, value: &mut Value) {
+        self.round_json_field("", value)
+    }
+
+    fn round_json_field(&self, name: &str, value: &mut Value) {
+        match value {
+            Value::Number(number) if !number.is_i64() && !number.is_u64() => {
+                if let Some(rounded) = number
+                    .as_f64()
+                    .and_then(|v| serde_json::Number::from_f64(self.round(name, v)))
+                {
+                    *number = rounded;
+                }
+            }
+            Value::Array(values) => {
+                for value in values {
+                    self.round_json_field(name, value);
+                }
+            }
+            Value::Object(fields) => {
+                for (name, value) in fields {
+                    self.round_json_field(name, value);
+                }
+            }
+            _ => (),
+        }
+    }
+}
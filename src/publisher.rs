@@ -1,161 +1,791 @@
-use std::sync::Mutex;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use log::{debug, error, info};
+use anyhow::{bail, Context};
+use chrono::{DateTime, Utc};
 use rumqttc::{
-    AsyncClient, Event as MqEvent, Incoming as MqIncoming, MqttOptions, Outgoing as MqOutgoing, QoS,
+    AsyncClient, Event as MqEvent, Incoming as MqIncoming, LastWill, MqttOptions,
+    Outgoing as MqOutgoing, QoS,
 };
+use serde::Serialize;
 use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, info_span, warn, Instrument};
 
+use crate::config::{ExporterParams, MqttParams, Shared, StationParams};
 use crate::decoder;
-use crate::{MqttParams, StationParams};
+use crate::domoticz::{self, DomoticzParams};
+use crate::schema;
+use crate::sink_queue::LastError;
+use prometheus::IntCounter;
+use tempest_exporter::backoff::{Backoff, BACKOFF_MAX};
+use tempest_exporter::changes::ChangesOnly;
+use tempest_exporter::clock::SharedClock;
+use tempest_exporter::exporter::PublisherMetrics;
+use tempest_exporter::fire::{self, FireDanger};
+use tempest_exporter::forecast;
+use tempest_exporter::leaf;
+use tempest_exporter::rain::{RainCounter, RainEvent, RainEventChange, RainEvents};
+use tempest_exporter::topic::TopicTemplate;
+use tempest_exporter::trend::{Trend, RAIN_RATE_WINDOWS};
 
-type Message = (String, bool, String);
+// Full topics are shared with the table they were rendered into rather than copied per message.
+type Message = (Arc<str>, bool, String);
 
-struct MsgSender(mpsc::Sender<Message>);
+struct MsgSender {
+    tx: mpsc::Sender<Message>,
+    topic_prefix: String,
+    topic_template: TopicTemplate,
+    // Serial number of the station, once a report has given it.
+    serial_number: Option<String>,
+    // Full topics rendered from the template, by topic below the prefix, so that each is rendered
+    // once rather than for every message. Rendered again once the serial number changes.
+    topics: Mutex<HashMap<String, Arc<str>>>,
+    exporter_params: Shared<ExporterParams>,
+    // What was last published to each retained topic, if only changed readings are published.
+    changes_only: Option<Mutex<ChangesOnly>>,
+    // Messages dropped because the queue was full.
+    dropped: IntCounter,
+    clock: SharedClock,
+}
 
 impl MsgSender {
+    fn set_serial_number(&mut self, serial_number: &str) {
+        if self.serial_number.as_deref() != Some(serial_number) {
+            self.serial_number = Some(serial_number.to_string());
+            self.topics.get_mut().unwrap().clear();
+        }
+    }
+
     fn send(&self, topic: impl std::borrow::Borrow<str>, retain: bool, payload: String) {
-        self.0
-            .try_send((topic.borrow().to_string(), retain, payload))
-            .ok();
+        let topic = topic.borrow();
+        let published = {
+            let filter = &self.exporter_params.read().unwrap().publish_filter;
+            // An include list picks readings, so availability and alerts aren't left out of it.
+            if topic == STATUS_TOPIC || topic.starts_with("alert/") {
+                !filter.excludes(topic)
+            } else {
+                filter.allows(topic)
+            }
+        };
+        if !published {
+            return;
+        }
+        match self.full_topic(topic) {
+            Some(topic) if retain => self.send_if_changed(topic, payload),
+            Some(topic) => self.send_unprefixed(topic, retain, payload),
+            None => debug!("Not publishing {} until the serial number is known", topic),
+        }
+    }
+
+    // The full topic for `topic`, from the table or rendered into it.
+    fn full_topic(&self, topic: &str) -> Option<Arc<str>> {
+        let mut topics = self.topics.lock().unwrap();
+        if let Some(full_topic) = topics.get(topic) {
+            return Some(full_topic.clone());
+        }
+        let full_topic: Arc<str> = self
+            .topic_template
+            .render(&self.topic_prefix, self.serial_number.as_deref(), topic)?
+            .into();
+        topics.insert(topic.to_string(), full_topic.clone());
+        Some(full_topic)
+    }
+
+    // Sends a retained reading, unless only changes are published and it is the same as was last
+    // published to the topic within the refresh interval.
+    fn send_if_changed(&self, topic: Arc<str>, payload: String) {
+        let changes_only = match &self.changes_only {
+            Some(changes_only) => changes_only,
+            None => return self.send_unprefixed(topic, true, payload),
+        };
+        let mut changes_only = changes_only.lock().unwrap();
+        let now = self.clock.now();
+        if !changes_only.is_due(&topic, &payload, now) {
+            return;
+        }
+        if self
+            .tx
+            .try_send((topic.clone(), true, payload.clone()))
+            .is_ok()
+        {
+            changes_only.record(topic, payload, now);
+        } else {
+            self.dropped.inc();
+        }
+    }
+
+    // Sends a reading, rounded to the precision configured for its topic.
+    fn send_value(&self, topic: impl std::borrow::Borrow<str>, retain: bool, value: f64) {
+        let topic = topic.borrow();
+        let value = self.round(topic, value);
+        self.send(topic, retain, value.to_string());
+    }
+
+    // Sends a versioned JSON document, with numbers rounded by the fields holding them.
+    fn send_document(&self, topic: &str, retain: bool, document: &impl Serialize) {
+        let mut document = serde_json::to_value(document).unwrap();
+        self.exporter_params
+            .read()
+            .unwrap()
+            .precision
+            .round_json(&mut document);
+        self.send(topic, retain, schema::versioned(&document));
+    }
+
+    fn round(&self, topic: &str, value: f64) -> f64 {
+        self.exporter_params
+            .read()
+            .unwrap()
+            .precision
+            .round(topic, value)
+    }
+
+    // Sends to a topic outside the prefix, for consumers with topics of their own.
+    fn send_unprefixed(&self, topic: Arc<str>, retain: bool, payload: String) {
+        if self.tx.try_send((topic, retain, payload)).is_err() {
+            self.dropped.inc();
+        }
     }
 }
 
-pub struct Publisher {
-    station_params: StationParams,
+// Retained under `<prefix>/status` while connected, and replaced by the last will (or on
+// shutdown) once not. An in-place upgrade leaves it online, as the new exporter takes over.
+const ONLINE: &str = "online";
+const OFFLINE: &str = "offline";
+const STATUS_TOPIC: &str = "status";
+
+fn status_topic(topic_prefix: &str) -> String {
+    format!("{}/{}", topic_prefix, STATUS_TOPIC)
+}
+
+// A running MQTT (or dummy) publishing backend, replaced wholesale when MQTT parameters change.
+struct Sink {
+    mqtt_params: MqttParams,
     sender: MsgSender,
-    shutdown_tx: Mutex<Option<oneshot::Sender<()>>>,
+    // Sent whether the backend is stopping for an in-place upgrade.
+    shutdown_tx: Option<oneshot::Sender<bool>>,
+    task: Option<JoinHandle<()>>,
 }
 
-impl Publisher {
-    pub fn new(station_params: StationParams, mqtt_params: MqttParams) -> Self {
-        let (message_tx, message_rx) = mpsc::channel(1024);
+impl Sink {
+    fn start(
+        mqtt_params: MqttParams,
+        exporter_params: Shared<ExporterParams>,
+        errors: Arc<LastError>,
+        metrics: PublisherMetrics,
+        clock: SharedClock,
+    ) -> Self {
+        let (message_tx, message_rx) = mpsc::channel(mqtt_params.mqtt_queue_capacity);
+        let dropped = metrics.dropped.clone();
         let (shutdown_tx, shutdown_rx) = oneshot::channel();
 
-        if mqtt_params.mqtt_broker.is_some() {
-            Self::start_actual(mqtt_params, message_rx, shutdown_rx);
+        let task = if mqtt_params.mqtt_broker.is_some() {
+            Publisher::start_actual(
+                mqtt_params.clone(),
+                message_rx,
+                shutdown_rx,
+                errors,
+                metrics,
+            )
         } else {
-            Self::start_dummy(message_rx, shutdown_rx);
+            Publisher::start_dummy(message_rx, shutdown_rx)
+        };
+
+        Self {
+            sender: MsgSender {
+                tx: message_tx,
+                topic_prefix: mqtt_params.mqtt_topic_prefix.clone(),
+                topic_template: mqtt_params.mqtt_topic_template.clone(),
+                serial_number: None,
+                topics: Mutex::new(HashMap::new()),
+                exporter_params,
+                changes_only: mqtt_params
+                    .mqtt_changes_only
+                    .map(|refresh| Mutex::new(ChangesOnly::new(refresh))),
+                dropped,
+                clock,
+            },
+            mqtt_params,
+            shutdown_tx: Some(shutdown_tx),
+            task: Some(task),
         }
+    }
+
+    // Asks the backend to flush and stop, returning the task to await for completion.
+    fn shutdown(&mut self, upgrade: bool) -> Option<JoinHandle<()>> {
+        self.shutdown_tx.take().map(|stx| stx.send(upgrade));
+        self.task.take()
+    }
+}
 
+// Consecutive failed connection attempts after which the broker is considered down.
+const BROKER_DOWN_AFTER: u32 = 10;
+
+pub struct Publisher {
+    station_params: Shared<StationParams>,
+    exporter_params: Shared<ExporterParams>,
+    sink: Mutex<Sink>,
+    // Last firmware revision seen from each hub and device, by serial number.
+    firmware_revisions: Mutex<HashMap<String, String>>,
+    rain_history: Mutex<Trend>,
+    // Topic of each rain rate window, built once rather than for every observation.
+    rain_rate_topics: Vec<(String, Duration)>,
+    rain_events: Mutex<RainEvents>,
+    pressure_trend: Mutex<Trend>,
+    fire_danger: Mutex<FireDanger>,
+    domoticz_rain: Mutex<RainCounter>,
+    errors: Arc<LastError>,
+    metrics: Mutex<PublisherMetrics>,
+    clock: SharedClock,
+}
+
+impl Publisher {
+    pub fn new(
+        station_params: Shared<StationParams>,
+        exporter_params: Shared<ExporterParams>,
+        mqtt_params: MqttParams,
+        metrics: PublisherMetrics,
+        clock: SharedClock,
+    ) -> Self {
+        let errors = Arc::new(LastError::default());
+        let sink = Sink::start(
+            mqtt_params,
+            exporter_params.clone(),
+            errors.clone(),
+            metrics.clone(),
+            clock.clone(),
+        );
         Self {
             station_params,
-            sender: MsgSender(message_tx),
-            shutdown_tx: Mutex::new(Some(shutdown_tx)),
+            exporter_params,
+            sink: Mutex::new(sink),
+            firmware_revisions: Mutex::new(HashMap::new()),
+            rain_history: Mutex::new(Trend::new()),
+            rain_rate_topics: RAIN_RATE_WINDOWS
+                .iter()
+                .map(|(suffix, window)| {
+                    (
+                        format!("observation/precip/rain_rate_mm_per_h_{}", suffix),
+                        *window,
+                    )
+                })
+                .collect(),
+            rain_events: Mutex::new(RainEvents::new()),
+            pressure_trend: Mutex::new(Trend::new()),
+            fire_danger: Mutex::new(FireDanger::new()),
+            domoticz_rain: Mutex::new(RainCounter::default()),
+            errors,
+            metrics: Mutex::new(metrics),
+            clock,
         }
     }
 
-    fn start_actual(
+    // Where MQTT errors are recorded, for `/debug/state`.
+    pub fn errors(&self) -> Arc<LastError> {
+        self.errors.clone()
+    }
+
+    // The Domoticz rain counter, to be saved across restarts.
+    pub fn domoticz_rain(&self) -> RainCounter {
+        self.domoticz_rain.lock().unwrap().clone()
+    }
+
+    // Continues the Domoticz rain counter from before a restart.
+    pub fn restore_domoticz_rain(&self, counter: RainCounter) {
+        *self.domoticz_rain.lock().unwrap() = counter;
+    }
+
+    // Restarts the connection when its parameters change, recording it in `metrics` from then on.
+    pub fn reconfigure(
+        &self,
         mqtt_params: MqttParams,
-        mut message_rx: mpsc::Receiver<Message>,
-        shutdown_rx: oneshot::Receiver<()>,
+        metrics: PublisherMetrics,
+        shutdown_timeout: Duration,
     ) {
-        let mut mqtt_options = MqttOptions::new(
-            "tempest-exporter",
-            mqtt_params.mqtt_broker.unwrap(), // Checked by caller
-            mqtt_params.mqtt_port,
-        );
-        mqtt_options.set_keep_alive(std::time::Duration::from_secs(15));
-        if let (Some(user), Some(pass)) = (mqtt_params.mqtt_username, mqtt_params.mqtt_password) {
+        let mut sink = self.sink.lock().unwrap();
+        *self.metrics.lock().unwrap() = metrics;
+        if sink.mqtt_params != mqtt_params {
+            info!("MQTT parameters changed, restarting publisher");
+            if let Some(task) = sink.shutdown(false) {
+                tokio::spawn(Self::await_flush(task, shutdown_timeout));
+            }
+            let serial_number = sink.sender.serial_number.take();
+            *sink = Sink::start(
+                mqtt_params,
+                self.exporter_params.clone(),
+                self.errors.clone(),
+                self.metrics.lock().unwrap().clone(),
+                self.clock.clone(),
+            );
+            if let Some(serial_number) = serial_number {
+                sink.sender.set_serial_number(&serial_number);
+            }
+        }
+    }
+
+    // Connects to the broker once and disconnects, to check that the MQTT parameters work. Uses a
+    // client ID of its own and no last will, so that a running exporter with the same parameters
+    // is neither disconnected nor marked offline.
+    pub async fn check_connection(mqtt_params: MqttParams) -> anyhow::Result<()> {
+        let broker = match &mqtt_params.mqtt_broker {
+            Some(broker) => broker.clone(),
+            None => bail!("No MQTT broker configured"),
+        };
+        let client_id = format!("{}-check", mqtt_params.mqtt_client_id);
+        let (client, mut event_loop) =
+            AsyncClient::new(Self::mqtt_options(client_id, broker, &mqtt_params), 10);
+        let connect = async {
+            loop {
+                match event_loop.poll().await? {
+                    MqEvent::Incoming(MqIncoming::ConnAck(_)) => return Ok(()),
+                    _ => continue,
+                }
+            }
+        };
+        let result: anyhow::Result<()> = tokio::time::timeout(Duration::from_secs(10), connect)
+            .await
+            .context("Timed out connecting to MQTT broker")?;
+        client.disconnect().await.ok();
+        result
+    }
+
+    fn mqtt_options(client_id: String, broker: String, mqtt_params: &MqttParams) -> MqttOptions {
+        let mut mqtt_options = MqttOptions::new(client_id, broker, mqtt_params.mqtt_port);
+        mqtt_options.set_keep_alive(Duration::from_secs(15));
+        if let (Some(user), Some(pass)) = (&mqtt_params.mqtt_username, &mqtt_params.mqtt_password) {
             mqtt_options.set_credentials(user, pass);
         }
+        mqtt_options
+    }
 
-        let (client, mut event_loop) = AsyncClient::new(mqtt_options, 10);
-        tokio::spawn(async move {
+    // Publishes until shutdown, then flushes queued messages and disconnects cleanly. The event
+    // loop runs on the same task so that abandoning a stuck flush also stops reconnect attempts.
+    fn start_actual(
+        mqtt_params: MqttParams,
+        mut message_rx: mpsc::Receiver<Message>,
+        mut shutdown_rx: oneshot::Receiver<bool>,
+        errors: Arc<LastError>,
+        metrics: PublisherMetrics,
+    ) -> JoinHandle<()> {
+        let broker = mqtt_params.mqtt_broker.clone().unwrap(); // Checked by caller
+        let status_topic = status_topic(&mqtt_params.mqtt_topic_prefix);
+        // With room for all of a report's publishes, they are handed over in one go rather than
+        // each waiting for the event loop to take the one before; the event loop sends them
+        // without waiting for acknowledgements.
+        let request_capacity = mqtt_params.mqtt_request_capacity;
+        let mut mqtt_options =
+            Self::mqtt_options(mqtt_params.mqtt_client_id.clone(), broker, &mqtt_params);
+        mqtt_options.set_last_will(LastWill::new(
+            status_topic.clone(),
+            OFFLINE,
+            QoS::AtLeastOnce,
+            true,
+        ));
+        let (client, mut event_loop) = AsyncClient::new(mqtt_options, request_capacity);
+        let connection_errors = errors.clone();
+        let status_client = client.clone();
+        let online_topic = status_topic.clone();
+        let event_loop = async move {
+            let mut connected_before = false;
+            let mut backoff = Backoff::default();
+            // When each publish awaiting acknowledgement was sent, by packet identifier.
+            let mut unacknowledged: HashMap<u16, Instant> = HashMap::new();
             loop {
                 match event_loop.poll().await {
                     Ok(MqEvent::Incoming(MqIncoming::Disconnect))
                     | Ok(MqEvent::Outgoing(MqOutgoing::Disconnect)) => {
                         info!("MQTT graceful disconnect");
+                        metrics.connected.set(0);
                         break;
                     }
                     Ok(MqEvent::Incoming(MqIncoming::ConnAck(_))) => {
-                        info!("MQTT connection established")
+                        match backoff.reset() {
+                            Some(failures) if failures >= BROKER_DOWN_AFTER => info!(
+                                "MQTT connection established after {} failed attempts",
+                                failures
+                            ),
+                            _ => info!("MQTT connection established"),
+                        }
+                        if connected_before {
+                            metrics.reconnects.inc();
+                        }
+                        connected_before = true;
+                        metrics.connected.set(1);
+                        // Polling the event loop is what makes room for requests, so this can't
+                        // wait for room.
+                        if let Err(e) =
+                            status_client.try_publish(&online_topic, QoS::AtLeastOnce, true, ONLINE)
+                        {
+                            warn!("MQTT: could not publish availability: {}", e);
+                        }
+                    }
+                    Ok(MqEvent::Outgoing(MqOutgoing::Publish(pkid))) => {
+                        // Resent after a reconnect, the latency is the final attempt's.
+                        unacknowledged.insert(pkid, Instant::now());
+                    }
+                    Ok(MqEvent::Incoming(MqIncoming::PubAck(ack))) => {
+                        metrics
+                            .last_publish
+                            .set(Utc::now().timestamp_millis() as f64 / 1000.0);
+                        if let Some(sent) = unacknowledged.remove(&ack.pkid) {
+                            metrics
+                                .publish_latency
+                                .observe(sent.elapsed().as_secs_f64());
+                        }
                     }
                     Ok(notif) => debug!("MQTT: {:?}", notif),
                     Err(e) => {
-                        error!("MQTT: {}", e);
-                        tokio::time::sleep(std::time::Duration::from_secs(2)).await;
-                    }
-                }
-            }
-        });
-        let publisher_task = tokio::spawn({
-            let client = client.clone();
-            async move {
-                loop {
-                    if let Some((topic, retain, payload)) = message_rx.recv().await {
-                        match client
-                            .publish(topic, QoS::AtLeastOnce, retain, payload)
-                            .await
-                        {
-                            Ok(()) => {}
-                            Err(e) => error!("MQTT publish failed: {}", e),
+                        connection_errors.record(&e);
+                        metrics.connected.set(0);
+                        let (failures, delay) = backoff.fail();
+                        // Once the broker looks down for good, say so once instead of logging
+                        // every attempt.
+                        match failures.cmp(&BROKER_DOWN_AFTER) {
+                            Ordering::Less => error!("MQTT: {}", e),
+                            Ordering::Equal => error!(
+                                "MQTT broker unreachable after {} attempts, retrying at most \
+                                 every {:?} and logging further failures at debug level: {}",
+                                failures, BACKOFF_MAX, e
+                            ),
+                            Ordering::Greater => debug!("MQTT: {}", e),
                         }
+                        tokio::time::sleep(delay).await;
                     }
                 }
             }
-        });
-        tokio::spawn(async move {
-            shutdown_rx.await.ok();
+        }
+        .instrument(info_span!("mqtt_event_loop"));
+        let publish = async move {
+            let upgrade = loop {
+                tokio::select! {
+                    msg = message_rx.recv() => match msg {
+                        Some(msg) => Self::publish(&client, msg, &errors).await,
+                        None => break false,
+                    },
+                    upgrade = &mut shutdown_rx => break upgrade.unwrap_or(false),
+                }
+            };
             info!("MQTT publisher stopping");
-            publisher_task.abort();
+            message_rx.close();
+            while let Some(msg) = message_rx.recv().await {
+                Self::publish(&client, msg, &errors).await;
+            }
+            // The broker only sends the last will if the connection drops, not on disconnect.
+            if !upgrade {
+                Self::publish(
+                    &client,
+                    (status_topic.into(), true, OFFLINE.into()),
+                    &errors,
+                )
+                .await;
+            }
             client.disconnect().await.ok();
-        });
+        }
+        .instrument(info_span!("mqtt_publish"));
+        tokio::spawn(async move {
+            tokio::join!(event_loop, publish);
+        })
+    }
+
+    async fn publish(client: &AsyncClient, (topic, retain, payload): Message, errors: &LastError) {
+        if let Err(e) = client
+            .publish(&*topic, QoS::AtLeastOnce, retain, payload)
+            .await
+        {
+            error!("MQTT publish failed: {}", e);
+            errors.record(format!("Publish failed: {}", e));
+        }
     }
 
-    fn start_dummy(mut message_rx: mpsc::Receiver<Message>, shutdown_rx: oneshot::Receiver<()>) {
-        let dummy_sink_task = tokio::spawn(async move {
+    fn start_dummy(
+        mut message_rx: mpsc::Receiver<Message>,
+        mut shutdown_rx: oneshot::Receiver<bool>,
+    ) -> JoinHandle<()> {
+        tokio::spawn(async move {
             loop {
-                if let Some((topic, _, payload)) = message_rx.recv().await {
-                    debug!("DUMMY: {} -> {}", topic, payload);
+                tokio::select! {
+                    msg = message_rx.recv() => match msg {
+                        Some((topic, _, payload)) => debug!("DUMMY: {} -> {}", topic, payload),
+                        None => break,
+                    },
+                    _ = &mut shutdown_rx => break,
                 }
             }
-        });
-        tokio::spawn(async move {
-            shutdown_rx.await.ok();
-            dummy_sink_task.abort();
-        });
+        })
     }
 
-    pub fn shutdown(&self) {
-        self.shutdown_tx
-            .lock()
-            .unwrap()
-            .take()
-            .map(|stx| stx.send(()));
+    // Waits for a stopped backend to finish flushing, abandoning it after `timeout`.
+    async fn await_flush(mut task: JoinHandle<()>, timeout: Duration) {
+        if tokio::time::timeout(timeout, &mut task).await.is_err() {
+            warn!(
+                "MQTT publisher did not finish flushing within {:?}",
+                timeout
+            );
+            task.abort();
+        }
+    }
+
+    // Flushes and disconnects, publishing that the exporter is offline unless it is upgrading in
+    // place.
+    pub async fn shutdown(&self, timeout: Duration, upgrade: bool) {
+        let task = self.sink.lock().unwrap().shutdown(upgrade);
+        if let Some(task) = task {
+            Self::await_flush(task, timeout).await;
+        }
+    }
+
+    // Publishes an alert notification, retained so that subscribers see whether it is firing.
+    pub fn publish_alert(&self, name: &str, payload: String) {
+        let sender = &self.sink.lock().unwrap().sender;
+        sender.send(format!("alert/{}", name), true, payload);
     }
 
     pub fn handle_report(&self, msg: &decoder::TempestMsg) {
         use decoder::TempestMsg as TM;
+        let mut sink = self.sink.lock().unwrap();
         match msg {
-            TM::PrecipEvent(pe) => pe.publish_to(&self.sender, &self.station_params),
-            TM::StrikeEvent(se) => se.publish_to(&self.sender, &self.station_params),
-            TM::RapidWind(rw) => rw.publish_to(&self.sender, &self.station_params),
-            TM::Observation(obs) => obs.publish_to(&self.sender, &self.station_params),
-            //TM::DeviceStatus(ds) => ds.publish_to(&self.sender, &self.station_params),
-            //TM::HubStatus(hs) => hs.publish_to(&self.sender, &self.station_params),
-            _ => {}
+            TM::Observation(decoder::Observation { serial_number, .. })
+            | TM::DeviceStatus(decoder::DeviceStatus { serial_number, .. }) => {
+                sink.sender.set_serial_number(serial_number);
+            }
+            _ => (),
+        }
+        let sender = &sink.sender;
+        let sp = &*self.station_params.read().unwrap();
+        match msg {
+            TM::PrecipEvent(pe) => pe.publish_to(sender, sp),
+            TM::StrikeEvent(se) => se.publish_to(sender, sp),
+            TM::RapidWind(rw) => rw.publish_to(sender, sp),
+            TM::Observation(obs) => {
+                obs.publish_to(sender, sp);
+                self.publish_rain_rates(sender, obs);
+                self.publish_rain_events(sender, obs);
+                self.publish_leaf_wetness(sender, obs);
+                self.publish_forecast(sender, obs, sp);
+                self.publish_fire_danger(sender, obs);
+                if let Some(domoticz) = &sink.mqtt_params.domoticz {
+                    self.publish_domoticz(sender, domoticz, obs, sp);
+                }
+            }
+            TM::DeviceStatus(ds) => self.check_firmware(
+                sender,
+                &ds.serial_number,
+                ds.firmware_revision.to_string(),
+                ds.timestamp,
+            ),
+            TM::HubStatus(hs) => self.check_firmware(
+                sender,
+                &hs.serial_number,
+                hs.firmware_revision.clone(),
+                hs.timestamp,
+            ),
+            // Forwarded for troubleshooting with WeatherFlow support.
+            TM::Debug(dm) if sink.mqtt_params.mqtt_forward_diagnostics => {
+                sender.send_document(&format!("debug/{}", dm.kind), false, dm)
+            }
+            TM::Debug(_) => (),
         }
     }
+
+    // Publishes rain rates over rolling windows, steadier than the previous minute's rain.
+    fn publish_rain_rates(&self, sender: &MsgSender, obs: &decoder::Observation) {
+        let precip = match &obs.precip {
+            Some(precip) => precip,
+            None => return,
+        };
+        let (_, longest) = RAIN_RATE_WINDOWS[RAIN_RATE_WINDOWS.len() - 1];
+        let mut history = self.rain_history.lock().unwrap();
+        history.push(obs.timestamp, precip.quantity_last_minute.0, longest);
+        for (topic, window) in &self.rain_rate_topics {
+            sender.send_value(
+                &**topic,
+                true,
+                history.sum(*window) * 3600.0 / window.as_secs_f64(),
+            );
+        }
+    }
+
+    // Publishes the Zambretti forecast, once there is enough pressure history for a trend.
+    fn publish_forecast(
+        &self,
+        sender: &MsgSender,
+        obs: &decoder::Observation,
+        station_params: &StationParams,
+    ) {
+        let pressure = match obs.station_pressure {
+            Some(pressure) => pressure,
+            None => return,
+        };
+        let window = self.exporter_params.read().unwrap().storm.window;
+        let mut trend = self.pressure_trend.lock().unwrap();
+        trend.push(obs.timestamp, pressure.0, window);
+        if let Some(forecast) = trend
+            .change(window)
+            .and_then(|change| forecast::for_observation(obs, station_params, change, window))
+        {
+            sender.send("observation/forecast/code", true, forecast.code.to_string());
+            sender.send("observation/forecast/text", true, forecast.text.to_string());
+        }
+    }
+
+    // Publishes leaf wetness, going by the end of the latest rain event for when it last rained.
+    fn publish_leaf_wetness(&self, sender: &MsgSender, obs: &decoder::Observation) {
+        let last_rain = self.rain_events.lock().unwrap().latest().map(|e| e.end);
+        if let Some(wetness) = leaf::for_observation(obs, last_rain) {
+            sender.send_value("observation/thermal/leaf_wetness", true, wetness);
+            sender.send(
+                "observation/thermal/leaf_wet",
+                true,
+                (wetness >= leaf::WET_SCORE).to_string(),
+            );
+        }
+    }
+
+    // Publishes the fire danger index, with the drought factor from recent rain it depends on.
+    fn publish_fire_danger(&self, sender: &MsgSender, obs: &decoder::Observation) {
+        let precip = match &obs.precip {
+            Some(precip) => precip,
+            None => return,
+        };
+        let mut fire_danger = self.fire_danger.lock().unwrap();
+        fire_danger.push(obs.timestamp, precip.quantity_last_minute.0);
+        let drought_factor = fire_danger.drought_factor();
+        sender.send_value("observation/fire/drought_factor", true, drought_factor);
+        if let Some(index) = fire::for_observation(obs, drought_factor) {
+            sender.send_value("observation/fire/danger_index", true, index);
+        }
+    }
+
+    // Updates Domoticz virtual devices, in the combined formats Domoticz expects.
+    fn publish_domoticz(
+        &self,
+        sender: &MsgSender,
+        params: &DomoticzParams,
+        obs: &decoder::Observation,
+        station_params: &StationParams,
+    ) {
+        let rain = obs
+            .precip
+            .as_ref()
+            .map_or(0.0, |p| p.quantity_last_minute.0);
+        let rain_total = self
+            .domoticz_rain
+            .lock()
+            .unwrap()
+            .observe(obs.timestamp, rain);
+        for payload in domoticz::updates(params, obs, station_params, rain_total) {
+            sender.send_unprefixed(params.topic.as_str().into(), false, payload);
+        }
+    }
+
+    // Announces the start and end of rain events, with the rain accumulated over them.
+    fn publish_rain_events(&self, sender: &MsgSender, obs: &decoder::Observation) {
+        let precip = match &obs.precip {
+            Some(precip) => precip,
+            None => return,
+        };
+        let dry_period = self.exporter_params.read().unwrap().rain_event_dry_period;
+        let changes = self.rain_events.lock().unwrap().observe(
+            obs.timestamp,
+            obs.report_interval,
+            precip.quantity_last_minute.0,
+            dry_period,
+        );
+        let payload = |event: &RainEvent| {
+            serde_json::json!({
+                "serial_number": obs.serial_number,
+                "start": event.start,
+                "end": event.end,
+                "duration_sec": event.duration().num_seconds(),
+                "total_mm": event.total,
+            })
+        };
+        for change in changes {
+            match change {
+                RainEventChange::Started(event) => {
+                    sender.send_document("event/rain_start", false, &payload(&event))
+                }
+                RainEventChange::Ended(event) => {
+                    info!(
+                        "Rain event ended after {} minutes with {} mm",
+                        event.duration().num_minutes(),
+                        event.total
+                    );
+                    sender.send_document("event/rain_stop", false, &payload(&event))
+                }
+            }
+        }
+    }
+
+    // Announces a firmware update, so that data anomalies can be correlated with it.
+    fn check_firmware(
+        &self,
+        sender: &MsgSender,
+        serial_number: &str,
+        revision: String,
+        timestamp: DateTime<Utc>,
+    ) {
+        let previous = self
+            .firmware_revisions
+            .lock()
+            .unwrap()
+            .insert(serial_number.to_string(), revision.clone());
+        let previous = match previous {
+            Some(previous) if previous != revision => previous,
+            _ => return,
+        };
+        info!(
+            "{} firmware changed from revision {} to {}",
+            serial_number, previous, revision
+        );
+        let event = serde_json::json!({
+            "serial_number": serial_number,
+            "previous_revision": previous,
+            "revision": revision,
+            "timestamp": timestamp,
+        });
+        sender.send_document("event/firmware", false, &event);
+    }
 }
 
-fn publish_wind(sender: &MsgSender, prefix: &str, wind: &decoder::Wind) {
-    sender.send(
-        format!("{}/speed_magnitude_m_per_s", prefix),
-        true,
-        wind.speed_magnitude().to_string(),
-    );
-    sender.send(
-        format!("{}/source_direction_deg", prefix),
-        true,
-        wind.source_direction().to_string(),
-    );
+// Topics of one wind reading, spelled out rather than formatted for every report.
+pub struct WindTopics {
+    pub speed_magnitude: &'static str,
+    pub source_direction: &'static str,
+    pub component_velocity: &'static str,
+}
+
+macro_rules! wind_topics {
+    ($prefix:literal) => {
+        WindTopics {
+            speed_magnitude: concat!($prefix, "/speed_magnitude_m_per_s"),
+            source_direction: concat!($prefix, "/source_direction_deg"),
+            component_velocity: concat!($prefix, "/component_velocity_m_per_s"),
+        }
+    };
+}
+
+pub const INSTANT_WIND_TOPICS: WindTopics = wind_topics!("instant_wind");
+pub const WIND_LULL_TOPICS: WindTopics = wind_topics!("observation/wind/lull");
+pub const WIND_AVG_TOPICS: WindTopics = wind_topics!("observation/wind/avg");
+pub const WIND_GUST_TOPICS: WindTopics = wind_topics!("observation/wind/gust");
+
+fn publish_wind(sender: &MsgSender, topics: &WindTopics, wind: &decoder::Wind) {
+    sender.send_value(topics.speed_magnitude, true, wind.speed_magnitude().0);
+    sender.send_value(topics.source_direction, true, wind.source_direction());
+    let topic = topics.component_velocity;
     let (north, east) = wind.component_velocity();
     sender.send(
-        format!("{}/component_velocity_m_per_s", prefix),
+        topic,
         true,
-        format!("{} {}", north, east),
+        format!(
+            "{} {}",
+            sender.round(topic, north),
+            sender.round(topic, east)
+        ),
     );
 }
 
@@ -165,115 +795,99 @@ trait PublishTo {
 
 impl PublishTo for decoder::PrecipEvent {
     fn publish_to(&self, sender: &MsgSender, _station_params: &StationParams) {
-        sender.send("tempest/event/precip", false, self.timestamp.to_rfc3339());
+        sender.send("event/precip", false, self.timestamp.to_rfc3339());
     }
 }
 
 impl PublishTo for decoder::StrikeEvent {
     fn publish_to(&self, sender: &MsgSender, _station_params: &StationParams) {
-        sender.send(
-            "tempest/event/lightning",
-            false,
-            serde_json::to_string(&self).unwrap(),
-        );
+        sender.send_document("event/lightning", false, self);
     }
 }
 
 impl PublishTo for decoder::RapidWind {
     fn publish_to(&self, sender: &MsgSender, _station_params: &StationParams) {
-        publish_wind(sender, "tempest/instant_wind", &self.wind);
+        publish_wind(sender, &INSTANT_WIND_TOPICS, &self.wind);
     }
 }
 
 impl PublishTo for decoder::Observation {
     fn publish_to(&self, sender: &MsgSender, station_params: &StationParams) {
-        sender.send(
-            "tempest/observation/timestamp",
-            true,
-            self.timestamp.to_rfc3339(),
-        );
+        sender.send("observation/timestamp", true, self.timestamp.to_rfc3339());
         if let Some(wind) = &self.wind {
-            publish_wind(sender, "tempest/observation/wind/lull", &wind.lull);
-            publish_wind(sender, "tempest/observation/wind/avg", &wind.avg);
-            publish_wind(sender, "tempest/observation/wind/gust", &wind.gust);
+            publish_wind(sender, &WIND_LULL_TOPICS, &wind.lull);
+            publish_wind(sender, &WIND_AVG_TOPICS, &wind.avg);
+            publish_wind(sender, &WIND_GUST_TOPICS, &wind.gust);
         }
-        self.station_pressure.map(|v| {
-            sender.send(
-                "tempest/observation/pressure/station_hpa",
-                true,
-                v.to_string(),
-            )
-        });
-        self.barometric_pressure(station_params.elevation).map(|v| {
-            sender.send(
-                "tempest/observation/pressure/barometric_hpa",
-                true,
-                v.to_string(),
-            )
-        });
-        self.air_temperature.map(|v| {
-            sender.send(
-                "tempest/observation/thermal/temperature_deg_c",
-                true,
-                v.to_string(),
-            )
-        });
-        self.relative_humidity.map(|v| {
-            sender.send(
-                "tempest/observation/thermal/relative_humidity_pct",
-                true,
-                v.to_string(),
-            )
-        });
-        self.dew_point().map(|v| {
-            sender.send(
-                "tempest/observation/thermal/dew_point_deg_c",
-                true,
-                v.to_string(),
-            )
-        });
+        self.station_pressure
+            .map(|v| sender.send_value("observation/pressure/station_hpa", true, v.0));
+        self.barometric_pressure(station_params.elevation)
+            .map(|v| sender.send_value("observation/pressure/barometric_hpa", true, v.0));
+        self.air_temperature
+            .map(|v| sender.send_value("observation/thermal/temperature_deg_c", true, v.0));
+        self.relative_humidity
+            .map(|v| sender.send_value("observation/thermal/relative_humidity_pct", true, v));
+        self.dew_point()
+            .map(|v| sender.send_value("observation/thermal/dew_point_deg_c", true, v.0));
         self.wet_bulb_temperature().map(|v| {
-            sender.send(
-                "tempest/observation/thermal/wet_bulb_temperature_deg_c",
+            sender.send_value("observation/thermal/wet_bulb_temperature_deg_c", true, v.0)
+        });
+        self.wet_bulb_globe_temperature().map(|v| {
+            sender.send_value(
+                "observation/thermal/wet_bulb_globe_temperature_deg_c",
                 true,
-                v.to_string(),
+                v.0,
             )
         });
-        self.apparent_temperature().map(|v| {
+        self.apparent_temperature_by(station_params.apparent_temperature_formula)
+            .map(|v| {
+                sender.send_value("observation/thermal/apparent_temperature_deg_c", true, v.0)
+            });
+        self.frost_risk()
+            .map(|v| sender.send("observation/thermal/frost_risk", true, v.to_string()));
+        self.comfort().map(|comfort| {
             sender.send(
-                "tempest/observation/thermal/apparent_temperature_deg_c",
+                "observation/thermal/comfort",
                 true,
-                v.to_string(),
+                comfort.as_str().to_string(),
             )
         });
         if let Some(solar) = &self.solar {
-            sender.send(
-                "tempest/observation/solar/illuminance_lux",
+            sender.send_value("observation/solar/illuminance_lux", true, solar.illuminance);
+            sender.send_value(
+                "observation/solar/irradiance_w_per_m2",
                 true,
-                solar.illuminance.to_string(),
-            );
-            sender.send(
-                "tempest/observation/solar/irradiance_w_per_m2",
-                true,
-                solar.irradiance.to_string(),
+                solar.irradiance,
             );
+            sender.send_value("observation/solar/uv_index", true, solar.ultraviolet_index);
+        }
+        station_params
+            .metadata
+            .location()
+            .and_then(|(latitude, longitude)| self.clear_sky_index(latitude, longitude))
+            .map(|v| sender.send_value("observation/solar/clear_sky_index", true, v));
+        self.estimated_precip_phase().map(|phase| {
             sender.send(
-                "tempest/observation/solar/uv_index",
+                "observation/precip/estimated_phase",
                 true,
-                solar.ultraviolet_index.to_string(),
-            );
-        }
+                phase.as_str().to_string(),
+            )
+        });
         if let Some(precip) = &self.precip {
-            sender.send(
-                "tempest/observation/precip/previous_minute_rain_mm",
+            sender.send_value(
+                "observation/precip/previous_minute_rain_mm",
                 true,
-                precip.quantity_last_minute.to_string(),
+                precip.quantity_last_minute.0,
             );
         }
-        sender.send(
-            "tempest/status/battery_volts",
-            true,
-            self.battery_volts.to_string(),
-        );
+        for derived in &station_params.derived {
+            derived.evaluate(self, station_params).map(|v| {
+                sender.send_value(format!("observation/derived/{}", derived.name), true, v)
+            });
+        }
+        sender.send_value("status/battery_volts", true, self.battery_volts);
+        if !station_params.metadata.is_empty() {
+            sender.send_document("station/info", true, &station_params.metadata);
+        }
     }
 }
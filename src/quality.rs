@@ -0,0 +1,113 @@
+//! Data quality score for each device, so that consumers can discount readings from a degraded
+//! station rather than treating all data equally.
+//!
+//! A device starts at 100 and loses points for failed sensors and lightning noise, weak radio
+//! signal, a battery low enough to put a Tempest into a power saving mode, and fields missing from
+//! its observations that it has reported before. The score is floored at 0.
+
+use std::collections::HashMap;
+
+use crate::decoder::{DeviceStatus, Observation};
+
+const FAILED_SENSOR_PENALTY: f64 = 20.0;
+const LIGHTNING_NOISE_PENALTY: f64 = 5.0;
+const MISSING_FIELD_PENALTY: f64 = 10.0;
+
+// Signal no weaker than this costs nothing, and the penalty grows linearly to the maximum at the
+// weak end.
+const RSSI_STRONG_DBM: f64 = -80.0;
+const RSSI_WEAK_DBM: f64 = -100.0;
+const RSSI_MAX_PENALTY: f64 = 20.0;
+
+// Battery voltages below which a Tempest enters power saving modes 1, 2 and 3, sampling sensors
+// less often, with the penalty for each.
+const BATTERY_MODES: &[(f64, f64)] = &[(2.375, 20.0), (2.41, 10.0), (2.455, 5.0)];
+
+/// Tracks the components of the quality score for each device, by serial number.
+#[derive(Debug, Default)]
+pub struct Quality {
+    devices: HashMap<String, DeviceQuality>,
+}
+
+#[derive(Debug, Default)]
+struct DeviceQuality {
+    status_penalty: f64,
+    battery_penalty: f64,
+    missing_penalty: f64,
+    // Observation fields the device has reported at some point, by bit.
+    fields_seen: u8,
+}
+
+impl DeviceQuality {
+    fn score(&self) -> f64 {
+        (100.0 - self.status_penalty - self.battery_penalty - self.missing_penalty).max(0.0)
+    }
+}
+
+impl Quality {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Updates the device's score from its sensor flags, signal strength and battery voltage, and
+    /// returns it.
+    pub fn update_status(&mut self, ds: &DeviceStatus) -> f64 {
+        let device = self.devices.entry(ds.serial_number.clone()).or_default();
+        let ss = &ds.sensor_status;
+        let failed = [
+            ss.lightning_failure,
+            ss.pressure_failed,
+            ss.temperature_failed,
+            ss.humidity_failed,
+            ss.wind_failed,
+            ss.precip_failed,
+            ss.irradiance_failed,
+        ]
+        .iter()
+        .filter(|failed| **failed)
+        .count();
+        let mut penalty = failed as f64 * FAILED_SENSOR_PENALTY;
+        if ss.lightning_noise {
+            penalty += LIGHTNING_NOISE_PENALTY;
+        }
+        penalty += rssi_penalty(ds.rssi.min(ds.hub_rssi));
+        device.status_penalty = penalty;
+        device.battery_penalty = battery_penalty(ds.voltage);
+        device.score()
+    }
+
+    /// Updates the device's score from the fields present in an observation and its battery
+    /// voltage, and returns it.
+    pub fn update_observation(&mut self, obs: &Observation) -> f64 {
+        let device = self.devices.entry(obs.serial_number.clone()).or_default();
+        let present = [
+            obs.wind.is_some(),
+            obs.station_pressure.is_some(),
+            obs.air_temperature.is_some(),
+            obs.relative_humidity.is_some(),
+            obs.solar.is_some(),
+            obs.precip.is_some(),
+            obs.lightning.is_some(),
+        ]
+        .iter()
+        .enumerate()
+        .fold(0u8, |bits, (i, present)| bits | ((*present as u8) << i));
+        device.fields_seen |= present;
+        let missing = (device.fields_seen & !present).count_ones();
+        device.missing_penalty = missing as f64 * MISSING_FIELD_PENALTY;
+        device.battery_penalty = battery_penalty(obs.battery_volts);
+        device.score()
+    }
+}
+
+fn rssi_penalty(rssi: f64) -> f64 {
+    let weakness = (RSSI_STRONG_DBM - rssi) / (RSSI_STRONG_DBM - RSSI_WEAK_DBM);
+    weakness.clamp(0.0, 1.0) * RSSI_MAX_PENALTY
+}
+
+fn battery_penalty(volts: f64) -> f64 {
+    BATTERY_MODES
+        .iter()
+        .find(|(below, _)| volts < *below)
+        .map_or(0.0, |(_, penalty)| *penalty)
+}
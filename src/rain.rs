@@ -0,0 +1,249 @@
+//! Rain events: spells of rain separated by dry periods, summarized the way the WeatherFlow app
+//! does. Also rain seasons, such as water years, totalled for comparison with regional norms, and
+//! a rain counter that only ever goes up.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::bail;
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::day::DayZone;
+
+/// A spell of rain, from the start of the first wet report interval to the end of the latest.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RainEvent {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// Rain accumulated over the event (mm).
+    pub total: f64,
+}
+
+impl RainEvent {
+    pub fn duration(&self) -> chrono::Duration {
+        self.end - self.start
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RainEventChange {
+    Started(RainEvent),
+    Ended(RainEvent),
+}
+
+/// Tracks the ongoing rain event, if any, from the rain in each observation.
+#[derive(Debug, Default, Serialize)]
+pub struct RainEvents {
+    current: Option<RainEvent>,
+    last: Option<RainEvent>,
+}
+
+impl RainEvents {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The ongoing event, if it is raining or has been within the dry period.
+    pub fn current(&self) -> Option<&RainEvent> {
+        self.current.as_ref()
+    }
+
+    /// The ongoing event, or failing that the one that ended most recently.
+    pub fn latest(&self) -> Option<&RainEvent> {
+        self.current.as_ref().or(self.last.as_ref())
+    }
+
+    /// Records `rain` (mm) fallen over the report interval ending at `timestamp`. An event ends
+    /// once there has been no rain for `dry_period`, and rain after that starts a new one, so an
+    /// observation may both end an event and start another. Observations older than the ongoing
+    /// event's latest rain are ignored.
+    pub fn observe(
+        &mut self,
+        timestamp: DateTime<Utc>,
+        interval: chrono::Duration,
+        rain: f64,
+        dry_period: Duration,
+    ) -> Vec<RainEventChange> {
+        let mut changes = Vec::new();
+        if let Some(event) = &self.current {
+            if timestamp <= event.end {
+                return changes;
+            }
+            // Only the part of the interval before any rain in it was dry.
+            let dry_until = if rain > 0.0 {
+                timestamp - interval
+            } else {
+                timestamp
+            };
+            if dry_until - event.end >= chrono::Duration::from_std(dry_period).unwrap() {
+                let event = self.current.take().unwrap();
+                self.last = Some(event.clone());
+                changes.push(RainEventChange::Ended(event));
+            }
+        }
+        if rain > 0.0 {
+            match &mut self.current {
+                Some(event) => {
+                    event.end = timestamp;
+                    event.total += rain;
+                }
+                None => {
+                    let event = RainEvent {
+                        start: timestamp - interval,
+                        end: timestamp,
+                        total: rain,
+                    };
+                    self.current = Some(event.clone());
+                    changes.push(RainEventChange::Started(event));
+                }
+            }
+        }
+        changes
+    }
+}
+
+/// The month and day a rain season starts on each year, written `MM-DD`. February 29 isn't
+/// accepted, as most years have none.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SeasonStart {
+    month: u32,
+    day: u32,
+}
+
+impl Default for SeasonStart {
+    /// October 1, the start of the water year in much of the northern hemisphere.
+    fn default() -> Self {
+        SeasonStart { month: 10, day: 1 }
+    }
+}
+
+impl SeasonStart {
+    /// The date the season including `date` started on.
+    pub fn season_of(&self, date: NaiveDate) -> NaiveDate {
+        let start = |year| NaiveDate::from_ymd_opt(year, self.month, self.day).unwrap();
+        match start(date.year()) {
+            this_year if this_year <= date => this_year,
+            _ => start(date.year() - 1),
+        }
+    }
+}
+
+impl FromStr for SeasonStart {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parsed = s
+            .split_once('-')
+            .and_then(|(month, day)| Some((month.parse().ok()?, day.parse().ok()?)));
+        match parsed {
+            // A day found in a common year is found in every year.
+            Some((month, day)) if NaiveDate::from_ymd_opt(2001, month, day).is_some() => {
+                Ok(SeasonStart { month, day })
+            }
+            _ => bail!(
+                "Rain season start {} is not a month and day such as 10-01",
+                s
+            ),
+        }
+    }
+}
+
+impl fmt::Display for SeasonStart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}-{:02}", self.month, self.day)
+    }
+}
+
+/// Rain accumulated over a season so far.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SeasonTotal {
+    /// Local date the season started on.
+    pub start: NaiveDate,
+    /// Rain since then (mm).
+    pub total: f64,
+}
+
+/// Tracks the rain accumulated since the current rain season started, in station local time.
+#[derive(Debug, Default, Serialize)]
+pub struct RainSeason(Option<SeasonTotal>);
+
+impl RainSeason {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records `rain` (mm) fallen by `timestamp`, starting the total over if a new season has
+    /// started since the last rain recorded. Rain from before the season being totalled is
+    /// ignored, as it belongs to an earlier one.
+    pub fn observe(
+        &mut self,
+        zone: DayZone,
+        season_start: SeasonStart,
+        timestamp: DateTime<Utc>,
+        rain: f64,
+    ) {
+        let date = zone.date(timestamp);
+        let season = season_start.season_of(date);
+        match &mut self.0 {
+            Some(current) if date < current.start => {}
+            Some(current) if current.start == season => current.total += rain,
+            _ => {
+                self.0 = Some(SeasonTotal {
+                    start: season,
+                    total: rain,
+                })
+            }
+        }
+    }
+
+    /// The total for the season including `at`, which is zero if it has started since the last
+    /// rain recorded. `None` if none has been.
+    pub fn total_at(
+        &self,
+        zone: DayZone,
+        season_start: SeasonStart,
+        at: DateTime<Utc>,
+    ) -> Option<f64> {
+        let current = self.0.as_ref()?;
+        let season = season_start.season_of(zone.date(at));
+        Some(if season == current.start {
+            current.total
+        } else {
+            0.0
+        })
+    }
+
+    /// The season being totalled, to be restored after a restart.
+    pub fn current(&self) -> Option<&SeasonTotal> {
+        self.0.as_ref()
+    }
+
+    /// Continues totalling a season from before a restart.
+    pub fn restore(&mut self, total: SeasonTotal) {
+        self.0 = Some(total);
+    }
+}
+
+/// Rain counted into a total that only ever goes up, for consumers that work out rain over a day
+/// from the counter's increase, such as Domoticz rain devices.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RainCounter {
+    /// Rain counted so far (mm).
+    pub total: f64,
+    /// Time of the latest observation counted.
+    pub counted_through: Option<DateTime<Utc>>,
+}
+
+impl RainCounter {
+    /// Counts `rain` (mm) fallen by `timestamp` and returns the total. An observation no later
+    /// than one already counted, such as a saved report replayed after a restart, isn't counted
+    /// again.
+    pub fn observe(&mut self, timestamp: DateTime<Utc>, rain: f64) -> f64 {
+        if !matches!(self.counted_through, Some(through) if timestamp <= through) {
+            self.total += rain;
+            self.counted_through = Some(timestamp);
+        }
+        self.total
+    }
+}
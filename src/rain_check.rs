@@ -0,0 +1,100 @@
+//! Yesterday's rain as corrected by the WeatherFlow cloud. The haptic rain sensor's totals are
+//! corrected after the fact against radar and nearby gauges (Rain Check), so the cloud is queried
+//! for both the raw and the corrected total.
+
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use serde::Deserialize;
+
+const API_BASE: &str = "https://swd.weatherflow.com/swd/rest";
+
+/// WeatherFlow station to reconcile rain with, and how often.
+#[derive(Clone, PartialEq)]
+pub struct RainCheckParams {
+    pub station_id: u64,
+    pub token: String,
+    pub interval: Duration,
+}
+
+// Keeps the token out of logs and `check-config` output.
+impl std::fmt::Debug for RainCheckParams {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RainCheckParams")
+            .field("station_id", &self.station_id)
+            .field("token", &"<redacted>")
+            .field("interval", &self.interval)
+            .finish()
+    }
+}
+
+/// Rain over yesterday in station local time (mm), as measured and, once Rain Check has run, as
+/// corrected.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DailyRain {
+    pub raw: f64,
+    pub corrected: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct StationObservations {
+    status: Status,
+    #[serde(default)]
+    obs: Vec<StationObservation>,
+}
+
+#[derive(Deserialize)]
+struct Status {
+    status_code: i64,
+    status_message: String,
+}
+
+#[derive(Deserialize)]
+struct StationObservation {
+    precip_accum_local_yesterday: Option<f64>,
+    precip_accum_local_yesterday_final: Option<f64>,
+}
+
+/// Extracts yesterday's rain from a response of the station observations endpoint.
+pub fn parse(body: &str) -> anyhow::Result<DailyRain> {
+    let response: StationObservations =
+        serde_json::from_str(body).context("Parsing station observations")?;
+    if response.status.status_code != 0 {
+        bail!(
+            "WeatherFlow API error {}: {}",
+            response.status.status_code,
+            response.status.status_message
+        );
+    }
+    let obs = match response.obs.first() {
+        Some(obs) => obs,
+        None => bail!("Station observations are empty"),
+    };
+    match obs.precip_accum_local_yesterday {
+        Some(raw) => Ok(DailyRain {
+            raw,
+            corrected: obs.precip_accum_local_yesterday_final,
+        }),
+        None => bail!("Station observations lack yesterday's rain"),
+    }
+}
+
+/// Queries the WeatherFlow REST API for yesterday's rain at the station.
+pub async fn fetch(http: &reqwest::Client, params: &RainCheckParams) -> anyhow::Result<DailyRain> {
+    let body = http
+        .get(format!(
+            "{}/observations/station/{}",
+            API_BASE, params.station_id
+        ))
+        .query(&[("token", &params.token)])
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        // The URL carries the token.
+        .map_err(|e| e.without_url())
+        .context("Querying WeatherFlow station observations")?
+        .text()
+        .await
+        .map_err(|e| e.without_url())?;
+    parse(&body)
+}
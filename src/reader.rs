@@ -1,9 +1,16 @@
+//! Deserialization of API datagrams into raw messages that mirror the JSON wire format.
+
+use std::str::FromStr;
+
+use anyhow::bail;
 use futures_core::stream::Stream;
-use log::warn;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Number, Value};
 use tokio_stream::StreamExt;
+use tracing::{trace_span, warn};
 
-#[derive(Deserialize, Debug)]
+/// A message from the Tempest local UDP API, exactly as sent by the hub.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 #[serde(tag = "type")]
 pub enum RawTempestMsg {
     #[serde(rename = "evt_precip")]
@@ -18,30 +25,34 @@ pub enum RawTempestMsg {
     DeviceStatus(RawDeviceStatus),
     #[serde(rename = "hub_status")]
     HubStatus(RawHubStatus),
+    /// Only produced by [`parse`], as debug and diagnostic message types vary and are
+    /// undocumented.
+    #[serde(skip)]
+    Debug(RawDebugMessage),
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct RawPrecipEvent {
     pub serial_number: String,
     pub hub_sn: String,
     pub evt: (i64,),
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct RawStrikeEvent {
     pub serial_number: String,
     pub hub_sn: String,
     pub evt: (i64, f64, f64),
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct RawRapidWind {
     pub serial_number: String,
     pub hub_sn: String,
     pub ob: (i64, f64, f64),
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct RawObservation {
     pub serial_number: String,
     pub hub_sn: String,
@@ -49,7 +60,7 @@ pub struct RawObservation {
     pub firmware_revision: i32,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct RawDeviceStatus {
     pub serial_number: String,
     pub hub_sn: String,
@@ -63,7 +74,7 @@ pub struct RawDeviceStatus {
     pub debug: i32,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct RawHubStatus {
     pub serial_number: String,
     pub firmware_revision: String,
@@ -75,13 +86,203 @@ pub struct RawHubStatus {
     pub radio_stats: [i32; 5],
 }
 
-pub fn new<RX: Stream<Item = String>>(receiver: RX) -> impl Stream<Item = RawTempestMsg> {
-    receiver.filter_map(|json| {
-        serde_json::from_str(&json)
+/// A debug message, sent alongside the usual reports while debugging is enabled on a device, or a
+/// diagnostic message such as newer hub firmware sends. Its contents are undocumented, so it is
+/// kept as it came.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RawDebugMessage {
+    /// Message type, such as `light_debug`, `hub_debug` or `radio_stats`.
+    pub kind: String,
+    /// Every field other than the type.
+    pub fields: Map<String, Value>,
+}
+
+/// How tolerant parsing is of datagrams that stray from the documented format, as new firmware
+/// revisions sometimes do.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DecodeMode {
+    /// Drops any datagram that doesn't match the documented format.
+    Strict,
+    /// Repairs what can be repaired first: observation values that aren't numbers are taken as
+    /// missing, as are unknown precipitation types, rows are padded or cut to the documented
+    /// length and only the latest of several is kept, and status fields newer firmware may leave
+    /// out or extend are filled in or trimmed. Unknown fields are ignored in either mode.
+    #[default]
+    Lenient,
+}
+
+impl FromStr for DecodeMode {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "strict" => Ok(DecodeMode::Strict),
+            "lenient" => Ok(DecodeMode::Lenient),
+            other => bail!("Unrecognized decode mode {}", other),
+        }
+    }
+}
+
+/// Parses a single JSON datagram strictly.
+pub fn parse(json: &str) -> serde_json::Result<RawTempestMsg> {
+    parse_slice(json.as_bytes(), DecodeMode::Strict)
+}
+
+/// Parses a single JSON datagram in the given mode.
+pub fn parse_with(json: &str, mode: DecodeMode) -> serde_json::Result<RawTempestMsg> {
+    parse_slice(json.as_bytes(), mode)
+}
+
+// Datagrams are parsed straight from the received bytes, without first copying them into a
+// string.
+fn parse_slice(json: &[u8], mode: DecodeMode) -> serde_json::Result<RawTempestMsg> {
+    if mode == DecodeMode::Strict {
+        return serde_json::from_slice(json).or_else(|e| parse_debug(json).ok_or(e));
+    }
+    let mut msg: Value = serde_json::from_slice(json)?;
+    repair(&mut msg);
+    serde_json::from_value(msg).or_else(|e| parse_debug(json).ok_or(e))
+}
+
+// Values in an `obs_st` row, and the precipitation types documented for it.
+const OBSERVATION_VALUES: usize = 18;
+const PRECIP_TYPE_INDEX: usize = 13;
+const PRECIP_TYPES: [i64; 4] = [0, 1, 2, 3];
+
+// Reset flag labels documented for `hub_status`.
+const RESET_FLAGS: [&str; 8] = ["BOR", "PIN", "POR", "SFT", "WDG", "WWD", "LPW", "HRDFLT"];
+
+// Brings a datagram of a known type as close to the documented format as it can be.
+fn repair(msg: &mut Value) {
+    let fields = match msg.as_object_mut() {
+        Some(fields) => fields,
+        None => return,
+    };
+    match fields.get("type").and_then(Value::as_str) {
+        Some("obs_st") => repair_observation(fields),
+        Some("device_status") => {
+            for field in ["sensor_status", "debug"] {
+                fields.entry(field).or_insert_with(|| 0.into());
+            }
+        }
+        Some("hub_status") => repair_hub_status(fields),
+        _ => (),
+    }
+}
+
+// A number, or a string holding one, and otherwise nothing.
+fn number(value: &Value) -> Option<Number> {
+    match value {
+        Value::Number(n) => Some(n.clone()),
+        Value::String(s) => s.trim().parse().ok().and_then(Number::from_f64),
+        _ => None,
+    }
+}
+
+fn repair_observation(fields: &mut Map<String, Value>) {
+    let row = fields
+        .get("obs")
+        .and_then(Value::as_array)
+        .and_then(|rows| rows.last())
+        .and_then(Value::as_array)
+        .map_or(&[][..], Vec::as_slice);
+    let mut row: Vec<Value> = row
+        .iter()
+        .map(|value| number(value).map_or(Value::Null, Value::Number))
+        .chain(std::iter::repeat(Value::Null))
+        .take(OBSERVATION_VALUES)
+        .collect();
+    let precip_type = row[PRECIP_TYPE_INDEX].as_f64();
+    if !matches!(precip_type, Some(t) if PRECIP_TYPES.contains(&(t as i64))) {
+        row[PRECIP_TYPE_INDEX] = Value::Null;
+    }
+    fields.insert("obs".to_string(), Value::Array(vec![Value::Array(row)]));
+}
+
+fn repair_hub_status(fields: &mut Map<String, Value>) {
+    let reset_flags = fields
+        .get("reset_flags")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .split(',')
+        .filter(|label| RESET_FLAGS.contains(label))
+        .collect::<Vec<_>>()
+        .join(",");
+    fields.insert("reset_flags".to_string(), reset_flags.into());
+    fields.entry("seq").or_insert_with(|| 0.into());
+    let radio_stats = fields
+        .get("radio_stats")
+        .and_then(Value::as_array)
+        .map_or(&[][..], Vec::as_slice);
+    let radio_stats: Vec<Value> = radio_stats
+        .iter()
+        .map(|value| Value::from(value.as_i64().unwrap_or(0)))
+        .chain(std::iter::repeat(Value::from(0)))
+        .take(5)
+        .collect();
+    fields.insert("radio_stats".to_string(), radio_stats.into());
+}
+
+// Parses a datagram of a debug or diagnostic message type, which is named like `light_debug` or
+// `radio_stats`.
+fn parse_debug(json: &[u8]) -> Option<RawTempestMsg> {
+    let mut fields: Map<String, Value> = serde_json::from_slice(json).ok()?;
+    match fields.remove("type")? {
+        Value::String(kind)
+            if kind.ends_with("_debug")
+                || kind.starts_with("debug")
+                || kind.ends_with("_stats") =>
+        {
+            Some(RawTempestMsg::Debug(RawDebugMessage { kind, fields }))
+        }
+        _ => None,
+    }
+}
+
+/// Parses a stream of JSON datagrams strictly, logging and dropping any that fail to parse. The
+/// datagrams may be anything holding bytes, such as the [`bytes::Bytes`] a
+/// [`crate::receiver::Receiver`] yields or `String`s.
+pub fn new<RX: Stream<Item = impl AsRef<[u8]>>>(receiver: RX) -> impl Stream<Item = RawTempestMsg> {
+    with_mode(receiver, DecodeMode::Strict)
+}
+
+/// Parses a stream of JSON datagrams in the given mode, logging and dropping any that fail to
+/// parse.
+pub fn with_mode<RX: Stream<Item = impl AsRef<[u8]>>>(
+    receiver: RX,
+    mode: DecodeMode,
+) -> impl Stream<Item = RawTempestMsg> {
+    with_drop_hook(receiver, mode, |_| {})
+}
+
+/// Like [`with_mode`], also passing the error for each dropped message to `dropped`, such as to
+/// count them.
+pub fn with_drop_hook<RX: Stream<Item = impl AsRef<[u8]>>>(
+    receiver: RX,
+    mode: DecodeMode,
+    mut dropped: impl FnMut(&serde_json::Error),
+) -> impl Stream<Item = RawTempestMsg> {
+    receiver.filter_map(move |json| {
+        let _span = trace_span!("read").entered();
+        parse_slice(json.as_ref(), mode)
             .map_err(|e| {
-                warn!("Dropped unreadable message: {}", json);
+                warn!(
+                    "Dropped unreadable message: {}",
+                    String::from_utf8_lossy(json.as_ref())
+                );
                 warn!(".. error was: {}", e);
+                dropped(&e);
             })
             .ok()
     })
 }
+
+/// Short snake_case name of the kind of parse error, suitable for metric labels: `malformed_json`
+/// for a datagram that isn't complete JSON, or `unrecognized` for JSON that isn't a known message.
+pub fn error_category(e: &serde_json::Error) -> &'static str {
+    match e.classify() {
+        serde_json::error::Category::Io => "io",
+        serde_json::error::Category::Syntax | serde_json::error::Category::Eof => "malformed_json",
+        serde_json::error::Category::Data => "unrecognized",
+    }
+}
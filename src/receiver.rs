@@ -1,28 +1,75 @@
+//! Reception of API datagrams from the local network.
+
 use std::pin::Pin;
 use std::task::Context;
 use std::task::Poll;
 
+use bytes::{Bytes, BytesMut};
 use futures_core::stream::Stream;
-use log::warn;
 use tokio::io::ReadBuf;
 use tokio::net::UdpSocket;
+use tracing::warn;
+
+/// UDP port Tempest hubs broadcast to.
+pub const API_PORT: u16 = 50222;
 
-pub struct Receiver(UdpSocket);
+// Largest datagram received whole; the hub's are a few hundred bytes.
+const MAX_DATAGRAM_SIZE: usize = 1024;
+
+/// Stream of JSON datagrams broadcast by Tempest hubs on the local network.
+///
+/// Datagrams are received into one buffer and handed out as slices of it, so that once earlier
+/// datagrams are dropped the buffer is reused rather than allocating for each one.
+pub struct Receiver {
+    socket: UdpSocket,
+    buf: BytesMut,
+}
 
 impl Receiver {
+    /// Binds the API's UDP broadcast port, 50222.
     pub async fn new() -> anyhow::Result<Self> {
-        Ok(Receiver(UdpSocket::bind("0.0.0.0:50222").await?))
+        Self::bind(API_PORT).await
+    }
+
+    /// Binds another UDP port, such as one a hub's broadcasts are forwarded to.
+    pub async fn bind(port: u16) -> anyhow::Result<Self> {
+        Ok(Receiver::with_socket(
+            UdpSocket::bind(("0.0.0.0", port)).await?,
+        ))
+    }
+
+    /// Receives on an already bound socket, such as one inherited through socket activation.
+    pub fn from_std(socket: std::net::UdpSocket) -> anyhow::Result<Self> {
+        socket.set_nonblocking(true)?;
+        Ok(Receiver::with_socket(UdpSocket::from_std(socket)?))
+    }
+
+    fn with_socket(socket: UdpSocket) -> Self {
+        Receiver {
+            socket,
+            buf: BytesMut::with_capacity(MAX_DATAGRAM_SIZE),
+        }
+    }
+}
+
+#[cfg(unix)]
+impl std::os::unix::io::AsRawFd for Receiver {
+    fn as_raw_fd(&self) -> std::os::unix::io::RawFd {
+        self.socket.as_raw_fd()
     }
 }
 
 impl Stream for Receiver {
-    type Item = String;
+    type Item = Bytes;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let mut buf = [0; 1024];
-        let mut readbuf = ReadBuf::new(&mut buf);
+        let this = self.get_mut();
+        // Reclaims the buffer in place if the datagrams split off it have all been dropped.
+        this.buf.clear();
+        this.buf.resize(MAX_DATAGRAM_SIZE, 0);
+        let mut readbuf = ReadBuf::new(&mut this.buf);
 
-        match self.0.poll_recv_from(cx, &mut readbuf) {
+        match this.socket.poll_recv_from(cx, &mut readbuf) {
             Poll::Pending => Poll::Pending,
 
             Poll::Ready(Err(e)) => {
@@ -30,13 +77,10 @@ impl Stream for Receiver {
                 Poll::Ready(None)
             }
 
-            Poll::Ready(Ok(_)) => match std::str::from_utf8(readbuf.filled()) {
-                Ok(json) => Poll::Ready(Some(json.to_string())),
-                Err(e) => {
-                    warn!("Receiver terminated: malformed JSON {}", e);
-                    Poll::Ready(None)
-                }
-            },
+            Poll::Ready(Ok(_)) => {
+                let len = readbuf.filled().len();
+                Poll::Ready(Some(this.buf.split_to(len).freeze()))
+            }
         }
     }
 }
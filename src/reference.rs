@@ -0,0 +1,125 @@
+//! Observations from an official reference station nearby, fetched from the US National Weather
+//! Service API, so that the station's temperature and pressure can be compared with it and
+//! calibration drift spotted over time.
+//!
+//! The reference is any NWS observation station, which includes the METAR stations at airports
+//! (such as `KSEA`). Their observations are usually hourly.
+
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use crate::units::{Celsius, HectoPascal};
+
+const API_BASE: &str = "https://api.weather.gov";
+
+/// NWS observation station to compare with, and how often to fetch its latest observation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReferenceParams {
+    pub station: String,
+    pub interval: Duration,
+}
+
+/// The latest observation from the reference station. Either reading may be missing, as stations
+/// don't report every field in every observation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReferenceObservation {
+    pub station: String,
+    pub timestamp: DateTime<Utc>,
+    /// Air temperature.
+    pub temperature: Option<Celsius>,
+    /// Pressure reduced to mean sea level.
+    pub barometric_pressure: Option<HectoPascal>,
+}
+
+#[derive(Deserialize)]
+struct LatestObservation {
+    properties: Properties,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Properties {
+    timestamp: DateTime<Utc>,
+    temperature: Option<Quantity>,
+    sea_level_pressure: Option<Quantity>,
+    barometric_pressure: Option<Quantity>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Quantity {
+    unit_code: String,
+    value: Option<f64>,
+}
+
+// Conversions from the units a quantity may come in to the unit wanted.
+type Conversions<'a, T> = &'a [(&'a str, fn(f64) -> T)];
+
+// The value of a quantity, if given, in the unit wanted.
+fn value_of<T>(
+    quantity: &Option<Quantity>,
+    conversions: Conversions<T>,
+) -> anyhow::Result<Option<T>> {
+    let quantity = match quantity {
+        Some(quantity) => quantity,
+        None => return Ok(None),
+    };
+    match conversions
+        .iter()
+        .find(|(unit, _)| *unit == quantity.unit_code)
+    {
+        Some((_, convert)) => Ok(quantity.value.map(convert)),
+        None => bail!("Unexpected unit {}", quantity.unit_code),
+    }
+}
+
+/// Extracts the readings from a response of the latest observation endpoint for `station`. Sea
+/// level pressure is preferred, falling back to the pressure the station's altimeter setting
+/// gives, which most METAR stations report even when they leave sea level pressure out.
+pub fn parse(station: &str, body: &str) -> anyhow::Result<ReferenceObservation> {
+    let response: LatestObservation =
+        serde_json::from_str(body).context("Parsing latest observation")?;
+    let properties = response.properties;
+    let temperature = value_of(
+        &properties.temperature,
+        &[
+            ("wmoUnit:degC", Celsius),
+            ("wmoUnit:degF", Celsius::from_fahrenheit),
+        ],
+    )
+    .context("Reading temperature")?;
+    let pascals: Conversions<_> = &[("wmoUnit:Pa", HectoPascal::from_pascals)];
+    let sea_level_pressure =
+        value_of(&properties.sea_level_pressure, pascals).context("Reading pressure")?;
+    let barometric_pressure =
+        value_of(&properties.barometric_pressure, pascals).context("Reading pressure")?;
+    Ok(ReferenceObservation {
+        station: station.to_string(),
+        timestamp: properties.timestamp,
+        temperature,
+        barometric_pressure: sea_level_pressure.or(barometric_pressure),
+    })
+}
+
+/// Queries the NWS API for the reference station's latest observation.
+pub async fn fetch(
+    http: &reqwest::Client,
+    params: &ReferenceParams,
+) -> anyhow::Result<ReferenceObservation> {
+    let body = http
+        .get(format!(
+            "{}/stations/{}/observations/latest",
+            API_BASE, params.station
+        ))
+        .header(reqwest::header::ACCEPT, "application/geo+json")
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .context("Querying NWS latest observation")?
+        .text()
+        .await?;
+    parse(&params.station, &body)
+}
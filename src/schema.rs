@@ -0,0 +1,96 @@
+use serde::Serialize;
+use serde_json::{json, Value};
+
+// Version of the JSON documents published over MQTT and posted to webhooks, carried in each as
+// `schema_version`. Bumped when a document changes incompatibly: a field is removed or renamed,
+// or its type or meaning changes. Fields may be added without a bump, so parsers should ignore
+// fields they don't know.
+pub const VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct Versioned<'a, T> {
+    schema_version: u32,
+    #[serde(flatten)]
+    document: &'a T,
+}
+
+// Serializes a document with `schema_version` ahead of its own fields.
+pub fn versioned<T: Serialize>(document: &T) -> String {
+    serde_json::to_string(&Versioned {
+        schema_version: VERSION,
+        document,
+    })
+    .unwrap()
+}
+
+// JSON Schema for every versioned document, served at `/api/v1/schema`.
+pub fn document() -> Value {
+    let timestamp = json!({ "type": "string", "format": "date-time" });
+    let station = json!({
+        "name": { "type": "string" },
+        "latitude": { "type": "number", "description": "Decimal degrees, positive north" },
+        "longitude": { "type": "number", "description": "Decimal degrees, positive east" },
+        "timezone": { "type": "string", "description": "IANA time zone name" },
+    });
+    let document = |description: &str, mut properties: Value, required: &[&str]| {
+        properties["schema_version"] = json!({ "const": VERSION });
+        let required: Vec<_> = ["schema_version"].iter().chain(required).collect();
+        json!({
+            "description": description,
+            "type": "object",
+            "properties": properties,
+            "required": required,
+        })
+    };
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "tempest-exporter documents",
+        "schema_version": VERSION,
+        "$defs": {
+            "rain_event": document(
+                "Published to event/rain_start and event/rain_stop",
+                json!({
+                    "serial_number": { "type": "string" },
+                    "start": timestamp,
+                    "end": timestamp,
+                    "duration_sec": { "type": "integer" },
+                    "total_mm": { "type": "number" },
+                }),
+                &["serial_number", "start", "end", "duration_sec", "total_mm"],
+            ),
+            "firmware_event": document(
+                "Published to event/firmware",
+                json!({
+                    "serial_number": { "type": "string" },
+                    "previous_revision": { "type": "string" },
+                    "revision": { "type": "string" },
+                    "timestamp": timestamp,
+                }),
+                &["serial_number", "previous_revision", "revision", "timestamp"],
+            ),
+            "lightning_event": document(
+                "Published to event/lightning",
+                json!({
+                    "timestamp": timestamp,
+                    "distance": { "type": "number", "description": "Estimated distance (km)" },
+                    "energy": { "type": "number", "description": "Relative energy (unitless)" },
+                }),
+                &["timestamp", "distance", "energy"],
+            ),
+            "station_info": document("Published to station/info", station.clone(), &[]),
+            "alert_notification": document(
+                "Published to alert/<name> and posted to the alert's webhook",
+                json!({
+                    "alert": { "type": "string" },
+                    "state": { "enum": ["firing", "resolved"] },
+                    "value": { "type": "number" },
+                    "above": { "type": "number" },
+                    "below": { "type": "number" },
+                    "timestamp": timestamp,
+                    "station": { "type": "object", "properties": station },
+                }),
+                &["alert", "state", "value", "timestamp"],
+            ),
+        },
+    })
+}
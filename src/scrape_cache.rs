@@ -0,0 +1,69 @@
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+// How long an encoded scrape is served again to other scrapers.
+pub const MAX_AGE: Duration = Duration::from_secs(1);
+
+// Encoded /metrics bodies, one per exposition format, reused for scrapes that come within MAX_AGE
+// of each other so that several Prometheus servers scraping at once cost one encoding. Scrapes
+// that arrive while the body is being encoded wait for it rather than encoding their own.
+pub struct ScrapeCache(Mutex<[Option<Scrape>; 2]>);
+
+struct Scrape {
+    at: Instant,
+    body: Bytes,
+    // Compressed on first request, as most scrapers don't ask for it.
+    gzipped: Option<Bytes>,
+}
+
+impl ScrapeCache {
+    pub fn new() -> Self {
+        ScrapeCache(Mutex::new([None, None]))
+    }
+
+    // The body in the format asked for, gzipped if asked for, encoding it afresh with `encode` if
+    // there is none recent enough.
+    pub fn get(&self, openmetrics: bool, gzip: bool, encode: impl FnOnce() -> Vec<u8>) -> Bytes {
+        let mut scrapes = self.0.lock().unwrap();
+        let slot = &mut scrapes[usize::from(openmetrics)];
+        let now = Instant::now();
+        let scrape = match slot {
+            Some(scrape) if now.duration_since(scrape.at) < MAX_AGE => scrape,
+            _ => slot.insert(Scrape {
+                at: now,
+                body: encode().into(),
+                gzipped: None,
+            }),
+        };
+        if !gzip {
+            return scrape.body.clone();
+        }
+        let body = &scrape.body;
+        scrape
+            .gzipped
+            .get_or_insert_with(|| {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(body).unwrap();
+                encoder.finish().unwrap().into()
+            })
+            .clone()
+    }
+}
+
+// Whether an Accept-Encoding header lists gzip without ruling it out with a zero quality.
+pub fn accepts_gzip(accept_encoding: &str) -> bool {
+    accept_encoding.split(',').any(|coding| {
+        let mut params = coding.split(';').map(str::trim);
+        let name = params.next().unwrap_or_default();
+        let refused = params.any(|param| {
+            matches!(param.split_once('='), Some((q, value))
+                if q.eq_ignore_ascii_case("q") && value.parse::<f64>() == Ok(0.0))
+        });
+        name.eq_ignore_ascii_case("gzip") && !refused
+    })
+}
@@ -0,0 +1,67 @@
+use std::ffi::OsString;
+use std::sync::Arc;
+use std::time::Duration;
+
+use structopt::StructOpt;
+use tokio::sync::Notify;
+use tracing::error;
+use windows_service::service::{
+    ServiceControl, ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus, ServiceType,
+};
+use windows_service::service_control_handler::{self, ServiceControlHandlerResult};
+use windows_service::{define_windows_service, service_dispatcher};
+
+use crate::config::Opt;
+
+const SERVICE_NAME: &str = "tempest-exporter";
+
+define_windows_service!(ffi_service_main, service_main);
+
+// Hands the process over to the Windows service control manager. Blocks until the service stops.
+pub fn start() -> anyhow::Result<()> {
+    service_dispatcher::start(SERVICE_NAME, ffi_service_main)?;
+    Ok(())
+}
+
+fn service_main(_arguments: Vec<OsString>) {
+    if let Err(e) = run_service() {
+        error!("Service failed: {:#}", e);
+    }
+}
+
+fn status(current_state: ServiceState) -> ServiceStatus {
+    ServiceStatus {
+        service_type: ServiceType::OWN_PROCESS,
+        current_state,
+        controls_accepted: match current_state {
+            ServiceState::Running => ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+            _ => ServiceControlAccept::empty(),
+        },
+        exit_code: ServiceExitCode::NO_ERROR,
+        checkpoint: 0,
+        wait_hint: Duration::default(),
+        process_id: None,
+    }
+}
+
+fn run_service() -> anyhow::Result<()> {
+    let stop = Arc::new(Notify::new());
+    let status_handle = service_control_handler::register(SERVICE_NAME, {
+        let stop = stop.clone();
+        move |control| match control {
+            ServiceControl::Stop | ServiceControl::Shutdown => {
+                stop.notify_one();
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+            _ => ServiceControlHandlerResult::NotImplemented,
+        }
+    })?;
+    status_handle.set_service_status(status(ServiceState::Running))?;
+
+    // The service is registered with its options on the command line, which are parsed as usual.
+    let result = tokio::runtime::Runtime::new()?.block_on(crate::run(Opt::from_args(), stop));
+
+    status_handle.set_service_status(status(ServiceState::Stopped))?;
+    result
+}
@@ -0,0 +1,63 @@
+use std::sync::Arc;
+
+use tokio::sync::Notify;
+#[cfg(unix)]
+use tracing::error;
+
+// Resolves when the process is asked to stop, either by a signal (or console control event on
+// Windows) or by the service manager through `service_stop`, describing what asked.
+pub async fn requested(service_stop: Arc<Notify>) -> anyhow::Result<&'static str> {
+    tokio::select! {
+        reason = signal() => reason,
+        _ = service_stop.notified() => Ok("service stop request"),
+    }
+}
+
+// Resolves when asked to upgrade in place by re-executing the binary (SIGUSR2), which is only
+// supported on Unix.
+#[cfg(unix)]
+pub async fn upgrade_requested() {
+    use tokio::signal::unix::{signal, SignalKind};
+    match signal(SignalKind::user_defined2()) {
+        Ok(mut upgrade) => {
+            upgrade.recv().await;
+        }
+        Err(e) => {
+            error!("Upgrade signal handling failure: {}", e);
+            std::future::pending().await
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub async fn upgrade_requested() {
+    std::future::pending().await
+}
+
+#[cfg(unix)]
+async fn signal() -> anyhow::Result<&'static str> {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut quit = signal(SignalKind::quit())?;
+    Ok(tokio::select! {
+        _ = interrupt.recv() => "interrupt signal",
+        _ = terminate.recv() => "terminate signal",
+        _ = quit.recv() => "quit signal",
+    })
+}
+
+#[cfg(windows)]
+async fn signal() -> anyhow::Result<&'static str> {
+    use tokio::signal::windows;
+    let mut ctrl_c = windows::ctrl_c()?;
+    let mut ctrl_break = windows::ctrl_break()?;
+    let mut ctrl_close = windows::ctrl_close()?;
+    let mut ctrl_shutdown = windows::ctrl_shutdown()?;
+    Ok(tokio::select! {
+        _ = ctrl_c.recv() => "console Ctrl-C",
+        _ = ctrl_break.recv() => "console Ctrl-Break",
+        _ = ctrl_close.recv() => "console close",
+        _ = ctrl_shutdown.recv() => "system shutdown",
+    })
+}
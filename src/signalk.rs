@@ -0,0 +1,196 @@
+use std::f64::consts::PI;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::config::{ExporterParams, Shared, StationParams};
+use crate::decoder::{TempestMsg, Wind};
+use crate::publisher::{WindTopics, INSTANT_WIND_TOPICS, WIND_AVG_TOPICS};
+use crate::sink_queue::LastError;
+use crate::units::{Celsius, HectoPascal};
+
+// How the station's wind readings are reported. On a vessel the station turns with the hull, so
+// its direction is relative to the bow and the wind it feels includes the vessel's own motion.
+// Ashore, or with the station aligned to north, readings are true wind.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WindConvention {
+    #[default]
+    Apparent,
+    True,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SignalKParams {
+    // Signal K server's UDP data connection.
+    pub target: SocketAddr,
+    pub wind: WindConvention,
+}
+
+// Sends readings as Signal K deltas over UDP, in Signal K's SI units on its standard
+// `environment` paths.
+pub struct SignalKSink {
+    station_params: Shared<StationParams>,
+    exporter_params: Shared<ExporterParams>,
+    params: Mutex<SignalKParams>,
+    socket: UdpSocket,
+    errors: Arc<LastError>,
+}
+
+impl SignalKSink {
+    pub fn new(
+        station_params: Shared<StationParams>,
+        exporter_params: Shared<ExporterParams>,
+        params: SignalKParams,
+    ) -> anyhow::Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0").context("Binding Signal K socket")?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            station_params,
+            exporter_params,
+            params: Mutex::new(params),
+            socket,
+            errors: Arc::new(LastError::default()),
+        })
+    }
+
+    // Where send errors are recorded, for `/debug/state`.
+    pub fn errors(&self) -> Arc<LastError> {
+        self.errors.clone()
+    }
+
+    pub fn reconfigure(&self, new_params: SignalKParams) {
+        let mut params = self.params.lock().unwrap();
+        if *params != new_params {
+            info!("Signal K parameters changed");
+            *params = new_params;
+        }
+    }
+
+    pub fn handle_report(&self, msg: &TempestMsg) {
+        let params = self.params.lock().unwrap();
+        let mut values = Vec::new();
+        match msg {
+            TempestMsg::RapidWind(rw) => {
+                wind_values(&mut values, &INSTANT_WIND_TOPICS, &rw.wind, params.wind)
+            }
+            TempestMsg::Observation(obs) => {
+                let station_params = self.station_params.read().unwrap();
+                if let Some(wind) = &obs.wind {
+                    wind_values(&mut values, &WIND_AVG_TOPICS, &wind.avg, params.wind);
+                }
+                let outside = [
+                    (
+                        "observation/thermal/temperature_deg_c",
+                        "environment.outside.temperature",
+                        obs.air_temperature.map(Celsius::kelvin),
+                    ),
+                    (
+                        "observation/thermal/dew_point_deg_c",
+                        "environment.outside.dewPointTemperature",
+                        obs.dew_point().map(Celsius::kelvin),
+                    ),
+                    (
+                        "observation/thermal/apparent_temperature_deg_c",
+                        "environment.outside.apparentWindChillTemperature",
+                        obs.heat_index_wind_chill().map(Celsius::kelvin),
+                    ),
+                    (
+                        "observation/thermal/relative_humidity_pct",
+                        "environment.outside.relativeHumidity",
+                        obs.relative_humidity.map(|rh| rh / 100.0),
+                    ),
+                    (
+                        "observation/pressure/barometric_hpa",
+                        "environment.outside.pressure",
+                        obs.barometric_pressure(station_params.elevation)
+                            .map(HectoPascal::pascals),
+                    ),
+                    (
+                        "observation/solar/illuminance_lux",
+                        "environment.outside.illuminance",
+                        obs.solar.as_ref().map(|solar| solar.illuminance),
+                    ),
+                ];
+                for (topic, path, value) in outside {
+                    if let Some(value) = value {
+                        values.push((topic, path, json!(value)));
+                    }
+                }
+            }
+            _ => return,
+        }
+        let filter = &self.exporter_params.read().unwrap().publish_filter;
+        values.retain(|(topic, _, _)| filter.allows(topic));
+        if values.is_empty() {
+            return;
+        }
+        let delta = delta(msg.timestamp(), values);
+        if let Err(e) = self.socket.send_to(delta.as_bytes(), params.target) {
+            warn!("Signal K send failed: {}", e);
+            self.errors.record(format!("Send failed: {}", e));
+        }
+    }
+}
+
+// Values for a delta, each with the MQTT topic of its reading, which names it to the publish
+// filter, and its Signal K path.
+type Values = Vec<(&'static str, &'static str, Value)>;
+
+// Apparent wind angles are relative to the bow, from -π (port) to π (starboard); true wind
+// directions are from north, from 0 to 2π.
+fn wind_values(values: &mut Values, topics: &WindTopics, wind: &Wind, convention: WindConvention) {
+    let direction = wind.source_direction().to_radians().rem_euclid(2.0 * PI);
+    match convention {
+        WindConvention::Apparent => {
+            let angle = if direction > PI {
+                direction - 2.0 * PI
+            } else {
+                direction
+            };
+            values.push((
+                topics.source_direction,
+                "environment.wind.angleApparent",
+                json!(angle),
+            ));
+            values.push((
+                topics.speed_magnitude,
+                "environment.wind.speedApparent",
+                json!(wind.speed_magnitude()),
+            ));
+        }
+        WindConvention::True => {
+            values.push((
+                topics.source_direction,
+                "environment.wind.directionTrue",
+                json!(direction),
+            ));
+            values.push((
+                topics.speed_magnitude,
+                "environment.wind.speedTrue",
+                json!(wind.speed_magnitude()),
+            ));
+        }
+    }
+}
+
+fn delta(timestamp: DateTime<Utc>, values: Values) -> String {
+    let values: Vec<_> = values
+        .into_iter()
+        .map(|(_, path, value)| json!({ "path": path, "value": value }))
+        .collect();
+    json!({
+        "context": "vessels.self",
+        "updates": [{
+            "source": { "label": "tempest-exporter" },
+            "timestamp": timestamp,
+            "values": values,
+        }],
+    })
+    .to_string()
+}
@@ -0,0 +1,278 @@
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::bail;
+use chrono::{Timelike, Utc};
+use rand::Rng;
+use serde_json::{json, Value};
+use tokio::net::UdpSocket;
+use tracing::info;
+
+const RAPID_WIND_INTERVAL: Duration = Duration::from_secs(3);
+const HUB_STATUS_EVERY: u64 = 3; // ~10 s in rapid wind intervals
+const OBSERVATION_EVERY: u64 = 20; // 60 s in rapid wind intervals
+
+#[derive(Clone, Copy, Debug)]
+pub enum Scenario {
+    Calm,
+    Storm,
+    SensorFailure,
+}
+
+impl FromStr for Scenario {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "calm" => Ok(Scenario::Calm),
+            "storm" => Ok(Scenario::Storm),
+            "sensor-failure" => Ok(Scenario::SensorFailure),
+            other => bail!("Unrecognized scenario {}", other),
+        }
+    }
+}
+
+// Baseline conditions for a scenario, perturbed with noise on each report.
+struct Conditions {
+    wind_avg: f64,
+    wind_gust_factor: f64,
+    station_pressure: f64,
+    pressure_trend_per_obs: f64,
+    temperature: f64,
+    relative_humidity: f64,
+    rain_per_minute: f64,
+    strike_probability: f64,
+    sensor_status: u32,
+}
+
+impl Conditions {
+    fn for_scenario(scenario: Scenario) -> Self {
+        match scenario {
+            Scenario::Calm => Self {
+                wind_avg: 1.0,
+                wind_gust_factor: 1.5,
+                station_pressure: 1015.0,
+                pressure_trend_per_obs: 0.0,
+                temperature: 18.0,
+                relative_humidity: 60.0,
+                rain_per_minute: 0.0,
+                strike_probability: 0.0,
+                sensor_status: 0,
+            },
+            Scenario::Storm => Self {
+                wind_avg: 12.0,
+                wind_gust_factor: 1.8,
+                station_pressure: 1002.0,
+                pressure_trend_per_obs: -0.05,
+                temperature: 14.0,
+                relative_humidity: 95.0,
+                rain_per_minute: 0.5,
+                strike_probability: 0.15,
+                sensor_status: 0b100, // Lightning disturber
+            },
+            Scenario::SensorFailure => Self {
+                wind_avg: 3.0,
+                wind_gust_factor: 1.6,
+                station_pressure: 1012.0,
+                pressure_trend_per_obs: 0.0,
+                temperature: 20.0,
+                relative_humidity: 50.0,
+                rain_per_minute: 0.0,
+                strike_probability: 0.0,
+                sensor_status: 0b110000, // Temperature and humidity failed
+            },
+        }
+    }
+}
+
+pub struct Simulator {
+    socket: UdpSocket,
+    target: SocketAddr,
+    serial_number: String,
+    hub_serial_number: String,
+    conditions: Conditions,
+    wind_direction: f64,
+    started: i64,
+    hub_seq: i64,
+}
+
+impl Simulator {
+    pub async fn new(
+        scenario: Scenario,
+        target: SocketAddr,
+        serial_number: String,
+        hub_serial_number: String,
+    ) -> anyhow::Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0").await?;
+        socket.set_broadcast(true)?;
+        Ok(Self {
+            socket,
+            target,
+            serial_number,
+            hub_serial_number,
+            conditions: Conditions::for_scenario(scenario),
+            wind_direction: rand::thread_rng().gen_range(0.0..360.0),
+            started: Utc::now().timestamp(),
+            hub_seq: 0,
+        })
+    }
+
+    pub async fn run(mut self) -> anyhow::Result<()> {
+        info!(
+            "Simulating station {} to {}",
+            self.serial_number, self.target
+        );
+        let mut interval = tokio::time::interval(RAPID_WIND_INTERVAL);
+        for tick in 0.. {
+            interval.tick().await;
+            let rapid_wind = self.rapid_wind();
+            self.send(rapid_wind).await?;
+            if tick % HUB_STATUS_EVERY == 0 {
+                let hub_status = self.hub_status();
+                self.send(hub_status).await?;
+            }
+            if tick % OBSERVATION_EVERY == 0 {
+                let observation = self.observation();
+                self.send(observation).await?;
+                let device_status = self.device_status();
+                self.send(device_status).await?;
+            }
+            if rand::thread_rng().gen_bool(self.conditions.strike_probability) {
+                let strike = self.strike();
+                self.send(strike).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn send(&self, msg: Value) -> anyhow::Result<()> {
+        self.socket
+            .send_to(msg.to_string().as_bytes(), self.target)
+            .await?;
+        Ok(())
+    }
+
+    fn noisy(value: f64, spread: f64) -> f64 {
+        value + rand::thread_rng().gen_range(-spread..=spread)
+    }
+
+    fn wind_speed(&mut self) -> f64 {
+        self.wind_direction = (Self::noisy(self.wind_direction, 10.0) + 360.0) % 360.0;
+        Self::noisy(self.conditions.wind_avg, self.conditions.wind_avg * 0.3).max(0.0)
+    }
+
+    fn rapid_wind(&mut self) -> Value {
+        let speed = self.wind_speed();
+        json!({
+            "serial_number": self.serial_number,
+            "type": "rapid_wind",
+            "hub_sn": self.hub_serial_number,
+            "ob": [Utc::now().timestamp(), speed, self.wind_direction.round()],
+        })
+    }
+
+    fn observation(&mut self) -> Value {
+        let now = Utc::now();
+        // Diurnal cycle peaking mid-afternoon (UTC, which is good enough for synthetic data).
+        let day_phase =
+            ((now.hour() as f64 + now.minute() as f64 / 60.0 - 9.0) / 24.0) * std::f64::consts::TAU;
+        let sun = day_phase.sin().max(0.0);
+
+        let avg = self.wind_speed();
+        let lull = avg * 0.5;
+        let gust = avg * self.conditions.wind_gust_factor;
+        self.conditions.station_pressure += self.conditions.pressure_trend_per_obs;
+        let failed = self.conditions.sensor_status & 0b110000 != 0;
+        let temperature = Self::noisy(self.conditions.temperature + 5.0 * day_phase.sin(), 0.2);
+        let humidity = Self::noisy(self.conditions.relative_humidity, 2.0).clamp(0.0, 100.0);
+        let rain = if self.conditions.rain_per_minute > 0.0 {
+            Self::noisy(
+                self.conditions.rain_per_minute,
+                self.conditions.rain_per_minute * 0.5,
+            )
+        } else {
+            0.0
+        };
+        let (strike_distance, strike_count) = if self.conditions.strike_probability > 0.0 {
+            (
+                rand::thread_rng().gen_range(3..15),
+                rand::thread_rng().gen_range(1..8),
+            )
+        } else {
+            (0, 0)
+        };
+        let cloud = if rain > 0.0 { 0.2 } else { 1.0 };
+        json!({
+            "serial_number": self.serial_number,
+            "type": "obs_st",
+            "hub_sn": self.hub_serial_number,
+            "obs": [[
+                now.timestamp(),
+                lull,
+                avg,
+                gust,
+                self.wind_direction.round(),
+                3,
+                Self::noisy(self.conditions.station_pressure, 0.05),
+                if failed { None } else { Some(temperature) },
+                if failed { None } else { Some(humidity) },
+                (100_000.0 * sun * cloud).round(),
+                (100.0 * sun * cloud).round() / 10.0,
+                (1000.0 * sun * cloud).round(),
+                rain.max(0.0),
+                if rain > 0.0 { 1 } else { 0 },
+                strike_distance,
+                strike_count,
+                Self::noisy(2.6, 0.01),
+                1,
+            ]],
+            "firmware_revision": 156,
+        })
+    }
+
+    fn strike(&self) -> Value {
+        json!({
+            "serial_number": self.serial_number,
+            "type": "evt_strike",
+            "hub_sn": self.hub_serial_number,
+            "evt": [
+                Utc::now().timestamp(),
+                rand::thread_rng().gen_range(1..40),
+                rand::thread_rng().gen_range(1000..10000),
+            ],
+        })
+    }
+
+    fn device_status(&self) -> Value {
+        let now = Utc::now().timestamp();
+        json!({
+            "serial_number": self.serial_number,
+            "type": "device_status",
+            "hub_sn": self.hub_serial_number,
+            "timestamp": now,
+            "uptime": now - self.started,
+            "voltage": Self::noisy(2.6, 0.01),
+            "firmware_revision": 156,
+            "rssi": Self::noisy(-60.0, 3.0).round(),
+            "hub_rssi": Self::noisy(-55.0, 3.0).round(),
+            "sensor_status": self.conditions.sensor_status,
+            "debug": 0,
+        })
+    }
+
+    fn hub_status(&mut self) -> Value {
+        let now = Utc::now().timestamp();
+        self.hub_seq += 1;
+        json!({
+            "serial_number": self.hub_serial_number,
+            "type": "hub_status",
+            "firmware_revision": "177",
+            "uptime": now - self.started,
+            "rssi": Self::noisy(-62.0, 3.0).round(),
+            "timestamp": now,
+            "reset_flags": "BOR,PIN,POR",
+            "seq": self.hub_seq,
+            "radio_stats": [25, 1, 0, 3, 16342],
+        })
+    }
+}
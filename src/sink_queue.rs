@@ -0,0 +1,227 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use prometheus::{IntCounter, IntGauge};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tracing::{debug, debug_span, info, warn};
+
+use crate::decoder::TempestMsg;
+
+// Names of the sinks that get a queue, when enabled.
+pub const SINK_NAMES: [&str; 10] = [
+    "exporter",
+    "history",
+    "mqtt",
+    "alerts",
+    "knx",
+    "signalk",
+    "nmea",
+    "ecowitt",
+    "grafana_live",
+    "modbus",
+];
+
+// What to do when a sink falls behind and its queue is full: discard a message, or hold up the
+// message pump, and with it every sink, until there is room.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DropPolicy {
+    DropOldest,
+    DropNewest,
+    Block,
+}
+
+// A sink queue's capacity and overflow policy as configured, each defaulting to the sink's own.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QueueOptions {
+    pub capacity: Option<usize>,
+    pub overflow: Option<DropPolicy>,
+}
+
+// The latest error a sink ran into, kept for `/debug/state`. Sinks record errors as they log
+// them, since most happen in background tasks rather than while handling a message.
+#[derive(Default)]
+pub struct LastError(Mutex<Option<SinkError>>);
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SinkError {
+    at: DateTime<Utc>,
+    message: String,
+}
+
+impl LastError {
+    pub fn record(&self, error: impl std::fmt::Display) {
+        *self.0.lock().unwrap() = Some(SinkError {
+            at: Utc::now(),
+            message: error.to_string(),
+        });
+    }
+
+    pub fn get(&self) -> Option<SinkError> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+// Snapshot of a sink queue for `/debug/state`.
+#[derive(Debug, Serialize)]
+pub struct SinkState {
+    name: &'static str,
+    capacity: usize,
+    overflow: DropPolicy,
+    depth: usize,
+    dropped: u64,
+    last_error: Option<SinkError>,
+}
+
+struct State {
+    messages: VecDeque<Arc<TempestMsg>>,
+    closed: bool,
+    // Messages dropped since the queue last had room, so that a sink falling behind is logged
+    // once when it starts dropping and once when it catches up, rather than for every message.
+    dropping: Option<u64>,
+}
+
+// A bounded queue feeding one sink from the message pump, so a stalled sink only loses its own
+// messages instead of holding up the others.
+pub struct SinkQueue {
+    name: &'static str,
+    capacity: usize,
+    policy: DropPolicy,
+    state: Mutex<State>,
+    ready: Notify,
+    room: Notify,
+    depth: IntGauge,
+    dropped: IntCounter,
+    errors: Arc<LastError>,
+}
+
+impl SinkQueue {
+    pub fn new(
+        name: &'static str,
+        capacity: usize,
+        policy: DropPolicy,
+        (depth, dropped): (IntGauge, IntCounter),
+        errors: Arc<LastError>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            name,
+            capacity,
+            policy,
+            state: Mutex::new(State {
+                messages: VecDeque::with_capacity(capacity),
+                closed: false,
+                dropping: None,
+            }),
+            ready: Notify::new(),
+            room: Notify::new(),
+            depth,
+            dropped,
+            errors,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn state(&self) -> SinkState {
+        SinkState {
+            name: self.name,
+            capacity: self.capacity,
+            overflow: self.policy,
+            depth: self.state.lock().unwrap().messages.len(),
+            dropped: self.dropped.get(),
+            last_error: self.errors.get(),
+        }
+    }
+
+    pub async fn push(&self, msg: Arc<TempestMsg>) {
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.closed {
+                    return;
+                }
+                if state.messages.len() < self.capacity {
+                    if let Some(dropped) = state.dropping.take() {
+                        info!(
+                            "Sink {} caught up after dropping {} messages",
+                            self.name, dropped
+                        );
+                    }
+                } else {
+                    match self.policy {
+                        DropPolicy::DropOldest => {
+                            self.drop_message(&mut state);
+                            state.messages.pop_front();
+                        }
+                        DropPolicy::DropNewest => {
+                            self.drop_message(&mut state);
+                            return;
+                        }
+                        DropPolicy::Block => {
+                            debug!("Sink {} is falling behind, waiting for room", self.name);
+                        }
+                    }
+                }
+                if state.messages.len() < self.capacity {
+                    state.messages.push_back(msg);
+                    self.depth.set(state.messages.len() as i64);
+                    self.ready.notify_one();
+                    return;
+                }
+            }
+            self.room.notified().await;
+        }
+    }
+
+    fn drop_message(&self, state: &mut State) {
+        let dropped = state.dropping.get_or_insert_with(|| {
+            warn!("Sink {} is falling behind, dropping messages", self.name);
+            0
+        });
+        *dropped += 1;
+        self.dropped.inc();
+    }
+
+    // Stops accepting messages. The sink task exits once it has handled what is already queued.
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.ready.notify_one();
+        self.room.notify_one();
+    }
+
+    async fn pop(&self) -> Option<Arc<TempestMsg>> {
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+                if let Some(msg) = state.messages.pop_front() {
+                    self.depth.set(state.messages.len() as i64);
+                    self.room.notify_one();
+                    return Some(msg);
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            self.ready.notified().await;
+        }
+    }
+
+    pub fn spawn<F>(self: &Arc<Self>, handler: F) -> JoinHandle<()>
+    where
+        F: Fn(&TempestMsg) + Send + 'static,
+    {
+        let queue = self.clone();
+        tokio::spawn(async move {
+            while let Some(msg) = queue.pop().await {
+                debug_span!("sink", name = queue.name, kind = msg.kind())
+                    .in_scope(|| handler(&msg));
+            }
+        })
+    }
+}
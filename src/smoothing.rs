@@ -0,0 +1,67 @@
+//! Exponential smoothing of noisy observation fields, exported alongside the raw values so that
+//! dashboards get readable graphs without recording rules.
+
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use chrono::{DateTime, Utc};
+
+use crate::derived::Variable;
+
+/// An observation field to smooth, and how heavily.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Smoothing {
+    pub variable: Variable,
+    /// Time for the smoothed value to cover ~63% of a step change in the field.
+    pub time_constant: Duration,
+}
+
+impl Smoothing {
+    /// Smoothing of the field named as in derived metric expressions.
+    pub fn new(name: &str, time_constant: Duration) -> anyhow::Result<Self> {
+        let variable =
+            Variable::from_name(name).ok_or_else(|| anyhow!("Unknown field {} to smooth", name))?;
+        // Averaging angles across north gives nonsense.
+        if variable == Variable::WindDirection {
+            bail!("Wind direction can't be smoothed");
+        }
+        if time_constant.is_zero() {
+            bail!("Smoothing time constant for {} must be positive", name);
+        }
+        Ok(Self {
+            variable,
+            time_constant,
+        })
+    }
+}
+
+/// Exponentially weighted moving average of irregularly timed readings. Each reading is weighted
+/// by how long it has been since the last, so gaps in reports don't leave stale values dominating.
+#[derive(Clone, Debug)]
+pub struct Ewma {
+    value: f64,
+    at: DateTime<Utc>,
+}
+
+impl Ewma {
+    pub fn new(at: DateTime<Utc>, value: f64) -> Self {
+        Self { value, at }
+    }
+
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+
+    /// Folds in a reading and returns the smoothed value. Readings no newer than the last are
+    /// ignored.
+    pub fn update(&mut self, at: DateTime<Utc>, value: f64, time_constant: Duration) -> f64 {
+        if let Ok(elapsed) = (at - self.at).to_std() {
+            if !elapsed.is_zero() {
+                let alpha = 1.0 - (-elapsed.as_secs_f64() / time_constant.as_secs_f64()).exp();
+                self.value += alpha * (value - self.value);
+                self.at = at;
+            }
+        }
+        self.value
+    }
+}
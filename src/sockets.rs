@@ -0,0 +1,137 @@
+use std::net::{TcpListener, UdpSocket};
+
+use anyhow::Context;
+
+// Listens for HTTP on the metrics port, or on a listening socket inherited from whoever started
+// the exporter, such as a reverse proxy supervisor holding the port.
+pub fn http_listener(port: u16, fd: Option<i32>) -> anyhow::Result<tokio::net::TcpListener> {
+    let listener = match fd {
+        Some(fd) => {
+            let listener: TcpListener = inherited(fd)?;
+            // Fails on a descriptor that isn't a socket, rather than when the first request
+            // would arrive.
+            let addr = listener
+                .local_addr()
+                .with_context(|| format!("Inherited file descriptor {} is not a socket", fd))?;
+            tracing::info!("Serving HTTP on inherited socket {}", addr);
+            listener
+        }
+        None => TcpListener::bind(("0.0.0.0", port))
+            .with_context(|| format!("Binding metrics port {}", port))?,
+    };
+    listener.set_nonblocking(true)?;
+    Ok(tokio::net::TcpListener::from_std(listener)?)
+}
+
+// Takes up inherited sockets to receive API datagrams on.
+pub fn api_sockets(fds: Vec<i32>) -> anyhow::Result<Vec<UdpSocket>> {
+    fds.into_iter()
+        .map(|fd| {
+            let socket: UdpSocket = inherited(fd)?;
+            let addr = socket
+                .local_addr()
+                .with_context(|| format!("Inherited file descriptor {} is not a socket", fd))?;
+            tracing::info!("Receiving on inherited socket {}", addr);
+            Ok(socket)
+        })
+        .collect()
+}
+
+// Removes and returns the socket bound to `port`, if there is one.
+pub fn take_for_port(sockets: &mut Vec<UdpSocket>, port: u16) -> Option<UdpSocket> {
+    let i = sockets
+        .iter()
+        .position(|socket| matches!(socket.local_addr(), Ok(addr) if addr.port() == port))?;
+    Some(sockets.swap_remove(i))
+}
+
+#[cfg(unix)]
+fn inherited<S: std::os::unix::io::FromRawFd>(fd: i32) -> anyhow::Result<S> {
+    // Safety: whoever passed the descriptor handed it over to us, and nothing else in the
+    // process uses it.
+    Ok(unsafe { S::from_raw_fd(fd) })
+}
+
+#[cfg(not(unix))]
+fn inherited<S>(_fd: i32) -> anyhow::Result<S> {
+    anyhow::bail!("Inheriting sockets is only supported on Unix")
+}
+
+// Duplicates of the sockets, kept open through shutdown so that an in-place upgrade can pass them
+// on to the new binary, which takes them up as if passed by systemd socket activation. Datagrams
+// and connections arriving in between wait in the sockets rather than being refused or lost.
+#[derive(Default)]
+pub struct Handover(#[cfg(unix)] Vec<std::os::unix::io::OwnedFd>);
+
+#[cfg(unix)]
+impl Handover {
+    pub fn keep<S: std::os::unix::io::AsRawFd>(&mut self, socket: &S) -> anyhow::Result<()> {
+        self.0.push(duplicate(socket.as_raw_fd(), 0)?);
+        Ok(())
+    }
+
+    // Replaces the process with a fresh start of the exporter, under the same process ID so that
+    // service managers see no restart. Only returns if that fails.
+    pub fn exec(self) -> anyhow::Error {
+        use std::os::unix::io::AsRawFd;
+        use std::os::unix::process::CommandExt;
+
+        const LISTEN_FDS_START: i32 = 3;
+        // Move the sockets clear of where they are going first, so that placing one can't clobber
+        // another.
+        let first_clear = LISTEN_FDS_START + self.0.len() as i32;
+        let sockets = match self
+            .0
+            .iter()
+            .map(|fd| duplicate(fd.as_raw_fd(), first_clear))
+            .collect::<anyhow::Result<Vec<_>>>()
+        {
+            Ok(sockets) => sockets,
+            Err(e) => return e,
+        };
+        for (target, socket) in (LISTEN_FDS_START..).zip(&sockets) {
+            // Safety: both are open descriptors, and nothing else uses the target once we exec.
+            // The duplicate doesn't inherit close-on-exec.
+            if unsafe { libc::dup2(socket.as_raw_fd(), target) } < 0 {
+                return anyhow::Error::from(std::io::Error::last_os_error())
+                    .context("Placing sockets for the new exporter");
+            }
+        }
+
+        // argv[0] rather than `current_exe`, which names the replaced binary after an upgrade.
+        let mut args = std::env::args_os();
+        let program = args.next().unwrap_or_else(|| "tempest-exporter".into());
+        let error = std::process::Command::new(program)
+            .args(args)
+            .env("LISTEN_FDS", sockets.len().to_string())
+            .env("LISTEN_PID", std::process::id().to_string())
+            .env_remove("LISTEN_FDNAMES")
+            .exec();
+        anyhow::Error::from(error).context("Re-executing the exporter")
+    }
+}
+
+#[cfg(not(unix))]
+impl Handover {
+    pub fn keep<S>(&mut self, _socket: &S) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    pub fn exec(self) -> anyhow::Error {
+        anyhow::anyhow!("Upgrading in place is only supported on Unix")
+    }
+}
+
+// Duplicates a descriptor onto the lowest free number no less than `min`, closed on exec.
+#[cfg(unix)]
+fn duplicate(fd: i32, min: i32) -> anyhow::Result<std::os::unix::io::OwnedFd> {
+    use std::os::unix::io::FromRawFd;
+
+    // Safety: fcntl doesn't touch memory, and a successful result is a descriptor we own.
+    let duplicate = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, min) };
+    if duplicate < 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("Duplicating file descriptor {}", fd));
+    }
+    Ok(unsafe { std::os::unix::io::OwnedFd::from_raw_fd(duplicate) })
+}
@@ -0,0 +1,45 @@
+//! Position of the sun and the irradiance it would deliver through a cloudless sky.
+
+use std::f64::consts::PI;
+
+use chrono::{DateTime, Datelike, Timelike, Utc};
+
+// Haurwitz clear-sky model constants.
+const HAURWITZ_A: f64 = 1098.0; // W m^-2
+const HAURWITZ_B: f64 = -0.057;
+
+/// Cosine of the solar zenith angle at a location (decimal degrees, positive north and east), per
+/// the NOAA low-precision solar position equations. Negative when the sun is below the horizon.
+pub fn zenith_cosine(timestamp: DateTime<Utc>, latitude: f64, longitude: f64) -> f64 {
+    let minutes = timestamp.hour() as f64 * 60.0
+        + timestamp.minute() as f64
+        + timestamp.second() as f64 / 60.0;
+    // Fractional year (rad).
+    let gamma = 2.0 * PI / 365.0 * (timestamp.ordinal0() as f64 + (minutes / 60.0 - 12.0) / 24.0);
+
+    // Equation of time (min) and solar declination (rad).
+    let eqtime = 229.18
+        * (0.000075 + 0.001868 * gamma.cos()
+            - 0.032077 * gamma.sin()
+            - 0.014615 * (2.0 * gamma).cos()
+            - 0.040849 * (2.0 * gamma).sin());
+    let decl = 0.006918 - 0.399912 * gamma.cos() + 0.070257 * gamma.sin()
+        - 0.006758 * (2.0 * gamma).cos()
+        + 0.000907 * (2.0 * gamma).sin()
+        - 0.002697 * (3.0 * gamma).cos()
+        + 0.00148 * (3.0 * gamma).sin();
+
+    let true_solar_time = minutes + eqtime + 4.0 * longitude;
+    let hour_angle = (true_solar_time / 4.0 - 180.0).to_radians();
+    let latitude = latitude.to_radians();
+    latitude.sin() * decl.sin() + latitude.cos() * decl.cos() * hour_angle.cos()
+}
+
+/// Global horizontal irradiance under a cloudless sky (W·m^-2), per Haurwitz, given the cosine of
+/// the solar zenith angle. Zero when the sun is below the horizon.
+pub fn clear_sky_irradiance(zenith_cosine: f64) -> f64 {
+    if zenith_cosine <= 0.0 {
+        return 0.0;
+    }
+    HAURWITZ_A * zenith_cosine * (HAURWITZ_B / zenith_cosine).exp()
+}
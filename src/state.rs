@@ -0,0 +1,153 @@
+//! The station's current conditions: the latest report of each kind from each device.
+//!
+//! A pipeline keeps one [`StationState`] up to date as reports are decoded and shares it through a
+//! `tokio::sync::watch` channel, so readers such as the HTTP API see the same latest values
+//! without keeping their own copies. It also keeps the same reports as received, which the
+//! checkpoint saves so that they can be restored after a restart.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use crate::decoder::{DeviceStatus, HubStatus, Observation, RapidWind, TempestMsg};
+use crate::reader::RawTempestMsg;
+
+/// The latest rapid wind, and the latest observation and status from each device.
+///
+/// Reports are shared with the sinks rather than copied. Serializes as an object with the time of
+/// the latest report, the rapid wind, and the observations, device statuses and hub statuses each
+/// keyed by serial number; the reports as received are left out.
+#[derive(Clone, Debug, Default)]
+pub struct StationState {
+    updated_at: Option<DateTime<Utc>>,
+    rapid_wind: Option<Arc<TempestMsg>>,
+    observations: BTreeMap<String, Arc<TempestMsg>>,
+    device_status: BTreeMap<String, Arc<TempestMsg>>,
+    hub_status: BTreeMap<String, Arc<TempestMsg>>,
+    received: BTreeMap<(&'static str, String), RawTempestMsg>,
+}
+
+impl StationState {
+    /// Takes in a report if it is of a kind that is kept and no older than the one it replaces.
+    /// Returns whether the state changed.
+    pub fn update(&mut self, msg: &Arc<TempestMsg>) -> bool {
+        use TempestMsg as TM;
+        let timestamp = msg.timestamp();
+        let newer =
+            |held: Option<&Arc<TempestMsg>>| held.is_none_or(|held| held.timestamp() <= timestamp);
+        let (reports, serial) = match &**msg {
+            TM::RapidWind(_) => {
+                if !newer(self.rapid_wind.as_ref()) {
+                    return false;
+                }
+                self.rapid_wind = Some(msg.clone());
+                self.touch(timestamp);
+                return true;
+            }
+            TM::Observation(obs) => (&mut self.observations, &obs.serial_number),
+            TM::DeviceStatus(ds) => (&mut self.device_status, &ds.serial_number),
+            TM::HubStatus(hs) => (&mut self.hub_status, &hs.serial_number),
+            _ => return false,
+        };
+        if !newer(reports.get(serial)) {
+            return false;
+        }
+        reports.insert(serial.clone(), msg.clone());
+        self.touch(timestamp);
+        true
+    }
+
+    /// Keeps an observation or status report as received, replacing the previous one of its type
+    /// from the same device. Other reports are not kept.
+    pub fn record(&mut self, raw: &RawTempestMsg) {
+        use RawTempestMsg as RTM;
+        let (kind, serial) = match raw {
+            RTM::Observation(obs) => ("observation", &obs.serial_number),
+            RTM::DeviceStatus(ds) => ("device_status", &ds.serial_number),
+            RTM::HubStatus(hs) => ("hub_status", &hs.serial_number),
+            _ => return,
+        };
+        self.received.insert((kind, serial.clone()), raw.clone());
+    }
+
+    /// The observation and status reports kept as received, for saving in a checkpoint.
+    pub fn received(&self) -> Vec<RawTempestMsg> {
+        self.received.values().cloned().collect()
+    }
+
+    fn touch(&mut self, timestamp: DateTime<Utc>) {
+        if !matches!(self.updated_at, Some(at) if at >= timestamp) {
+            self.updated_at = Some(timestamp);
+        }
+    }
+
+    /// Time of the latest report taken in, if any.
+    pub fn updated_at(&self) -> Option<DateTime<Utc>> {
+        self.updated_at
+    }
+
+    /// The latest instantaneous wind.
+    pub fn rapid_wind(&self) -> Option<&RapidWind> {
+        match self.rapid_wind.as_deref() {
+            Some(TempestMsg::RapidWind(rw)) => Some(rw),
+            _ => None,
+        }
+    }
+
+    /// The latest observation from each device, by serial number.
+    pub fn observations(&self) -> impl Iterator<Item = &Observation> {
+        self.observations.values().filter_map(|msg| match &**msg {
+            TempestMsg::Observation(obs) => Some(obs),
+            _ => None,
+        })
+    }
+
+    /// The most recent observation from any device.
+    pub fn observation(&self) -> Option<&Observation> {
+        self.observations().max_by_key(|obs| obs.timestamp)
+    }
+
+    /// The latest status from each device, by serial number.
+    pub fn device_statuses(&self) -> impl Iterator<Item = &DeviceStatus> {
+        self.device_status.values().filter_map(|msg| match &**msg {
+            TempestMsg::DeviceStatus(ds) => Some(ds),
+            _ => None,
+        })
+    }
+
+    /// The latest status from each hub, by serial number.
+    pub fn hub_statuses(&self) -> impl Iterator<Item = &HubStatus> {
+        self.hub_status.values().filter_map(|msg| match &**msg {
+            TempestMsg::HubStatus(hs) => Some(hs),
+            _ => None,
+        })
+    }
+
+    /// Whether no report has been taken in yet.
+    pub fn is_empty(&self) -> bool {
+        self.updated_at.is_none()
+    }
+}
+
+impl Serialize for StationState {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("StationState", 5)?;
+        state.serialize_field("updated_at", &self.updated_at)?;
+        state.serialize_field("rapid_wind", &self.rapid_wind.as_deref())?;
+        state.serialize_field("observations", &Reports(&self.observations))?;
+        state.serialize_field("device_status", &Reports(&self.device_status))?;
+        state.serialize_field("hub_status", &Reports(&self.hub_status))?;
+        state.end()
+    }
+}
+
+// Reports keyed by serial number.
+struct Reports<'a>(&'a BTreeMap<String, Arc<TempestMsg>>);
+
+impl Serialize for Reports<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(serial, msg)| (serial, &**msg)))
+    }
+}
@@ -0,0 +1,148 @@
+#[cfg(unix)]
+pub use unix::*;
+
+// Sockets passed by systemd socket activation, sorted by kind. With a `.socket` unit holding the
+// UDP ports, datagrams arriving while the exporter restarts queue in the sockets instead of being
+// lost.
+#[derive(Debug, Default)]
+pub struct ActivatedSockets {
+    pub http: Option<i32>,
+    pub udp: Vec<i32>,
+}
+
+#[cfg(not(unix))]
+pub use other::*;
+
+#[cfg(unix)]
+mod unix {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use sd_notify::NotifyState;
+    use tracing::{debug, warn};
+
+    use super::ActivatedSockets;
+    use crate::heartbeat::Heartbeat;
+
+    // Watchdog pings are withheld once the pipeline has gone this long without a decoded message,
+    // so that systemd restarts a wedged exporter. Hub status alone arrives every ~10 seconds.
+    const PIPELINE_STALL_TIMEOUT: Duration = Duration::from_secs(60);
+
+    // All notifications are no-ops when not running under systemd with `Type=notify`.
+    fn notify(state: NotifyState) {
+        if let Err(e) = sd_notify::notify(false, &[state]) {
+            warn!("systemd notification failed: {}", e);
+        }
+    }
+
+    pub fn notify_ready() {
+        notify(NotifyState::Ready);
+    }
+
+    pub fn notify_stopping() {
+        notify(NotifyState::Stopping);
+    }
+
+    // Newer systemd requires the monotonic timestamp alongside, to match the reload up with the
+    // readiness notification that ends it.
+    pub fn notify_reloading() {
+        match NotifyState::monotonic_usec_now() {
+            Ok(now) => {
+                if let Err(e) = sd_notify::notify(false, &[NotifyState::Reloading, now]) {
+                    warn!("systemd notification failed: {}", e);
+                }
+            }
+            Err(e) => warn!("Reading the monotonic clock failed: {}", e),
+        }
+    }
+
+    pub fn notify_status(status: &str) {
+        notify(NotifyState::Status(status));
+    }
+
+    // Takes the sockets systemd passed through `LISTEN_FDS`, if any: a stream socket to serve HTTP
+    // on and datagram sockets to receive from hubs on.
+    pub fn activated_sockets() -> anyhow::Result<ActivatedSockets> {
+        let mut sockets = ActivatedSockets::default();
+        for fd in sd_notify::listen_fds()? {
+            match socket_type(fd)? {
+                libc::SOCK_STREAM => {
+                    if sockets.http.replace(fd).is_some() {
+                        anyhow::bail!("systemd passed more than one stream socket");
+                    }
+                }
+                libc::SOCK_DGRAM => sockets.udp.push(fd),
+                _ => warn!("Ignoring activated file descriptor {} of unknown type", fd),
+            }
+        }
+        if sockets.http.is_some() || !sockets.udp.is_empty() {
+            debug!("systemd passed sockets {:?}", sockets);
+        }
+        Ok(sockets)
+    }
+
+    fn socket_type(fd: i32) -> std::io::Result<libc::c_int> {
+        let mut socket_type: libc::c_int = 0;
+        let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+        // Safety: the option buffer and its length describe a valid c_int.
+        let result = unsafe {
+            libc::getsockopt(
+                fd,
+                libc::SOL_SOCKET,
+                libc::SO_TYPE,
+                &mut socket_type as *mut libc::c_int as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        if result == 0 {
+            Ok(socket_type)
+        } else {
+            Err(std::io::Error::last_os_error())
+        }
+    }
+
+    pub fn spawn_watchdog(heartbeat: Arc<Heartbeat>) {
+        let mut usec = 0;
+        if !sd_notify::watchdog_enabled(false, &mut usec) {
+            return;
+        }
+        let period = Duration::from_micros(usec) / 2;
+        debug!("systemd watchdog enabled, pinging every {:?}", period);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                if heartbeat.since_last() < PIPELINE_STALL_TIMEOUT {
+                    notify(NotifyState::Watchdog);
+                } else {
+                    warn!(
+                        "No messages for {:?}, withholding watchdog ping",
+                        heartbeat.since_last()
+                    );
+                }
+            }
+        });
+    }
+}
+
+#[cfg(not(unix))]
+mod other {
+    use std::sync::Arc;
+
+    use super::ActivatedSockets;
+    use crate::heartbeat::Heartbeat;
+
+    pub fn activated_sockets() -> anyhow::Result<ActivatedSockets> {
+        Ok(ActivatedSockets::default())
+    }
+
+    pub fn notify_ready() {}
+
+    pub fn notify_stopping() {}
+
+    pub fn notify_reloading() {}
+
+    pub fn notify_status(_status: &str) {}
+
+    pub fn spawn_watchdog(_heartbeat: Arc<Heartbeat>) {}
+}
@@ -0,0 +1,57 @@
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{fmt, EnvFilter};
+
+// Log output is filtered by `RUST_LOG` directives when set, otherwise by the configured level.
+// Records from crates using `log` are forwarded into `tracing`.
+pub fn init(level: log::LevelFilter, otlp_endpoint: Option<&str>) -> anyhow::Result<()> {
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(level.to_string().to_lowercase()));
+    let registry = tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer());
+
+    #[cfg(feature = "otlp")]
+    let registry = registry.with(match otlp_endpoint {
+        Some(endpoint) => Some(otlp::layer(endpoint)?),
+        None => None,
+    });
+    #[cfg(not(feature = "otlp"))]
+    debug_assert!(otlp_endpoint.is_none(), "Rejected by config resolution");
+
+    registry.try_init()?;
+    Ok(())
+}
+
+// Flushes any traces not yet exported.
+pub fn shutdown() {
+    #[cfg(feature = "otlp")]
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+#[cfg(feature = "otlp")]
+mod otlp {
+    use opentelemetry::sdk::{trace, Resource};
+    use opentelemetry::KeyValue;
+    use opentelemetry_otlp::WithExportConfig;
+    use tracing_subscriber::registry::LookupSpan;
+    use tracing_subscriber::Layer;
+
+    pub fn layer<S>(endpoint: &str) -> anyhow::Result<impl Layer<S>>
+    where
+        S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+    {
+        let tracer =
+            opentelemetry_otlp::new_pipeline()
+                .tracing()
+                .with_exporter(
+                    opentelemetry_otlp::new_exporter()
+                        .tonic()
+                        .with_endpoint(endpoint),
+                )
+                .with_trace_config(trace::config().with_resource(Resource::new(vec![
+                    KeyValue::new("service.name", env!("CARGO_PKG_NAME")),
+                ])))
+                .install_batch(opentelemetry::runtime::Tokio)?;
+        Ok(tracing_opentelemetry::layer().with_tracer(tracer))
+    }
+}
@@ -0,0 +1,72 @@
+//! Threshold alerts: when a value past a limit starts an alert firing, and when it resolves.
+
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+/// The limit an alert fires beyond.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Threshold {
+    Above(f64),
+    Below(f64),
+}
+
+impl Threshold {
+    fn breached(&self, value: f64) -> bool {
+        match *self {
+            Threshold::Above(limit) => value > limit,
+            Threshold::Below(limit) => value < limit,
+        }
+    }
+
+    fn recovered(&self, value: f64, hysteresis: f64) -> bool {
+        match *self {
+            Threshold::Above(limit) => value <= limit - hysteresis,
+            Threshold::Below(limit) => value >= limit + hysteresis,
+        }
+    }
+}
+
+/// A change in whether an alert is firing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertState {
+    Firing,
+    Resolved,
+}
+
+/// Whether an alert is firing, and when it last started to.
+#[derive(Debug, Default)]
+pub struct Trigger {
+    firing: bool,
+    last_fired: Option<Instant>,
+}
+
+impl Trigger {
+    /// Takes in a value received at `now`, returning the new state when it changes. The value
+    /// must go `hysteresis` back past the threshold to resolve the alert, and the alert doesn't
+    /// fire again within `cooldown` of last firing, so that a value hovering around the threshold
+    /// doesn't flap.
+    pub fn update(
+        &mut self,
+        threshold: Threshold,
+        hysteresis: f64,
+        cooldown: Duration,
+        value: f64,
+        now: Instant,
+    ) -> Option<AlertState> {
+        if !self.firing && threshold.breached(value) {
+            if matches!(self.last_fired, Some(last) if now.duration_since(last) < cooldown) {
+                return None;
+            }
+            self.firing = true;
+            self.last_fired = Some(now);
+            Some(AlertState::Firing)
+        } else if self.firing && threshold.recovered(value, hysteresis) {
+            self.firing = false;
+            Some(AlertState::Resolved)
+        } else {
+            None
+        }
+    }
+}
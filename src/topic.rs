@@ -0,0 +1,140 @@
+//! MQTT topic templates, so that published topics can be fitted into an existing broker hierarchy.
+//!
+//! A template is a topic with placeholders in braces, filled in for each published value:
+//!
+//! - `{prefix}`: the configured topic prefix
+//! - `{serial}`: the serial number of the station the value came from
+//! - `{topic}`: the value's own topic, such as `observation/thermal/temperature_deg_c`
+//! - `{category}`: the first level of its topic, such as `observation`
+//! - `{field}`: the rest of its topic, such as `thermal/temperature_deg_c`
+//!
+//! Literal braces are written doubled, as `{{` and `}}`.
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::bail;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Prefix,
+    Serial,
+    Topic,
+    Category,
+    Field,
+}
+
+/// A parsed topic template.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TopicTemplate(Vec<Part>);
+
+impl Default for TopicTemplate {
+    /// `{prefix}/{topic}`, the topics published without a template.
+    fn default() -> Self {
+        Self(vec![
+            Part::Prefix,
+            Part::Literal("/".to_string()),
+            Part::Topic,
+        ])
+    }
+}
+
+impl TopicTemplate {
+    /// Whether rendering needs the station's serial number.
+    pub fn uses_serial_number(&self) -> bool {
+        self.0.contains(&Part::Serial)
+    }
+
+    /// The full topic for a value published under `topic`. `None` if the template uses the
+    /// serial number and it isn't known yet.
+    pub fn render(&self, prefix: &str, serial_number: Option<&str>, topic: &str) -> Option<String> {
+        let (category, field) = topic.split_once('/').unwrap_or((topic, ""));
+        let mut rendered = String::new();
+        for part in &self.0 {
+            rendered.push_str(match part {
+                Part::Literal(literal) => literal,
+                Part::Prefix => prefix,
+                Part::Serial => serial_number?,
+                Part::Topic => topic,
+                Part::Category => category,
+                Part::Field => field,
+            });
+        }
+        Some(rendered)
+    }
+}
+
+impl FromStr for TopicTemplate {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.contains(['+', '#']) {
+            bail!("Topic template {} contains MQTT wildcards", s);
+        }
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let name = match rest.find('}') {
+                        Some(end) => &rest[..end],
+                        None => bail!("Unclosed placeholder in topic template {}", s),
+                    };
+                    let part = match name {
+                        "prefix" => Part::Prefix,
+                        "serial" => Part::Serial,
+                        "topic" => Part::Topic,
+                        "category" => Part::Category,
+                        "field" => Part::Field,
+                        _ => bail!("Unknown placeholder {{{}}} in topic template {}", name, s),
+                    };
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(part);
+                    chars = rest[name.len() + 1..].chars();
+                }
+                '}' => bail!("Unmatched }} in topic template {}", s),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        if !parts
+            .iter()
+            .any(|part| matches!(part, Part::Topic | Part::Field))
+        {
+            bail!("Topic template {} must include {{topic}} or {{field}}", s);
+        }
+        Ok(Self(parts))
+    }
+}
+
+impl fmt::Display for TopicTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for part in &self.0 {
+            match part {
+                Part::Literal(literal) => {
+                    write!(f, "{}", literal.replace('{', "{{").replace('}', "}}"))?
+                }
+                Part::Prefix => write!(f, "{{prefix}}")?,
+                Part::Serial => write!(f, "{{serial}}")?,
+                Part::Topic => write!(f, "{{topic}}")?,
+                Part::Category => write!(f, "{{category}}")?,
+                Part::Field => write!(f, "{{field}}")?,
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,111 @@
+//! Rate of change and rolling totals of a reading over a sliding window of reports.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Windows for rolling rain rates, by the suffix their metrics and topics are named with.
+pub const RAIN_RATE_WINDOWS: [(&str, Duration); 2] = [
+    ("10m", Duration::from_secs(10 * 60)),
+    ("1h", Duration::from_secs(60 * 60)),
+];
+
+/// Tracks readings over a sliding window, such as station pressure for storm detection.
+#[derive(Debug, Default, Serialize)]
+pub struct Trend {
+    samples: VecDeque<(DateTime<Utc>, f64)>,
+}
+
+impl Trend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a reading, forgetting those older than `window` before it. Readings are expected in
+    /// time order; one older than the latest is ignored.
+    pub fn push(&mut self, timestamp: DateTime<Utc>, value: f64, window: Duration) {
+        if matches!(self.samples.back(), Some((latest, _)) if *latest >= timestamp) {
+            return;
+        }
+        self.samples.push_back((timestamp, value));
+        let cutoff = timestamp - chrono::Duration::from_std(window).unwrap();
+        while matches!(self.samples.front(), Some((oldest, _)) if *oldest < cutoff) {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Change over `window`, from the oldest reading to the latest, scaled up to the full window
+    /// if the readings span less. `None` until they span at least half the window, so that a few
+    /// readings after startup don't extrapolate wildly.
+    pub fn change(&self, window: Duration) -> Option<f64> {
+        let (oldest_at, oldest) = self.samples.front()?;
+        let (latest_at, latest) = self.samples.back()?;
+        let span = (*latest_at - *oldest_at).to_std().ok()?;
+        if span.is_zero() || span < window / 2 {
+            return None;
+        }
+        Some((latest - oldest) * window.as_secs_f64() / span.as_secs_f64())
+    }
+
+    /// The reading closest in time to `timestamp`, if one is within `tolerance` of it.
+    pub fn at(&self, timestamp: DateTime<Utc>, tolerance: Duration) -> Option<f64> {
+        let tolerance = chrono::Duration::from_std(tolerance).unwrap();
+        self.samples
+            .iter()
+            .map(|(at, value)| ((*at - timestamp).abs(), *value))
+            .filter(|(offset, _)| *offset <= tolerance)
+            .min_by_key(|(offset, _)| *offset)
+            .map(|(_, value)| value)
+    }
+
+    /// Total of the readings within `window` of the latest, counting the latest, such as rain
+    /// accumulated over the last hour. Until the readings span the window, this only totals
+    /// those there are.
+    pub fn sum(&self, window: Duration) -> f64 {
+        let (latest_at, _) = match self.samples.back() {
+            Some(latest) => latest,
+            None => return 0.0,
+        };
+        let cutoff = *latest_at - chrono::Duration::from_std(window).unwrap();
+        self.samples
+            .iter()
+            .filter(|(timestamp, _)| *timestamp > cutoff)
+            .map(|(_, value)| value)
+            .sum()
+    }
+
+    /// Integral over time of the readings within `window` of the latest, each standing until the
+    /// next, in value-seconds, such as how long a condition has held when readings are 1 or 0.
+    pub fn integral(&self, window: Duration) -> f64 {
+        self.held(window).0
+    }
+
+    /// Mean of the readings within `window` of the latest, each weighted by how long it stood
+    /// until the next, such as the mean temperature over the last hour. Until the readings span
+    /// the window, this only averages over the time they do span; a lone reading is its own mean.
+    pub fn time_weighted_mean(&self, window: Duration) -> Option<f64> {
+        let (_, latest) = self.samples.back()?;
+        let (integral, span) = self.held(window);
+        Some(if span > 0.0 { integral / span } else { *latest })
+    }
+
+    // Integral of the readings within `window` of the latest, and the seconds they span.
+    fn held(&self, window: Duration) -> (f64, f64) {
+        let (latest_at, _) = match self.samples.back() {
+            Some(latest) => latest,
+            None => return (0.0, 0.0),
+        };
+        let cutoff = *latest_at - chrono::Duration::from_std(window).unwrap();
+        let (mut integral, mut span) = (0.0, 0.0);
+        for ((at, value), (next_at, _)) in self.samples.iter().zip(self.samples.iter().skip(1)) {
+            let held = (*next_at - (*at).max(cutoff)).num_milliseconds();
+            if held > 0 {
+                integral += value * held as f64 / 1000.0;
+                span += held as f64 / 1000.0;
+            }
+        }
+        (integral, span)
+    }
+}
@@ -0,0 +1,107 @@
+//! Unit-typed wrappers for the physical quantities the station reports.
+//!
+//! Each wraps an `f64` in the unit the Tempest reports in, serializes as the bare number, and
+//! converts to the other units sinks publish in, so that a value in one unit can't be passed where
+//! another is expected. [`f64::from`] unwraps a value where the unit is settled, such as a metric
+//! named for it.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+macro_rules! quantity {
+    ($(#[$doc:meta])* $name:ident) => {
+        $(#[$doc])*
+        #[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd, Serialize, Deserialize)]
+        #[serde(transparent)]
+        pub struct $name(pub f64);
+
+        impl From<$name> for f64 {
+            fn from(quantity: $name) -> f64 {
+                quantity.0
+            }
+        }
+
+        // The bare number, honoring precision.
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt::Display::fmt(&self.0, f)
+            }
+        }
+    };
+}
+
+quantity!(
+    /// Temperature (°C).
+    Celsius
+);
+quantity!(
+    /// Pressure (hPa).
+    HectoPascal
+);
+quantity!(
+    /// Speed (m/s).
+    MetersPerSecond
+);
+quantity!(
+    /// Depth of precipitation (mm).
+    Millimeters
+);
+
+const ZERO_C_KELVIN: f64 = 273.15;
+const INHG_PER_HPA: f64 = 0.029_53;
+const KNOTS_PER_M_PER_S: f64 = 1.943_844;
+const MPH_PER_M_PER_S: f64 = 2.236_936;
+const MM_PER_INCH: f64 = 25.4;
+
+impl Celsius {
+    pub fn from_fahrenheit(fahrenheit: f64) -> Self {
+        Celsius((fahrenheit - 32.0) * 5.0 / 9.0)
+    }
+
+    pub fn fahrenheit(self) -> f64 {
+        self.0 * 9.0 / 5.0 + 32.0
+    }
+
+    pub fn kelvin(self) -> f64 {
+        self.0 + ZERO_C_KELVIN
+    }
+}
+
+impl HectoPascal {
+    pub fn from_pascals(pascals: f64) -> Self {
+        HectoPascal(pascals / 100.0)
+    }
+
+    pub fn pascals(self) -> f64 {
+        self.0 * 100.0
+    }
+
+    pub fn bars(self) -> f64 {
+        self.0 / 1000.0
+    }
+
+    pub fn inches_of_mercury(self) -> f64 {
+        self.0 * INHG_PER_HPA
+    }
+}
+
+impl MetersPerSecond {
+    pub fn kilometers_per_hour(self) -> f64 {
+        self.0 * 3.6
+    }
+
+    pub fn knots(self) -> f64 {
+        self.0 * KNOTS_PER_M_PER_S
+    }
+
+    pub fn miles_per_hour(self) -> f64 {
+        self.0 * MPH_PER_M_PER_S
+    }
+}
+
+impl Millimeters {
+    pub fn inches(self) -> f64 {
+        self.0 / MM_PER_INCH
+    }
+}
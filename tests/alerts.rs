@@ -0,0 +1,52 @@
+// When threshold alerts change, timed by a clock that moves only when told to.
+
+use std::time::Duration;
+
+use chrono::{TimeZone, Utc};
+use tempest_exporter::clock::{Clock, ManualClock};
+use tempest_exporter::threshold::{AlertState, Threshold, Trigger};
+
+const MINUTE: Duration = Duration::from_secs(60);
+
+fn clock() -> ManualClock {
+    ManualClock::new(Utc.with_ymd_and_hms(2026, 10, 16, 12, 0, 0).unwrap())
+}
+
+#[test]
+fn threshold_alert_fires_and_resolves_past_the_hysteresis() {
+    let clock = clock();
+    let mut trigger = Trigger::default();
+    let threshold = Threshold::Above(30.0);
+    let mut update = |value| trigger.update(threshold, 2.0, Duration::ZERO, value, clock.now());
+    assert_eq!(update(29.0), None);
+    assert_eq!(update(31.0), Some(AlertState::Firing));
+    assert_eq!(update(32.0), None);
+    // Back below the threshold, but not by the hysteresis.
+    assert_eq!(update(29.0), None);
+    assert_eq!(update(28.0), Some(AlertState::Resolved));
+    assert_eq!(update(27.0), None);
+
+    let mut trigger = Trigger::default();
+    let threshold = Threshold::Below(0.0);
+    let mut update = |value| trigger.update(threshold, 1.0, Duration::ZERO, value, clock.now());
+    assert_eq!(update(-0.5), Some(AlertState::Firing));
+    assert_eq!(update(0.5), None);
+    assert_eq!(update(1.0), Some(AlertState::Resolved));
+}
+
+#[test]
+fn threshold_alert_does_not_fire_again_within_the_cooldown() {
+    let clock = clock();
+    let mut trigger = Trigger::default();
+    let threshold = Threshold::Above(30.0);
+    let cooldown = 10 * MINUTE;
+    let mut update = |value| trigger.update(threshold, 0.0, cooldown, value, clock.now());
+    assert_eq!(update(31.0), Some(AlertState::Firing));
+    assert_eq!(update(29.0), Some(AlertState::Resolved));
+
+    clock.advance(cooldown - Duration::from_secs(1));
+    assert_eq!(update(31.0), None);
+    assert_eq!(update(29.0), None);
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(update(31.0), Some(AlertState::Firing));
+}
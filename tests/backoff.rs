@@ -0,0 +1,56 @@
+// Reconnection delays: doubling, capped, and jittered within the upper half of each ceiling.
+
+use std::time::Duration;
+
+use tempest_exporter::backoff::{Backoff, BACKOFF_INITIAL, BACKOFF_MAX};
+
+#[test]
+fn delays_double_up_to_the_cap_with_jitter() {
+    let mut backoff = Backoff::default();
+    let mut ceiling = BACKOFF_INITIAL;
+    // Well past the point where the doubling itself stops.
+    for attempt in 1..=40 {
+        let (failures, delay) = backoff.fail();
+        assert_eq!(failures, attempt);
+        assert!(
+            delay >= ceiling / 2 && delay <= ceiling,
+            "attempt {}: {:?} outside [{:?}, {:?}]",
+            attempt,
+            delay,
+            ceiling / 2,
+            ceiling
+        );
+        ceiling = (ceiling * 2).min(BACKOFF_MAX);
+    }
+    assert_eq!(ceiling, BACKOFF_MAX);
+}
+
+#[test]
+fn delays_are_spread_across_the_range() {
+    // The ninth failure's ceiling is 256 s; a thousand draws all landing in one quarter of the
+    // range would mean there is no jitter.
+    let delays: Vec<_> = (0..1000)
+        .map(|_| {
+            let mut backoff = Backoff::default();
+            (0..9).map(|_| backoff.fail().1).last().unwrap()
+        })
+        .collect();
+    assert!(delays.iter().any(|&delay| delay < Duration::from_secs(160)));
+    assert!(delays.iter().any(|&delay| delay > Duration::from_secs(224)));
+}
+
+#[test]
+fn reset_reports_the_failures_once() {
+    let mut backoff = Backoff::default();
+    assert_eq!(backoff.reset(), None);
+    backoff.fail();
+    backoff.fail();
+    backoff.fail();
+    assert_eq!(backoff.reset(), Some(3));
+    assert_eq!(backoff.reset(), None);
+
+    // And the delays start over.
+    let (failures, delay) = backoff.fail();
+    assert_eq!(failures, 1);
+    assert!(delay <= BACKOFF_INITIAL);
+}
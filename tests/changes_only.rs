@@ -0,0 +1,32 @@
+// Publishing only the payloads that changed, with unchanged ones refreshed now and then.
+
+use std::time::Duration;
+
+use chrono::Utc;
+use tempest_exporter::changes::ChangesOnly;
+use tempest_exporter::clock::{Clock, ManualClock};
+
+const REFRESH: Duration = Duration::from_secs(300);
+
+#[test]
+fn only_changed_payloads_are_due_until_the_refresh() {
+    let clock = ManualClock::new(Utc::now());
+    let mut changes = ChangesOnly::new(REFRESH);
+    assert!(changes.is_due("temperature", "12.5", clock.now()));
+    changes.record("temperature".into(), "12.5".to_string(), clock.now());
+
+    clock.advance(Duration::from_secs(60));
+    assert!(!changes.is_due("temperature", "12.5", clock.now()));
+    assert!(changes.is_due("temperature", "12.6", clock.now()));
+    // Topics are tracked separately.
+    assert!(changes.is_due("humidity", "12.5", clock.now()));
+
+    clock.advance(REFRESH - Duration::from_secs(61));
+    assert!(!changes.is_due("temperature", "12.5", clock.now()));
+    clock.advance(Duration::from_secs(1));
+    assert!(changes.is_due("temperature", "12.5", clock.now()));
+
+    // A refresh restarts the wait for the next.
+    changes.record("temperature".into(), "12.5".to_string(), clock.now());
+    assert!(!changes.is_due("temperature", "12.5", clock.now()));
+}
@@ -0,0 +1,201 @@
+// Checkpoints of cumulative metrics and recent reports must survive a round trip through the state
+// file, and a bad state file must never prevent startup.
+
+use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
+
+use tempest_exporter::checkpoint::{self, Checkpoint};
+use tempest_exporter::decoder::TempestMsg;
+use tempest_exporter::exporter::Exporter;
+use tempest_exporter::reader;
+use tempest_exporter::state::StationState;
+
+mod common;
+
+fn exporter() -> Exporter {
+    common::exporter()
+}
+
+fn feed(exporter: &Exporter, fixture: &str) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures/decode")
+        .join(fixture);
+    let raw = reader::parse(&fs::read_to_string(path).unwrap()).unwrap();
+    let msg = TempestMsg::try_from(raw).map_err(|(_, e)| e).unwrap();
+    exporter.handle_report(&msg);
+}
+
+// The rain and lightning observation fixture, as if made `age_secs` ago.
+fn observation_aged(age_secs: i64) -> reader::RawTempestMsg {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures/decode/obs_st_fw156_rain_lightning.json");
+    let timestamp = chrono::Utc::now().timestamp() - age_secs;
+    let json = fs::read_to_string(path)
+        .unwrap()
+        .replace("1635567982", &timestamp.to_string());
+    reader::parse(&json).unwrap()
+}
+
+fn sample(exposition: &str, series: &str) -> f64 {
+    exposition
+        .lines()
+        .find_map(|line| line.strip_prefix(series)?.strip_prefix(' '))
+        .unwrap_or_else(|| panic!("No sample for {}", series))
+        .parse()
+        .unwrap()
+}
+
+#[test]
+fn restored_metrics_continue_from_checkpoint() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("state.json");
+
+    let before = exporter();
+    feed(&before, "obs_st_fw156_rain_lightning.json");
+    feed(&before, "evt_strike_tempest.json");
+    checkpoint::save(&path, &before.checkpoint()).unwrap();
+
+    let after = exporter();
+    after.restore(&checkpoint::load(&path).unwrap());
+    feed(&after, "obs_st_fw156_rain_lightning.json");
+
+    let exposition = String::from_utf8(after.encode()).unwrap();
+    let received = "tempest_exporter_messages_received_total";
+    assert_eq!(
+        sample(
+            &exposition,
+            &format!("{}{{type=\"observation\"}}", received)
+        ),
+        2.0
+    );
+    assert_eq!(
+        sample(
+            &exposition,
+            &format!("{}{{type=\"strike_event\"}}", received)
+        ),
+        1.0
+    );
+    assert_eq!(
+        sample(&exposition, "tempest_station_observation_rain_count"),
+        2.0
+    );
+    assert!((sample(&exposition, "tempest_station_observation_rain_sum") - 0.62).abs() < 1e-9);
+    assert_eq!(
+        sample(
+            &exposition,
+            "tempest_station_observation_rain_bucket{le=\"0.398\"}"
+        ),
+        2.0
+    );
+    assert_eq!(after.checkpoint().histograms, {
+        let twice = exporter();
+        feed(&twice, "obs_st_fw156_rain_lightning.json");
+        feed(&twice, "obs_st_fw156_rain_lightning.json");
+        twice.checkpoint().histograms
+    });
+}
+
+#[test]
+fn missing_state_file_is_not_an_error() {
+    let dir = tempfile::tempdir().unwrap();
+    assert_eq!(checkpoint::load(&dir.path().join("state.json")), None);
+}
+
+#[test]
+fn corrupt_state_file_is_ignored() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("state.json");
+    fs::write(&path, "{\"version\": 1, \"saved_at\": ").unwrap();
+    assert_eq!(checkpoint::load(&path), None);
+}
+
+#[test]
+fn state_file_of_other_version_is_ignored() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("state.json");
+    let checkpoint = Checkpoint {
+        version: checkpoint::VERSION + 1,
+        ..exporter().checkpoint()
+    };
+    checkpoint::save(&path, &checkpoint).unwrap();
+    assert_eq!(checkpoint::load(&path), None);
+}
+
+#[test]
+fn histograms_with_changed_buckets_are_not_restored() {
+    let before = exporter();
+    feed(&before, "obs_st_fw156_rain_lightning.json");
+    let mut checkpoint = before.checkpoint();
+    for state in checkpoint.histograms.values_mut() {
+        state.upper_bounds.push(1000.0);
+        state.bucket_counts.push(1);
+    }
+
+    let after = exporter();
+    after.restore(&checkpoint);
+    assert_eq!(
+        after.checkpoint().histograms,
+        exporter().checkpoint().histograms
+    );
+}
+
+#[test]
+fn recent_report_is_restored_without_counting_it_again() {
+    let exporter = exporter();
+    let msg = TempestMsg::try_from(observation_aged(600))
+        .map_err(|(_, e)| e)
+        .unwrap();
+    assert!(exporter.restore_report(&msg));
+
+    let exposition = String::from_utf8(exporter.encode()).unwrap();
+    assert_eq!(
+        sample(&exposition, "tempest_station_observation_temperature_deg_c"),
+        9.52
+    );
+    assert_eq!(
+        sample(&exposition, "tempest_station_observation_rain_count"),
+        0.0
+    );
+    assert!(!exposition.contains("tempest_exporter_messages_received_total{"));
+}
+
+#[test]
+fn stale_report_is_not_restored() {
+    let exporter = exporter();
+    let msg = TempestMsg::try_from(observation_aged(7200))
+        .map_err(|(_, e)| e)
+        .unwrap();
+    assert!(!exporter.restore_report(&msg));
+    let exposition = String::from_utf8(exporter.encode()).unwrap();
+    assert!(!exposition.contains("tempest_station_observation_temperature_deg_c"));
+}
+
+#[test]
+fn station_state_keeps_latest_status_and_observation_per_device_as_received() {
+    let mut state = StationState::default();
+    state.record(&observation_aged(120));
+    let latest = observation_aged(60);
+    state.record(&latest);
+    for fixture in [
+        "rapid_wind_tempest.json",
+        "device_status_tempest_fw156.json",
+    ] {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures/decode")
+            .join(fixture);
+        state.record(&reader::parse(&fs::read_to_string(path).unwrap()).unwrap());
+    }
+    let kept = state.received();
+    assert_eq!(kept.len(), 2);
+    assert!(kept.contains(&latest));
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("state.json");
+    let checkpoint = Checkpoint {
+        last_reports: kept.clone(),
+        ..exporter().checkpoint()
+    };
+    checkpoint::save(&path, &checkpoint).unwrap();
+    assert_eq!(checkpoint::load(&path).unwrap().last_reports, kept);
+}
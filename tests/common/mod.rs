@@ -0,0 +1,41 @@
+// Exporters set up for tests, so that each test file states only the parameters it depends on and
+// a new parameter touches only its default.
+
+// Each test crate uses only some of these.
+#![allow(dead_code)]
+
+use std::time::Duration;
+
+use tempest_exporter::exporter::Exporter;
+use tempest_exporter::params::{self, ExporterParams, StationParams};
+
+pub const HOUR: Duration = Duration::from_secs(3600);
+
+// A station 100 m above sea level, otherwise at the defaults.
+pub fn station_params() -> StationParams {
+    StationParams {
+        elevation: 100.0,
+        ..Default::default()
+    }
+}
+
+// The defaults, except that metrics stay fresh for an hour so that tests can feed reports with
+// timestamps some way apart.
+pub fn exporter_params() -> ExporterParams {
+    ExporterParams {
+        instant_wind_ttl: HOUR,
+        observation_ttl: HOUR,
+        ..Default::default()
+    }
+}
+
+pub fn exporter() -> Exporter {
+    exporter_with(station_params(), exporter_params())
+}
+
+pub fn exporter_with(station_params: StationParams, exporter_params: ExporterParams) -> Exporter {
+    Exporter::new(
+        params::shared(station_params),
+        params::shared(exporter_params),
+    )
+}
@@ -0,0 +1,73 @@
+// Days start over at local midnight, however long daylight saving makes them.
+
+use chrono::{DateTime, NaiveDate, Utc};
+use tempest_exporter::day::{Daily, DayZone};
+
+fn at(rfc3339: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(rfc3339)
+        .unwrap()
+        .with_timezone(&Utc)
+}
+
+fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(y, m, d).unwrap()
+}
+
+fn zone(name: &str) -> DayZone {
+    DayZone::Named(name.parse().unwrap())
+}
+
+#[test]
+fn dates_are_local() {
+    let new_york = zone("America/New_York");
+    assert_eq!(new_york.date(at("2023-06-01T03:59:59Z")), date(2023, 5, 31));
+    assert_eq!(new_york.date(at("2023-06-01T04:00:00Z")), date(2023, 6, 1));
+    assert_eq!(DayZone::from(None), DayZone::System);
+}
+
+#[test]
+fn days_start_at_local_midnight_across_daylight_saving() {
+    let new_york = zone("America/New_York");
+    // Clocks go forward at 2am, so the day is 23 hours long.
+    let start = new_york.start_of(date(2023, 3, 12));
+    let end = new_york.start_of(date(2023, 3, 13));
+    assert_eq!(start, at("2023-03-12T05:00:00Z"));
+    assert_eq!((end - start).num_hours(), 23);
+    // And back at 2am, so it is 25.
+    let start = new_york.start_of(date(2023, 11, 5));
+    let end = new_york.start_of(date(2023, 11, 6));
+    assert_eq!((end - start).num_hours(), 25);
+}
+
+#[test]
+fn day_starts_when_midnight_is_skipped() {
+    // Chile's clocks went forward from midnight to 1am.
+    let santiago = zone("America/Santiago");
+    assert_eq!(
+        santiago.start_of(date(2022, 9, 11)),
+        at("2022-09-11T04:00:00Z")
+    );
+}
+
+#[test]
+fn daily_values_start_over_on_a_new_local_day() {
+    let new_york = zone("America/New_York");
+    let mut rain = Daily::<f64>::default();
+    assert_eq!(rain.day(), None);
+    assert_eq!(
+        *rain.update(new_york, at("2023-03-12T05:30:00Z"), |t| *t += 1.0),
+        1.0
+    );
+    // Past UTC midnight, but still the 12th in New York.
+    rain.update(new_york, at("2023-03-13T00:30:00Z"), |t| *t += 1.0);
+    assert_eq!(
+        *rain.update(new_york, at("2023-03-13T03:30:00Z"), |t| *t += 0.5),
+        2.5
+    );
+    assert_eq!(rain.day(), Some(date(2023, 3, 12)));
+    assert_eq!(
+        *rain.update(new_york, at("2023-03-13T04:30:00Z"), |t| *t += 0.25),
+        0.25
+    );
+    assert_eq!(rain.day(), Some(date(2023, 3, 13)));
+}
@@ -0,0 +1,107 @@
+// Internal exporter state, as served for troubleshooting.
+
+use std::convert::TryFrom;
+
+use chrono::Utc;
+use serde_json::json;
+use tempest_exporter::decoder::TempestMsg;
+use tempest_exporter::exporter::Exporter;
+use tempest_exporter::params::ExporterParams;
+use tempest_exporter::reader;
+
+mod common;
+
+fn exporter() -> Exporter {
+    // Metrics expire on the default schedule, which the state shows.
+    common::exporter_with(common::station_params(), ExporterParams::default())
+}
+
+fn handle(exporter: &Exporter, datagram: serde_json::Value) {
+    let raw = reader::parse(&datagram.to_string()).unwrap();
+    exporter.handle_report(&TempestMsg::try_from(raw).map_err(|(_, e)| e).unwrap());
+}
+
+#[test]
+fn debug_state_shows_freshness_and_tracked_reports() {
+    let exporter = exporter();
+    let datagram = json!({
+        "serial_number": "ST-00000001",
+        "type": "obs_st",
+        "hub_sn": "HB-00000001",
+        "obs": [[Utc::now().timestamp(), 1.0, 2.0, 3.0, 180, 3, 1010.0, 10.0, 80.0, 0, 0.0, 0, 0.4, 1, 0, 0, 2.6, 1]],
+        "firmware_revision": 156,
+    });
+    handle(&exporter, datagram);
+
+    let state = exporter.debug_state();
+    let temperature = &state["perishable"]["observation_temperature"];
+    assert!(temperature["age_sec"].as_f64().unwrap() < 5.0);
+    let expires_in = temperature["expires_in_sec"].as_f64().unwrap();
+    assert!(expires_in > 175.0 && expires_in <= 180.0, "{}", expires_in);
+    assert_eq!(state["perishable"]["instant_wind"]["age_sec"], json!(null));
+    assert_eq!(state["pressure_trend"]["samples"][0][1], json!(1010.0));
+    assert_eq!(state["rain_events"]["current"]["total"], json!(0.4));
+    assert!(state["last_rain"].is_string());
+}
+
+#[test]
+fn debug_messages_are_kept_and_counted() {
+    let exporter = exporter();
+    handle(
+        &exporter,
+        json!({
+            "serial_number": "ST-00000001",
+            "type": "device_status",
+            "hub_sn": "HB-00000001",
+            "timestamp": Utc::now().timestamp(),
+            "uptime": 1000,
+            "voltage": 2.6,
+            "firmware_revision": 171,
+            "rssi": -60,
+            "hub_rssi": -60,
+            "sensor_status": 0,
+            "debug": 1,
+        }),
+    );
+    for ob in [1, 2] {
+        handle(
+            &exporter,
+            json!({
+                "serial_number": "ST-00000001",
+                "type": "light_debug",
+                "hub_sn": "HB-00000001",
+                "ob": [1635568010, ob, 0, 0, 0],
+            }),
+        );
+    }
+
+    let light_debug = &exporter.debug_state()["debug_messages"]["light_debug"];
+    assert_eq!(light_debug["serial_number"], json!("ST-00000001"));
+    assert_eq!(light_debug["timestamp"], json!("2021-10-30T04:26:50Z"));
+    assert_eq!(light_debug["fields"]["ob"], json!([1635568010, 2, 0, 0, 0]));
+    let exposition = String::from_utf8(exporter.encode()).unwrap();
+    assert!(exposition.contains("tempest_station_status_debug_enabled 1\n"));
+    assert!(exposition.contains("tempest_station_debug_messages_total{type=\"light_debug\"} 2\n"));
+}
+
+#[test]
+fn hub_diagnostics_are_counted_by_type() {
+    let exporter = exporter();
+    for kind in ["hub_debug", "radio_stats", "radio_stats"] {
+        handle(
+            &exporter,
+            json!({
+                "serial_number": "HB-00000001",
+                "type": kind,
+                "timestamp": 1635568030,
+                "reboot_count": 1,
+            }),
+        );
+    }
+    let radio_stats = &exporter.debug_state()["debug_messages"]["radio_stats"];
+    assert_eq!(radio_stats["serial_number"], json!("HB-00000001"));
+    assert_eq!(radio_stats["fields"]["reboot_count"], json!(1));
+    let exposition = String::from_utf8(exporter.encode()).unwrap();
+    assert!(exposition.contains("tempest_station_debug_messages_total{type=\"hub_debug\"} 1\n"));
+    assert!(exposition.contains("tempest_station_debug_messages_total{type=\"radio_stats\"} 2\n"));
+}
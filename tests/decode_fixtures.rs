@@ -0,0 +1,522 @@
+// Golden-file tests: every `fixtures/decode/NAME.json` datagram is read and decoded, and the
+// outcome must match `fixtures/decode/NAME.expected.json`. Run with `UPDATE_GOLDEN=1` to rewrite
+// the expected files after an intentional change, and review the diff.
+
+use std::convert::TryFrom;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value};
+use tempest_exporter::decoder::{DecodeError, TempestMsg};
+use tempest_exporter::reader;
+use tempest_exporter::units::{Celsius, HectoPascal, MetersPerSecond, Millimeters};
+
+fn fixtures() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/decode");
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            let name = path.file_name().unwrap().to_str().unwrap();
+            name.ends_with(".json") && !name.ends_with(".expected.json")
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn decode(datagram: &str) -> Value {
+    let raw = match reader::parse(datagram) {
+        Ok(raw) => raw,
+        Err(_) => return json!({ "outcome": "read_error" }),
+    };
+    match TempestMsg::try_from(raw) {
+        Ok(msg) => json!({ "outcome": "decoded", "message": msg }),
+        Err((_, e)) => json!({ "outcome": "decode_error", "error": e.to_string() }),
+    }
+}
+
+// Derived values go through transcendental functions and JSON float parsing, so tolerate
+// last-digit differences.
+fn approx_eq(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => {
+            let (a, b) = (a.as_f64().unwrap(), b.as_f64().unwrap());
+            (a - b).abs() <= 1e-9 * a.abs().max(b.abs()).max(1.0)
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| approx_eq(a, b))
+        }
+        (Value::Object(a), Value::Object(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(key, a)| matches!(b.get(key), Some(b) if approx_eq(a, b)))
+        }
+        (a, b) => a == b,
+    }
+}
+
+fn decoded(fixture: &str) -> TempestMsg {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures/decode")
+        .join(fixture);
+    let raw = reader::parse(&fs::read_to_string(path).unwrap()).unwrap();
+    TempestMsg::try_from(raw).map_err(|(_, e)| e).unwrap()
+}
+
+#[test]
+fn fixtures_match_golden_output() {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let mut mismatched = vec![];
+    for path in fixtures() {
+        let actual = decode(&fs::read_to_string(&path).unwrap());
+        let expected_path = path.with_extension("expected.json");
+        if update {
+            let mut text = serde_json::to_string_pretty(&actual).unwrap();
+            text.push('\n');
+            fs::write(&expected_path, text).unwrap();
+            continue;
+        }
+        let expected: Value = match fs::read_to_string(&expected_path) {
+            Ok(text) => serde_json::from_str(&text).unwrap(),
+            Err(_) => {
+                mismatched.push(format!("{}: no expected output", path.display()));
+                continue;
+            }
+        };
+        if !approx_eq(&actual, &expected) {
+            mismatched.push(format!(
+                "{}:\nexpected {}\n  actual {}",
+                path.display(),
+                expected,
+                actual
+            ));
+        }
+    }
+    assert!(mismatched.is_empty(), "{}", mismatched.join("\n\n"));
+}
+
+fn decode_error(fixture: &str) -> DecodeError {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures/decode")
+        .join(fixture);
+    let raw = reader::parse(&fs::read_to_string(path).unwrap()).unwrap();
+    match TempestMsg::try_from(raw) {
+        Ok(msg) => panic!("{} decoded: {:?}", fixture, msg),
+        Err((_, e)) => e,
+    }
+}
+
+#[test]
+fn decode_errors_are_categorized() {
+    let cases = [
+        (
+            "obs_st_missing_timestamp.json",
+            DecodeError::MissingField("observation timestamp"),
+            "missing_field",
+        ),
+        (
+            "obs_st_unknown_precip_type.json",
+            DecodeError::UnknownValue {
+                field: "precip type",
+                value: "7".to_string(),
+            },
+            "unknown_value",
+        ),
+        (
+            "hub_status_unknown_reset_flag.json",
+            DecodeError::UnknownValue {
+                field: "reset flag label",
+                value: "XYZ".to_string(),
+            },
+            "unknown_value",
+        ),
+        (
+            "rapid_wind_timestamp_out_of_range.json",
+            DecodeError::InvalidTimestamp(99999999999999999),
+            "invalid_timestamp",
+        ),
+        (
+            "hub_status_implausible_timestamp.json",
+            DecodeError::ImplausibleTimestamp(1670133),
+            "implausible_timestamp",
+        ),
+    ];
+    for (fixture, expected, category) in cases {
+        let error = decode_error(fixture);
+        assert_eq!(error, expected, "{}", fixture);
+        assert_eq!(error.category(), category, "{}", fixture);
+    }
+}
+
+#[test]
+fn observation_fields_map_to_documented_indices() {
+    let obs = match decoded("obs_st_fw156_rain_lightning.json") {
+        TempestMsg::Observation(obs) => obs,
+        other => panic!("Expected observation, got {:?}", other),
+    };
+    assert_eq!(obs.timestamp.timestamp(), 1635567982);
+    let wind = obs.wind.as_ref().unwrap();
+    assert_eq!(wind.lull.speed_magnitude(), MetersPerSecond(1.12));
+    assert_eq!(wind.avg.speed_magnitude(), MetersPerSecond(2.42));
+    assert_eq!(wind.gust.speed_magnitude(), MetersPerSecond(3.89));
+    assert_eq!(wind.avg.source_direction(), 271.0);
+    assert_eq!(wind.interval.num_seconds(), 3);
+    assert_eq!(obs.station_pressure, Some(HectoPascal(1003.28)));
+    assert_eq!(obs.air_temperature, Some(Celsius(9.52)));
+    assert_eq!(obs.relative_humidity, Some(94.31));
+    let solar = obs.solar.as_ref().unwrap();
+    assert_eq!(solar.illuminance, 2207.0);
+    assert_eq!(solar.ultraviolet_index, 0.18);
+    assert_eq!(solar.irradiance, 18.0);
+    assert_eq!(
+        obs.precip.as_ref().unwrap().quantity_last_minute,
+        Millimeters(0.31)
+    );
+    let lightning = obs.lightning.as_ref().unwrap();
+    assert_eq!(lightning.average_distance, 12.0);
+    assert_eq!(lightning.count, 3);
+    assert_eq!(obs.battery_volts, 2.621);
+    assert_eq!(obs.report_interval.num_minutes(), 1);
+}
+
+#[test]
+fn device_status_fields_map_to_documented_names() {
+    let status = match decoded("device_status_tempest_fw156.json") {
+        TempestMsg::DeviceStatus(status) => status,
+        other => panic!("Expected device status, got {:?}", other),
+    };
+    assert_eq!(status.serial_number, "ST-00028405");
+    assert_eq!(status.hub_serial_number, "HB-00027548");
+    assert_eq!(status.timestamp.timestamp(), 1635567982);
+    assert_eq!(status.uptime.num_seconds(), 2862471);
+    assert_eq!(status.voltage, 2.621);
+    assert_eq!(status.firmware_revision, 156);
+    assert_eq!(status.rssi, -63.0);
+    assert_eq!(status.hub_rssi, -58.0);
+    assert!(!status.debug);
+}
+
+#[test]
+fn hub_status_fields_map_to_documented_names() {
+    let status = match decoded("hub_status_fw171_all_reset_flags.json") {
+        TempestMsg::HubStatus(status) => status,
+        other => panic!("Expected hub status, got {:?}", other),
+    };
+    assert_eq!(status.serial_number, "HB-00027548");
+    assert_eq!(status.firmware_revision, "171");
+    assert_eq!(status.timestamp.timestamp(), 1635568010);
+    assert_eq!(status.uptime.num_seconds(), 2862499);
+    assert_eq!(status.rssi, -54.0);
+    assert_eq!(status.seq, 286211);
+}
+
+// Every status fixture that decodes carries its time and uptime over from the fields of those
+// names, which being both in seconds are easily swapped.
+#[test]
+fn status_times_come_from_their_own_fields() {
+    for path in fixtures() {
+        // Some fixtures aren't JSON at all.
+        let datagram: Value = match serde_json::from_str(&fs::read_to_string(&path).unwrap()) {
+            Ok(datagram) => datagram,
+            Err(_) => continue,
+        };
+        if !matches!(
+            datagram["type"].as_str(),
+            Some("device_status" | "hub_status")
+        ) {
+            continue;
+        }
+        let decoded = decode(&datagram.to_string());
+        if decoded["outcome"] != "decoded" {
+            continue;
+        }
+        let msg = TempestMsg::try_from(reader::parse(&datagram.to_string()).unwrap())
+            .map_err(|(_, e)| e)
+            .unwrap();
+        assert_eq!(
+            Some(msg.timestamp().timestamp()),
+            datagram["timestamp"].as_i64(),
+            "{}",
+            path.display()
+        );
+        assert_eq!(
+            decoded["message"]["uptime_sec"],
+            datagram["uptime"],
+            "{}",
+            path.display()
+        );
+    }
+}
+
+#[test]
+fn derived_values_are_physically_plausible() {
+    let obs = match decoded("obs_st_fw129.json") {
+        TempestMsg::Observation(obs) => obs,
+        other => panic!("Expected observation, got {:?}", other),
+    };
+    let (t, rh) = (22.37, 50.26);
+    let dew_point = obs.dew_point().unwrap().0;
+    let wet_bulb = obs.wet_bulb_temperature().unwrap().0;
+    assert!((dew_point - 11.5).abs() < 0.5, "dew point {}", dew_point);
+    assert!(
+        dew_point < wet_bulb && wet_bulb < t,
+        "wet bulb {}",
+        wet_bulb
+    );
+    assert!(
+        (obs.vapor_pressure_actual().unwrap().0 / obs.vapor_pressure_saturated().unwrap().0
+            * 100.0
+            - rh)
+            .abs()
+            < 1e-9
+    );
+    assert_eq!(obs.barometric_pressure(0.0), obs.station_pressure);
+    let slp = obs.barometric_pressure(100.0).unwrap().0;
+    assert!((slp - 1029.3).abs() < 0.5, "sea level pressure {}", slp);
+}
+
+#[test]
+fn frost_risk_follows_radiative_cooling() {
+    // Temperature, humidity, average wind and irradiance, and whether frost is expected.
+    let cases = [
+        (3.0, 70.0, 0.5, 0.0, true), // Clear calm night cools surfaces below freezing
+        (3.0, 70.0, 3.0, 0.0, false), // A breeze mixes warmer air down
+        (1.5, 70.0, 3.0, 0.0, true), // .. but not enough when it's nearly freezing
+        (3.0, 70.0, 6.0, 0.0, false), // Wind prevents radiative cooling
+        (3.0, 70.0, 0.5, 400.0, false), // Sunshine warms surfaces
+        (6.0, 70.0, 0.5, 0.0, false), // Too warm even with cooling
+        (3.0, 100.0, 0.5, 0.0, false), // Saturated air condenses before reaching freezing
+        (-2.0, 50.0, 6.0, 400.0, true), // Below freezing whatever the conditions
+    ];
+    for (t, rh, wind, irradiance, expected) in cases {
+        let datagram = json!({
+            "serial_number": "ST-00000001",
+            "type": "obs_st",
+            "hub_sn": "HB-00000001",
+            "obs": [[1635567982, wind, wind, wind, 0, 3, 1000.0, t, rh, 0, 0.0, irradiance, 0.0, 0, 0, 0, 2.6, 1]],
+            "firmware_revision": 156,
+        });
+        let obs = match TempestMsg::try_from(reader::parse(&datagram.to_string()).unwrap()) {
+            Ok(TempestMsg::Observation(obs)) => obs,
+            other => panic!("Expected observation, got {:?}", other),
+        };
+        assert_eq!(
+            obs.frost_risk(),
+            Some(expected),
+            "{} °C, {} %, {} m/s, {} W/m², surface {:?}",
+            t,
+            rh,
+            wind,
+            irradiance,
+            obs.surface_temperature()
+        );
+    }
+}
+
+#[test]
+fn clear_sky_index_compares_with_cloudless_sky() {
+    use chrono::{TimeZone, Utc};
+    use tempest_exporter::solar;
+
+    // Greenwich around solar noon on the March equinox, when the sun is about 38.5° high.
+    let (latitude, longitude) = (51.4779, -0.0015);
+    let noon = Utc.with_ymd_and_hms(2021, 3, 20, 12, 7, 0).unwrap();
+    let zenith_cosine = solar::zenith_cosine(noon, latitude, longitude);
+    assert!(
+        (zenith_cosine - 38.5f64.to_radians().sin()).abs() < 0.01,
+        "zenith cosine {}",
+        zenith_cosine
+    );
+    let clear_sky = solar::clear_sky_irradiance(zenith_cosine);
+
+    let observation = |timestamp: i64, irradiance: f64| {
+        let datagram = json!({
+            "serial_number": "ST-00000001",
+            "type": "obs_st",
+            "hub_sn": "HB-00000001",
+            "obs": [[timestamp, 1.0, 2.0, 3.0, 0, 3, 1000.0, 10.0, 70.0, 0, 0.0, irradiance, 0.0, 0, 0, 0, 2.6, 1]],
+            "firmware_revision": 156,
+        });
+        match TempestMsg::try_from(reader::parse(&datagram.to_string()).unwrap()) {
+            Ok(TempestMsg::Observation(obs)) => obs,
+            other => panic!("Expected observation, got {:?}", other),
+        }
+    };
+    let clear = observation(noon.timestamp(), clear_sky.round());
+    let index = clear.clear_sky_index(latitude, longitude).unwrap();
+    assert!((index - 1.0).abs() < 0.01, "clear index {}", index);
+    let overcast = observation(noon.timestamp(), (clear_sky / 4.0).round());
+    let index = overcast.clear_sky_index(latitude, longitude).unwrap();
+    assert!((index - 0.25).abs() < 0.01, "overcast index {}", index);
+    let midnight = observation(noon.timestamp() - 12 * 3600, 0.0);
+    assert_eq!(midnight.clear_sky_index(latitude, longitude), None);
+}
+
+#[test]
+fn floors_zero_faint_light() {
+    let mut obs = match decoded("obs_st_fw129.json") {
+        TempestMsg::Observation(obs) => obs,
+        other => panic!("Expected observation, got {:?}", other),
+    };
+    obs.apply_floors(1.0, 0.01);
+    let solar = obs.solar.as_ref().unwrap();
+    assert_eq!((solar.illuminance, solar.ultraviolet_index), (328.0, 0.03));
+    obs.apply_floors(500.0, 0.05);
+    let solar = obs.solar.as_ref().unwrap();
+    assert_eq!((solar.illuminance, solar.ultraviolet_index), (0.0, 0.0));
+    assert_eq!(solar.irradiance, 3.0);
+}
+
+#[test]
+fn apparent_temperature_formulas_follow_their_conventions() {
+    use tempest_exporter::decoder::ApparentTemperatureFormula as F;
+
+    // Formula, temperature, humidity and average wind, and the expected apparent temperature.
+    let cases = [
+        (F::HeatIndexWindChill, 0.0, 70.0, 5.0, -4.9), // Wind chill
+        (F::HeatIndexWindChill, 0.0, 70.0, 1.0, 0.0),  // Too calm for wind chill
+        (F::HeatIndexWindChill, 20.0, 70.0, 5.0, 20.0), // Neither cold nor hot
+        (F::HeatIndexWindChill, 32.2, 60.0, 1.0, 37.6), // Heat index, 90 °F and 60 % feel like 99.7 °F
+        (F::AustralianAt, 25.0, 50.0, 2.0, 24.8),
+        (F::WbgtEstimate, 25.0, 50.0, 2.0, 24.3),
+    ];
+    for (formula, t, rh, wind, expected) in cases {
+        let datagram = json!({
+            "serial_number": "ST-00000001",
+            "type": "obs_st",
+            "hub_sn": "HB-00000001",
+            "obs": [[1635567982, wind, wind, wind, 0, 3, 1000.0, t, rh, 0, 0.0, 0, 0.0, 0, 0, 0, 2.6, 1]],
+            "firmware_revision": 156,
+        });
+        let obs = match TempestMsg::try_from(reader::parse(&datagram.to_string()).unwrap()) {
+            Ok(TempestMsg::Observation(obs)) => obs,
+            other => panic!("Expected observation, got {:?}", other),
+        };
+        let apparent = obs.apparent_temperature_by(formula).unwrap().0;
+        assert!(
+            (apparent - expected).abs() < 0.1,
+            "{:?} at {} °C, {} %, {} m/s gave {}",
+            formula,
+            t,
+            rh,
+            wind,
+            apparent
+        );
+    }
+    for formula in [
+        F::Steadman,
+        F::HeatIndexWindChill,
+        F::AustralianAt,
+        F::WbgtEstimate,
+    ] {
+        assert_eq!(formula.as_str().parse::<F>().unwrap(), formula);
+    }
+    assert!("feels-like".parse::<F>().is_err());
+}
+
+#[test]
+fn wet_bulb_globe_temperature_rises_in_sun_and_falls_in_wind() {
+    let observation = |wind: f64, irradiance: f64| {
+        let datagram = json!({
+            "serial_number": "ST-00000001",
+            "type": "obs_st",
+            "hub_sn": "HB-00000001",
+            "obs": [[1635567982, wind, wind, wind, 0, 3, 1000.0, 30.0, 50.0, 0, 0.0, irradiance, 0.0, 0, 0, 0, 2.6, 1]],
+            "firmware_revision": 156,
+        });
+        match TempestMsg::try_from(reader::parse(&datagram.to_string()).unwrap()) {
+            Ok(TempestMsg::Observation(obs)) => obs,
+            other => panic!("Expected observation, got {:?}", other),
+        }
+    };
+    // In shade the globe sits at air temperature.
+    let shade = observation(1.0, 0.0);
+    assert!((shade.globe_temperature().unwrap().0 - 30.0).abs() < 1e-6);
+    let wbgt_shade = shade.wet_bulb_globe_temperature().unwrap().0;
+    assert!(
+        (wbgt_shade - (0.7 * shade.wet_bulb_temperature().unwrap().0 + 0.3 * 30.0)).abs() < 1e-6
+    );
+
+    // Full sun in light air heats the globe well above air temperature, less so in a wind.
+    let sun = observation(1.0, 900.0);
+    let globe = sun.globe_temperature().unwrap().0;
+    assert!((40.0..55.0).contains(&globe), "globe {}", globe);
+    let windy = observation(8.0, 900.0);
+    assert!(windy.globe_temperature().unwrap().0 < globe);
+    let wbgt_sun = sun.wet_bulb_globe_temperature().unwrap().0;
+    assert!(
+        wbgt_sun > wbgt_shade && wbgt_sun < 32.0,
+        "WBGT {}",
+        wbgt_sun
+    );
+}
+
+#[test]
+fn comfort_follows_dew_point() {
+    use tempest_exporter::decoder::Comfort;
+
+    // Temperature and humidity, and the expected comfort.
+    let cases = [
+        (30.0, 30.0, Comfort::Comfortable), // Dew point about 10.5 °C
+        (25.0, 70.0, Comfort::Humid),       // About 19.1 °C
+        (30.0, 70.0, Comfort::Oppressive),  // About 23.9 °C
+        (12.0, 100.0, Comfort::Comfortable),
+    ];
+    for (t, rh, expected) in cases {
+        let datagram = json!({
+            "serial_number": "ST-00000001",
+            "type": "obs_st",
+            "hub_sn": "HB-00000001",
+            "obs": [[1635567982, 1.0, 1.0, 1.0, 0, 3, 1000.0, t, rh, 0, 0.0, 0, 0.0, 0, 0, 0, 2.6, 1]],
+            "firmware_revision": 156,
+        });
+        let obs = match TempestMsg::try_from(reader::parse(&datagram.to_string()).unwrap()) {
+            Ok(TempestMsg::Observation(obs)) => obs,
+            other => panic!("Expected observation, got {:?}", other),
+        };
+        assert_eq!(
+            obs.comfort(),
+            Some(expected),
+            "{} °C, {} %, dew point {:?}",
+            t,
+            rh,
+            obs.dew_point()
+        );
+    }
+}
+
+#[test]
+fn precip_phase_follows_wet_bulb_temperature() {
+    use tempest_exporter::decoder::PrecipPhase;
+
+    // Temperature and humidity, and the expected phase.
+    let cases = [
+        (-2.0, 90.0, PrecipPhase::Snow),
+        (1.5, 95.0, PrecipPhase::Mixed),
+        (3.0, 40.0, PrecipPhase::Snow), // Dry air cools falling snow enough to keep it frozen
+        (3.0, 95.0, PrecipPhase::Rain),
+        (7.0, 10.0, PrecipPhase::Rain), // Too warm for snow however dry
+    ];
+    for (t, rh, expected) in cases {
+        let datagram = json!({
+            "serial_number": "ST-00000001",
+            "type": "obs_st",
+            "hub_sn": "HB-00000001",
+            "obs": [[1635567982, 1.0, 1.0, 1.0, 0, 3, 1000.0, t, rh, 0, 0.0, 0, 0.0, 0, 0, 0, 2.6, 1]],
+            "firmware_revision": 156,
+        });
+        let obs = match TempestMsg::try_from(reader::parse(&datagram.to_string()).unwrap()) {
+            Ok(TempestMsg::Observation(obs)) => obs,
+            other => panic!("Expected observation, got {:?}", other),
+        };
+        assert_eq!(
+            obs.estimated_precip_phase(),
+            Some(expected),
+            "{} °C, {} %, wet bulb {:?}",
+            t,
+            rh,
+            obs.wet_bulb_temperature()
+        );
+    }
+}
@@ -0,0 +1,103 @@
+// Lenient decoding repairs datagrams that strict decoding drops.
+
+use std::convert::TryFrom;
+
+use serde_json::{json, Value};
+use tempest_exporter::decoder::TempestMsg;
+use tempest_exporter::reader::{self, DecodeMode};
+use tempest_exporter::units::{Celsius, HectoPascal};
+
+fn decode(datagram: Value, mode: DecodeMode) -> Option<TempestMsg> {
+    let raw = reader::parse_with(&datagram.to_string(), mode).ok()?;
+    TempestMsg::try_from(raw).ok()
+}
+
+fn observation(obs: Value) -> Value {
+    json!({
+        "serial_number": "ST-00000001",
+        "type": "obs_st",
+        "hub_sn": "HB-00000001",
+        "obs": obs,
+        "firmware_revision": 176,
+    })
+}
+
+#[test]
+fn malformed_values_are_taken_as_missing() {
+    let datagram = observation(json!([[
+        1635568010, 0.5, 1.0, 1.5, "N/A", 3, 1010.0, "12.5", 80.0, 0, 0.0, 0, 0.0, 9, 0, 0, 2.6, 1
+    ]]));
+    assert!(decode(datagram.clone(), DecodeMode::Strict).is_none());
+    let obs = match decode(datagram, DecodeMode::Lenient) {
+        Some(TempestMsg::Observation(obs)) => obs,
+        other => panic!("not an observation: {:?}", other),
+    };
+    assert!(obs.wind.is_none());
+    assert_eq!(obs.air_temperature, Some(Celsius(12.5)));
+    // An unknown precipitation type leaves precipitation unknown rather than dropping the rest.
+    assert!(obs.precip.is_none());
+    assert_eq!(obs.station_pressure, Some(HectoPascal(1010.0)));
+}
+
+#[test]
+fn rows_are_fitted_to_the_documented_shape() {
+    let long = observation(json!([[
+        1635568010, 0.5, 1.0, 1.5, 180, 3, 1010.0, 10.0, 80.0, 0, 0.0, 0, 0.0, 0, 0, 0, 2.6, 1, 42
+    ]]));
+    assert!(decode(long.clone(), DecodeMode::Strict).is_none());
+    assert!(decode(long, DecodeMode::Lenient).is_some());
+
+    let rows = observation(json!([
+        [1635567950, 0.5, 1.0, 1.5, 180, 3, 1010.0, 9.0, 80.0, 0, 0.0, 0, 0.0, 0, 0, 0, 2.6, 1],
+        [1635568010, 0.5, 1.0, 1.5, 180, 3, 1010.0, 10.0, 80.0, 0, 0.0, 0, 0.0, 0, 0, 0, 2.6, 1],
+    ]));
+    assert!(decode(rows.clone(), DecodeMode::Strict).is_none());
+    match decode(rows, DecodeMode::Lenient) {
+        Some(TempestMsg::Observation(obs)) => assert_eq!(obs.air_temperature, Some(Celsius(10.0))),
+        other => panic!("not an observation: {:?}", other),
+    }
+}
+
+#[test]
+fn status_fields_are_filled_in_or_trimmed() {
+    let device_status = json!({
+        "serial_number": "ST-00000001",
+        "type": "device_status",
+        "hub_sn": "HB-00000001",
+        "timestamp": 1635568010,
+        "uptime": 1000,
+        "voltage": 2.6,
+        "firmware_revision": 176,
+        "rssi": -60,
+        "hub_rssi": -60,
+    });
+    assert!(decode(device_status.clone(), DecodeMode::Strict).is_none());
+    assert!(decode(device_status, DecodeMode::Lenient).is_some());
+
+    let hub_status = json!({
+        "serial_number": "HB-00000001",
+        "type": "hub_status",
+        "firmware_revision": "194",
+        "uptime": 10,
+        "rssi": -54,
+        "timestamp": 1635568010,
+        "reset_flags": "BOR,XYZ",
+        "radio_stats": [25, 1, 0, 3, 16342, 7],
+    });
+    assert!(decode(hub_status.clone(), DecodeMode::Strict).is_none());
+    match decode(hub_status, DecodeMode::Lenient) {
+        Some(TempestMsg::HubStatus(hs)) => assert!(hs.reset_flags.brownout),
+        other => panic!("not a hub status: {:?}", other),
+    }
+}
+
+#[test]
+fn modes_parse_from_their_names() {
+    assert_eq!("strict".parse::<DecodeMode>().unwrap(), DecodeMode::Strict);
+    assert_eq!(
+        "lenient".parse::<DecodeMode>().unwrap(),
+        DecodeMode::Lenient
+    );
+    assert!("loose".parse::<DecodeMode>().is_err());
+    assert_eq!(DecodeMode::default(), DecodeMode::Lenient);
+}
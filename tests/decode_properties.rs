@@ -0,0 +1,112 @@
+// Property tests: no input, however malformed, may panic the reader or decoder.
+
+use std::convert::TryFrom;
+
+use proptest::prelude::*;
+use serde_json::{json, Value};
+use tempest_exporter::decoder::TempestMsg;
+use tempest_exporter::reader;
+
+fn read_and_decode(datagram: &str) {
+    if let Ok(raw) = reader::parse(datagram) {
+        let _ = TempestMsg::try_from(raw);
+    }
+}
+
+fn arbitrary_json() -> impl Strategy<Value = Value> {
+    let leaf = prop_oneof![
+        Just(Value::Null),
+        any::<bool>().prop_map(Value::from),
+        any::<i64>().prop_map(Value::from),
+        any::<f64>().prop_map(Value::from),
+        "[a-z_]{0,12}".prop_map(Value::from),
+    ];
+    leaf.prop_recursive(4, 64, 18, |inner| {
+        prop_oneof![
+            prop::collection::vec(inner.clone(), 0..18).prop_map(Value::Array),
+            prop::collection::hash_map("[a-z_]{1,16}", inner, 0..8)
+                .prop_map(|map| Value::Object(map.into_iter().collect())),
+        ]
+    })
+}
+
+fn extreme_number() -> impl Strategy<Value = Value> {
+    prop_oneof![
+        any::<i64>().prop_map(Value::from),
+        any::<f64>().prop_map(Value::from),
+        Just(Value::Null),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn arbitrary_text_never_panics(datagram in "\\PC{0,256}") {
+        read_and_decode(&datagram);
+    }
+
+    #[test]
+    fn arbitrary_json_never_panics(value in arbitrary_json()) {
+        read_and_decode(&value.to_string());
+    }
+
+    #[test]
+    fn observations_with_extreme_values_never_panic(
+        obs in prop::collection::vec(extreme_number(), 18),
+    ) {
+        let datagram = json!({
+            "serial_number": "ST-00000001",
+            "type": "obs_st",
+            "hub_sn": "HB-00000001",
+            "obs": [obs],
+            "firmware_revision": 171,
+        });
+        read_and_decode(&datagram.to_string());
+    }
+
+    #[test]
+    fn statuses_and_events_with_extreme_values_never_panic(
+        timestamp in any::<i64>(),
+        uptime in any::<i64>(),
+        sensor_status in any::<u32>(),
+    ) {
+        let device_status = json!({
+            "serial_number": "ST-00000001",
+            "type": "device_status",
+            "hub_sn": "HB-00000001",
+            "timestamp": timestamp,
+            "uptime": uptime,
+            "voltage": 2.6,
+            "firmware_revision": 171,
+            "rssi": -60,
+            "hub_rssi": -60,
+            "sensor_status": sensor_status,
+            "debug": 0,
+        });
+        read_and_decode(&device_status.to_string());
+        let hub_status = json!({
+            "serial_number": "HB-00000001",
+            "type": "hub_status",
+            "firmware_revision": "171",
+            "uptime": uptime,
+            "rssi": -60,
+            "timestamp": timestamp,
+            "reset_flags": "BOR,PIN,POR",
+            "seq": 1,
+            "radio_stats": [25, 1, 0, 3, 16342],
+        });
+        read_and_decode(&hub_status.to_string());
+        for (event, field, values) in [
+            ("evt_precip", "evt", json!([timestamp])),
+            ("evt_strike", "evt", json!([timestamp, 12.0, 2421.0])),
+            ("rapid_wind", "ob", json!([timestamp, 1.0, 90.0])),
+        ] {
+            let mut datagram = json!({
+                "serial_number": "ST-00000001",
+                "type": event,
+                "hub_sn": "HB-00000001",
+            });
+            datagram[field] = values;
+            read_and_decode(&datagram.to_string());
+        }
+    }
+}
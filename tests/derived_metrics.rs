@@ -0,0 +1,131 @@
+// User-defined derived metrics: parsing and evaluating their expressions, and exporting them.
+
+use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
+
+use tempest_exporter::decoder::{Observation, TempestMsg};
+use tempest_exporter::derived::DerivedMetric;
+use tempest_exporter::params::StationParams;
+use tempest_exporter::reader;
+
+mod common;
+
+fn station_params(derived: Vec<DerivedMetric>) -> StationParams {
+    StationParams {
+        derived,
+        ..common::station_params()
+    }
+}
+
+fn report(fixture: &str) -> TempestMsg {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures/decode")
+        .join(fixture);
+    let raw = reader::parse(&fs::read_to_string(path).unwrap()).unwrap();
+    TempestMsg::try_from(raw).map_err(|(_, e)| e).unwrap()
+}
+
+fn observation(fixture: &str) -> Observation {
+    match report(fixture) {
+        TempestMsg::Observation(obs) => obs,
+        other => panic!("{} is not an observation: {:?}", fixture, other),
+    }
+}
+
+fn evaluate(fixture: &str, expression: &str) -> Option<f64> {
+    let metric = DerivedMetric::new("test", expression).unwrap();
+    metric.evaluate(&observation(fixture), &station_params(Vec::new()))
+}
+
+#[test]
+fn expressions_follow_arithmetic_precedence() {
+    let obs = "obs_st_fw156_rain_lightning.json";
+    for (expression, expected) in [
+        ("2 + 3 * 4 ^ 2 / 8", 8.0),
+        ("(2 + 3) * 4", 20.0),
+        ("10 - 4 - 3", 3.0),
+        ("2 ^ 3 ^ 2", 512.0),
+        ("-2 ^ 2", -4.0),
+        ("2 * -3", -6.0),
+        ("1.5e2 + 2.5E-1", 150.25),
+        ("max(1, 7, 3) - min(4, abs(-2))", 5.0),
+        ("sqrt(16) + ln(exp(2))", 6.0),
+    ] {
+        let value = evaluate(obs, expression).unwrap();
+        assert!(
+            (value - expected).abs() < 1e-9,
+            "{} = {}, expected {}",
+            expression,
+            value,
+            expected
+        );
+    }
+}
+
+#[test]
+fn expressions_read_observation_fields() {
+    let obs = "obs_st_fw156_rain_lightning.json";
+    let fahrenheit = evaluate(obs, "temperature * 1.8 + 32").unwrap();
+    assert!((fahrenheit - 49.136).abs() < 1e-9, "{}", fahrenheit);
+    let depression = evaluate(obs, "temperature - dew_point").unwrap();
+    assert!((depression - (9.52 - 8.634159257030783)).abs() < 1e-9);
+    assert_eq!(evaluate(obs, "lightning_count"), Some(3.0));
+}
+
+#[test]
+fn missing_fields_and_non_finite_results_yield_nothing() {
+    // The temperature and humidity sensor failed, but the barometer still reads.
+    let failed = "obs_st_fw171_sensor_failure.json";
+    assert_eq!(evaluate(failed, "temperature * 1.8 + 32"), None);
+    assert!(evaluate(failed, "station_pressure / 10").is_some());
+    let obs = "obs_st_fw156_rain_lightning.json";
+    assert_eq!(evaluate(obs, "temperature / 0"), None);
+    assert_eq!(evaluate(obs, "sqrt(-temperature)"), None);
+}
+
+#[test]
+fn invalid_definitions_are_rejected() {
+    for (expression, error) in [
+        ("temperature * 1.8 +", "Unexpected end of expression"),
+        ("(temperature + 1", "Expected ')' at end of expression"),
+        ("temperature 2", "Unexpected number 2 in expression"),
+        ("humidity * 2", "Unknown field humidity in expression"),
+        ("log(temperature)", "Unknown function log in expression"),
+        (
+            "sqrt(1, 2)",
+            "Wrong number of arguments to sqrt in expression",
+        ),
+        ("temperature % 2", "Unexpected character '%' in expression"),
+        ("1.2.3", "Invalid number 1.2.3 in expression"),
+    ] {
+        let e = DerivedMetric::new("test", expression).unwrap_err();
+        assert_eq!(e.to_string(), format!("Derived metric test: {}", error));
+    }
+    for name in ["", "Temp_F", "9lives", "temp-f"] {
+        assert!(DerivedMetric::new(name, "temperature").is_err(), "{}", name);
+    }
+}
+
+#[test]
+fn derived_metrics_are_exported_as_gauges() {
+    let derived = vec![
+        DerivedMetric::new("temperature_f", "temperature * 1.8 + 32").unwrap(),
+        DerivedMetric::new("station_pressure_kpa", "station_pressure / 10").unwrap(),
+    ];
+    let exporter = common::exporter_with(station_params(derived), common::exporter_params());
+    exporter.handle_report(&report("obs_st_fw171_sensor_failure.json"));
+    let exposition = String::from_utf8(exporter.encode()).unwrap();
+    assert!(exposition.contains(
+        "# HELP tempest_station_derived_station_pressure_kpa Derived from the observation as \
+         station_pressure / 10\n"
+    ));
+    let value: f64 = exposition
+        .lines()
+        .find_map(|line| line.strip_prefix("tempest_station_derived_station_pressure_kpa "))
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((value - 101.142).abs() < 1e-9, "{}", value);
+    assert!(!exposition.contains("tempest_station_derived_temperature_f"));
+}
@@ -0,0 +1,66 @@
+// Per-device metrics expire device by device.
+
+use std::convert::TryFrom;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{TimeZone, Utc};
+use serde_json::json;
+use tempest_exporter::clock::{Clock, ManualClock};
+use tempest_exporter::decoder::TempestMsg;
+use tempest_exporter::exporter::Exporter;
+use tempest_exporter::params::{self, ExporterParams};
+use tempest_exporter::reader;
+
+mod common;
+
+const TTL: Duration = Duration::from_secs(60);
+
+fn observe(exporter: &Exporter, clock: &ManualClock, serial_number: &str) {
+    let datagram = json!({
+        "serial_number": serial_number,
+        "type": "obs_st",
+        "hub_sn": "HB-00000001",
+        "obs": [[clock.utc().timestamp(), 1.0, 2.0, 3.0, 180, 3, 1010.0, 10.0, 80.0, 0, 0.0, 0, 0.0, 0, 0, 0, 2.6, 1]],
+        "firmware_revision": 156,
+    });
+    let raw = reader::parse(&datagram.to_string()).unwrap();
+    exporter.handle_report(&TempestMsg::try_from(raw).map_err(|(_, e)| e).unwrap());
+}
+
+fn scored(exporter: &Exporter, serial_number: &str) -> bool {
+    String::from_utf8(exporter.encode())
+        .unwrap()
+        .contains(&format!(
+            "tempest_station_status_quality_score{{serial_number=\"{}\"}}",
+            serial_number
+        ))
+}
+
+#[test]
+fn quiet_device_loses_its_quality_score() {
+    let clock = Arc::new(ManualClock::new(Utc.timestamp_opt(1635567982, 0).unwrap()));
+    let exporter = Exporter::with_clock(
+        params::shared(common::station_params()),
+        params::shared(ExporterParams {
+            instant_wind_ttl: TTL,
+            observation_ttl: TTL,
+            ..common::exporter_params()
+        }),
+        clock.clone(),
+    );
+    observe(&exporter, &clock, "ST-00000001");
+    observe(&exporter, &clock, "ST-00000002");
+    assert!(scored(&exporter, "ST-00000001"));
+    assert!(scored(&exporter, "ST-00000002"));
+
+    clock.advance(TTL / 2);
+    observe(&exporter, &clock, "ST-00000002");
+    clock.advance(TTL * 3 / 4);
+    assert!(!scored(&exporter, "ST-00000001"));
+    assert!(scored(&exporter, "ST-00000002"));
+
+    // A device that reports again is scored again.
+    observe(&exporter, &clock, "ST-00000001");
+    assert!(scored(&exporter, "ST-00000001"));
+}
@@ -0,0 +1,65 @@
+// Messages dropped on the way through the pipeline are counted by stage and reason.
+
+use std::sync::Arc;
+
+use serde_json::json;
+use tempest_exporter::exporter::Exporter;
+use tempest_exporter::{decoder, reader};
+use tokio_stream::StreamExt;
+
+mod common;
+
+fn exporter() -> Exporter {
+    common::exporter()
+}
+
+fn dropped(exposition: &str, stage: &str, reason: &str) -> Option<u64> {
+    let series = format!(
+        "tempest_exporter_messages_dropped_total{{reason=\"{}\",stage=\"{}\"}} ",
+        reason, stage
+    );
+    exposition
+        .lines()
+        .find_map(|line| line.strip_prefix(&series))
+        .map(|value| value.parse().unwrap())
+}
+
+#[tokio::test]
+async fn drops_are_counted_by_stage_and_reason() {
+    let exporter = Arc::new(exporter());
+    let datagrams = vec![
+        "{\"serial_number\":".to_string(),
+        json!({ "type": "no_such_type" }).to_string(),
+        json!({
+            "serial_number": "ST-00000001",
+            "type": "obs_st",
+            "hub_sn": "HB-00000001",
+            "obs": [[null, 1.0, 2.0, 3.0, 180, 3, 1010.0, 10.0, 80.0, 0, 0.0, 0, 0.0, 0, 0, 0, 2.6, 1]],
+            "firmware_revision": 156,
+        })
+        .to_string(),
+        json!({
+            "serial_number": "ST-00000001",
+            "type": "rapid_wind",
+            "hub_sn": "HB-00000001",
+            "ob": [1588948614, 2.5, 90],
+        })
+        .to_string(),
+    ];
+    let rdr = reader::with_drop_hook(tokio_stream::iter(datagrams), reader::DecodeMode::Strict, {
+        let exporter = exporter.clone();
+        move |e| exporter.handle_dropped("read", reader::error_category(e))
+    });
+    let dec = decoder::with_drop_hook(rdr, {
+        let exporter = exporter.clone();
+        move |e| exporter.handle_dropped("decode", e.category())
+    });
+    let decoded: Vec<_> = dec.collect().await;
+    assert_eq!(decoded.len(), 1);
+
+    let exposition = String::from_utf8(exporter.encode()).unwrap();
+    assert_eq!(dropped(&exposition, "read", "malformed_json"), Some(1));
+    assert_eq!(dropped(&exposition, "read", "unrecognized"), Some(1));
+    assert_eq!(dropped(&exposition, "decode", "missing_field"), Some(1));
+    assert_eq!(dropped(&exposition, "dedup", "hub_copy"), None);
+}
@@ -0,0 +1,1187 @@
+// The exporter binary end to end: fixture datagrams sent to its UDP port come out as Prometheus
+// metrics and as MQTT publishes to a broker stood up by the test.
+
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, UdpSocket};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use flate2::read::GzDecoder;
+
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+fn datagram(fixture: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures/decode")
+        .join(fixture);
+    fs::read_to_string(path).unwrap()
+}
+
+// A port nothing is listening on, for the exporter to bind.
+fn free_udp_port() -> u16 {
+    UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+fn free_tcp_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+// Polls until `check` returns something or the timeout runs out.
+fn eventually<T>(what: &str, mut check: impl FnMut() -> Option<T>) -> T {
+    let deadline = Instant::now() + TIMEOUT;
+    loop {
+        if let Some(value) = check() {
+            return value;
+        }
+        assert!(Instant::now() < deadline, "timed out waiting for {}", what);
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+// Just enough of an MQTT 3.1.1 broker to accept a client and record what it publishes.
+struct Broker {
+    port: u16,
+    published: Arc<Mutex<Vec<(String, String)>>>,
+    connected: Arc<Mutex<Vec<Connect>>>,
+}
+
+// What a client connected with.
+#[derive(Debug, PartialEq)]
+struct Connect {
+    client_id: String,
+    will: bool,
+    password: Option<String>,
+}
+
+impl Broker {
+    fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let published = Arc::new(Mutex::new(Vec::new()));
+        let connected = Arc::new(Mutex::new(Vec::new()));
+        thread::spawn({
+            let published = published.clone();
+            let connected = connected.clone();
+            move || {
+                for stream in listener.incoming().flatten() {
+                    let published = published.clone();
+                    let connected = connected.clone();
+                    thread::spawn(move || Broker::serve(stream, &published, &connected));
+                }
+            }
+        });
+        Broker {
+            port,
+            published,
+            connected,
+        }
+    }
+
+    fn serve(
+        mut stream: TcpStream,
+        published: &Mutex<Vec<(String, String)>>,
+        connected: &Mutex<Vec<Connect>>,
+    ) -> io::Result<()> {
+        loop {
+            let mut header = [0; 1];
+            stream.read_exact(&mut header)?;
+            let mut length = 0;
+            for shift in (0..28).step_by(7) {
+                let mut byte = [0; 1];
+                stream.read_exact(&mut byte)?;
+                length |= usize::from(byte[0] & 0x7f) << shift;
+                if byte[0] & 0x80 == 0 {
+                    break;
+                }
+            }
+            let mut body = vec![0; length];
+            stream.read_exact(&mut body)?;
+            match header[0] >> 4 {
+                // CONNECT: record, and accept.
+                1 => {
+                    let mut fields = body.as_slice();
+                    Broker::field(&mut fields); // Protocol name
+                                                // Then come the level, flags and keep alive.
+                    let flags = fields[1];
+                    fields = &fields[4..];
+                    let client_id = Broker::field(&mut fields);
+                    let will = flags & 0x04 != 0;
+                    if will {
+                        Broker::field(&mut fields); // Topic
+                        Broker::field(&mut fields); // Message
+                    }
+                    if flags & 0x80 != 0 {
+                        Broker::field(&mut fields); // Username
+                    }
+                    let password = (flags & 0x40 != 0).then(|| Broker::field(&mut fields));
+                    connected.lock().unwrap().push(Connect {
+                        client_id,
+                        will,
+                        password,
+                    });
+                    stream.write_all(&[0x20, 0x02, 0x00, 0x00])?
+                }
+                // PUBLISH: record, and acknowledge at QoS 1.
+                3 => {
+                    let topic_len = usize::from(u16::from_be_bytes([body[0], body[1]]));
+                    let topic = String::from_utf8_lossy(&body[2..2 + topic_len]).into_owned();
+                    let mut payload = 2 + topic_len;
+                    if (header[0] >> 1) & 0x03 > 0 {
+                        stream.write_all(&[0x40, 0x02, body[payload], body[payload + 1]])?;
+                        payload += 2;
+                    }
+                    let payload = String::from_utf8_lossy(&body[payload..]).into_owned();
+                    published.lock().unwrap().push((topic, payload));
+                }
+                // PINGREQ
+                12 => stream.write_all(&[0xd0, 0x00])?,
+                // DISCONNECT
+                14 => return Ok(()),
+                _ => {}
+            }
+        }
+    }
+
+    // Takes a length-prefixed string off the front of a packet's fields.
+    fn field(fields: &mut &[u8]) -> String {
+        let len = usize::from(u16::from_be_bytes([fields[0], fields[1]]));
+        let (field, rest) = fields[2..].split_at(len);
+        *fields = rest;
+        String::from_utf8_lossy(field).into_owned()
+    }
+
+    // Everything published to `topic`, in order.
+    fn payloads(&self, topic: &str) -> Vec<String> {
+        self.published
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(published, _)| published == topic)
+            .map(|(_, payload)| payload.clone())
+            .collect()
+    }
+
+    fn payload(&self, topic: &str) -> Option<String> {
+        self.published
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .find(|(published, _)| published == topic)
+            .map(|(_, payload)| payload.clone())
+    }
+}
+
+// The exporter process, killed when dropped so a failing test doesn't leave it running.
+struct Exporter {
+    child: Child,
+    udp_port: u16,
+    metrics_port: u16,
+}
+
+impl Exporter {
+    fn start(broker: &Broker) -> Self {
+        Exporter::start_with(broker, &[])
+    }
+
+    fn start_with(broker: &Broker, args: &[&str]) -> Self {
+        Exporter::start_command(broker, args, |_| {})
+    }
+
+    // Started allowed only `limit` open file descriptors.
+    #[cfg(unix)]
+    fn start_with_fd_limit(broker: &Broker, args: &[&str], limit: u64) -> Self {
+        use std::os::unix::process::CommandExt;
+
+        Exporter::start_command(broker, args, |command| {
+            // Safety: setrlimit is async-signal-safe and touches no memory of the parent's.
+            unsafe {
+                command.pre_exec(move || {
+                    let limit = libc::rlimit {
+                        rlim_cur: limit,
+                        rlim_max: limit,
+                    };
+                    match libc::setrlimit(libc::RLIMIT_NOFILE, &limit) {
+                        0 => Ok(()),
+                        _ => Err(io::Error::last_os_error()),
+                    }
+                });
+            }
+        })
+    }
+
+    fn start_command(broker: &Broker, args: &[&str], configure: impl FnOnce(&mut Command)) -> Self {
+        let udp_port = free_udp_port();
+        let metrics_port = free_tcp_port();
+        let mut command = Command::new(env!("CARGO_BIN_EXE_tempest-exporter"));
+        command
+            .args(["--station-elevation", "100"])
+            .args(["--startup-mode", "serve"])
+            .args(["--api-port", &udp_port.to_string()])
+            .args(["--metrics-port", &metrics_port.to_string()])
+            .args(["--mqtt-broker", "127.0.0.1"])
+            .args(["--mqtt-port", &broker.port.to_string()])
+            .args(["--mqtt-topic-prefix", "e2e"])
+            .args(args)
+            .env_remove("TEMPEST_CONFIG")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        configure(&mut command);
+        let child = command.spawn().unwrap();
+        let exporter = Exporter {
+            child,
+            udp_port,
+            metrics_port,
+        };
+        eventually("the exporter to serve HTTP", || exporter.get("/healthz"));
+        exporter
+    }
+
+    #[cfg(unix)]
+    fn signal(&self, signal: i32) {
+        // Safety: kill doesn't touch memory.
+        assert_eq!(unsafe { libc::kill(self.child.id() as i32, signal) }, 0);
+    }
+
+    fn send(&self, datagram: &str) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .send_to(datagram.as_bytes(), ("127.0.0.1", self.udp_port))
+            .unwrap();
+    }
+
+    // The body of a successful GET, if the server answered with one.
+    fn get(&self, path: &str) -> Option<String> {
+        let (_, body) = self.request(path, "")?;
+        String::from_utf8(body).ok()
+    }
+
+    // The head and body of a successful GET with extra header lines.
+    fn request(&self, path: &str, headers: &str) -> Option<(String, Vec<u8>)> {
+        let mut stream = TcpStream::connect(("127.0.0.1", self.metrics_port)).ok()?;
+        stream.set_read_timeout(Some(TIMEOUT)).ok()?;
+        write!(
+            stream,
+            "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n{}\r\n",
+            path, headers
+        )
+        .ok()?;
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).ok()?;
+        let split = response.windows(4).position(|w| w == b"\r\n\r\n")?;
+        let head = String::from_utf8(response[..split].to_vec()).ok()?;
+        let body = response[split + 4..].to_vec();
+        head.starts_with("HTTP/1.1 200").then_some((head, body))
+    }
+}
+
+impl Drop for Exporter {
+    fn drop(&mut self) {
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+// The value of the first sample of `metric` in an exposition.
+fn sample(exposition: &str, metric: &str) -> Option<f64> {
+    exposition
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .find(|line| line.starts_with(metric) && line[metric.len()..].starts_with(['{', ' ']))
+        .and_then(|line| line.rsplit(' ').next()?.parse().ok())
+}
+
+#[test]
+fn datagrams_reach_metrics_and_mqtt() {
+    let broker = Broker::start();
+    let exporter = Exporter::start(&broker);
+    let observation = datagram("obs_st_fw156_rain_lightning.json");
+    let rapid_wind = datagram("rapid_wind_tempest.json");
+
+    // The listener may still be binding its UDP socket after HTTP is up, so keep sending until
+    // the reports show up.
+    let metrics = eventually("observation metrics", || {
+        exporter.send(&observation);
+        exporter.send(&rapid_wind);
+        let metrics = exporter.get("/metrics")?;
+        sample(&metrics, "tempest_station_observation_temperature_deg_c")?;
+        sample(
+            &metrics,
+            "tempest_station_instant_wind_speed_magnitude_m_per_s",
+        )?;
+        Some(metrics)
+    });
+    assert_eq!(
+        sample(&metrics, "tempest_station_observation_temperature_deg_c"),
+        Some(9.52)
+    );
+    assert_eq!(exporter.get("/metrics/anything"), None);
+
+    let temperature = eventually("the temperature to be published", || {
+        broker.payload("e2e/observation/thermal/temperature_deg_c")
+    });
+    assert_eq!(temperature, "9.52");
+    let wind_speed = eventually("the instant wind to be published", || {
+        broker.payload("e2e/instant_wind/speed_magnitude_m_per_s")
+    });
+    assert_eq!(wind_speed, "0.27");
+}
+
+#[test]
+fn checking_the_broker_connection_leaves_a_running_exporter_alone() {
+    let broker = Broker::start();
+    assert!(check_connection(&broker, &[]));
+    // Connecting as the exporter would disconnect it, and its last will would mark it offline.
+    assert_eq!(
+        *broker.connected.lock().unwrap(),
+        [Connect {
+            client_id: "tempest-exporter-check".to_string(),
+            will: false,
+            password: None,
+        }]
+    );
+}
+
+#[test]
+fn a_password_file_on_the_command_line_beats_a_password_in_the_config_file() {
+    let broker = Broker::start();
+    let config = tempfile::NamedTempFile::new().unwrap();
+    fs::write(
+        config.path(),
+        "[mqtt]\nusername = \"tempest\"\npassword = \"from config\"\n",
+    )
+    .unwrap();
+    let password = tempfile::NamedTempFile::new().unwrap();
+    fs::write(password.path(), "from file\n").unwrap();
+    assert!(check_connection(
+        &broker,
+        &[
+            "--config",
+            config.path().to_str().unwrap(),
+            "--mqtt-password-file",
+            password.path().to_str().unwrap(),
+        ],
+    ));
+    let connected = broker.connected.lock().unwrap();
+    assert_eq!(connected[0].password.as_deref(), Some("from file"));
+}
+
+// Runs `check-config --connect` against the broker, returning whether it succeeded.
+fn check_connection(broker: &Broker, args: &[&str]) -> bool {
+    Command::new(env!("CARGO_BIN_EXE_tempest-exporter"))
+        .args(["--station-elevation", "100"])
+        .args(["--mqtt-broker", "127.0.0.1"])
+        .args(["--mqtt-port", &broker.port.to_string()])
+        .args(args)
+        .args(["check-config", "--connect"])
+        .env_remove("TEMPEST_CONFIG")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .unwrap()
+        .success()
+}
+
+// Runs `check-config`, returning whether it succeeded and what it reported on stderr.
+fn check_config(args: &[&str]) -> (bool, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_tempest-exporter"))
+        .args(["--station-elevation", "100"])
+        .args(args)
+        .arg("check-config")
+        .env_remove("TEMPEST_CONFIG")
+        .output()
+        .unwrap();
+    (
+        output.status.success(),
+        String::from_utf8_lossy(&output.stderr).into_owned(),
+    )
+}
+
+#[test]
+fn a_zero_checkpoint_interval_is_rejected() {
+    let (ok, stderr) = check_config(&["--checkpoint-interval", "0"]);
+    assert!(!ok);
+    assert!(
+        stderr.contains("Checkpoint interval must be at least 1 second"),
+        "{}",
+        stderr
+    );
+    assert!(check_config(&["--checkpoint-interval", "1"]).0);
+}
+
+#[test]
+fn a_reference_station_must_be_a_station_id() {
+    for station in ["", "KSEA/../../alerts", "KSEA?x=1"] {
+        let (ok, stderr) = check_config(&["--reference-station", station]);
+        assert!(!ok, "{:?}", station);
+        assert!(stderr.contains("is not a station ID"), "{}", stderr);
+    }
+    assert!(check_config(&["--reference-station", "KSEA"]).0);
+}
+
+#[test]
+fn a_zero_rain_check_interval_is_rejected() {
+    let args = [
+        "--rain-check-station-id",
+        "1234",
+        "--rain-check-token",
+        "token",
+        "--rain-check-interval",
+    ];
+    let (ok, stderr) = check_config(&[&args[..], &["0"]].concat());
+    assert!(!ok);
+    assert!(stderr.contains("must be at least an hour"), "{}", stderr);
+    assert!(check_config(&[&args[..], &["1"]].concat()).0);
+}
+
+#[test]
+fn metrics_are_gzipped_for_scrapers_asking() {
+    let broker = Broker::start();
+    let exporter = Exporter::start(&broker);
+    let observation = datagram("obs_st_fw156_rain_lightning.json");
+    eventually("observation metrics", || {
+        exporter.send(&observation);
+        sample(
+            &exporter.get("/metrics")?,
+            "tempest_station_observation_temperature_deg_c",
+        )
+    });
+
+    let (head, body) = exporter
+        .request("/metrics", "Accept-Encoding: gzip, deflate\r\n")
+        .unwrap();
+    assert!(head.contains("content-encoding: gzip"), "{}", head);
+    let mut metrics = String::new();
+    GzDecoder::new(&body[..])
+        .read_to_string(&mut metrics)
+        .unwrap();
+    assert_eq!(
+        sample(&metrics, "tempest_station_observation_temperature_deg_c"),
+        Some(9.52)
+    );
+
+    let (head, _) = exporter
+        .request("/metrics", "Accept-Encoding: gzip;q=0, identity\r\n")
+        .unwrap();
+    assert!(!head.contains("content-encoding"), "{}", head);
+}
+
+#[test]
+fn stalled_clients_are_dropped_to_make_room() {
+    let broker = Broker::start();
+    let exporter = Exporter::start_with(
+        &broker,
+        &["--http-max-connections", "1", "--http-request-timeout", "1"],
+    );
+
+    // Trickles in a request it never finishes, holding the only connection.
+    let mut stalled = TcpStream::connect(("127.0.0.1", exporter.metrics_port)).unwrap();
+    stalled.write_all(b"GET /healthz HTTP/1.1\r\n").unwrap();
+    let started = Instant::now();
+    let body = exporter.get("/healthz");
+    assert_eq!(body.as_deref(), Some("ok"));
+    assert!(started.elapsed() >= Duration::from_millis(500));
+
+    let mut rest = Vec::new();
+    stalled.set_read_timeout(Some(TIMEOUT)).unwrap();
+    assert_eq!(stalled.read_to_end(&mut rest).unwrap_or_default(), 0);
+}
+
+#[cfg(unix)]
+#[test]
+fn http_is_served_again_after_running_out_of_file_descriptors() {
+    let broker = Broker::start();
+    let exporter = Exporter::start_with_fd_limit(&broker, &["--http-max-connections", "1000"], 32);
+
+    // More clients than the exporter can have descriptors for, so accepting some fails.
+    let clients: Vec<_> = (0..48)
+        .map(|_| TcpStream::connect(("127.0.0.1", exporter.metrics_port)).unwrap())
+        .collect();
+    thread::sleep(Duration::from_millis(500));
+    drop(clients);
+
+    let body = eventually("HTTP to be served again", || exporter.get("/healthz"));
+    assert_eq!(body, "ok");
+}
+
+#[cfg(unix)]
+#[test]
+fn availability_stays_online_across_an_upgrade() {
+    let broker = Broker::start();
+    let mut exporter = Exporter::start(&broker);
+    eventually("the exporter to come online", || {
+        broker
+            .payload("e2e/status")
+            .filter(|status| status == "online")
+    });
+
+    // The new exporter connects in turn and says it's online, with no offline in between.
+    exporter.signal(libc::SIGUSR2);
+    eventually("the upgraded exporter to come online", || {
+        (broker.payloads("e2e/status").len() > 1).then_some(())
+    });
+    assert_eq!(broker.payloads("e2e/status"), ["online", "online"]);
+
+    // Whereas stopping it does say it's offline.
+    exporter.signal(libc::SIGTERM);
+    exporter.child.wait().unwrap();
+    assert_eq!(broker.payload("e2e/status").as_deref(), Some("offline"));
+}
+
+#[test]
+fn publish_filter_applies_to_other_outputs() {
+    let broker = Broker::start();
+    let signalk = UdpSocket::bind("127.0.0.1:0").unwrap();
+    signalk
+        .set_read_timeout(Some(Duration::from_millis(100)))
+        .unwrap();
+    let config = tempfile::NamedTempFile::new().unwrap();
+    fs::write(
+        config.path(),
+        format!(
+            "[publish]\nexclude = [\"*temperature*\"]\n\n[signalk]\ntarget = \"{}\"\n",
+            signalk.local_addr().unwrap()
+        ),
+    )
+    .unwrap();
+    let exporter = Exporter::start_with(&broker, &["--config", config.path().to_str().unwrap()]);
+    let observation = datagram("obs_st_fw156_rain_lightning.json");
+
+    // Signal K values are filtered by the MQTT topics of their readings.
+    let delta = eventually("an observation delta", || {
+        exporter.send(&observation);
+        let mut buf = [0; 65536];
+        let (len, _) = signalk.recv_from(&mut buf).ok()?;
+        let delta = String::from_utf8_lossy(&buf[..len]).into_owned();
+        delta.contains("relativeHumidity").then_some(delta)
+    });
+    assert!(delta.contains("environment.outside.dewPointTemperature"));
+    assert!(
+        !delta.contains("environment.outside.temperature"),
+        "{}",
+        delta
+    );
+    assert!(!delta.contains("apparentWindChillTemperature"), "{}", delta);
+}
+
+#[cfg(unix)]
+#[test]
+fn reloading_the_configuration_starts_and_stops_outputs() {
+    let broker = Broker::start();
+    let signalk = UdpSocket::bind("127.0.0.1:0").unwrap();
+    signalk
+        .set_read_timeout(Some(Duration::from_millis(100)))
+        .unwrap();
+    let config = tempfile::NamedTempFile::new().unwrap();
+    let exporter = Exporter::start_with(&broker, &["--config", config.path().to_str().unwrap()]);
+    let observation = datagram("obs_st_fw156_rain_lightning.json");
+    let delta = || {
+        let mut buf = [0; 65536];
+        signalk.recv_from(&mut buf).ok().map(|_| ())
+    };
+
+    fs::write(
+        config.path(),
+        format!(
+            "[signalk]\ntarget = \"{}\"\n",
+            signalk.local_addr().unwrap()
+        ),
+    )
+    .unwrap();
+    exporter.signal(libc::SIGHUP);
+    eventually("Signal K output to start", || {
+        exporter.send(&observation);
+        delta()
+    });
+
+    fs::write(config.path(), "").unwrap();
+    exporter.signal(libc::SIGHUP);
+    eventually("Signal K output to stop", || {
+        while delta().is_some() {}
+        exporter.send(&observation);
+        thread::sleep(Duration::from_millis(200));
+        delta().is_none().then_some(())
+    });
+}
+
+#[test]
+fn nmea_sentences_are_served_over_tcp() {
+    let broker = Broker::start();
+    let nmea_port = free_tcp_port();
+    let config = tempfile::NamedTempFile::new().unwrap();
+    fs::write(config.path(), format!("[nmea]\ntcp_port = {}\n", nmea_port)).unwrap();
+    let exporter = Exporter::start_with(&broker, &["--config", config.path().to_str().unwrap()]);
+    let observation = datagram("obs_st_fw156_rain_lightning.json");
+    let rapid_wind = datagram("rapid_wind_tempest.json");
+
+    let stream = eventually("the NMEA port to accept", || {
+        TcpStream::connect(("127.0.0.1", nmea_port)).ok()
+    });
+    stream
+        .set_read_timeout(Some(Duration::from_millis(100)))
+        .unwrap();
+    let mut lines = BufReader::new(stream);
+    let mut line = String::new();
+    let mut sentences = Vec::<String>::new();
+    let sentence = |sentences: &[String], kind: &str| {
+        sentences
+            .iter()
+            .find(|sentence| sentence.starts_with(kind))
+            .cloned()
+    };
+    eventually("MWV, MDA and XDR sentences", || {
+        exporter.send(&observation);
+        exporter.send(&rapid_wind);
+        // A line cut off by the timeout is finished on the next pass.
+        while lines.read_line(&mut line).is_ok() && line.ends_with('\n') {
+            sentences.push(line.clone());
+            line.clear();
+        }
+        ["$WIMWV", "$WIMDA", "$WIXDR"]
+            .iter()
+            .all(|kind| sentence(&sentences, kind).is_some())
+            .then_some(())
+    });
+    assert_eq!(
+        sentence(&sentences, "$WIMWV").unwrap(),
+        "$WIMWV,144.0,R,0.3,M,A*22\r\n"
+    );
+    assert_eq!(
+        sentence(&sentences, "$WIMDA").unwrap(),
+        "$WIMDA,29.99,I,1.0155,B,9.5,C,,C,94.3,,8.6,C,,T,,M,4.7,N,2.4,M*28\r\n"
+    );
+    assert_eq!(
+        sentence(&sentences, "$WIXDR").unwrap(),
+        "$WIXDR,P,1.0155,B,Barometer,C,9.5,C,AirTemp,H,94.3,P,Humidity*6C\r\n"
+    );
+}
+
+// Sends one Modbus TCP request, returning the PDU of the response.
+fn modbus_request(stream: &mut TcpStream, transaction: u16, pdu: &[u8]) -> Vec<u8> {
+    let mut frame = transaction.to_be_bytes().to_vec();
+    frame.extend([0, 0]);
+    frame.extend((pdu.len() as u16 + 1).to_be_bytes());
+    frame.push(1);
+    frame.extend(pdu);
+    stream.write_all(&frame).unwrap();
+    let mut header = [0; 7];
+    stream.read_exact(&mut header).unwrap();
+    assert_eq!(header[..4], frame[..4]);
+    assert_eq!(header[6], 1);
+    let mut response = vec![0; usize::from(u16::from_be_bytes([header[4], header[5]])) - 1];
+    stream.read_exact(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn modbus_registers_hold_scaled_values() {
+    let broker = Broker::start();
+    let modbus_port = free_tcp_port();
+    let config = tempfile::NamedTempFile::new().unwrap();
+    fs::write(
+        config.path(),
+        format!(
+            "[modbus]\nport = {}\n\n\
+             [[modbus.registers]]\naddress = 0\nvalue = \"temperature\"\nscale = 100\n\n\
+             [[modbus.registers]]\naddress = 1\nvalue = \"station_pressure\"\nscale = 100\n\
+             format = \"u32\"\n",
+            modbus_port
+        ),
+    )
+    .unwrap();
+    let exporter = Exporter::start_with(&broker, &["--config", config.path().to_str().unwrap()]);
+    let observation = datagram("obs_st_fw156_rain_lightning.json");
+    let mut stream = eventually("the Modbus port to accept", || {
+        TcpStream::connect(("127.0.0.1", modbus_port)).ok()
+    });
+    stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+    let read_all = [0x03, 0x00, 0x00, 0x00, 0x03];
+
+    // Before a report, signed registers hold their minimum and unsigned ones their maximum.
+    assert_eq!(
+        modbus_request(&mut stream, 1, &read_all),
+        [0x03, 6, 0x80, 0x00, 0xff, 0xff, 0xff, 0xff]
+    );
+
+    // 9.52 °C as hundredths, and 1003.28 hPa as hundredths high word first.
+    let response = eventually("the observation in registers", || {
+        exporter.send(&observation);
+        let response = modbus_request(&mut stream, 2, &read_all);
+        (response[2..4] != [0x80, 0x00]).then_some(response)
+    });
+    assert_eq!(response, [0x03, 6, 0x03, 0xb8, 0x00, 0x01, 0x87, 0xe8]);
+
+    // Past the mapped registers.
+    assert_eq!(
+        modbus_request(&mut stream, 3, &[0x03, 0x00, 0x02, 0x00, 0x02]),
+        [0x83, 0x02]
+    );
+    // Registers can't be written.
+    assert_eq!(
+        modbus_request(&mut stream, 4, &[0x06, 0x00, 0x00, 0x00, 0x01]),
+        [0x86, 0x01]
+    );
+}
+
+#[test]
+fn knx_group_writes_are_routed_as_dpt_9() {
+    let broker = Broker::start();
+    let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+    gateway
+        .set_read_timeout(Some(Duration::from_millis(100)))
+        .unwrap();
+    let config = tempfile::NamedTempFile::new().unwrap();
+    fs::write(
+        config.path(),
+        format!(
+            "[knx]\ngateway = \"{}\"\n\n\
+             [[knx.groups]]\naddress = \"1/2/4\"\nvalue = \"illuminance\"\n\n\
+             [[knx.groups]]\naddress = \"1/2/3\"\nvalue = \"temperature\"\n",
+            gateway.local_addr().unwrap()
+        ),
+    )
+    .unwrap();
+    let exporter = Exporter::start_with(&broker, &["--config", config.path().to_str().unwrap()]);
+    let fixture = datagram("obs_st_fw156_rain_lightning.json");
+    let observation = |temperature: &str, illuminance: &str| {
+        fixture
+            .replace(",9.52,", &format!(",{},", temperature))
+            .replace(",2207,", &format!(",{},", illuminance))
+    };
+    let mut frames = Vec::new();
+    // Sends `observation` until a write of `temperature` arrives, returning the frame.
+    let mut write = |observation: &str, temperature: [u8; 2]| {
+        eventually("a temperature group write", || {
+            exporter.send(observation);
+            let mut buf = [0; 512];
+            while let Ok((len, _)) = gateway.recv_from(&mut buf) {
+                frames.push(buf[..len].to_vec());
+            }
+            frames
+                .iter()
+                .find(|frame| frame[12..14] == [0x0a, 0x03] && frame[17..] == temperature)
+                .cloned()
+        })
+    };
+
+    // A routing indication of a group value write from 15.15.250 to 1/2/3.
+    assert_eq!(
+        write(&observation("21.5", "700000"), [0x0c, 0x33]),
+        [
+            0x06, 0x10, 0x05, 0x30, 0x00, 0x13, 0x29, 0x00, 0xbc, 0xe0, 0xff, 0xfa, 0x0a, 0x03,
+            0x03, 0x00, 0x80, 0x0c, 0x33
+        ]
+    );
+    write(&observation("0.0", "2207"), [0x00, 0x00]);
+    write(&observation("-1.0", "2207"), [0x87, 0x9c]);
+
+    // Illuminance beyond what DPT 9 holds is left out, not wrapped or clamped.
+    let illuminance: Vec<_> = frames
+        .iter()
+        .filter(|frame| frame[12..14] == [0x0a, 0x04])
+        .map(|frame| frame[17..].to_vec())
+        .collect();
+    assert!(!illuminance.is_empty());
+    assert!(
+        illuminance.iter().all(|data| data == &[0x3e, 0xbc]),
+        "{:?}",
+        illuminance
+    );
+}
+
+// A request recorded by `Uploads`: its method and the fields of its query string or form body.
+type Upload = (String, Vec<(String, String)>);
+
+// Just enough of an HTTP server to record the uploads made to it.
+struct Uploads {
+    port: u16,
+    received: Arc<Mutex<Vec<Upload>>>,
+}
+
+impl Uploads {
+    fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        thread::spawn({
+            let received = received.clone();
+            move || {
+                for stream in listener.incoming().flatten() {
+                    let received = received.clone();
+                    thread::spawn(move || Uploads::serve(stream, &received));
+                }
+            }
+        });
+        Uploads { port, received }
+    }
+
+    fn serve(stream: TcpStream, received: &Mutex<Vec<Upload>>) -> io::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        loop {
+            let mut request_line = String::new();
+            if reader.read_line(&mut request_line)? == 0 {
+                return Ok(());
+            }
+            let mut length = 0;
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header)?;
+                let header = header.trim_end();
+                if header.is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = header.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body)?;
+            let mut parts = request_line.split(' ');
+            let method = parts.next().unwrap_or_default().to_string();
+            let query = parts
+                .next()
+                .and_then(|target| target.split_once('?'))
+                .map_or("", |(_, query)| query);
+            let encoded = match method.as_str() {
+                "GET" => query.to_string(),
+                _ => String::from_utf8_lossy(&body).into_owned(),
+            };
+            received
+                .lock()
+                .unwrap()
+                .push((method, form_fields(&encoded)));
+            (&stream).write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")?;
+        }
+    }
+
+    // The upload of the observation taken at `dateutc`, if it has arrived.
+    fn upload(&self, dateutc: &str) -> Option<Upload> {
+        self.received
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(_, fields)| field(fields, "dateutc") == Some(dateutc))
+            .cloned()
+    }
+}
+
+// Decodes `application/x-www-form-urlencoded` fields.
+fn form_fields(encoded: &str) -> Vec<(String, String)> {
+    let decode = |s: &str| {
+        let s = s.replace('+', " ");
+        let mut bytes = Vec::new();
+        let mut rest = s.as_bytes();
+        while let Some((&b, tail)) = rest.split_first() {
+            if b == b'%' && tail.len() >= 2 {
+                let hex = std::str::from_utf8(&tail[..2]).unwrap();
+                bytes.push(u8::from_str_radix(hex, 16).unwrap());
+                rest = &tail[2..];
+            } else {
+                bytes.push(b);
+                rest = tail;
+            }
+        }
+        String::from_utf8(bytes).unwrap()
+    };
+    encoded
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode(name), decode(value))
+        })
+        .collect()
+}
+
+fn field<'a>(fields: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    fields
+        .iter()
+        .find(|(field, _)| field == name)
+        .map(|(_, value)| value.as_str())
+}
+
+// Runs an exporter uploading to a local server with `upload_config` for its `[ecowitt]` table,
+// and sends it an observation with 0.31 mm of rain at 00:26 New York time, another at 23:59 the
+// same day, and a third at 00:01 the next. Returns the uploads of each.
+fn ecowitt_uploads(upload_config: &str) -> [Upload; 3] {
+    let broker = Broker::start();
+    let uploads = Uploads::start();
+    let config = tempfile::NamedTempFile::new().unwrap();
+    fs::write(
+        config.path(),
+        format!(
+            "[ecowitt]\nurl = \"http://127.0.0.1:{}/data/report/\"\n{}",
+            uploads.port, upload_config
+        ),
+    )
+    .unwrap();
+    let exporter = Exporter::start_with(
+        &broker,
+        &[
+            "--config",
+            config.path().to_str().unwrap(),
+            "--station-timezone",
+            "America/New_York",
+        ],
+    );
+    let fixture = datagram("obs_st_fw156_rain_lightning.json");
+    let observation = |timestamp: &str, rain: &str| {
+        fixture
+            .replace("1635567982", timestamp)
+            .replace(",0.310000,", &format!(",{},", rain))
+    };
+
+    // Dry until the exporter is listening, so that the day's rain starts from the next.
+    eventually("a first upload", || {
+        exporter.send(&observation("1635567922", "0"));
+        uploads.upload("2021-10-30 04:25:22")
+    });
+    [
+        ("1635567982", "2021-10-30 04:26:22"),
+        ("1635652740", "2021-10-31 03:59:00"),
+        ("1635652860", "2021-10-31 04:01:00"),
+    ]
+    .map(|(timestamp, dateutc)| {
+        exporter.send(&observation(timestamp, "0.31"));
+        eventually("an upload", || uploads.upload(dateutc))
+    })
+}
+
+#[test]
+fn ecowitt_uploads_are_posted_in_imperial_units() {
+    let [first, same_day, next_day] = ecowitt_uploads("");
+    let stationtype = format!("tempest-exporter_{}", env!("CARGO_PKG_VERSION"));
+    let expected = [
+        ("PASSKEY", "ST-00028405"),
+        ("stationtype", stationtype.as_str()),
+        ("dateutc", "2021-10-30 04:26:22"),
+        ("tempf", "49.1"),
+        ("humidity", "94"),
+        ("baromrelin", "29.987"),
+        ("baromabsin", "29.627"),
+        ("winddir", "271"),
+        ("windspeedmph", "5.4"),
+        ("windgustmph", "8.7"),
+        ("solarradiation", "18.0"),
+        ("uv", "0"),
+        ("rainratein", "0.732"),
+        ("dailyrainin", "0.012"),
+    ]
+    .map(|(name, value)| (name.to_string(), value.to_string()));
+    assert_eq!(first, ("POST".to_string(), expected.to_vec()));
+
+    // Rain adds up over the local day, and starts over at local midnight.
+    assert_eq!(field(&same_day.1, "dailyrainin"), Some("0.024"));
+    assert_eq!(field(&next_day.1, "dailyrainin"), Some("0.012"));
+}
+
+#[test]
+fn ambient_uploads_are_sent_as_queries() {
+    let [first, same_day, next_day] =
+        ecowitt_uploads("protocol = \"ambient\"\nstation_key = \"00:11:22:33:44:55\"\n");
+    let expected = [
+        ("MAC", "00:11:22:33:44:55"),
+        ("dateutc", "2021-10-30 04:26:22"),
+        ("tempf", "49.1"),
+        ("humidity", "94"),
+        ("baromrelin", "29.987"),
+        ("baromabsin", "29.627"),
+        ("winddir", "271"),
+        ("windspeedmph", "5.4"),
+        ("windgustmph", "8.7"),
+        ("solarradiation", "18.0"),
+        ("uv", "0"),
+        ("hourlyrainin", "0.732"),
+        ("dailyrainin", "0.012"),
+    ]
+    .map(|(name, value)| (name.to_string(), value.to_string()));
+    assert_eq!(first, ("GET".to_string(), expected.to_vec()));
+
+    assert_eq!(field(&same_day.1, "dailyrainin"), Some("0.024"));
+    assert_eq!(field(&next_day.1, "dailyrainin"), Some("0.012"));
+}
+
+#[test]
+fn domoticz_devices_are_updated_with_combined_values() {
+    let broker = Broker::start();
+    let config = tempfile::NamedTempFile::new().unwrap();
+    fs::write(
+        config.path(),
+        "[domoticz.devices]\ntemp_hum_baro = 7\nwind = 8\n",
+    )
+    .unwrap();
+    let exporter = Exporter::start_with(&broker, &["--config", config.path().to_str().unwrap()]);
+    let observation = datagram("obs_st_fw156_rain_lightning.json");
+
+    let updates = eventually("the Domoticz device updates", || {
+        exporter.send(&observation);
+        let updates = broker.payloads("domoticz/in");
+        (updates.len() >= 2).then_some(updates)
+    });
+    // Temperature, humidity, its status (wet), barometric pressure and no forecast.
+    assert_eq!(
+        updates[0],
+        r#"{"idx":7,"nvalue":0,"svalue":"9.5;94;3;1015.5;0"}"#
+    );
+    // Bearing, compass point, average and gust speeds in tenths of a m/s, temperature and wind
+    // chill.
+    assert_eq!(
+        updates[1],
+        r#"{"idx":8,"nvalue":0,"svalue":"271;W;24;39;9.5;8.3"}"#
+    );
+}
+
+#[test]
+fn failed_ambient_uploads_keep_the_station_key_out_of_errors() {
+    let broker = Broker::start();
+    let config = tempfile::NamedTempFile::new().unwrap();
+    // Nothing listens at the upload URL.
+    fs::write(
+        config.path(),
+        format!(
+            "[ecowitt]\nurl = \"http://127.0.0.1:{}/data/report/\"\nprotocol = \"ambient\"\n\
+             station_key = \"00:11:22:33:44:55\"\n",
+            free_tcp_port()
+        ),
+    )
+    .unwrap();
+    let exporter = Exporter::start_with(
+        &broker,
+        &[
+            "--config",
+            config.path().to_str().unwrap(),
+            "--debug-token",
+            "debug",
+        ],
+    );
+    let observation = datagram("obs_st_fw156_rain_lightning.json");
+
+    let error = eventually("a failed upload", || {
+        exporter.send(&observation);
+        let (_, body) = exporter.request("/debug/state", "Authorization: Bearer debug\r\n")?;
+        let state: serde_json::Value = serde_json::from_slice(&body).ok()?;
+        state["sinks"]
+            .as_array()?
+            .iter()
+            .find(|sink| sink["name"] == "ecowitt")?["last_error"]["message"]
+            .as_str()
+            .map(str::to_string)
+    });
+    assert!(error.starts_with("Upload failed"), "{}", error);
+    assert!(!error.contains("00:11:22:33:44:55"), "{}", error);
+    assert!(!error.contains("00%3A11%3A22%3A33%3A44%3A55"), "{}", error);
+}
+
+#[test]
+fn the_debug_api_only_accepts_the_whole_token() {
+    let broker = Broker::start();
+    let exporter = Exporter::start_with(&broker, &["--debug-token", "debug"]);
+
+    eventually("the debug API", || {
+        exporter.request("/debug/state", "Authorization: Bearer debug\r\n")
+    });
+    for token in ["", "debu", "debugs", "bebug"] {
+        let header = format!("Authorization: Bearer {}\r\n", token);
+        assert!(
+            exporter.request("/debug/state", &header).is_none(),
+            "{:?}",
+            token
+        );
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn the_domoticz_rain_counter_carries_on_across_a_restart() {
+    let broker = Broker::start();
+    let state_dir = tempfile::tempdir().unwrap();
+    let state_file = state_dir.path().join("state.json");
+    let config = tempfile::NamedTempFile::new().unwrap();
+    fs::write(config.path(), "[domoticz.devices]\nrain = 9\n").unwrap();
+    let args = [
+        "--config",
+        config.path().to_str().unwrap(),
+        "--state-file",
+        state_file.to_str().unwrap(),
+    ];
+    let fixture = datagram("obs_st_fw156_rain_lightning.json");
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    // Recent, so that the latest is replayed after the restart.
+    let observation = |ago: u64| fixture.replace("1635567982", &(now - ago).to_string());
+    let counters = || -> Vec<f64> {
+        broker
+            .payloads("domoticz/in")
+            .iter()
+            .filter_map(|payload| {
+                let update: serde_json::Value = serde_json::from_str(payload).ok()?;
+                update["svalue"].as_str()?.split(';').nth(1)?.parse().ok()
+            })
+            .collect()
+    };
+    // Sends `observation` until the counter reaches `total`.
+    let count = |exporter: &Exporter, observation: &str, total: f64| {
+        eventually("the rain counter to go up", || {
+            exporter.send(observation);
+            counters().contains(&total).then_some(())
+        })
+    };
+
+    let mut exporter = Exporter::start_with(&broker, &args);
+    count(&exporter, &observation(120), 0.3);
+    count(&exporter, &observation(60), 0.6);
+    exporter.signal(libc::SIGTERM);
+    exporter.child.wait().unwrap();
+
+    let exporter = Exporter::start_with(&broker, &args);
+    count(&exporter, &observation(0), 0.9);
+    let counters = counters();
+    assert!(
+        counters.windows(2).all(|pair| pair[0] <= pair[1]),
+        "{:?}",
+        counters
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn modbus_clients_are_dropped_when_modbus_is_disabled() {
+    let broker = Broker::start();
+    let modbus_port = free_tcp_port();
+    let config = tempfile::NamedTempFile::new().unwrap();
+    fs::write(
+        config.path(),
+        format!(
+            "[modbus]\nport = {}\n\n[[modbus.registers]]\naddress = 0\nvalue = \"temperature\"\n",
+            modbus_port
+        ),
+    )
+    .unwrap();
+    let exporter = Exporter::start_with(&broker, &["--config", config.path().to_str().unwrap()]);
+    let mut stream = eventually("the Modbus port to accept", || {
+        TcpStream::connect(("127.0.0.1", modbus_port)).ok()
+    });
+    stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+    modbus_request(&mut stream, 1, &[0x03, 0x00, 0x00, 0x00, 0x01]);
+
+    fs::write(config.path(), "").unwrap();
+    exporter.signal(libc::SIGHUP);
+    let mut rest = Vec::new();
+    assert_eq!(stream.read_to_end(&mut rest).unwrap(), 0);
+}
@@ -0,0 +1,153 @@
+// Notable device and hub events are logged from changes between status reports.
+
+use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
+
+use serde_json::json;
+use tempest_exporter::decoder::TempestMsg;
+use tempest_exporter::events::{EventKind, CAPACITY};
+use tempest_exporter::exporter::Exporter;
+use tempest_exporter::reader;
+
+mod common;
+
+fn exporter() -> Exporter {
+    common::exporter()
+}
+
+// A fixture report with some of its fields replaced.
+fn report(fixture: &str, replacements: &[(&str, String)]) -> TempestMsg {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures/decode")
+        .join(fixture);
+    let mut json = fs::read_to_string(path).unwrap();
+    for (from, to) in replacements {
+        json = json.replace(from, to);
+    }
+    let raw = reader::parse(&json).unwrap();
+    TempestMsg::try_from(raw).map_err(|(_, e)| e).unwrap()
+}
+
+// The fixture's status has only the lightning disturber flag set; pressure failure is bit 3.
+fn device_status(uptime: i64, firmware: i32, pressure_failed: bool) -> TempestMsg {
+    let flags = 0xa0004 | (pressure_failed as u32) << 3;
+    report(
+        "device_status_tempest_fw156.json",
+        &[
+            ("\"uptime\":2862471", format!("\"uptime\":{}", uptime)),
+            (
+                "\"firmware_revision\":156",
+                format!("\"firmware_revision\":{}", firmware),
+            ),
+            (
+                "\"sensor_status\":655364",
+                format!("\"sensor_status\":{}", flags),
+            ),
+        ],
+    )
+}
+
+fn hub_status(seq: i32) -> TempestMsg {
+    report(
+        "hub_status_fw171_all_reset_flags.json",
+        &[
+            ("\"seq\":286211", format!("\"seq\":{}", seq)),
+            ("BOR,PIN,POR,SFT,WDG,WWD,LPW,HRDFLT", "WDG".to_string()),
+        ],
+    )
+}
+
+fn kinds(exporter: &Exporter) -> Vec<&'static str> {
+    exporter
+        .events()
+        .iter()
+        .map(|event| event.kind.name())
+        .collect()
+}
+
+#[test]
+fn status_changes_are_logged() {
+    let exporter = exporter();
+    exporter.handle_report(&device_status(1000, 156, false));
+    exporter.handle_report(&hub_status(100));
+    assert!(exporter.events().is_empty());
+
+    exporter.handle_report(&device_status(1060, 156, true));
+    exporter.handle_report(&device_status(1120, 171, true));
+    exporter.handle_report(&device_status(10, 171, false));
+    exporter.handle_report(&hub_status(1));
+    assert_eq!(
+        kinds(&exporter),
+        [
+            "sensor_failed",
+            "firmware_changed",
+            "device_restarted",
+            "sensor_recovered",
+            "hub_rebooted",
+        ]
+    );
+    let events = exporter.events();
+    assert_eq!(
+        events[1].kind,
+        EventKind::FirmwareChanged {
+            from: "156".to_string(),
+            to: "171".to_string(),
+        }
+    );
+    assert_eq!(
+        serde_json::to_value(&events[0]).unwrap(),
+        json!({
+            "at": "2021-10-30T04:26:22Z",
+            "serial_number": "ST-00028405",
+            "event": "sensor_failed",
+            "sensor": "pressure",
+        })
+    );
+    assert_eq!(
+        serde_json::to_value(&events[4]).unwrap()["reason"],
+        json!("watchdog")
+    );
+}
+
+#[test]
+fn events_are_counted() {
+    let exporter = exporter();
+    exporter.handle_report(&device_status(1000, 156, true));
+    exporter.handle_report(&device_status(1060, 156, false));
+    exporter.handle_report(&device_status(1120, 156, true));
+    let exposition = String::from_utf8(exporter.encode()).unwrap();
+    assert!(exposition.contains(
+        "tempest_station_events_total{event=\"sensor_failed\",serial_number=\"ST-00028405\"} 2\n"
+    ));
+    assert!(exposition.contains(
+        "tempest_station_events_total{event=\"sensor_recovered\",serial_number=\"ST-00028405\"} 1\n"
+    ));
+}
+
+#[test]
+fn only_the_latest_events_are_kept() {
+    let exporter = exporter();
+    for firmware in 0..CAPACITY as i32 + 10 {
+        exporter.handle_report(&device_status(1000 + firmware as i64, firmware, false));
+    }
+    let events = exporter.events();
+    assert_eq!(events.len(), CAPACITY);
+    assert_eq!(
+        events.last().unwrap().kind,
+        EventKind::FirmwareChanged {
+            from: format!("{}", CAPACITY + 8),
+            to: format!("{}", CAPACITY + 9),
+        }
+    );
+}
+
+#[test]
+fn restored_status_is_the_baseline() {
+    let exporter = exporter();
+    exporter.restore_report(&device_status(1000, 156, true));
+    exporter.handle_report(&device_status(1060, 156, true));
+    assert_eq!(kinds(&exporter), ["sensor_failed"]);
+    let exposition = String::from_utf8(exporter.encode()).unwrap();
+    assert!(!exposition.contains("tempest_station_events_total{"));
+}
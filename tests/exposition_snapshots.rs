@@ -0,0 +1,164 @@
+// Snapshot tests of the `/metrics` exposition: fixture datagrams are fed through an `Exporter`
+// and the rendered text must match `fixtures/exposition/NAME.prom` (or `NAME.openmetrics` for the
+// OpenMetrics format). Run with `UPDATE_GOLDEN=1` to rewrite the snapshots after an intentional
+// metric change, and review the diff.
+
+use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
+
+use tempest_exporter::decoder::TempestMsg;
+use tempest_exporter::exporter::Exporter;
+use tempest_exporter::params::{StationMetadata, StationParams};
+use tempest_exporter::reader;
+
+mod common;
+
+// Metrics whose values depend on when the test runs rather than on the fixtures.
+const TIME_DEPENDENT_METRICS: &[&str] = &[
+    "tempest_exporter_message_latency_sec_sum",
+    "tempest_station_instant_wind_age_sec",
+    "tempest_station_observation_humidity_age_sec",
+    "tempest_station_observation_precip_age_sec",
+    "tempest_station_observation_pressure_age_sec",
+    "tempest_station_observation_solar_age_sec",
+    "tempest_station_observation_temperature_age_sec",
+    "tempest_station_observation_wind_age_sec",
+    "tempest_station_time_since_rain_sec",
+    "tempest_station_time_since_strike_sec",
+];
+
+fn exporter() -> Exporter {
+    exporter_with_metadata(StationMetadata::default())
+}
+
+fn exporter_with_metadata(metadata: StationMetadata) -> Exporter {
+    common::exporter_with(
+        StationParams {
+            metadata,
+            ..common::station_params()
+        },
+        common::exporter_params(),
+    )
+}
+
+fn feed(exporter: &Exporter, fixture: &str) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures/decode")
+        .join(fixture);
+    let raw = reader::parse(&fs::read_to_string(path).unwrap()).unwrap();
+    let msg = TempestMsg::try_from(raw).map_err(|(_, e)| e).unwrap();
+    exporter.handle_report(&msg);
+}
+
+// Time-dependent values are masked, and the rest rounded so that last-digit differences in
+// floating point math between platforms don't fail the comparison.
+fn normalize(exposition: &str) -> String {
+    exposition
+        .lines()
+        .map(|line| {
+            if line.starts_with('#') {
+                return format!("{}\n", line);
+            }
+            let (sample, exemplar) = match line.split_once(" # ") {
+                Some((sample, exemplar)) => (sample, format!(" # {}", exemplar)),
+                None => (line, String::new()),
+            };
+            let (series, value) = sample.rsplit_once(' ').unwrap();
+            let name = series.split('{').next().unwrap();
+            if TIME_DEPENDENT_METRICS.contains(&name) {
+                format!("{} <normalized>{}\n", series, exemplar)
+            } else {
+                let value: f64 = value.parse().unwrap();
+                format!("{} {}{}\n", series, (value * 1e10).round() / 1e10, exemplar)
+            }
+        })
+        .collect()
+}
+
+fn assert_snapshot(file_name: &str, exposition: Vec<u8>) {
+    let actual = normalize(&String::from_utf8(exposition).unwrap());
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures/exposition")
+        .join(file_name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, &actual).unwrap();
+        return;
+    }
+    let expected =
+        fs::read_to_string(&path).unwrap_or_else(|_| panic!("No snapshot at {}", path.display()));
+    if actual != expected {
+        let diff: Vec<String> = expected
+            .lines()
+            .filter(|line| !actual.lines().any(|a| a == *line))
+            .map(|line| format!("- {}", line))
+            .chain(
+                actual
+                    .lines()
+                    .filter(|line| !expected.lines().any(|e| e == *line))
+                    .map(|line| format!("+ {}", line)),
+            )
+            .collect();
+        panic!(
+            "Exposition differs from {}:\n{}",
+            path.display(),
+            diff.join("\n")
+        );
+    }
+}
+
+#[test]
+fn no_reports() {
+    assert_snapshot("no_reports.prom", exporter().encode());
+}
+
+fn full_station_exporter() -> Exporter {
+    let exporter = exporter();
+    for fixture in [
+        "obs_st_fw156_rain_lightning.json",
+        "rapid_wind_tempest.json",
+        "evt_strike_tempest.json",
+        "evt_precip_sky.json",
+        "device_status_tempest_fw156.json",
+        "hub_status_fw171_all_reset_flags.json",
+    ] {
+        feed(&exporter, fixture);
+    }
+    exporter
+}
+
+#[test]
+fn full_station() {
+    assert_snapshot("full_station.prom", full_station_exporter().encode());
+}
+
+#[test]
+fn full_station_openmetrics() {
+    assert_snapshot(
+        "full_station.openmetrics",
+        full_station_exporter().encode_openmetrics(),
+    );
+}
+
+#[test]
+fn failed_sensors() {
+    let exporter = exporter();
+    for fixture in [
+        "obs_st_fw171_sensor_failure.json",
+        "device_status_tempest_fw171_failures.json",
+    ] {
+        feed(&exporter, fixture);
+    }
+    assert_snapshot("failed_sensors.prom", exporter.encode());
+}
+
+#[test]
+fn station_metadata() {
+    let exporter = exporter_with_metadata(StationMetadata {
+        name: Some("Rooftop".to_string()),
+        latitude: Some(51.4779),
+        longitude: Some(-0.0015),
+        timezone: Some(chrono_tz::Europe::London),
+    });
+    assert_snapshot("station_metadata.prom", exporter.encode());
+}
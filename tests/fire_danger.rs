@@ -0,0 +1,47 @@
+// Fire danger index, and the drought factor estimated from recent rain.
+
+use chrono::{Duration, TimeZone, Utc};
+use tempest_exporter::fire::{self, FireDanger};
+use tempest_exporter::units::{Celsius, MetersPerSecond};
+
+#[test]
+fn index_matches_mcarthur_formula() {
+    // A hot, dry, windy day with fully dry fuel: severe.
+    let index = fire::ffdi(Celsius(35.0), 10.0, MetersPerSecond(40.0 / 3.6), 10.0);
+    assert!((index - 72.95).abs() < 0.01, "{}", index);
+    let index = fire::ffdi(Celsius(20.0), 60.0, MetersPerSecond(3.0), 5.0);
+    assert!((index - 1.99).abs() < 0.01, "{}", index);
+}
+
+#[test]
+fn fuel_is_dry_without_rain() {
+    let start = Utc.timestamp_opt(1_600_000_000, 0).unwrap();
+    let mut fire_danger = FireDanger::new();
+    assert_eq!(fire_danger.drought_factor(), 10.0);
+    fire_danger.push(start, 0.0);
+    // Under the 2 mm a rain event needs.
+    fire_danger.push(start + Duration::minutes(1), 1.5);
+    assert_eq!(fire_danger.drought_factor(), 10.0);
+}
+
+#[test]
+fn recent_heavy_rain_wets_the_fuel_until_it_dries_out() {
+    let start = Utc.timestamp_opt(1_600_000_000, 0).unwrap();
+    let mut fire_danger = FireDanger::new();
+    for minute in 0..30 {
+        fire_danger.push(start + Duration::minutes(minute), 1.0);
+    }
+    let fresh = fire_danger.drought_factor();
+    assert!((fresh - 0.511).abs() < 0.001, "{}", fresh);
+
+    fire_danger.push(start + Duration::days(1) + Duration::minutes(30), 0.0);
+    let day_old = fire_danger.drought_factor();
+    assert!((day_old - 0.769).abs() < 0.001, "{}", day_old);
+
+    fire_danger.push(start + Duration::days(10) + Duration::minutes(30), 0.0);
+    let older = fire_danger.drought_factor();
+    assert!((older - 9.009).abs() < 0.001, "{}", older);
+
+    fire_danger.push(start + Duration::days(21), 0.0);
+    assert_eq!(fire_danger.drought_factor(), 10.0);
+}
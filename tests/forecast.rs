@@ -0,0 +1,136 @@
+// Zambretti forecasts from pressure, its tendency, wind direction and season.
+
+use std::convert::TryFrom;
+
+use chrono::Utc;
+use serde_json::json;
+use tempest_exporter::decoder::TempestMsg;
+use tempest_exporter::forecast::{zambretti, Forecast, PressureTendency};
+use tempest_exporter::reader;
+use tempest_exporter::units::HectoPascal;
+
+mod common;
+
+#[test]
+fn tendency_is_judged_against_a_threshold() {
+    assert_eq!(
+        PressureTendency::from_change(-1.6),
+        PressureTendency::Falling
+    );
+    assert_eq!(
+        PressureTendency::from_change(-1.5),
+        PressureTendency::Steady
+    );
+    assert_eq!(PressureTendency::from_change(1.5), PressureTendency::Steady);
+    assert_eq!(PressureTendency::from_change(2.0), PressureTendency::Rising);
+}
+
+#[test]
+fn high_steady_pressure_is_settled_and_low_falling_pressure_stormy() {
+    assert_eq!(
+        zambretti(
+            HectoPascal(1035.0),
+            PressureTendency::Steady,
+            None,
+            1,
+            false
+        ),
+        Forecast {
+            code: 'A',
+            text: "Settled fine",
+        }
+    );
+    assert_eq!(
+        zambretti(
+            HectoPascal(985.0),
+            PressureTendency::Falling,
+            None,
+            1,
+            false
+        ),
+        Forecast {
+            code: 'Z',
+            text: "Stormy, much rain",
+        }
+    );
+    assert_eq!(
+        zambretti(
+            HectoPascal(1000.0),
+            PressureTendency::Rising,
+            None,
+            1,
+            false
+        ),
+        Forecast {
+            code: 'I',
+            text: "Showery early, improving",
+        }
+    );
+}
+
+#[test]
+fn southerly_wind_worsens_the_forecast_in_the_north() {
+    let northerly = zambretti(
+        HectoPascal(1013.0),
+        PressureTendency::Steady,
+        Some(0.0),
+        1,
+        false,
+    );
+    let southerly = zambretti(
+        HectoPascal(1013.0),
+        PressureTendency::Steady,
+        Some(180.0),
+        1,
+        false,
+    );
+    assert_eq!(northerly.code, 'E');
+    assert_eq!(southerly.code, 'N');
+}
+
+#[test]
+fn southern_hemisphere_mirrors_wind_and_season() {
+    for pressure in [990.0, 1005.0, 1020.0] {
+        for tendency in [
+            PressureTendency::Falling,
+            PressureTendency::Steady,
+            PressureTendency::Rising,
+        ] {
+            assert_eq!(
+                zambretti(HectoPascal(pressure), tendency, Some(10.0), 1, true),
+                zambretti(HectoPascal(pressure), tendency, Some(190.0), 7, false),
+            );
+        }
+    }
+}
+
+#[test]
+fn forecast_is_exported_once_pressure_has_a_trend() {
+    let exporter = common::exporter();
+    let now = Utc::now().timestamp();
+    let forecasts = |pressure: f64, minutes_ago: i64| {
+        let datagram = json!({
+            "serial_number": "ST-00000001",
+            "type": "obs_st",
+            "hub_sn": "HB-00000001",
+            "obs": [[now - minutes_ago * 60, 1.0, 2.0, 3.0, 180, 3, pressure, 10.0, 80.0, 0, 0.0, 0, 0.0, 0, 0, 0, 2.6, 1]],
+            "firmware_revision": 156,
+        });
+        let raw = reader::parse(&datagram.to_string()).unwrap();
+        exporter.handle_report(&TempestMsg::try_from(raw).map_err(|(_, e)| e).unwrap());
+        String::from_utf8(exporter.encode())
+            .unwrap()
+            .lines()
+            .filter(|line| line.starts_with("tempest_station_observation_forecast{"))
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    };
+    assert!(forecasts(1010.0, 120).is_empty());
+    assert!(forecasts(1009.0, 60).is_empty());
+    assert_eq!(forecasts(1008.0, 30).len(), 1);
+    // A different forecast replaces the previous one.
+    let latest = forecasts(970.0, 0);
+    assert_eq!(latest.len(), 1);
+    assert!(latest[0].ends_with(" 1"));
+    assert!(latest[0].contains("code=\"Z\""), "{}", latest[0]);
+}
@@ -0,0 +1,60 @@
+// Grass temperature estimated from radiative cooling, and its overnight minimum.
+
+use std::convert::TryFrom;
+
+use chrono::Utc;
+use serde_json::json;
+use tempest_exporter::decoder::TempestMsg;
+use tempest_exporter::exporter::Exporter;
+use tempest_exporter::reader;
+
+mod common;
+
+fn exporter() -> Exporter {
+    common::exporter()
+}
+
+// Feeds calm, dry observations a minute apart, as (air temperature, solar irradiance).
+fn observe(exporter: &Exporter, readings: &[(f64, f64)]) -> String {
+    let start = Utc::now().timestamp() - readings.len() as i64 * 60;
+    for (i, (temperature, irradiance)) in readings.iter().enumerate() {
+        let datagram = json!({
+            "serial_number": "ST-00000001",
+            "type": "obs_st",
+            "hub_sn": "HB-00000001",
+            "obs": [[start + i as i64 * 60, 0.5, 1.0, 1.5, 180, 3, 1010.0, temperature, 50.0, 0, 0.0, irradiance, 0.0, 0, 0, 0, 2.6, 1]],
+            "firmware_revision": 156,
+        });
+        let raw = reader::parse(&datagram.to_string()).unwrap();
+        exporter.handle_report(&TempestMsg::try_from(raw).map_err(|(_, e)| e).unwrap());
+    }
+    String::from_utf8(exporter.encode()).unwrap()
+}
+
+#[test]
+fn night_minimum_is_kept_through_the_day() {
+    let exporter = exporter();
+    let exposition = observe(
+        &exporter,
+        &[
+            (15.0, 400.0),
+            (5.0, 0.0),
+            (3.0, 0.0),
+            (6.0, 0.0),
+            (8.0, 200.0),
+        ],
+    );
+    assert!(exposition.contains("tempest_station_observation_grass_temperature_deg_c 8\n"));
+    assert!(exposition.contains("tempest_station_observation_grass_temperature_min_deg_c -1\n"));
+}
+
+#[test]
+fn minimum_starts_over_at_nightfall() {
+    let exporter = exporter();
+    let exposition = observe(
+        &exporter,
+        &[(3.0, 0.0), (12.0, 300.0), (10.0, 0.0), (9.0, 0.0)],
+    );
+    assert!(exposition.contains("tempest_station_observation_grass_temperature_deg_c 5\n"));
+    assert!(exposition.contains("tempest_station_observation_grass_temperature_min_deg_c 5\n"));
+}
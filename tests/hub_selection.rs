@@ -0,0 +1,115 @@
+// Choosing between copies of a station's reports relayed by more than one hub.
+
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use serde_json::json;
+use tempest_exporter::clock::{Clock, ManualClock};
+use tempest_exporter::hubs::{HubPreference, HubSelector, FAILOVER};
+use tempest_exporter::reader::{self, RawTempestMsg};
+
+fn status(hub: &str, hub_rssi: f64) -> RawTempestMsg {
+    let datagram = json!({
+        "serial_number": "ST-00000001",
+        "type": "device_status",
+        "hub_sn": hub,
+        "timestamp": 1635567982,
+        "uptime": 1000,
+        "voltage": 2.6,
+        "firmware_revision": 171,
+        "rssi": -60,
+        "hub_rssi": hub_rssi,
+        "sensor_status": 0,
+        "debug": 0,
+    });
+    reader::parse(&datagram.to_string()).unwrap()
+}
+
+fn wind(hub: &str) -> RawTempestMsg {
+    let datagram = json!({
+        "serial_number": "ST-00000001",
+        "type": "rapid_wind",
+        "hub_sn": hub,
+        "ob": [1635567982, 2.3, 128],
+    });
+    reader::parse(&datagram.to_string()).unwrap()
+}
+
+#[test]
+fn a_single_hub_is_always_used() {
+    let mut selector = HubSelector::new(HubPreference::StrongestSignal);
+    let now = Instant::now();
+    assert!(selector.select(&wind("HB-A"), now));
+    assert!(selector.select(&status("HB-A", -90.0), now));
+    assert!(selector.select(&wind("HB-A"), now));
+}
+
+#[test]
+fn the_hub_hearing_the_station_strongest_is_preferred() {
+    let mut selector = HubSelector::new(HubPreference::StrongestSignal);
+    let now = Instant::now();
+    assert!(selector.select(&status("HB-A", -80.0), now));
+    assert!(!selector.select(&status("HB-B", -80.0), now));
+    // Equally strong, so the first hub is kept.
+    assert!(selector.select(&wind("HB-A"), now));
+    assert!(!selector.select(&wind("HB-B"), now));
+
+    assert!(selector.select(&status("HB-B", -60.0), now));
+    assert!(!selector.select(&status("HB-A", -80.0), now));
+    assert!(selector.select(&wind("HB-B"), now));
+    assert!(!selector.select(&wind("HB-A"), now));
+}
+
+#[test]
+fn the_primary_hub_is_preferred_while_it_relays() {
+    let mut selector = HubSelector::new(HubPreference::Primary("HB-B".into()));
+    let start = Instant::now();
+    // Until the primary is heard from, the other hub is used.
+    assert!(selector.select(&status("HB-A", -50.0), start));
+    assert!(selector.select(&status("HB-B", -90.0), start));
+    assert!(!selector.select(&status("HB-A", -50.0), start));
+    assert!(selector.select(&wind("HB-B"), start));
+    assert!(!selector.select(&wind("HB-A"), start));
+
+    // The primary falls silent.
+    let later = start + FAILOVER + Duration::from_secs(1);
+    assert!(selector.select(&wind("HB-A"), later));
+    assert!(selector.select(&wind("HB-B"), later));
+    assert!(!selector.select(&wind("HB-A"), later));
+}
+
+#[test]
+fn the_stronger_hub_is_failed_over_from_when_it_falls_silent() {
+    let clock = ManualClock::new(Utc::now());
+    let mut selector = HubSelector::new(HubPreference::StrongestSignal);
+    assert!(selector.select(&status("HB-A", -60.0), clock.now()));
+    assert!(!selector.select(&status("HB-B", -80.0), clock.now()));
+
+    // HB-A is only failed over from once it has been silent for FAILOVER.
+    clock.advance(FAILOVER - Duration::from_secs(1));
+    assert!(!selector.select(&wind("HB-B"), clock.now()));
+    clock.advance(Duration::from_secs(1));
+    assert!(selector.select(&wind("HB-B"), clock.now()));
+
+    // Once heard from again, HB-A is stronger and used again.
+    assert!(selector.select(&status("HB-A", -60.0), clock.now()));
+    assert!(!selector.select(&wind("HB-B"), clock.now()));
+}
+
+#[test]
+fn hub_status_is_always_used() {
+    let mut selector = HubSelector::new(HubPreference::Primary("HB-B".into()));
+    let datagram = json!({
+        "serial_number": "HB-A",
+        "type": "hub_status",
+        "firmware_revision": "171",
+        "uptime": 1000,
+        "rssi": -60,
+        "timestamp": 1635567982,
+        "reset_flags": "BOR,PIN,POR",
+        "seq": 1,
+        "radio_stats": [25, 1, 0, 3, 2839],
+    });
+    let raw = reader::parse(&datagram.to_string()).unwrap();
+    assert!(selector.select(&raw, Instant::now()));
+}
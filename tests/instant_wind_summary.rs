@@ -0,0 +1,114 @@
+// Rapid wind is counted and summarized over each observation interval.
+
+use std::convert::TryFrom;
+
+use chrono::Utc;
+use serde_json::{json, Value};
+use tempest_exporter::decoder::TempestMsg;
+use tempest_exporter::exporter::Exporter;
+use tempest_exporter::reader;
+
+mod common;
+
+fn exporter() -> Exporter {
+    common::exporter()
+}
+
+fn report(datagram: Value) -> TempestMsg {
+    let raw = reader::parse(&datagram.to_string()).unwrap();
+    TempestMsg::try_from(raw).map_err(|(_, e)| e).unwrap()
+}
+
+fn rapid_wind(speed: f64) -> TempestMsg {
+    rapid_wind_ago(0, speed)
+}
+
+fn rapid_wind_ago(seconds: i64, speed: f64) -> TempestMsg {
+    report(json!({
+        "serial_number": "ST-00000001",
+        "type": "rapid_wind",
+        "hub_sn": "HB-00000001",
+        "ob": [Utc::now().timestamp() - seconds, speed, 128],
+    }))
+}
+
+fn observation() -> TempestMsg {
+    report(json!({
+        "serial_number": "ST-00000001",
+        "type": "obs_st",
+        "hub_sn": "HB-00000001",
+        "obs": [[Utc::now().timestamp(), 1.0, 2.0, 3.0, 180, 3, 1010.0, 10.0, 80.0, 0, 0.0, 0, 0.0, 0, 0, 0, 2.6, 1]],
+        "firmware_revision": 156,
+    }))
+}
+
+fn interval_stat(exposition: &str, stat: &str) -> Option<f64> {
+    summary_stat(exposition, "interval", stat)
+}
+
+fn summary_stat(exposition: &str, summary: &str, stat: &str) -> Option<f64> {
+    let series = format!(
+        "tempest_station_instant_wind_{}_speed_magnitude_m_per_s{{stat=\"{}\"}} ",
+        summary, stat
+    );
+    exposition
+        .lines()
+        .find_map(|line| line.strip_prefix(&series))
+        .map(|value| value.parse().unwrap())
+}
+
+#[test]
+fn rapid_wind_samples_are_counted() {
+    let exporter = exporter();
+    for _ in 0..3 {
+        exporter.handle_report(&rapid_wind(2.0));
+    }
+    let exposition = String::from_utf8(exporter.encode()).unwrap();
+    assert!(exposition.contains("tempest_station_instant_wind_samples_total 3\n"));
+}
+
+#[test]
+fn summary_covers_rapid_wind_since_the_last_observation() {
+    let exporter = exporter();
+    for speed in [1.0, 4.0, 2.5] {
+        exporter.handle_report(&rapid_wind(speed));
+    }
+    let exposition = String::from_utf8(exporter.encode()).unwrap();
+    assert_eq!(interval_stat(&exposition, "max"), None);
+
+    exporter.handle_report(&observation());
+    let exposition = String::from_utf8(exporter.encode()).unwrap();
+    assert_eq!(interval_stat(&exposition, "min"), Some(1.0));
+    assert_eq!(interval_stat(&exposition, "mean"), Some(2.5));
+    assert_eq!(interval_stat(&exposition, "max"), Some(4.0));
+
+    exporter.handle_report(&rapid_wind(6.0));
+    exporter.handle_report(&observation());
+    let exposition = String::from_utf8(exporter.encode()).unwrap();
+    assert_eq!(interval_stat(&exposition, "min"), Some(6.0));
+    assert_eq!(interval_stat(&exposition, "max"), Some(6.0));
+
+    // An interval without rapid wind leaves the last summary in place until it expires.
+    exporter.handle_report(&observation());
+    let exposition = String::from_utf8(exporter.encode()).unwrap();
+    assert_eq!(interval_stat(&exposition, "mean"), Some(6.0));
+}
+
+#[test]
+fn window_summary_covers_rapid_wind_before_each_scrape() {
+    let exporter = exporter();
+    let exposition = String::from_utf8(exporter.encode()).unwrap();
+    assert_eq!(summary_stat(&exposition, "window", "max"), None);
+
+    // A gust between scrapes, and one too long ago to count.
+    for (ago, speed) in [(90, 12.0), (45, 2.0), (30, 9.0), (3, 3.0)] {
+        exporter.handle_report(&rapid_wind_ago(ago, speed));
+    }
+    let exposition = String::from_utf8(exporter.encode()).unwrap();
+    assert_eq!(summary_stat(&exposition, "window", "min"), Some(2.0));
+    assert_eq!(summary_stat(&exposition, "window", "max"), Some(9.0));
+    assert_eq!(
+        summary_stat(&exposition, "window", "mean").map(|mean| mean.round()),
+        Some(5.0)
+    );
+}
@@ -0,0 +1,27 @@
+// Leaf wetness estimated from dew point depression and recent rain.
+
+use chrono::Duration;
+use tempest_exporter::leaf::{self, WET_SCORE};
+
+#[test]
+fn dew_wets_leaves_as_the_air_nears_saturation() {
+    assert_eq!(leaf::wetness(Some(8.0), None), 0.0);
+    assert_eq!(leaf::wetness(Some(4.0), None), 0.0);
+    assert!((leaf::wetness(Some(2.5), None) - 0.5).abs() < 1e-9);
+    assert_eq!(leaf::wetness(Some(0.6), None), 1.0);
+    assert!(leaf::wetness(Some(2.0), None) >= WET_SCORE);
+}
+
+#[test]
+fn leaves_dry_out_over_two_hours_after_rain() {
+    assert_eq!(leaf::wetness(Some(6.0), Some(Duration::zero())), 1.0);
+    assert!((leaf::wetness(Some(6.0), Some(Duration::minutes(90))) - 0.25).abs() < 1e-9);
+    assert_eq!(leaf::wetness(Some(6.0), Some(Duration::hours(3))), 0.0);
+}
+
+#[test]
+fn the_wetter_of_dew_and_rain_counts() {
+    let wetness = leaf::wetness(Some(1.75), Some(Duration::minutes(90)));
+    assert!((wetness - 0.75).abs() < 1e-9);
+    assert_eq!(leaf::wetness(None, None), 0.0);
+}
@@ -0,0 +1,173 @@
+// Lightning alert levels as strikes arrive and leave the window, timed by a clock that moves only
+// when told to.
+
+use std::time::Duration;
+
+use chrono::{TimeZone, Utc};
+use tempest_exporter::clock::{Clock, ManualClock};
+use tempest_exporter::decoder::{PrecipEvent, StrikeEvent, TempestMsg};
+use tempest_exporter::lightning::{LightningLevel, LightningParams, LightningTracker};
+
+const MINUTE: Duration = Duration::from_secs(60);
+
+fn clock() -> ManualClock {
+    ManualClock::new(Utc.with_ymd_and_hms(2026, 10, 16, 12, 0, 0).unwrap())
+}
+
+fn lightning_params() -> LightningParams {
+    LightningParams {
+        window: 30 * MINUTE,
+        min_strikes: 2,
+        near_distance: 15.0,
+        overhead_distance: 5.0,
+    }
+}
+
+fn strike(clock: &ManualClock, distance: f64) -> TempestMsg {
+    TempestMsg::StrikeEvent(StrikeEvent {
+        timestamp: clock.utc(),
+        distance,
+        energy: 1000.0,
+    })
+}
+
+// Any other report, to re-evaluate the level without a new strike.
+fn precip(clock: &ManualClock) -> TempestMsg {
+    TempestMsg::PrecipEvent(PrecipEvent {
+        timestamp: clock.utc(),
+    })
+}
+
+#[test]
+fn lightning_level_rises_with_strikes_and_falls_as_they_leave_the_window() {
+    let clock = clock();
+    let mut tracker = LightningTracker::new(lightning_params());
+    // A lone strike isn't enough.
+    assert_eq!(
+        tracker.update(&strike(&clock, 30.0), clock.utc()),
+        Some(LightningLevel::None)
+    );
+    clock.advance(MINUTE);
+    assert_eq!(
+        tracker.update(&strike(&clock, 30.0), clock.utc()),
+        Some(LightningLevel::Distant)
+    );
+    clock.advance(MINUTE);
+    assert_eq!(
+        tracker.update(&strike(&clock, 10.0), clock.utc()),
+        Some(LightningLevel::Near)
+    );
+    clock.advance(MINUTE);
+    assert_eq!(
+        tracker.update(&strike(&clock, 3.0), clock.utc()),
+        Some(LightningLevel::Overhead)
+    );
+    assert_eq!(tracker.update(&precip(&clock), clock.utc()), None);
+
+    // The first strike leaves the window, but the closest are still within it.
+    clock.advance(27 * MINUTE);
+    assert_eq!(tracker.update(&precip(&clock), clock.utc()), None);
+    // Then only the last is left, too few to raise the level.
+    clock.advance(2 * MINUTE);
+    assert_eq!(
+        tracker.update(&precip(&clock), clock.utc()),
+        Some(LightningLevel::None)
+    );
+}
+
+#[test]
+fn lightning_level_follows_a_reconfigured_window() {
+    let clock = clock();
+    let mut tracker = LightningTracker::new(lightning_params());
+    tracker.update(&strike(&clock, 30.0), clock.utc());
+    clock.advance(10 * MINUTE);
+    assert_eq!(
+        tracker.update(&strike(&clock, 30.0), clock.utc()),
+        Some(LightningLevel::Distant)
+    );
+
+    tracker.reconfigure(LightningParams {
+        window: 5 * MINUTE,
+        ..lightning_params()
+    });
+    assert_eq!(
+        tracker.update(&precip(&clock), clock.utc()),
+        Some(LightningLevel::None)
+    );
+}
+
+#[test]
+fn lightning_level_falls_back_a_step_at_a_time() {
+    let clock = clock();
+    let mut tracker = LightningTracker::new(lightning_params());
+    tracker.update(&strike(&clock, 3.0), clock.utc());
+    clock.advance(MINUTE);
+    assert_eq!(
+        tracker.update(&strike(&clock, 30.0), clock.utc()),
+        Some(LightningLevel::Overhead)
+    );
+    clock.advance(MINUTE);
+    assert_eq!(tracker.update(&strike(&clock, 10.0), clock.utc()), None);
+    clock.advance(18 * MINUTE);
+    assert_eq!(tracker.update(&strike(&clock, 30.0), clock.utc()), None);
+    clock.advance(MINUTE);
+    assert_eq!(tracker.update(&strike(&clock, 30.0), clock.utc()), None);
+
+    // The overhead strike leaves the window, then the near one.
+    clock.advance(9 * MINUTE);
+    assert_eq!(
+        tracker.update(&precip(&clock), clock.utc()),
+        Some(LightningLevel::Near)
+    );
+    clock.advance(MINUTE);
+    assert_eq!(tracker.update(&precip(&clock), clock.utc()), None);
+    clock.advance(MINUTE);
+    assert_eq!(
+        tracker.update(&precip(&clock), clock.utc()),
+        Some(LightningLevel::Distant)
+    );
+    clock.advance(18 * MINUTE);
+    assert_eq!(
+        tracker.update(&precip(&clock), clock.utc()),
+        Some(LightningLevel::None)
+    );
+    clock.advance(MINUTE);
+    assert_eq!(tracker.update(&precip(&clock), clock.utc()), None);
+}
+
+#[test]
+fn lightning_level_waits_for_enough_strikes() {
+    let clock = clock();
+    let mut tracker = LightningTracker::new(LightningParams {
+        min_strikes: 3,
+        ..lightning_params()
+    });
+    // The first report sets the level, and the next strikes don't raise it until there are
+    // enough, however close.
+    assert_eq!(
+        tracker.update(&precip(&clock), clock.utc()),
+        Some(LightningLevel::None)
+    );
+    assert_eq!(tracker.update(&strike(&clock, 1.0), clock.utc()), None);
+    clock.advance(MINUTE);
+    assert_eq!(tracker.update(&strike(&clock, 1.0), clock.utc()), None);
+    clock.advance(MINUTE);
+    assert_eq!(
+        tracker.update(&strike(&clock, 1.0), clock.utc()),
+        Some(LightningLevel::Overhead)
+    );
+
+    // No minimum at all still takes a strike.
+    let mut tracker = LightningTracker::new(LightningParams {
+        min_strikes: 0,
+        ..lightning_params()
+    });
+    assert_eq!(
+        tracker.update(&precip(&clock), clock.utc()),
+        Some(LightningLevel::None)
+    );
+    assert_eq!(
+        tracker.update(&strike(&clock, 10.0), clock.utc()),
+        Some(LightningLevel::Near)
+    );
+}
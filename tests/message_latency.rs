@@ -0,0 +1,82 @@
+// Delay from a device making a report to the exporter receiving it.
+
+use std::convert::TryFrom;
+
+use chrono::Utc;
+use serde_json::json;
+use tempest_exporter::decoder::TempestMsg;
+use tempest_exporter::exporter::Exporter;
+use tempest_exporter::reader;
+
+mod common;
+
+fn exporter() -> Exporter {
+    common::exporter()
+}
+
+fn rapid_wind(seconds_ago: i64) -> TempestMsg {
+    let datagram = json!({
+        "serial_number": "ST-00000001",
+        "type": "rapid_wind",
+        "hub_sn": "HB-00000001",
+        "ob": [Utc::now().timestamp() - seconds_ago, 2.3, 128],
+    });
+    let raw = reader::parse(&datagram.to_string()).unwrap();
+    TempestMsg::try_from(raw).map_err(|(_, e)| e).unwrap()
+}
+
+fn sample<'a>(exposition: &'a str, series: &str) -> &'a str {
+    exposition
+        .lines()
+        .find_map(|line| line.strip_prefix(series)?.strip_prefix(' '))
+        .unwrap_or_else(|| panic!("{} not exported", series))
+}
+
+#[test]
+fn latency_is_observed_by_message_type() {
+    let exporter = exporter();
+    exporter.handle_report(&rapid_wind(0));
+    exporter.handle_report(&rapid_wind(20));
+    let exposition = String::from_utf8(exporter.encode()).unwrap();
+    let bucket = |le| {
+        sample(
+            &exposition,
+            &format!(
+                "tempest_exporter_message_latency_sec_bucket{{type=\"instant_wind\",le=\"{}\"}}",
+                le
+            ),
+        )
+    };
+    assert_eq!(bucket("2"), "1");
+    assert_eq!(bucket("10"), "1");
+    assert_eq!(bucket("30"), "2");
+    assert_eq!(
+        sample(
+            &exposition,
+            "tempest_exporter_message_latency_sec_count{type=\"instant_wind\"}"
+        ),
+        "2"
+    );
+}
+
+#[test]
+fn reports_from_the_future_have_no_latency() {
+    let exporter = exporter();
+    exporter.handle_report(&rapid_wind(-60));
+    let exposition = String::from_utf8(exporter.encode()).unwrap();
+    assert_eq!(
+        sample(
+            &exposition,
+            "tempest_exporter_message_latency_sec_sum{type=\"instant_wind\"}"
+        ),
+        "0"
+    );
+}
+
+#[test]
+fn restored_reports_are_not_observed() {
+    let exporter = exporter();
+    exporter.restore_report(&rapid_wind(5));
+    let exposition = String::from_utf8(exporter.encode()).unwrap();
+    assert!(!exposition.contains("tempest_exporter_message_latency_sec_count"));
+}
@@ -0,0 +1,67 @@
+// Perishable metric groups have age gauges that outlive them.
+
+use std::convert::TryFrom;
+use std::time::Duration;
+
+use chrono::Utc;
+use serde_json::json;
+use tempest_exporter::decoder::TempestMsg;
+use tempest_exporter::exporter::Exporter;
+use tempest_exporter::params::ExporterParams;
+use tempest_exporter::reader;
+
+mod common;
+
+fn exporter(observation_ttl: Duration) -> Exporter {
+    common::exporter_with(
+        common::station_params(),
+        ExporterParams {
+            observation_ttl,
+            ..common::exporter_params()
+        },
+    )
+}
+
+fn feed_observation(exporter: &Exporter) {
+    let datagram = json!({
+        "serial_number": "ST-00000001",
+        "type": "obs_st",
+        "hub_sn": "HB-00000001",
+        "obs": [[Utc::now().timestamp(), 1.0, 2.0, 3.0, 180, 3, 1010.0, 10.0, 80.0, 0, 0.0, 0, 0.0, 0, 0, 0, 2.6, 1]],
+        "firmware_revision": 156,
+    });
+    let raw = reader::parse(&datagram.to_string()).unwrap();
+    exporter.handle_report(&TempestMsg::try_from(raw).map_err(|(_, e)| e).unwrap());
+}
+
+fn age(exposition: &str, group: &str) -> Option<f64> {
+    let series = format!("tempest_station_{}_age_sec ", group);
+    exposition
+        .lines()
+        .find_map(|line| line.strip_prefix(&series))
+        .map(|value| value.parse().unwrap())
+}
+
+#[test]
+fn ages_are_exported_once_a_group_is_updated() {
+    let exporter = exporter(Duration::from_secs(3600));
+    let exposition = String::from_utf8(exporter.encode()).unwrap();
+    assert_eq!(age(&exposition, "observation_temperature"), None);
+
+    feed_observation(&exporter);
+    let exposition = String::from_utf8(exporter.encode()).unwrap();
+    let temperature_age = age(&exposition, "observation_temperature").unwrap();
+    assert!((0.0..5.0).contains(&temperature_age), "{}", temperature_age);
+    assert!(age(&exposition, "observation_wind").is_some());
+    assert_eq!(age(&exposition, "instant_wind"), None);
+}
+
+#[test]
+fn ages_outlive_expired_groups() {
+    let exporter = exporter(Duration::ZERO);
+    feed_observation(&exporter);
+    std::thread::sleep(Duration::from_millis(10));
+    let exposition = String::from_utf8(exporter.encode()).unwrap();
+    assert!(!exposition.contains("tempest_station_observation_temperature_deg_c "));
+    assert!(age(&exposition, "observation_temperature").unwrap() > 0.0);
+}
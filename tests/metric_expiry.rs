@@ -0,0 +1,119 @@
+// Perishable metrics expire by the exporter's clock, so a manual clock times them exactly.
+
+use std::convert::TryFrom;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{TimeZone, Utc};
+use serde_json::json;
+use tempest_exporter::clock::{Clock, ManualClock};
+use tempest_exporter::decoder::TempestMsg;
+use tempest_exporter::exporter::Exporter;
+use tempest_exporter::params::{self, ExporterParams};
+use tempest_exporter::reader;
+
+mod common;
+
+const INSTANT_WIND_TTL: Duration = Duration::from_secs(60);
+const OBSERVATION_TTL: Duration = Duration::from_secs(600);
+
+fn exporter(clock: &Arc<ManualClock>) -> Exporter {
+    Exporter::with_clock(
+        params::shared(common::station_params()),
+        params::shared(ExporterParams {
+            instant_wind_ttl: INSTANT_WIND_TTL,
+            observation_ttl: OBSERVATION_TTL,
+            ..common::exporter_params()
+        }),
+        clock.clone(),
+    )
+}
+
+fn clock() -> Arc<ManualClock> {
+    Arc::new(ManualClock::new(Utc.timestamp_opt(1635567982, 0).unwrap()))
+}
+
+fn decoded(datagram: serde_json::Value) -> TempestMsg {
+    let raw = reader::parse(&datagram.to_string()).unwrap();
+    TempestMsg::try_from(raw).map_err(|(_, e)| e).unwrap()
+}
+
+fn observation(timestamp: i64) -> TempestMsg {
+    decoded(json!({
+        "serial_number": "ST-00000001",
+        "type": "obs_st",
+        "hub_sn": "HB-00000001",
+        "obs": [[timestamp, 1.0, 2.0, 3.0, 180, 3, 1010.0, 10.0, 80.0, 0, 0.0, 0, 0.0, 0, 0, 0, 2.6, 1]],
+        "firmware_revision": 156,
+    }))
+}
+
+fn feed_observation(exporter: &Exporter, timestamp: i64) {
+    exporter.handle_report(&observation(timestamp));
+}
+
+fn feed_rapid_wind(exporter: &Exporter, timestamp: i64) {
+    exporter.handle_report(&decoded(json!({
+        "serial_number": "ST-00000001",
+        "type": "rapid_wind",
+        "hub_sn": "HB-00000001",
+        "ob": [timestamp, 2.5, 90],
+    })));
+}
+
+fn sample(exporter: &Exporter, series: &str) -> Option<f64> {
+    let series = format!("{} ", series);
+    String::from_utf8(exporter.encode())
+        .unwrap()
+        .lines()
+        .find_map(|line| line.strip_prefix(&series))
+        .map(|value| value.parse().unwrap())
+}
+
+#[test]
+fn groups_expire_on_their_own_ttl() {
+    let clock = clock();
+    let exporter = exporter(&clock);
+    feed_observation(&exporter, clock.utc().timestamp());
+    feed_rapid_wind(&exporter, clock.utc().timestamp());
+    let temperature = "tempest_station_observation_temperature_deg_c";
+    let wind_speed = "tempest_station_instant_wind_speed_magnitude_m_per_s";
+    assert_eq!(sample(&exporter, temperature), Some(10.0));
+    assert_eq!(sample(&exporter, wind_speed), Some(2.5));
+
+    clock.advance(INSTANT_WIND_TTL);
+    assert_eq!(sample(&exporter, wind_speed), Some(2.5));
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(sample(&exporter, wind_speed), None);
+    assert_eq!(sample(&exporter, temperature), Some(10.0));
+
+    clock.advance(OBSERVATION_TTL - INSTANT_WIND_TTL);
+    assert_eq!(sample(&exporter, temperature), None);
+}
+
+#[test]
+fn restored_reports_expire_as_long_after_they_were_made() {
+    let clock = clock();
+    let exporter = exporter(&clock);
+    // Saved five minutes ago, so it has five minutes left to live.
+    assert!(exporter.restore_report(&observation(clock.utc().timestamp() - 300)));
+    let temperature = "tempest_station_observation_temperature_deg_c";
+    assert_eq!(sample(&exporter, temperature), Some(10.0));
+
+    clock.advance(Duration::from_secs(299));
+    assert_eq!(sample(&exporter, temperature), Some(10.0));
+    clock.advance(Duration::from_secs(2));
+    assert_eq!(sample(&exporter, temperature), None);
+}
+
+#[test]
+fn ages_follow_the_clock() {
+    let clock = clock();
+    let exporter = exporter(&clock);
+    feed_observation(&exporter, clock.utc().timestamp());
+    clock.advance(Duration::from_secs(90));
+    assert_eq!(
+        sample(&exporter, "tempest_station_observation_temperature_age_sec"),
+        Some(90.0)
+    );
+}
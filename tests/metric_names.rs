@@ -0,0 +1,75 @@
+// Renamed metrics are exposed under their current name, legacy name or both during their
+// deprecation window.
+
+use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
+
+use tempest_exporter::decoder::TempestMsg;
+use tempest_exporter::exporter;
+use tempest_exporter::params::{ExporterParams, NameMode};
+use tempest_exporter::reader;
+
+mod common;
+
+const CURRENT: &str = "tempest_exporter_messages_received_total";
+const LEGACY: &str = "tempest_exporter_messages_received";
+
+fn exposition(metric_names: &[(&str, NameMode)]) -> String {
+    let exporter = common::exporter_with(
+        common::station_params(),
+        ExporterParams {
+            metric_names: metric_names
+                .iter()
+                .map(|(name, mode)| (name.to_string(), *mode))
+                .collect(),
+            ..common::exporter_params()
+        },
+    );
+    let path =
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/decode/evt_strike_tempest.json");
+    let raw = reader::parse(&fs::read_to_string(path).unwrap()).unwrap();
+    exporter.handle_report(&TempestMsg::try_from(raw).map_err(|(_, e)| e).unwrap());
+    String::from_utf8(exporter.encode()).unwrap()
+}
+
+fn sample(exposition: &str, name: &str) -> Option<String> {
+    let prefix = format!("{}{{type=\"strike_event\"}} ", name);
+    exposition
+        .lines()
+        .find_map(|line| line.strip_prefix(&prefix))
+        .map(str::to_string)
+}
+
+#[test]
+fn renamed_metrics_are_exposed_under_both_names_by_default() {
+    let exposition = exposition(&[]);
+    assert_eq!(sample(&exposition, CURRENT).as_deref(), Some("1"));
+    assert_eq!(sample(&exposition, LEGACY).as_deref(), Some("1"));
+    assert!(exposition.contains(&format!("# TYPE {} counter\n", LEGACY)));
+    assert!(exposition.contains(&format!(
+        "# HELP {} API messages received (deprecated, renamed {})\n",
+        LEGACY, CURRENT
+    )));
+}
+
+#[test]
+fn name_mode_selects_one_name() {
+    let exposition_current = exposition(&[(CURRENT, NameMode::Current)]);
+    assert!(sample(&exposition_current, CURRENT).is_some());
+    assert!(!exposition_current.contains(&format!("{}{{", LEGACY)));
+
+    let exposition_legacy = exposition(&[(CURRENT, NameMode::Legacy)]);
+    assert!(sample(&exposition_legacy, LEGACY).is_some());
+    assert!(!exposition_legacy.contains(&format!("{}{{", CURRENT)));
+}
+
+#[test]
+fn renamed_metrics_are_found_by_either_name() {
+    assert_eq!(exporter::renamed_metric(LEGACY), Some(CURRENT));
+    assert_eq!(exporter::renamed_metric(CURRENT), Some(CURRENT));
+    assert_eq!(
+        exporter::renamed_metric("tempest_station_observation_temperature_deg_c"),
+        None
+    );
+}
@@ -0,0 +1,47 @@
+// Mold and condensation risk from hours of humid air over the last day.
+
+use chrono::{Duration, TimeZone, Utc};
+use tempest_exporter::mold::MoldRisk;
+
+#[test]
+fn humid_hours_count_time_at_high_humidity() {
+    let start = Utc.timestamp_opt(1_600_000_000, 0).unwrap();
+    let mut risk = MoldRisk::new();
+    assert_eq!(risk.humid_hours(), 0.0);
+    // Humid overnight for 8 hours, then drier.
+    for hour in 0..=8 {
+        risk.push(start + Duration::hours(hour), 12.0, 92.0);
+    }
+    risk.push(start + Duration::hours(9), 18.0, 60.0);
+    assert!((risk.humid_hours() - 9.0).abs() < 1e-9);
+    assert_eq!(risk.level(), 1);
+    risk.push(start + Duration::hours(12), 22.0, 55.0);
+    assert!((risk.humid_hours() - 9.0).abs() < 1e-9);
+}
+
+#[test]
+fn freezing_air_is_not_conducive() {
+    let start = Utc.timestamp_opt(1_600_000_000, 0).unwrap();
+    let mut risk = MoldRisk::new();
+    for hour in 0..=20 {
+        risk.push(start + Duration::hours(hour), -3.0, 95.0);
+    }
+    assert_eq!(risk.humid_hours(), 0.0);
+    assert_eq!(risk.level(), 0);
+}
+
+#[test]
+fn risk_rises_with_sustained_humidity_and_falls_after_a_day() {
+    let start = Utc.timestamp_opt(1_600_000_000, 0).unwrap();
+    let mut risk = MoldRisk::new();
+    for hour in 0..=20 {
+        risk.push(start + Duration::hours(hour), 15.0, 85.0);
+    }
+    assert_eq!(risk.level(), 3);
+    for hour in 21..=40 {
+        risk.push(start + Duration::hours(hour), 15.0, 50.0);
+    }
+    // Only the humid hours from 16 until the first dry reading at 21 are within the last day.
+    assert!((risk.humid_hours() - 5.0).abs() < 1e-9);
+    assert_eq!(risk.level(), 0);
+}
@@ -0,0 +1,75 @@
+// Several stations' metrics served together, told apart by a station label.
+
+use std::convert::TryFrom;
+
+use chrono::Utc;
+use serde_json::json;
+use tempest_exporter::decoder::TempestMsg;
+use tempest_exporter::exporter::{self, Exporter};
+use tempest_exporter::params::StationParams;
+use tempest_exporter::reader;
+
+mod common;
+
+fn exporter(elevation: f64) -> Exporter {
+    common::exporter_with(
+        StationParams {
+            elevation,
+            ..common::station_params()
+        },
+        common::exporter_params(),
+    )
+}
+
+fn observe(exporter: &Exporter, serial_number: &str, air_temperature: f64) {
+    let datagram = json!({
+        "serial_number": serial_number,
+        "type": "obs_st",
+        "hub_sn": "HB-00000001",
+        "obs": [[Utc::now().timestamp(), 1.0, 2.0, 3.0, 180, 3, 1010.0, air_temperature, 80.0, 0, 0.0, 0, 0.0, 0, 0, 0, 2.6, 1]],
+        "firmware_revision": 156,
+    });
+    let raw = reader::parse(&datagram.to_string()).unwrap();
+    exporter.handle_report(&TempestMsg::try_from(raw).map_err(|(_, e)| e).unwrap());
+}
+
+#[test]
+fn series_are_labelled_by_station() {
+    let home = exporter(100.0);
+    let cabin = exporter(1500.0);
+    observe(&home, "ST-00000001", 20.0);
+    observe(&cabin, "ST-00000002", 5.5);
+
+    let exposition = String::from_utf8(exporter::encode_stations(&[
+        ("home", &home),
+        ("cabin", &cabin),
+    ]))
+    .unwrap();
+    assert!(
+        exposition.contains("tempest_station_observation_temperature_deg_c{station=\"home\"} 20\n")
+    );
+    assert!(exposition
+        .contains("tempest_station_observation_temperature_deg_c{station=\"cabin\"} 5.5\n"));
+    assert!(exposition.contains(
+        "tempest_station_status_quality_score{serial_number=\"ST-00000002\",station=\"cabin\"}"
+    ));
+    // Each family is described once, however many stations report it.
+    assert_eq!(
+        exposition
+            .matches("# TYPE tempest_station_observation_temperature_deg_c ")
+            .count(),
+        1
+    );
+}
+
+#[test]
+fn openmetrics_is_labelled_by_station() {
+    let home = exporter(100.0);
+    observe(&home, "ST-00000001", 20.0);
+    let exposition =
+        String::from_utf8(exporter::encode_stations_openmetrics(&[("home", &home)])).unwrap();
+    assert!(
+        exposition.contains("tempest_station_observation_temperature_deg_c{station=\"home\"} 20")
+    );
+    assert!(exposition.ends_with("# EOF\n"));
+}
@@ -0,0 +1,92 @@
+// Published values rounded to a configured precision by category, told from their names' units.
+
+use serde_json::json;
+use tempest_exporter::precision::Precision;
+
+fn precision() -> Precision {
+    Precision {
+        temperature: Some(1),
+        humidity: Some(0),
+        wind_speed: Some(2),
+        rain: Some(1),
+        ..Precision::default()
+    }
+}
+
+#[test]
+fn values_are_unrounded_by_default() {
+    let precision = Precision::default();
+    assert_eq!(
+        precision.round("observation/thermal/temperature_deg_c", 22.340000000000003),
+        22.340000000000003
+    );
+}
+
+#[test]
+fn categories_are_told_from_unit_suffixes() {
+    let precision = precision();
+    assert_eq!(
+        precision
+            .round("observation/thermal/temperature_deg_c", 22.340000000000003)
+            .to_string(),
+        "22.3"
+    );
+    assert_eq!(
+        precision
+            .round("observation/thermal/relative_humidity_pct", 64.6)
+            .to_string(),
+        "65"
+    );
+    assert_eq!(
+        precision.round("instant_wind/speed_magnitude_m_per_s", 1.23456),
+        1.23
+    );
+    assert_eq!(
+        precision.round("observation/precip/rain_rate_mm_per_h_10m", 0.06),
+        0.1
+    );
+    // Pressure is unset and there is no default.
+    assert_eq!(
+        precision.round("observation/pressure/station_hpa", 1013.456),
+        1013.456
+    );
+}
+
+#[test]
+fn default_covers_unlisted_categories() {
+    let precision = Precision {
+        default: Some(2),
+        ..precision()
+    };
+    assert_eq!(precision.digits("observation/derived/vpd"), Some(2));
+    assert_eq!(
+        precision.digits("observation/pressure/station_hpa"),
+        Some(2)
+    );
+    assert_eq!(
+        precision.digits("observation/thermal/dew_point_deg_c"),
+        Some(1)
+    );
+}
+
+#[test]
+fn json_numbers_are_rounded_by_field_name() {
+    let mut document = json!({
+        "serial_number": "ST-00000001",
+        "duration_sec": 600,
+        "total_mm": 1.2345,
+        "station": { "temperature_deg_c": [21.06, 21.14] },
+        "relative_humidity_pct": null,
+    });
+    precision().round_json(&mut document);
+    assert_eq!(
+        document,
+        json!({
+            "serial_number": "ST-00000001",
+            "duration_sec": 600,
+            "total_mm": 1.2,
+            "station": { "temperature_deg_c": [21.1, 21.1] },
+            "relative_humidity_pct": null,
+        })
+    );
+}
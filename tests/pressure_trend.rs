@@ -0,0 +1,69 @@
+// Pressure trend over a sliding window, and the storm warning derived from it.
+
+use std::convert::TryFrom;
+use std::time::Duration;
+
+use chrono::{TimeZone, Utc};
+use serde_json::json;
+use tempest_exporter::decoder::TempestMsg;
+use tempest_exporter::reader;
+use tempest_exporter::trend::Trend;
+
+mod common;
+
+const HOUR: Duration = Duration::from_secs(3600);
+
+#[test]
+fn change_needs_half_a_window_of_readings() {
+    let window = 3 * HOUR;
+    let start = Utc.timestamp_opt(1_600_000_000, 0).unwrap();
+    let mut trend = Trend::new();
+    trend.push(start, 1010.0, window);
+    trend.push(start + chrono::Duration::minutes(60), 1009.0, window);
+    assert_eq!(trend.change(window), None);
+    trend.push(start + chrono::Duration::minutes(90), 1008.5, window);
+    // 1.5 hPa over 90 minutes, scaled to the 3 hour window.
+    assert!((trend.change(window).unwrap() + 3.0).abs() < 1e-9);
+}
+
+#[test]
+fn readings_older_than_the_window_are_forgotten() {
+    let window = 3 * HOUR;
+    let start = Utc.timestamp_opt(1_600_000_000, 0).unwrap();
+    let mut trend = Trend::new();
+    for (hours, pressure) in [(0, 1000.0), (1, 1009.0), (2, 1011.0), (4, 1012.0)] {
+        trend.push(start + chrono::Duration::hours(hours), pressure, window);
+    }
+    assert!((trend.change(window).unwrap() - 3.0).abs() < 1e-9);
+}
+
+#[test]
+fn out_of_order_readings_are_ignored() {
+    let window = 3 * HOUR;
+    let start = Utc.timestamp_opt(1_600_000_000, 0).unwrap();
+    let mut trend = Trend::new();
+    trend.push(start, 1010.0, window);
+    trend.push(start + chrono::Duration::hours(2), 1008.0, window);
+    trend.push(start + chrono::Duration::hours(1), 900.0, window);
+    assert!((trend.change(window).unwrap() + 3.0).abs() < 1e-9);
+}
+
+#[test]
+fn falling_pressure_raises_storm_warning() {
+    let exporter = common::exporter();
+    let now = Utc::now().timestamp();
+    for (minutes_ago, pressure) in [(120, 1010.0), (60, 1008.0), (0, 1006.5)] {
+        let datagram = json!({
+            "serial_number": "ST-00000001",
+            "type": "obs_st",
+            "hub_sn": "HB-00000001",
+            "obs": [[now - minutes_ago * 60, 1.0, 2.0, 3.0, 180, 3, pressure, 10.0, 80.0, 0, 0.0, 0, 0.0, 0, 0, 0, 2.6, 1]],
+            "firmware_revision": 156,
+        });
+        let raw = reader::parse(&datagram.to_string()).unwrap();
+        exporter.handle_report(&TempestMsg::try_from(raw).map_err(|(_, e)| e).unwrap());
+    }
+    let exposition = String::from_utf8(exporter.encode()).unwrap();
+    assert!(exposition.contains("tempest_station_observation_storm_warning 1\n"));
+    assert!(exposition.contains("tempest_station_observation_pressure_change_hpa -5.25\n"));
+}
@@ -0,0 +1,74 @@
+// Include and exclude lists suppressing MQTT topics and Prometheus metrics.
+
+use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
+
+use tempest_exporter::decoder::TempestMsg;
+use tempest_exporter::filter::PublishFilter;
+use tempest_exporter::params::ExporterParams;
+use tempest_exporter::reader;
+
+mod common;
+
+fn filter(include: &[&str], exclude: &[&str]) -> PublishFilter {
+    let strings = |patterns: &[&str]| patterns.iter().map(|p| p.to_string()).collect();
+    PublishFilter::new(strings(include), strings(exclude))
+}
+
+#[test]
+fn everything_is_published_by_default() {
+    let filter = PublishFilter::default();
+    assert!(filter.allows("observation/solar/illuminance_lux"));
+    assert!(filter.allows("tempest_station_observation_illuminance_lux"));
+}
+
+#[test]
+fn one_pattern_matches_topics_and_metrics() {
+    let filter = filter(&[], &["*illuminance*", "*/component_velocity_m_per_s"]);
+    assert!(!filter.allows("observation/solar/illuminance_lux"));
+    assert!(!filter.allows("tempest_station_observation_illuminance_lux"));
+    assert!(!filter.allows("instant_wind/component_velocity_m_per_s"));
+    assert!(!filter.allows("observation/wind/gust/component_velocity_m_per_s"));
+    assert!(filter.allows("observation/solar/irradiance_w_per_m2"));
+    assert!(filter.allows("instant_wind/speed_magnitude_m_per_s"));
+}
+
+#[test]
+fn exclusions_override_inclusions() {
+    let filter = filter(
+        &["observation/thermal/*", "status/battery_volts"],
+        &["*wet_bulb*"],
+    );
+    assert!(filter.allows("observation/thermal/temperature_deg_c"));
+    assert!(filter.allows("status/battery_volts"));
+    assert!(!filter.allows("status/battery_volts_extra"));
+    assert!(!filter.allows("observation/thermal/wet_bulb_temperature_deg_c"));
+    assert!(!filter.allows("observation/solar/uv_index"));
+}
+
+#[test]
+fn only_exclusions_apply_to_topics_exempt_from_inclusion() {
+    let filter = filter(&["observation/thermal/*"], &["alert/noisy_*"]);
+    // Alerts aren't readings an include list would name.
+    assert!(!filter.allows("alert/high_wind"));
+    assert!(!filter.excludes("alert/high_wind"));
+    assert!(filter.excludes("alert/noisy_lightning"));
+}
+
+#[test]
+fn excluded_metrics_are_not_exposed() {
+    let exporter = common::exporter_with(
+        common::station_params(),
+        ExporterParams {
+            publish_filter: filter(&[], &["*illuminance*"]),
+            ..common::exporter_params()
+        },
+    );
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/decode/obs_st_fw129.json");
+    let raw = reader::parse(&fs::read_to_string(path).unwrap()).unwrap();
+    exporter.handle_report(&TempestMsg::try_from(raw).map_err(|(_, e)| e).unwrap());
+    let exposition = String::from_utf8(exporter.encode()).unwrap();
+    assert!(exposition.contains("tempest_station_observation_irradiance_w_per_m2"));
+    assert!(!exposition.contains("illuminance"));
+}
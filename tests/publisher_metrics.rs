@@ -0,0 +1,45 @@
+// Health of the MQTT leg, exported alongside the station's metrics.
+
+use tempest_exporter::exporter::{Exporter, PublisherMetrics};
+
+mod common;
+
+fn exporter() -> Exporter {
+    common::exporter()
+}
+
+#[test]
+fn publisher_metrics_are_exported_once_claimed() {
+    let exporter = exporter();
+    let exposition = String::from_utf8(exporter.encode()).unwrap();
+    assert!(!exposition.contains("tempest_exporter_publisher_"));
+
+    let metrics = exporter.publisher_metrics();
+    metrics.connected.set(1);
+    metrics.reconnects.inc();
+    metrics.last_publish.set(1_600_000_000.5);
+    metrics.publish_latency.observe(0.02);
+    metrics.dropped.inc();
+    let exposition = String::from_utf8(exporter.encode()).unwrap();
+    assert!(exposition.contains("tempest_exporter_publisher_connected 1\n"));
+    assert!(exposition.contains("tempest_exporter_publisher_reconnects_total 1\n"));
+    assert!(exposition
+        .contains("tempest_exporter_publisher_last_publish_timestamp_unix_sec 1600000000.5\n"));
+    assert!(exposition
+        .contains("tempest_exporter_publisher_publish_latency_sec_bucket{le=\"0.025\"} 1\n"));
+    assert!(exposition.contains("tempest_exporter_publisher_publish_latency_sec_count 1\n"));
+    assert!(exposition.contains("tempest_exporter_publisher_messages_dropped_total 1\n"));
+
+    // Claiming them again hands out the same metrics.
+    exporter.publisher_metrics().connected.set(0);
+    let exposition = String::from_utf8(exporter.encode()).unwrap();
+    assert!(exposition.contains("tempest_exporter_publisher_connected 0\n"));
+}
+
+#[test]
+fn detached_publisher_metrics_are_not_exported() {
+    let exporter = exporter();
+    PublisherMetrics::new().connected.set(1);
+    let exposition = String::from_utf8(exporter.encode()).unwrap();
+    assert!(!exposition.contains("tempest_exporter_publisher_connected"));
+}
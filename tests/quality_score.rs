@@ -0,0 +1,91 @@
+// Device data quality scores computed from decoded fixture reports.
+
+use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
+
+use tempest_exporter::decoder::TempestMsg;
+use tempest_exporter::quality::Quality;
+use tempest_exporter::reader;
+
+fn decode(fixture: &str) -> TempestMsg {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures/decode")
+        .join(fixture);
+    let raw = reader::parse(&fs::read_to_string(path).unwrap()).unwrap();
+    TempestMsg::try_from(raw).map_err(|(_, e)| e).unwrap()
+}
+
+fn update(quality: &mut Quality, fixture: &str) -> f64 {
+    match decode(fixture) {
+        TempestMsg::Observation(obs) => quality.update_observation(&obs),
+        TempestMsg::DeviceStatus(ds) => quality.update_status(&ds),
+        other => panic!("{} has no quality score: {:?}", fixture, other),
+    }
+}
+
+#[test]
+fn healthy_station_scores_full_marks() {
+    let mut quality = Quality::new();
+    assert_eq!(
+        update(&mut quality, "device_status_tempest_fw156.json"),
+        100.0
+    );
+    assert_eq!(
+        update(&mut quality, "obs_st_fw156_rain_lightning.json"),
+        100.0
+    );
+}
+
+#[test]
+fn failed_sensors_cost_points() {
+    let mut quality = Quality::new();
+    // Temperature and humidity failed.
+    assert_eq!(
+        update(&mut quality, "device_status_tempest_fw171_failures.json"),
+        60.0
+    );
+}
+
+#[test]
+fn weak_signal_costs_points() {
+    let mut quality = Quality::new();
+    // The hub hears the device at -87 dBm, 7 dB into the 20 dB weak range.
+    assert_eq!(update(&mut quality, "device_status_air_fw17.json"), 93.0);
+}
+
+#[test]
+fn power_saving_battery_costs_points() {
+    let mut quality = Quality::new();
+    // 2.41 V is in the first power saving mode.
+    assert_eq!(update(&mut quality, "obs_st_fw129.json"), 95.0);
+}
+
+#[test]
+fn only_fields_seen_before_count_as_missing() {
+    let mut quality = Quality::new();
+    assert_eq!(
+        update(&mut quality, "obs_st_fw171_sensor_failure.json"),
+        100.0
+    );
+    assert_eq!(
+        update(&mut quality, "obs_st_fw156_rain_lightning.json"),
+        100.0
+    );
+    // Temperature and humidity are now missing.
+    assert_eq!(
+        update(&mut quality, "obs_st_fw171_sensor_failure.json"),
+        80.0
+    );
+}
+
+#[test]
+fn status_and_observation_penalties_combine() {
+    let mut quality = Quality::new();
+    update(&mut quality, "obs_st_fw156_rain_lightning.json");
+    update(&mut quality, "device_status_tempest_fw171_failures.json");
+    assert_eq!(
+        update(&mut quality, "obs_st_fw171_sensor_failure.json"),
+        40.0
+    );
+}
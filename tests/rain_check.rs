@@ -0,0 +1,76 @@
+// Yesterday's rain from WeatherFlow station observations, raw and as corrected by Rain Check.
+
+use tempest_exporter::rain_check::{self, DailyRain};
+
+mod common;
+
+const CORRECTED: &str = r#"{
+    "station_id": 12345,
+    "station_name": "Rooftop",
+    "obs": [{
+        "timestamp": 1635567982,
+        "air_temperature": 9.5,
+        "precip_accum_local_day": 0.3,
+        "precip_accum_local_yesterday": 4.21,
+        "precip_accum_local_yesterday_final": 6.8,
+        "precip_analysis_type_yesterday": 1
+    }],
+    "status": {"status_code": 0, "status_message": "SUCCESS"}
+}"#;
+
+const UNCORRECTED: &str = r#"{
+    "station_id": 12345,
+    "obs": [{"timestamp": 1635567982, "precip_accum_local_yesterday": 4.21}],
+    "status": {"status_code": 0, "status_message": "SUCCESS"}
+}"#;
+
+#[test]
+fn raw_and_corrected_rain_are_parsed() {
+    assert_eq!(
+        rain_check::parse(CORRECTED).unwrap(),
+        DailyRain {
+            raw: 4.21,
+            corrected: Some(6.8),
+        }
+    );
+    assert_eq!(
+        rain_check::parse(UNCORRECTED).unwrap(),
+        DailyRain {
+            raw: 4.21,
+            corrected: None,
+        }
+    );
+}
+
+#[test]
+fn api_errors_and_missing_data_are_reported() {
+    let unauthorized = r#"{"status": {"status_code": 401, "status_message": "UNAUTHORIZED"}}"#;
+    assert_eq!(
+        rain_check::parse(unauthorized).unwrap_err().to_string(),
+        "WeatherFlow API error 401: UNAUTHORIZED"
+    );
+    let empty = r#"{"obs": [], "status": {"status_code": 0, "status_message": "SUCCESS"}}"#;
+    assert_eq!(
+        rain_check::parse(empty).unwrap_err().to_string(),
+        "Station observations are empty"
+    );
+    assert!(rain_check::parse("<html>").is_err());
+}
+
+#[test]
+fn daily_rain_is_exported_by_source() {
+    let exporter = common::exporter();
+    exporter.set_daily_rain(&rain_check::parse(CORRECTED).unwrap());
+    let exposition = String::from_utf8(exporter.encode()).unwrap();
+    assert!(exposition.contains("tempest_station_daily_rain_mm{source=\"raw\"} 4.21\n"));
+    assert!(exposition.contains("tempest_station_daily_rain_mm{source=\"corrected\"} 6.8\n"));
+
+    // A new day, not yet corrected.
+    exporter.set_daily_rain(&DailyRain {
+        raw: 1.5,
+        corrected: None,
+    });
+    let exposition = String::from_utf8(exporter.encode()).unwrap();
+    assert!(exposition.contains("tempest_station_daily_rain_mm{source=\"raw\"} 1.5\n"));
+    assert!(!exposition.contains("source=\"corrected\""));
+}
@@ -0,0 +1,126 @@
+// Rain events start with the first rain and end after a dry period.
+
+use std::convert::TryFrom;
+use std::time::Duration;
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde_json::json;
+use tempest_exporter::decoder::TempestMsg;
+use tempest_exporter::params::ExporterParams;
+use tempest_exporter::rain::{RainEvent, RainEventChange, RainEvents};
+use tempest_exporter::reader;
+
+mod common;
+
+const DRY_PERIOD: Duration = Duration::from_secs(30 * 60);
+
+fn minute(n: i64) -> DateTime<Utc> {
+    Utc.timestamp_opt(1_600_000_000, 0).unwrap() + chrono::Duration::minutes(n)
+}
+
+fn observe(events: &mut RainEvents, at: i64, rain: f64) -> Vec<RainEventChange> {
+    events.observe(minute(at), chrono::Duration::minutes(1), rain, DRY_PERIOD)
+}
+
+#[test]
+fn event_spans_rain_and_ends_after_dry_period() {
+    let mut events = RainEvents::new();
+    assert_eq!(observe(&mut events, 0, 0.0), vec![]);
+    assert_eq!(
+        observe(&mut events, 1, 0.2),
+        vec![RainEventChange::Started(RainEvent {
+            start: minute(0),
+            end: minute(1),
+            total: 0.2,
+        })]
+    );
+    observe(&mut events, 2, 0.3);
+    // A lull shorter than the dry period doesn't end the event.
+    for at in 3..20 {
+        assert_eq!(observe(&mut events, at, 0.0), vec![]);
+    }
+    observe(&mut events, 20, 0.5);
+    for at in 21..50 {
+        assert_eq!(observe(&mut events, at, 0.0), vec![]);
+    }
+    let ended = RainEvent {
+        start: minute(0),
+        end: minute(20),
+        total: 1.0,
+    };
+    assert_eq!(
+        observe(&mut events, 50, 0.0),
+        vec![RainEventChange::Ended(ended.clone())]
+    );
+    assert_eq!(ended.duration(), chrono::Duration::minutes(20));
+    assert_eq!(events.current(), None);
+    assert_eq!(events.latest(), Some(&ended));
+}
+
+#[test]
+fn rain_after_a_gap_in_reports_ends_one_event_and_starts_another() {
+    let mut events = RainEvents::new();
+    observe(&mut events, 0, 0.4);
+    assert_eq!(
+        observe(&mut events, 45, 0.1),
+        vec![
+            RainEventChange::Ended(RainEvent {
+                start: minute(-1),
+                end: minute(0),
+                total: 0.4,
+            }),
+            RainEventChange::Started(RainEvent {
+                start: minute(44),
+                end: minute(45),
+                total: 0.1,
+            }),
+        ]
+    );
+}
+
+#[test]
+fn stale_observations_are_ignored() {
+    let mut events = RainEvents::new();
+    observe(&mut events, 10, 0.4);
+    assert_eq!(observe(&mut events, 5, 0.2), vec![]);
+    assert_eq!(events.current().unwrap().total, 0.4);
+}
+
+#[test]
+fn rain_event_metrics_follow_the_latest_event() {
+    let exporter = common::exporter_with(
+        common::station_params(),
+        ExporterParams {
+            rain_event_dry_period: DRY_PERIOD,
+            ..common::exporter_params()
+        },
+    );
+    let now = Utc::now().timestamp();
+    let feed = |minutes_ago: i64, rain: f64| {
+        let datagram = json!({
+            "serial_number": "ST-00000001",
+            "type": "obs_st",
+            "hub_sn": "HB-00000001",
+            "obs": [[now - minutes_ago * 60, 1.0, 2.0, 3.0, 180, 3, 1010.0, 10.0, 80.0, 0, 0.0, 0, rain, 1, 0, 0, 2.6, 1]],
+            "firmware_revision": 156,
+        });
+        let raw = reader::parse(&datagram.to_string()).unwrap();
+        exporter.handle_report(&TempestMsg::try_from(raw).map_err(|(_, e)| e).unwrap());
+        String::from_utf8(exporter.encode()).unwrap()
+    };
+
+    let exposition = feed(50, 0.0);
+    assert!(exposition.contains("tempest_station_observation_rain_event_active 0\n"));
+    assert!(!exposition.contains("tempest_station_observation_rain_event_total_mm "));
+
+    feed(45, 0.25);
+    let exposition = feed(40, 0.5);
+    assert!(exposition.contains("tempest_station_observation_rain_event_active 1\n"));
+    assert!(exposition.contains("tempest_station_observation_rain_event_duration_sec 360\n"));
+    assert!(exposition.contains("tempest_station_observation_rain_event_total_mm 0.75\n"));
+
+    let exposition = feed(0, 0.0);
+    assert!(exposition.contains("tempest_station_observation_rain_event_active 0\n"));
+    assert!(exposition.contains("tempest_station_observation_rain_event_duration_sec 360\n"));
+    assert!(exposition.contains("tempest_station_observation_rain_event_total_mm 0.75\n"));
+}
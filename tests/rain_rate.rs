@@ -0,0 +1,67 @@
+// Rain rates from rolling totals of each observation's rain over 10 minute and 1 hour windows.
+
+use std::convert::TryFrom;
+use std::time::Duration;
+
+use chrono::{TimeZone, Utc};
+use serde_json::json;
+use tempest_exporter::decoder::TempestMsg;
+use tempest_exporter::reader;
+use tempest_exporter::trend::Trend;
+
+mod common;
+
+const HOUR: Duration = Duration::from_secs(3600);
+
+#[test]
+fn sum_totals_readings_within_the_window() {
+    let start = Utc.timestamp_opt(1_600_000_000, 0).unwrap();
+    let mut trend = Trend::new();
+    assert_eq!(trend.sum(HOUR), 0.0);
+    for minute in 0..16 {
+        trend.push(start + chrono::Duration::minutes(minute), 0.5, HOUR);
+    }
+    // Minutes 6 through 15; the reading at minute 5 is exactly 10 minutes old, so its rain fell
+    // before the window.
+    assert!((trend.sum(Duration::from_secs(600)) - 5.0).abs() < 1e-9);
+    assert!((trend.sum(HOUR) - 8.0).abs() < 1e-9);
+}
+
+fn sample(exposition: &str, name: &str) -> f64 {
+    exposition
+        .lines()
+        .find_map(|line| line.strip_prefix(&format!("{} ", name)))
+        .unwrap_or_else(|| panic!("No sample for {}", name))
+        .parse()
+        .unwrap()
+}
+
+#[test]
+fn rain_rates_are_exported_per_window() {
+    let exporter = common::exporter();
+    // An hour of dry weather, then a shower of 0.1 mm each minute for the last 5 minutes.
+    let now = Utc::now().timestamp();
+    for minutes_ago in (0..60).rev() {
+        let rain = if minutes_ago < 5 { 0.1 } else { 0.0 };
+        let datagram = json!({
+            "serial_number": "ST-00000001",
+            "type": "obs_st",
+            "hub_sn": "HB-00000001",
+            "obs": [[now - minutes_ago * 60, 1.0, 2.0, 3.0, 180, 3, 1010.0, 10.0, 80.0, 0, 0.0, 0, rain, 1, 0, 0, 2.6, 1]],
+            "firmware_revision": 156,
+        });
+        let raw = reader::parse(&datagram.to_string()).unwrap();
+        exporter.handle_report(&TempestMsg::try_from(raw).map_err(|(_, e)| e).unwrap());
+    }
+    let exposition = String::from_utf8(exporter.encode()).unwrap();
+    let rate_10m = sample(
+        &exposition,
+        "tempest_station_observation_rain_rate_mm_per_h_10m",
+    );
+    let rate_1h = sample(
+        &exposition,
+        "tempest_station_observation_rain_rate_mm_per_h_1h",
+    );
+    assert!((rate_10m - 3.0).abs() < 1e-9, "{}", rate_10m);
+    assert!((rate_1h - 0.5).abs() < 1e-9, "{}", rate_1h);
+}
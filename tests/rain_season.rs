@@ -0,0 +1,164 @@
+// Rain is totalled over a season starting on a configured date, such as a water year, which
+// carries across restarts and starts over at local midnight on the start date.
+
+use std::convert::TryFrom;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde_json::json;
+use tempest_exporter::clock::{Clock, ManualClock};
+use tempest_exporter::day::DayZone;
+use tempest_exporter::decoder::TempestMsg;
+use tempest_exporter::exporter::Exporter;
+use tempest_exporter::params::{self, ExporterParams, StationMetadata, StationParams};
+use tempest_exporter::rain::{RainSeason, SeasonStart};
+use tempest_exporter::reader;
+
+mod common;
+
+const SEASON_TOTAL: &str = "tempest_station_rain_season_total_mm";
+
+fn at(rfc3339: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(rfc3339)
+        .unwrap()
+        .with_timezone(&Utc)
+}
+
+fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(y, m, d).unwrap()
+}
+
+fn exporter(clock: &Arc<ManualClock>) -> Exporter {
+    Exporter::with_clock(
+        params::shared(StationParams {
+            metadata: StationMetadata {
+                timezone: Some(chrono_tz::America::Los_Angeles),
+                ..Default::default()
+            },
+            ..common::station_params()
+        }),
+        params::shared(ExporterParams::default()),
+        clock.clone(),
+    )
+}
+
+fn observation(timestamp: DateTime<Utc>, rain: f64) -> TempestMsg {
+    let datagram = json!({
+        "serial_number": "ST-00000001",
+        "type": "obs_st",
+        "hub_sn": "HB-00000001",
+        "obs": [[timestamp.timestamp(), 1.0, 2.0, 3.0, 180, 3, 1010.0, 10.0, 80.0, 0, 0.0, 0, rain, 1, 0, 0, 2.6, 1]],
+        "firmware_revision": 156,
+    });
+    let raw = reader::parse(&datagram.to_string()).unwrap();
+    TempestMsg::try_from(raw).map_err(|(_, e)| e).unwrap()
+}
+
+fn sample(exporter: &Exporter, series: &str) -> Option<f64> {
+    let series = format!("{} ", series);
+    String::from_utf8(exporter.encode())
+        .unwrap()
+        .lines()
+        .find_map(|line| line.strip_prefix(&series))
+        .map(|value| value.parse().unwrap())
+}
+
+#[test]
+fn seasons_start_on_the_configured_day() {
+    let water_year = SeasonStart::default();
+    assert_eq!(water_year.to_string(), "10-01");
+    assert_eq!(water_year.season_of(date(2026, 9, 30)), date(2025, 10, 1));
+    assert_eq!(water_year.season_of(date(2026, 10, 1)), date(2026, 10, 1));
+    assert_eq!(water_year.season_of(date(2027, 1, 1)), date(2026, 10, 1));
+
+    let calendar_year: SeasonStart = "01-01".parse().unwrap();
+    assert_eq!(
+        calendar_year.season_of(date(2026, 12, 31)),
+        date(2026, 1, 1)
+    );
+    assert_eq!(
+        "07-01"
+            .parse::<SeasonStart>()
+            .unwrap()
+            .season_of(date(2026, 6, 30)),
+        date(2025, 7, 1)
+    );
+
+    for invalid in ["02-29", "13-01", "04-31", "10", "oct-01", ""] {
+        assert!(invalid.parse::<SeasonStart>().is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+fn totals_start_over_with_each_season() {
+    let zone = DayZone::Named(chrono_tz::UTC);
+    let start = SeasonStart::default();
+    let mut season = RainSeason::new();
+    assert_eq!(
+        season.total_at(zone, start, at("2026-09-30T12:00:00Z")),
+        None
+    );
+
+    season.observe(zone, start, at("2026-09-30T12:00:00Z"), 1.5);
+    season.observe(zone, start, at("2026-09-30T12:01:00Z"), 0.5);
+    assert_eq!(
+        season.total_at(zone, start, at("2026-09-30T23:59:59Z")),
+        Some(2.0)
+    );
+    // Nothing has been observed since the season started, but it has started.
+    assert_eq!(
+        season.total_at(zone, start, at("2026-10-01T00:00:00Z")),
+        Some(0.0)
+    );
+
+    season.observe(zone, start, at("2026-10-01T00:01:00Z"), 0.25);
+    assert_eq!(season.current().unwrap().start, date(2026, 10, 1));
+    assert_eq!(
+        season.total_at(zone, start, at("2026-10-01T00:01:00Z")),
+        Some(0.25)
+    );
+    // A late observation from last season doesn't add to this one or start it over.
+    season.observe(zone, start, at("2026-09-30T23:59:00Z"), 1.0);
+    assert_eq!(
+        season.total_at(zone, start, at("2026-10-01T00:02:00Z")),
+        Some(0.25)
+    );
+}
+
+#[test]
+fn season_total_is_exported_in_station_local_time() {
+    // 6:00 UTC on October 1 is still September 30 in Los Angeles.
+    let clock = Arc::new(ManualClock::new(at("2026-10-01T06:00:00Z")));
+    let exporter = exporter(&clock);
+    assert_eq!(sample(&exporter, SEASON_TOTAL), None);
+
+    exporter.handle_report(&observation(clock.utc(), 1.5));
+    clock.advance(Duration::from_secs(60));
+    exporter.handle_report(&observation(clock.utc(), 0.5));
+    assert_eq!(sample(&exporter, SEASON_TOTAL), Some(2.0));
+
+    // Local midnight starts the season over, whether or not it is raining.
+    clock.advance(Duration::from_secs(2 * 3600));
+    assert_eq!(sample(&exporter, SEASON_TOTAL), Some(0.0));
+    exporter.handle_report(&observation(clock.utc(), 0.75));
+    assert_eq!(sample(&exporter, SEASON_TOTAL), Some(0.75));
+}
+
+#[test]
+fn season_total_survives_a_restart() {
+    let clock = Arc::new(ManualClock::new(at("2026-10-16T12:00:00Z")));
+    let before = exporter(&clock);
+    before.handle_report(&observation(clock.utc(), 3.0));
+    let checkpoint = before.checkpoint();
+    assert_eq!(checkpoint.rain_season.as_ref().unwrap().total, 3.0);
+
+    let restarted = exporter(&clock);
+    restarted.restore(&checkpoint);
+    // Restoring the last observation too doesn't count its rain again.
+    assert!(restarted.restore_report(&observation(clock.utc(), 3.0)));
+    assert_eq!(sample(&restarted, SEASON_TOTAL), Some(3.0));
+    clock.advance(Duration::from_secs(60));
+    restarted.handle_report(&observation(clock.utc(), 1.0));
+    assert_eq!(sample(&restarted, SEASON_TOTAL), Some(4.0));
+}
@@ -0,0 +1,72 @@
+// Minute-by-minute history of recent observations.
+
+use std::convert::TryFrom;
+use std::time::Duration;
+
+use chrono::{TimeZone, Utc};
+use serde_json::json;
+use tempest_exporter::decoder::{Observation, TempestMsg};
+use tempest_exporter::history::History;
+use tempest_exporter::reader;
+
+const START: i64 = 1_600_000_020;
+
+fn observation(offset_sec: i64, temperature: Option<f64>, rain: f64) -> Observation {
+    let datagram = json!({
+        "serial_number": "ST-00000001",
+        "type": "obs_st",
+        "hub_sn": "HB-00000001",
+        "obs": [[START + offset_sec, 0.5, 1.5, 3.0, 270, 3, 1010.0, temperature, 80.0, 0, 0.0, 0, rain, 0, 0, 0, 2.6, 1]],
+        "firmware_revision": 156,
+    });
+    match TempestMsg::try_from(reader::parse(&datagram.to_string()).unwrap()) {
+        Ok(TempestMsg::Observation(obs)) => obs,
+        other => panic!("not an observation: {:?}", other.map_err(|(_, e)| e)),
+    }
+}
+
+#[test]
+fn observations_within_a_minute_share_a_sample() {
+    let mut history = History::default();
+    history.push(&observation(0, Some(10.0), 0.2));
+    history.push(&observation(30, None, 0.3));
+    history.push(&observation(60, Some(12.0), 0.0));
+    let samples: Vec<_> = history.samples(None).collect();
+    assert_eq!(samples.len(), 2);
+    assert_eq!(samples[0].timestamp, Utc.timestamp_opt(START, 0).unwrap());
+    // The latest reading is kept, unless it was missing, and rain adds up.
+    assert_eq!(samples[0].temperature_deg_c, Some(10.0));
+    assert!((samples[0].rain_mm.unwrap() - 0.5).abs() < 1e-9);
+    assert_eq!(samples[1].temperature_deg_c, Some(12.0));
+}
+
+#[test]
+fn samples_older_than_the_retention_are_forgotten() {
+    let mut history = History::new(Duration::from_secs(3600));
+    for minute in 0..90 {
+        history.push(&observation(minute * 60, Some(minute as f64), 0.0));
+    }
+    // Late observations are ignored.
+    history.push(&observation(0, Some(-5.0), 0.0));
+    let samples: Vec<_> = history.samples(None).collect();
+    assert_eq!(samples.len(), 60);
+    assert_eq!(samples[0].temperature_deg_c, Some(30.0));
+
+    history.set_retention(Duration::from_secs(600));
+    assert_eq!(history.samples(None).count(), 10);
+}
+
+#[test]
+fn history_renders_as_csv_from_a_given_time() {
+    let mut history = History::default();
+    history.push(&observation(0, Some(10.0), 0.0));
+    history.push(&observation(60, None, 0.25));
+    let since = Utc.timestamp_opt(START + 60, 0).unwrap();
+    assert_eq!(
+        history.to_csv(Some(since)),
+        "timestamp,temperature_deg_c,relative_humidity_pct,station_pressure_hpa,\
+         wind_avg_m_per_s,wind_gust_m_per_s,wind_direction_deg,irradiance_w_per_m2,uv_index,\
+         rain_mm\n\
+         2020-09-13T12:28:00+00:00,,80,1010,1.5,3,270,0,0,0.25\n"
+    );
+}
@@ -0,0 +1,207 @@
+// Latest observations from an NWS reference station, and how the station's readings differ.
+
+use std::convert::TryFrom;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use serde_json::json;
+use tempest_exporter::decoder::TempestMsg;
+use tempest_exporter::exporter::Exporter;
+use tempest_exporter::reader;
+use tempest_exporter::reference::{self, ReferenceObservation};
+use tempest_exporter::trend::Trend;
+use tempest_exporter::units::{Celsius, HectoPascal};
+
+mod common;
+
+// Abridged from https://api.weather.gov/stations/KSEA/observations/latest.
+const LATEST: &str = r#"{
+    "id": "https://api.weather.gov/stations/KSEA/observations/2026-10-16T11:53:00+00:00",
+    "type": "Feature",
+    "properties": {
+        "station": "https://api.weather.gov/stations/KSEA",
+        "timestamp": "2026-10-16T11:53:00+00:00",
+        "rawMessage": "KSEA 161153Z 17006KT 10SM FEW050 11/08 A3010",
+        "temperature": {"unitCode": "wmoUnit:degC", "value": 11.1, "qualityControl": "V"},
+        "dewpoint": {"unitCode": "wmoUnit:degC", "value": 8.3, "qualityControl": "V"},
+        "barometricPressure": {"unitCode": "wmoUnit:Pa", "value": 101930, "qualityControl": "V"},
+        "seaLevelPressure": {"unitCode": "wmoUnit:Pa", "value": 101920, "qualityControl": "V"}
+    }
+}"#;
+
+fn exporter() -> Exporter {
+    common::exporter()
+}
+
+fn observation(timestamp: DateTime<Utc>, temperature: f64) -> TempestMsg {
+    let datagram = json!({
+        "serial_number": "ST-00000001",
+        "type": "obs_st",
+        "hub_sn": "HB-00000001",
+        "obs": [[timestamp.timestamp(), 1.0, 2.0, 3.0, 180, 3, 1010.0, temperature, 80.0, 0, 0.0, 0, 0.0, 0, 0, 0, 2.6, 1]],
+        "firmware_revision": 156,
+    });
+    let raw = reader::parse(&datagram.to_string()).unwrap();
+    TempestMsg::try_from(raw).map_err(|(_, e)| e).unwrap()
+}
+
+fn sample(exporter: &Exporter, series: &str) -> Option<f64> {
+    let series = format!("{} ", series);
+    String::from_utf8(exporter.encode())
+        .unwrap()
+        .lines()
+        .find_map(|line| line.strip_prefix(&series))
+        .map(|value| value.parse().unwrap())
+}
+
+#[test]
+fn latest_observation_is_parsed() {
+    assert_eq!(
+        reference::parse("KSEA", LATEST).unwrap(),
+        ReferenceObservation {
+            station: "KSEA".to_string(),
+            timestamp: Utc.with_ymd_and_hms(2026, 10, 16, 11, 53, 0).unwrap(),
+            temperature: Some(Celsius(11.1)),
+            barometric_pressure: Some(HectoPascal(1019.2)),
+        }
+    );
+}
+
+#[test]
+fn missing_readings_and_other_units_are_handled() {
+    let body = r#"{"properties": {
+        "timestamp": "2026-10-16T11:53:00+00:00",
+        "temperature": {"unitCode": "wmoUnit:degF", "value": 50.0},
+        "barometricPressure": {"unitCode": "wmoUnit:Pa", "value": 101930},
+        "seaLevelPressure": {"unitCode": "wmoUnit:Pa", "value": null}
+    }}"#;
+    let observation = reference::parse("KSEA", body).unwrap();
+    assert_eq!(observation.temperature, Some(Celsius(10.0)));
+    // Without sea level pressure, the pressure from the altimeter setting is used.
+    assert_eq!(observation.barometric_pressure, Some(HectoPascal(1019.3)));
+
+    let body = r#"{"properties": {
+        "timestamp": "2026-10-16T11:53:00+00:00",
+        "temperature": {"unitCode": "wmoUnit:degC", "value": null}
+    }}"#;
+    let observation = reference::parse("KSEA", body).unwrap();
+    assert_eq!(observation.temperature, None);
+    assert_eq!(observation.barometric_pressure, None);
+
+    let body = r#"{"properties": {
+        "timestamp": "2026-10-16T11:53:00+00:00",
+        "temperature": {"unitCode": "wmoUnit:K", "value": 284.25}
+    }}"#;
+    let error = reference::parse("KSEA", body).unwrap_err();
+    assert_eq!(
+        format!("{:#}", error),
+        "Reading temperature: Unexpected unit wmoUnit:K"
+    );
+    assert!(reference::parse("KSEA", "<html>").is_err());
+}
+
+#[test]
+fn reference_and_differences_are_exported() {
+    let exporter = exporter();
+    let reference = reference::parse("KSEA", LATEST).unwrap();
+    // Nothing to compare with yet.
+    exporter.set_reference(&reference);
+    assert_eq!(
+        sample(
+            &exporter,
+            "tempest_reference_temperature_deg_c{station=\"KSEA\"}"
+        ),
+        Some(11.1)
+    );
+    assert_eq!(
+        sample(
+            &exporter,
+            "tempest_reference_observation_timestamp_seconds{station=\"KSEA\"}"
+        ),
+        Some(reference.timestamp.timestamp() as f64)
+    );
+    assert_eq!(
+        sample(
+            &exporter,
+            "tempest_reference_temperature_delta_deg_c{station=\"KSEA\"}"
+        ),
+        None
+    );
+
+    exporter.handle_report(&observation(
+        reference.timestamp - Duration::minutes(2),
+        12.5,
+    ));
+    let own = sample(
+        &exporter,
+        "tempest_station_observation_barometric_pressure_hpa",
+    )
+    .unwrap();
+    // The station warming up since doesn't count.
+    exporter.handle_report(&observation(reference.timestamp + Duration::hours(1), 15.0));
+    exporter.set_reference(&reference);
+    let delta = sample(
+        &exporter,
+        "tempest_reference_temperature_delta_deg_c{station=\"KSEA\"}",
+    )
+    .unwrap();
+    assert!((delta - 1.4).abs() < 1e-9, "{}", delta);
+    let delta = sample(
+        &exporter,
+        "tempest_reference_barometric_pressure_delta_hpa{station=\"KSEA\"}",
+    )
+    .unwrap();
+    assert!((delta - (own - 1019.2)).abs() < 1e-9, "{}", delta);
+
+    // The reference stopped reporting temperature.
+    exporter.set_reference(&ReferenceObservation {
+        temperature: None,
+        ..reference
+    });
+    let exposition = String::from_utf8(exporter.encode()).unwrap();
+    assert!(!exposition.contains("tempest_reference_temperature"));
+    assert!(exposition.contains("tempest_reference_barometric_pressure_delta_hpa"));
+}
+
+#[test]
+fn the_reading_closest_to_a_time_is_found() {
+    let start = Utc.timestamp_opt(1_600_000_000, 0).unwrap();
+    let window = std::time::Duration::from_secs(3 * 3600);
+    let tolerance = std::time::Duration::from_secs(5 * 60);
+    let mut trend = Trend::new();
+    assert_eq!(trend.at(start, tolerance), None);
+    for (minutes, temperature) in [(0, 10.0), (3, 11.0), (20, 12.0)] {
+        trend.push(start + Duration::minutes(minutes), temperature, window);
+    }
+    assert_eq!(
+        trend.at(start + Duration::minutes(1), tolerance),
+        Some(10.0)
+    );
+    assert_eq!(
+        trend.at(start + Duration::minutes(2), tolerance),
+        Some(11.0)
+    );
+    assert_eq!(trend.at(start + Duration::minutes(10), tolerance), None);
+    assert_eq!(
+        trend.at(start + Duration::minutes(25), tolerance),
+        Some(12.0)
+    );
+}
+
+#[test]
+fn readings_from_long_before_or_after_the_reference_are_not_compared() {
+    let exporter = exporter();
+    let reference = reference::parse("KSEA", LATEST).unwrap();
+    exporter.handle_report(&observation(
+        reference.timestamp - Duration::minutes(30),
+        12.5,
+    ));
+    exporter.handle_report(&observation(
+        reference.timestamp + Duration::minutes(30),
+        12.5,
+    ));
+    exporter.set_reference(&reference);
+    let exposition = String::from_utf8(exporter.encode()).unwrap();
+    assert!(exposition.contains("tempest_reference_temperature_deg_c"));
+    assert!(!exposition.contains("tempest_reference_temperature_delta"));
+    assert!(!exposition.contains("tempest_reference_barometric_pressure_delta"));
+}
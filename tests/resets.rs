@@ -0,0 +1,165 @@
+// Hub reboots, device restarts, firmware updates and lightning sensor interference are spotted
+// from changes between status reports.
+
+use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
+
+use tempest_exporter::decoder::TempestMsg;
+use tempest_exporter::exporter::Exporter;
+use tempest_exporter::reader;
+
+mod common;
+
+fn exporter() -> Exporter {
+    common::exporter()
+}
+
+// A fixture report with some of its fields replaced.
+fn report(fixture: &str, replacements: &[(&str, &str)]) -> TempestMsg {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures/decode")
+        .join(fixture);
+    let mut json = fs::read_to_string(path).unwrap();
+    for (from, to) in replacements {
+        json = json.replace(from, to);
+    }
+    let raw = reader::parse(&json).unwrap();
+    TempestMsg::try_from(raw).map_err(|(_, e)| e).unwrap()
+}
+
+fn hub_status(seq: i32, uptime: i64, reset_flags: &str) -> TempestMsg {
+    report(
+        "hub_status_fw171_all_reset_flags.json",
+        &[
+            ("\"seq\":286211", &format!("\"seq\":{}", seq)),
+            ("\"uptime\":2862499", &format!("\"uptime\":{}", uptime)),
+            ("BOR,PIN,POR,SFT,WDG,WWD,LPW,HRDFLT", reset_flags),
+        ],
+    )
+}
+
+fn device_status(uptime: i64) -> TempestMsg {
+    report(
+        "device_status_tempest_fw156.json",
+        &[("\"uptime\":2862471", &format!("\"uptime\":{}", uptime))],
+    )
+}
+
+// The fixture's status has the disturber flag set; noise is bit 1 and disturber bit 2.
+fn sensor_status(noise: bool, disturber: bool) -> TempestMsg {
+    let flags = 0xa0000 | (noise as u32) << 1 | (disturber as u32) << 2;
+    report(
+        "device_status_tempest_fw156.json",
+        &[(
+            "\"sensor_status\":655364",
+            &format!("\"sensor_status\":{}", flags),
+        )],
+    )
+}
+
+fn exposition(exporter: &Exporter) -> String {
+    String::from_utf8(exporter.encode()).unwrap()
+}
+
+#[test]
+fn steady_hub_has_no_reboots() {
+    let exporter = exporter();
+    exporter.handle_report(&hub_status(100, 1000, "POR"));
+    exporter.handle_report(&hub_status(101, 1010, "POR"));
+    assert!(!exposition(&exporter).contains("tempest_hub_reboots_total{"));
+}
+
+#[test]
+fn hub_reboot_is_counted_by_most_telling_reason() {
+    let exporter = exporter();
+    exporter.handle_report(&hub_status(100, 1000, "POR"));
+    // Sequence number went backwards.
+    exporter.handle_report(&hub_status(1, 1010, "POR,WDG"));
+    // Uptime went backwards.
+    exporter.handle_report(&hub_status(2, 5, "BOR,POR"));
+    let exposition = exposition(&exporter);
+    assert!(exposition.contains("tempest_hub_reboots_total{reason=\"watchdog\"} 1\n"));
+    assert!(exposition.contains("tempest_hub_reboots_total{reason=\"brownout\"} 1\n"));
+}
+
+#[test]
+fn device_restart_is_counted() {
+    let exporter = exporter();
+    exporter.handle_report(&device_status(1000));
+    exporter.handle_report(&device_status(1060));
+    assert!(exposition(&exporter).contains("tempest_station_restarts_total 0\n"));
+    exporter.handle_report(&device_status(30));
+    assert!(exposition(&exporter).contains("tempest_station_restarts_total 1\n"));
+}
+
+#[test]
+fn reboot_while_down_is_counted_from_restored_status() {
+    let exporter = exporter();
+    exporter.restore_report(&hub_status(100, 1000, "POR"));
+    exporter.restore_report(&device_status(1000));
+    exporter.handle_report(&hub_status(1, 10, "SFT"));
+    exporter.handle_report(&device_status(10));
+    let exposition = exposition(&exporter);
+    assert!(exposition.contains("tempest_hub_reboots_total{reason=\"software\"} 1\n"));
+    assert!(exposition.contains("tempest_station_restarts_total 1\n"));
+}
+
+#[test]
+fn reboot_counts_survive_checkpoint() {
+    let before = exporter();
+    before.handle_report(&hub_status(100, 1000, "POR"));
+    before.handle_report(&hub_status(1, 10, "HRDFLT,POR"));
+    before.handle_report(&device_status(1000));
+    before.handle_report(&device_status(10));
+
+    let after = exporter();
+    after.restore(&before.checkpoint());
+    let exposition = exposition(&after);
+    assert!(exposition.contains("tempest_hub_reboots_total{reason=\"hard_fault\"} 1\n"));
+    assert!(exposition.contains("tempest_station_restarts_total 1\n"));
+}
+
+#[test]
+fn firmware_info_follows_latest_revision() {
+    let exporter = exporter();
+    exporter.handle_report(&device_status(1000));
+    exporter.handle_report(&report(
+        "device_status_tempest_fw156.json",
+        &[("\"firmware_revision\":156", "\"firmware_revision\":171")],
+    ));
+    let exposition = exposition(&exporter);
+    assert!(exposition.contains(
+        "tempest_station_status_firmware_info{revision=\"171\",serial_number=\"ST-00028405\"} 1\n"
+    ));
+    assert!(!exposition.contains("revision=\"156\""));
+}
+
+#[test]
+fn lightning_interference_is_counted_when_it_starts() {
+    let exporter = exporter();
+    exporter.handle_report(&sensor_status(false, false));
+    exporter.handle_report(&sensor_status(true, false));
+    exporter.handle_report(&sensor_status(true, true));
+    exporter.handle_report(&sensor_status(true, true));
+    exporter.handle_report(&sensor_status(false, false));
+    exporter.handle_report(&sensor_status(false, true));
+    let exposition = exposition(&exporter);
+    assert!(exposition.contains("tempest_station_lightning_noise_events_total 1\n"));
+    assert!(exposition.contains("tempest_station_lightning_disturber_events_total 2\n"));
+}
+
+#[test]
+fn lightning_interference_counts_survive_checkpoint() {
+    let before = exporter();
+    before.handle_report(&sensor_status(true, true));
+
+    let after = exporter();
+    after.restore(&before.checkpoint());
+    // Interference ongoing since before the restart isn't counted again.
+    after.restore_report(&sensor_status(true, true));
+    after.handle_report(&sensor_status(true, true));
+    let exposition = exposition(&after);
+    assert!(exposition.contains("tempest_station_lightning_noise_events_total 1\n"));
+    assert!(exposition.contains("tempest_station_lightning_disturber_events_total 1\n"));
+}
@@ -0,0 +1,88 @@
+// Noisy observation fields exported exponentially smoothed, alongside the raw values.
+
+use std::convert::TryFrom;
+use std::time::Duration;
+
+use chrono::{TimeZone, Utc};
+use serde_json::json;
+use tempest_exporter::decoder::TempestMsg;
+use tempest_exporter::derived::Variable;
+use tempest_exporter::params::ExporterParams;
+use tempest_exporter::reader;
+use tempest_exporter::smoothing::{Ewma, Smoothing};
+
+mod common;
+
+const MINUTE: Duration = Duration::from_secs(60);
+
+#[test]
+fn ewma_covers_most_of_a_step_after_one_time_constant() {
+    let start = Utc.timestamp_opt(1_600_000_000, 0).unwrap();
+    let mut ewma = Ewma::new(start, 0.0);
+    let smoothed = ewma.update(start + chrono::Duration::minutes(5), 100.0, 5 * MINUTE);
+    assert!((smoothed - 63.2).abs() < 0.1, "{}", smoothed);
+
+    // Older readings are ignored.
+    assert_eq!(ewma.update(start, -100.0, 5 * MINUTE), smoothed);
+
+    // After a long gap the new reading dominates.
+    let smoothed = ewma.update(start + chrono::Duration::hours(5), 20.0, 5 * MINUTE);
+    assert!((smoothed - 20.0).abs() < 1e-9, "{}", smoothed);
+}
+
+#[test]
+fn smoothing_is_configured_by_field_name() {
+    assert_eq!(
+        Smoothing::new("uv_index", MINUTE).unwrap(),
+        Smoothing {
+            variable: Variable::UvIndex,
+            time_constant: MINUTE,
+        }
+    );
+    assert_eq!(
+        Smoothing::new("windspeed", MINUTE).unwrap_err().to_string(),
+        "Unknown field windspeed to smooth"
+    );
+    assert!(Smoothing::new("wind_direction", MINUTE).is_err());
+    assert!(Smoothing::new("illuminance", Duration::ZERO).is_err());
+}
+
+#[test]
+fn smoothed_and_raw_series_are_both_exported() {
+    let exporter = common::exporter_with(
+        common::station_params(),
+        ExporterParams {
+            smoothing: vec![Smoothing::new("uv_index", 10 * MINUTE).unwrap()],
+            ..common::exporter_params()
+        },
+    );
+    let now = Utc::now().timestamp();
+    let feed = |minutes_ago: i64, uv_index: f64| {
+        let datagram = json!({
+            "serial_number": "ST-00000001",
+            "type": "obs_st",
+            "hub_sn": "HB-00000001",
+            "obs": [[now - minutes_ago * 60, 1.0, 2.0, 3.0, 180, 3, 1010.0, 10.0, 80.0, 10000, uv_index, 100, 0.0, 0, 0, 0, 2.6, 1]],
+            "firmware_revision": 156,
+        });
+        let raw = reader::parse(&datagram.to_string()).unwrap();
+        exporter.handle_report(&TempestMsg::try_from(raw).map_err(|(_, e)| e).unwrap());
+        String::from_utf8(exporter.encode()).unwrap()
+    };
+    let smoothed = |exposition: &str| -> f64 {
+        exposition
+            .lines()
+            .find_map(|line| line.strip_prefix("tempest_station_smoothed_uv_index "))
+            .unwrap()
+            .parse()
+            .unwrap()
+    };
+
+    let exposition = feed(1, 2.0);
+    assert_eq!(smoothed(&exposition), 2.0);
+    let exposition = feed(0, 8.0);
+    assert!(exposition.contains("tempest_station_observation_uv_index 8\n"));
+    let value = smoothed(&exposition);
+    assert!(value > 2.0 && value < 3.0, "{}", value);
+    assert!(!exposition.contains("tempest_station_smoothed_illuminance"));
+}
@@ -0,0 +1,100 @@
+// The station state keeps the latest report of each kind from each device.
+
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+use serde_json::json;
+use tempest_exporter::decoder::TempestMsg;
+use tempest_exporter::reader;
+use tempest_exporter::state::StationState;
+
+fn decoded(datagram: serde_json::Value) -> Arc<TempestMsg> {
+    let raw = reader::parse(&datagram.to_string()).unwrap();
+    Arc::new(TempestMsg::try_from(raw).map_err(|(_, e)| e).unwrap())
+}
+
+fn observation(serial_number: &str, timestamp: i64, temperature: f64) -> Arc<TempestMsg> {
+    decoded(json!({
+        "serial_number": serial_number,
+        "type": "obs_st",
+        "hub_sn": "HB-00000001",
+        "obs": [[timestamp, 1.0, 2.0, 3.0, 180, 3, 1010.0, temperature, 80.0, 0, 0.0, 0, 0.0, 0, 0, 0, 2.6, 1]],
+        "firmware_revision": 156,
+    }))
+}
+
+fn rapid_wind(timestamp: i64, speed: f64) -> Arc<TempestMsg> {
+    decoded(json!({
+        "serial_number": "ST-00000001",
+        "type": "rapid_wind",
+        "hub_sn": "HB-00000001",
+        "ob": [timestamp, speed, 90],
+    }))
+}
+
+#[test]
+fn keeps_latest_report_per_device() {
+    let mut state = StationState::default();
+    assert!(state.is_empty());
+
+    assert!(state.update(&observation("ST-00000001", 1588948600, 10.0)));
+    assert!(state.update(&observation("ST-00000002", 1588948660, 20.0)));
+    assert!(state.update(&observation("ST-00000001", 1588948720, 11.0)));
+    let temperatures: Vec<_> = state
+        .observations()
+        .map(|obs| {
+            (
+                obs.serial_number.as_str(),
+                obs.air_temperature.map(f64::from),
+            )
+        })
+        .collect();
+    assert_eq!(
+        temperatures,
+        [("ST-00000001", Some(11.0)), ("ST-00000002", Some(20.0))]
+    );
+    assert_eq!(
+        state
+            .observation()
+            .map(|obs| obs.air_temperature.map(f64::from)),
+        Some(Some(11.0))
+    );
+    assert_eq!(
+        state.updated_at().map(|at| at.timestamp()),
+        Some(1588948720)
+    );
+}
+
+#[test]
+fn ignores_older_reports() {
+    let mut state = StationState::default();
+    assert!(state.update(&rapid_wind(1588948614, 2.5)));
+    assert!(!state.update(&rapid_wind(1588948610, 9.0)));
+    let wind = state.rapid_wind().unwrap();
+    assert_eq!(wind.wind.speed_magnitude().0, 2.5);
+
+    assert!(state.update(&observation("ST-00000001", 1588948720, 11.0)));
+    assert!(!state.update(&observation("ST-00000001", 1588948600, 10.0)));
+    assert_eq!(
+        state
+            .observation()
+            .map(|obs| obs.air_temperature.map(f64::from)),
+        Some(Some(11.0))
+    );
+}
+
+#[test]
+fn serializes_reports_by_serial_number() {
+    let mut state = StationState::default();
+    state.update(&rapid_wind(1588948614, 2.5));
+    state.update(&observation("ST-00000001", 1588948600, 10.0));
+    let value = serde_json::to_value(&state).unwrap();
+    assert_eq!(value["updated_at"], json!("2020-05-08T14:36:54Z"));
+    assert_eq!(value["rapid_wind"]["type"], json!("rapid_wind"));
+    assert_eq!(
+        value["observations"]["ST-00000001"]["temperature_deg_c"],
+        json!(10.0)
+    );
+    assert_eq!(value["device_status"], json!({}));
+    assert_eq!(value["hub_status"], json!({}));
+}
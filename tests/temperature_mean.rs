@@ -0,0 +1,61 @@
+// Time-weighted mean temperature over the last hour.
+
+use std::convert::TryFrom;
+use std::time::Duration;
+
+use chrono::{TimeZone, Utc};
+use serde_json::json;
+use tempest_exporter::decoder::TempestMsg;
+use tempest_exporter::reader;
+use tempest_exporter::trend::Trend;
+
+mod common;
+
+const HOUR: Duration = Duration::from_secs(3600);
+
+#[test]
+fn readings_are_weighted_by_how_long_they_stood() {
+    let start = Utc.timestamp_opt(1_600_000_000, 0).unwrap();
+    let mut trend = Trend::new();
+    assert_eq!(trend.time_weighted_mean(HOUR), None);
+    trend.push(start, 10.0, HOUR);
+    assert_eq!(trend.time_weighted_mean(HOUR), Some(10.0));
+    // 10 °C for 45 minutes, then 20 °C for 5 minutes until a report delayed by an outage.
+    trend.push(start + chrono::Duration::minutes(45), 20.0, HOUR);
+    trend.push(start + chrono::Duration::minutes(50), 30.0, HOUR);
+    assert!((trend.time_weighted_mean(HOUR).unwrap() - 11.0).abs() < 1e-9);
+}
+
+#[test]
+fn mean_covers_only_the_window() {
+    let start = Utc.timestamp_opt(1_600_000_000, 0).unwrap();
+    let mut trend = Trend::new();
+    for (minutes, temperature) in [(0, 0.0), (30, 10.0), (60, 20.0), (90, 20.0)] {
+        trend.push(
+            start + chrono::Duration::minutes(minutes),
+            temperature,
+            HOUR,
+        );
+    }
+    assert!((trend.time_weighted_mean(HOUR).unwrap() - 15.0).abs() < 1e-9);
+}
+
+#[test]
+fn mean_temperature_is_exported_alongside_the_current() {
+    let exporter = common::exporter();
+    let now = Utc::now().timestamp();
+    for (minutes_ago, temperature) in [(90, 30.0), (40, 12.0), (20, 16.0), (0, 25.0)] {
+        let datagram = json!({
+            "serial_number": "ST-00000001",
+            "type": "obs_st",
+            "hub_sn": "HB-00000001",
+            "obs": [[now - minutes_ago * 60, 1.0, 2.0, 3.0, 180, 3, 1010.0, temperature, 80.0, 0, 0.0, 0, 0.0, 0, 0, 0, 2.6, 1]],
+            "firmware_revision": 156,
+        });
+        let raw = reader::parse(&datagram.to_string()).unwrap();
+        exporter.handle_report(&TempestMsg::try_from(raw).map_err(|(_, e)| e).unwrap());
+    }
+    let exposition = String::from_utf8(exporter.encode()).unwrap();
+    assert!(exposition.contains("tempest_station_observation_temperature_deg_c 25\n"));
+    assert!(exposition.contains("tempest_station_observation_temperature_mean_1h_deg_c 14\n"));
+}
@@ -0,0 +1,104 @@
+// Time since the latest rain and lightning strike, kept across restarts.
+
+use std::convert::TryFrom;
+
+use chrono::Utc;
+use serde_json::{json, Value};
+use tempest_exporter::decoder::TempestMsg;
+use tempest_exporter::exporter::Exporter;
+use tempest_exporter::reader;
+
+mod common;
+
+fn exporter() -> Exporter {
+    common::exporter()
+}
+
+fn feed(exporter: &Exporter, datagram: Value) {
+    let raw = reader::parse(&datagram.to_string()).unwrap();
+    exporter.handle_report(&TempestMsg::try_from(raw).map_err(|(_, e)| e).unwrap());
+}
+
+fn strike(seconds_ago: i64) -> Value {
+    json!({
+        "serial_number": "ST-00000001",
+        "type": "evt_strike",
+        "hub_sn": "HB-00000001",
+        "evt": [Utc::now().timestamp() - seconds_ago, 12, 3848],
+    })
+}
+
+fn precip(seconds_ago: i64) -> Value {
+    json!({
+        "serial_number": "ST-00000001",
+        "type": "evt_precip",
+        "hub_sn": "HB-00000001",
+        "evt": [Utc::now().timestamp() - seconds_ago],
+    })
+}
+
+fn observation(seconds_ago: i64, rain: f64) -> Value {
+    json!({
+        "serial_number": "ST-00000001",
+        "type": "obs_st",
+        "hub_sn": "HB-00000001",
+        "obs": [[Utc::now().timestamp() - seconds_ago, 1.0, 2.0, 3.0, 180, 3, 1010.0, 10.0, 80.0, 0, 0.0, 0, rain, 1, 0, 0, 2.6, 1]],
+        "firmware_revision": 156,
+    })
+}
+
+fn seconds(exporter: &Exporter, name: &str) -> Option<i64> {
+    let exposition = String::from_utf8(exporter.encode()).unwrap();
+    exposition
+        .lines()
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(' '))
+        .map(|value| value.parse().unwrap())
+}
+
+#[test]
+fn nothing_is_exported_before_the_first_event() {
+    let exporter = exporter();
+    feed(&exporter, observation(0, 0.0));
+    assert_eq!(
+        seconds(&exporter, "tempest_station_time_since_rain_sec"),
+        None
+    );
+    assert_eq!(
+        seconds(&exporter, "tempest_station_time_since_strike_sec"),
+        None
+    );
+}
+
+#[test]
+fn elapsed_time_follows_the_latest_event() {
+    let exporter = exporter();
+    feed(&exporter, strike(600));
+    feed(&exporter, precip(900));
+    let since_strike = seconds(&exporter, "tempest_station_time_since_strike_sec").unwrap();
+    assert!((600..605).contains(&since_strike), "{}", since_strike);
+    let since_rain = seconds(&exporter, "tempest_station_time_since_rain_sec").unwrap();
+    assert!((900..905).contains(&since_rain), "{}", since_rain);
+
+    // Rain continuing after it started counts, and a late event doesn't turn back the clock.
+    feed(&exporter, observation(120, 0.3));
+    feed(&exporter, observation(60, 0.0));
+    feed(&exporter, strike(3600));
+    let since_rain = seconds(&exporter, "tempest_station_time_since_rain_sec").unwrap();
+    assert!((120..125).contains(&since_rain), "{}", since_rain);
+    let since_strike = seconds(&exporter, "tempest_station_time_since_strike_sec").unwrap();
+    assert!((600..605).contains(&since_strike), "{}", since_strike);
+}
+
+#[test]
+fn latest_events_are_restored_from_a_checkpoint() {
+    let before = exporter();
+    feed(&before, strike(300));
+    feed(&before, precip(400));
+    let checkpoint = before.checkpoint();
+
+    let after = exporter();
+    after.restore(&checkpoint);
+    assert_eq!(after.checkpoint().last_strike, checkpoint.last_strike);
+    assert_eq!(after.checkpoint().last_rain, checkpoint.last_rain);
+    assert!(seconds(&after, "tempest_station_time_since_strike_sec").unwrap() >= 300);
+}
@@ -0,0 +1,70 @@
+// MQTT topic templates, which place published values in a custom topic hierarchy.
+
+use tempest_exporter::topic::TopicTemplate;
+
+const TOPIC: &str = "observation/thermal/temperature_deg_c";
+
+#[test]
+fn default_template_publishes_under_the_prefix() {
+    let template = TopicTemplate::default();
+    assert_eq!(
+        template.render("tempest", None, TOPIC).unwrap(),
+        "tempest/observation/thermal/temperature_deg_c"
+    );
+    assert_eq!(template, "{prefix}/{topic}".parse().unwrap());
+}
+
+#[test]
+fn placeholders_split_the_topic_into_category_and_field() {
+    let template: TopicTemplate = "site/{prefix}/{serial}/{category}/{field}".parse().unwrap();
+    assert_eq!(
+        template
+            .render("weather", Some("ST-00000001"), TOPIC)
+            .unwrap(),
+        "site/weather/ST-00000001/observation/thermal/temperature_deg_c"
+    );
+    assert_eq!(
+        template
+            .render("weather", Some("ST-00000001"), "status")
+            .unwrap(),
+        "site/weather/ST-00000001/status/"
+    );
+}
+
+#[test]
+fn serial_number_must_be_known_if_used() {
+    let template: TopicTemplate = "{serial}/{topic}".parse().unwrap();
+    assert!(template.uses_serial_number());
+    assert_eq!(template.render("tempest", None, TOPIC), None);
+    assert!(!TopicTemplate::default().uses_serial_number());
+}
+
+#[test]
+fn doubled_braces_are_literal() {
+    let template: TopicTemplate = "{{x}}/{topic}".parse().unwrap();
+    assert_eq!(
+        template
+            .render("tempest", None, "status/battery_volts")
+            .unwrap(),
+        "{x}/status/battery_volts"
+    );
+    assert_eq!(template.to_string(), "{{x}}/{topic}");
+}
+
+#[test]
+fn invalid_templates_are_rejected() {
+    for template in [
+        "{prefix}/{station}/{topic}",
+        "{prefix}/{topic",
+        "{prefix}}/{topic}",
+        "{prefix}/+/{topic}",
+        "{prefix}/#",
+        "{prefix}/{serial}",
+    ] {
+        assert!(
+            template.parse::<TopicTemplate>().is_err(),
+            "{} should be rejected",
+            template
+        );
+    }
+}
@@ -0,0 +1,45 @@
+// Unit conversions that sinks publish in.
+
+use serde_json::json;
+use tempest_exporter::units::{Celsius, HectoPascal, MetersPerSecond, Millimeters};
+
+fn assert_close(actual: f64, expected: f64) {
+    assert!(
+        (actual - expected).abs() < 1e-3,
+        "{} != {}",
+        actual,
+        expected
+    );
+}
+
+#[test]
+fn converts_to_publishing_units() {
+    assert_close(Celsius(100.0).fahrenheit(), 212.0);
+    assert_close(Celsius(-40.0).fahrenheit(), -40.0);
+    assert_close(Celsius(0.0).kelvin(), 273.15);
+    assert_close(HectoPascal(1013.25).inches_of_mercury(), 29.921);
+    assert_close(HectoPascal(1013.25).pascals(), 101_325.0);
+    assert_close(HectoPascal(1013.25).bars(), 1.01325);
+    assert_close(MetersPerSecond(10.0).kilometers_per_hour(), 36.0);
+    assert_close(MetersPerSecond(10.0).knots(), 19.438);
+    assert_close(MetersPerSecond(10.0).miles_per_hour(), 22.369);
+    assert_close(Millimeters(25.4).inches(), 1.0);
+}
+
+#[test]
+fn converts_from_other_units() {
+    assert_close(Celsius::from_fahrenheit(212.0).0, 100.0);
+    assert_close(Celsius::from_fahrenheit(-40.0).0, -40.0);
+    assert_close(HectoPascal::from_pascals(101_325.0).0, 1013.25);
+}
+
+#[test]
+fn serializes_and_formats_as_the_bare_number() {
+    assert_eq!(serde_json::to_value(Celsius(21.5)).unwrap(), json!(21.5));
+    assert_eq!(
+        serde_json::from_value::<HectoPascal>(json!(1003.28)).unwrap(),
+        HectoPascal(1003.28)
+    );
+    assert_eq!(format!("{:.1}", MetersPerSecond(2.42)), "2.4");
+    assert_eq!(f64::from(Millimeters(0.31)), 0.31);
+}